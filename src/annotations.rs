@@ -0,0 +1,423 @@
+use crate::{AnnotationError, AttributeVal, Edit, Netlist};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{Read, Write};
+
+/// Names an object in a netlist that can carry an annotation
+#[derive(Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectPath {
+    /// A module
+    Module { module: String },
+    /// A cell in a module
+    Cell { module: String, cell: String },
+    /// A netname in a module, or one bit of it (indexed from 0, not by HDL index)
+    Net {
+        module: String,
+        net: String,
+        bit: Option<usize>,
+    },
+}
+
+impl ObjectPath {
+    /// Path of a module
+    pub fn module(module: &str) -> Self {
+        ObjectPath::Module {
+            module: module.to_owned(),
+        }
+    }
+
+    /// Path of a cell
+    pub fn cell(module: &str, cell: &str) -> Self {
+        ObjectPath::Cell {
+            module: module.to_owned(),
+            cell: cell.to_owned(),
+        }
+    }
+
+    /// Path of a whole netname
+    pub fn net(module: &str, net: &str) -> Self {
+        ObjectPath::Net {
+            module: module.to_owned(),
+            net: net.to_owned(),
+            bit: None,
+        }
+    }
+
+    /// Path of one bit of a netname
+    pub fn net_bit(module: &str, net: &str, bit: usize) -> Self {
+        ObjectPath::Net {
+            module: module.to_owned(),
+            net: net.to_owned(),
+            bit: Some(bit),
+        }
+    }
+
+    /// Name of the module containing the object
+    pub fn module_name(&self) -> &str {
+        match self {
+            ObjectPath::Module { module }
+            | ObjectPath::Cell { module, .. }
+            | ObjectPath::Net { module, .. } => module,
+        }
+    }
+
+    fn resolves(&self, netlist: &Netlist) -> bool {
+        let Some(module) = netlist.modules.get(self.module_name()) else {
+            return false;
+        };
+        match self {
+            ObjectPath::Module { .. } => true,
+            ObjectPath::Cell { cell, .. } => module.cells.contains_key(cell),
+            ObjectPath::Net { net, bit, .. } => module
+                .netnames
+                .get(net)
+                .is_some_and(|n| !bit.is_some_and(|bit| bit >= n.bits.len())),
+        }
+    }
+}
+
+impl fmt::Display for ObjectPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjectPath::Module { module } => write!(f, "{}", module),
+            ObjectPath::Cell { module, cell } => write!(f, "{}/{}", module, cell),
+            ObjectPath::Net {
+                module,
+                net,
+                bit: None,
+            } => write!(f, "{}/{}", module, net),
+            ObjectPath::Net {
+                module,
+                net,
+                bit: Some(bit),
+            } => write!(f, "{}/{}/{}", module, net, bit),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    path: ObjectPath,
+    value: Value,
+}
+
+/// Analysis results attached to objects of a netlist, stored outside of the netlist
+///
+/// Annotations are kept separate from Yosys attributes and can be saved to and
+/// loaded from their own JSON file. Paths aren't checked when annotations are
+/// added; use `attach` to check them against a netlist.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Vec<Entry>", into = "Vec<Entry>")]
+pub struct Annotations {
+    entries: BTreeMap<ObjectPath, Value>,
+}
+
+impl From<Vec<Entry>> for Annotations {
+    fn from(entries: Vec<Entry>) -> Self {
+        Annotations {
+            entries: entries.into_iter().map(|e| (e.path, e.value)).collect(),
+        }
+    }
+}
+
+impl From<Annotations> for Vec<Entry> {
+    fn from(annotations: Annotations) -> Self {
+        annotations
+            .entries
+            .into_iter()
+            .map(|(path, value)| Entry { path, value })
+            .collect()
+    }
+}
+
+impl Annotations {
+    /// Create an empty set of annotations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the annotation of an object, returning the old one
+    pub fn insert(&mut self, path: ObjectPath, value: Value) -> Option<Value> {
+        self.entries.insert(path, value)
+    }
+
+    /// The annotation of an object
+    pub fn get(&self, path: &ObjectPath) -> Option<&Value> {
+        self.entries.get(path)
+    }
+
+    /// Remove the annotation of an object
+    pub fn remove(&mut self, path: &ObjectPath) -> Option<Value> {
+        self.entries.remove(path)
+    }
+
+    /// Iterate over all annotations, sorted by path
+    pub fn iter(&self) -> impl Iterator<Item = (&ObjectPath, &Value)> {
+        self.entries.iter()
+    }
+
+    /// Number of annotated objects
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no annotations
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Check that every path names an object in `netlist`
+    pub fn attach(&self, netlist: &Netlist) -> Result<(), AnnotationError> {
+        let unresolved = self
+            .entries
+            .keys()
+            .filter(|path| !path.resolves(netlist))
+            .cloned()
+            .collect::<Vec<_>>();
+        if unresolved.is_empty() {
+            Ok(())
+        } else {
+            Err(AnnotationError::Unresolved(unresolved))
+        }
+    }
+
+    /// Read annotations from a sidecar JSON file
+    pub fn from_reader<R: Read>(reader: R) -> Result<Annotations, serde_json::Error> {
+        serde_json::from_reader(reader)
+    }
+
+    /// Write annotations as a sidecar JSON file
+    pub fn to_writer<W: Write>(&self, writer: W) -> Result<(), serde_json::Error> {
+        serde_json::to_writer(writer, self)
+    }
+
+    /// Move or drop annotations after objects were renamed or removed
+    ///
+    /// `f` returns the new path of each object, or `None` if it no longer exists.
+    pub fn remap(&mut self, mut f: impl FnMut(&ObjectPath) -> Option<ObjectPath>) {
+        let entries = std::mem::take(&mut self.entries);
+        self.entries = entries
+            .into_iter()
+            .filter_map(|(path, value)| Some((f(&path)?, value)))
+            .collect();
+    }
+
+    /// Update paths in `module` for the renames and removals in a `Recorder` journal
+    pub fn apply_journal(&mut self, module: &str, edits: &[Edit]) {
+        for edit in edits {
+            match edit {
+                Edit::RenameCell { from, to } => self.remap(|path| match path {
+                    ObjectPath::Cell { module: m, cell } if m == module && cell == from => {
+                        Some(ObjectPath::cell(module, to))
+                    }
+                    _ => Some(path.clone()),
+                }),
+                Edit::RenameNet { from, to } => self.remap(|path| match path {
+                    ObjectPath::Net {
+                        module: m,
+                        net,
+                        bit,
+                    } if m == module && net == from => Some(ObjectPath::Net {
+                        module: module.to_owned(),
+                        net: to.clone(),
+                        bit: *bit,
+                    }),
+                    _ => Some(path.clone()),
+                }),
+                Edit::RemoveCell { name, .. } => self.remap(|path| match path {
+                    ObjectPath::Cell { module: m, cell } if m == module && cell == name => None,
+                    _ => Some(path.clone()),
+                }),
+                _ => {}
+            }
+        }
+    }
+
+    /// Store every annotation as an attribute named `prefix` (or `prefix[bit]` for a
+    /// bit of a net) so the netlist can be passed through Yosys
+    ///
+    /// Non-negative integers become numeric attributes and other values become their
+    /// JSON text. Nothing is changed if any path doesn't resolve.
+    pub fn fold_into_attributes(
+        &self,
+        netlist: &mut Netlist,
+        prefix: &str,
+    ) -> Result<(), AnnotationError> {
+        self.attach(netlist)?;
+        for (path, value) in &self.entries {
+            let module = netlist.modules.get_mut(path.module_name()).unwrap();
+            let (attributes, name) = match path {
+                ObjectPath::Module { .. } => (&mut module.attributes, prefix.to_owned()),
+                ObjectPath::Cell { cell, .. } => (
+                    &mut module.cells.get_mut(cell).unwrap().attributes,
+                    prefix.to_owned(),
+                ),
+                ObjectPath::Net { net, bit, .. } => (
+                    &mut module.netnames.get_mut(net).unwrap().attributes,
+                    match bit {
+                        Some(bit) => format!("{}[{}]", prefix, bit),
+                        None => prefix.to_owned(),
+                    },
+                ),
+            };
+            let value = match value.as_u64() {
+                Some(n) => AttributeVal::N(n as usize),
+                None => AttributeVal::S(value.to_string()),
+            };
+            attributes.insert(name, value);
+        }
+        Ok(())
+    }
+
+    /// Collect annotations stored by `fold_into_attributes`, leaving the attributes
+    /// in place
+    pub fn from_attributes(netlist: &Netlist, prefix: &str) -> Annotations {
+        fn value(val: &AttributeVal) -> Value {
+            match val {
+                AttributeVal::N(n) => Value::from(*n),
+                AttributeVal::S(s) => {
+                    serde_json::from_str(s).unwrap_or_else(|_| Value::String(s.clone()))
+                }
+            }
+        }
+
+        let mut annotations = Annotations::new();
+        for (module_name, module) in &netlist.modules {
+            let attr =
+                |attributes: &HashMap<String, AttributeVal>| attributes.get(prefix).map(value);
+            if let Some(v) = attr(&module.attributes) {
+                annotations.insert(ObjectPath::module(module_name), v);
+            }
+            for (cell_name, cell) in &module.cells {
+                if let Some(v) = attr(&cell.attributes) {
+                    annotations.insert(ObjectPath::cell(module_name, cell_name), v);
+                }
+            }
+            for (net_name, netname) in &module.netnames {
+                for (key, val) in &netname.attributes {
+                    let Some(rest) = key.strip_prefix(prefix) else {
+                        continue;
+                    };
+                    let path = if rest.is_empty() {
+                        ObjectPath::net(module_name, net_name)
+                    } else if let Some(bit) = rest
+                        .strip_prefix('[')
+                        .and_then(|x| x.strip_suffix(']'))
+                        .and_then(|x| x.parse().ok())
+                    {
+                        ObjectPath::net_bit(module_name, net_name, bit)
+                    } else {
+                        continue;
+                    };
+                    annotations.insert(path, value(val));
+                }
+            }
+        }
+        annotations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{netlist, Recorder};
+    use serde_json::json;
+
+    fn test_netlist() -> Netlist {
+        netlist! {
+            module top {
+                input a[2];
+                output y;
+                cell "$and" u_and { A: a[0], B: a[1], Y: y }
+            }
+        }
+    }
+
+    #[test]
+    fn attach_test() {
+        let netlist = test_netlist();
+        let mut annotations = Annotations::new();
+        annotations.insert(ObjectPath::cell("top", "u_and"), json!({ "slack": 1.5 }));
+        annotations.insert(ObjectPath::net_bit("top", "a", 1), json!(3));
+        annotations.insert(ObjectPath::module("top"), json!("root"));
+        assert_eq!(annotations.attach(&netlist), Ok(()));
+
+        annotations.insert(ObjectPath::net_bit("top", "a", 2), json!(0));
+        annotations.insert(ObjectPath::cell("other", "u"), json!(0));
+        assert_eq!(
+            annotations.attach(&netlist),
+            Err(AnnotationError::Unresolved(vec![
+                ObjectPath::cell("other", "u"),
+                ObjectPath::net_bit("top", "a", 2),
+            ]))
+        );
+    }
+
+    #[test]
+    fn sidecar_test() {
+        let mut annotations = Annotations::new();
+        annotations.insert(ObjectPath::cell("top", "u_and"), json!([1, 2]));
+        annotations.insert(ObjectPath::net("top", "y"), json!(null));
+
+        let mut out = Vec::new();
+        annotations.to_writer(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out.clone()).unwrap(),
+            r#"[{"path":{"cell":{"module":"top","cell":"u_and"}},"value":[1,2]},{"path":{"net":{"module":"top","net":"y","bit":null}},"value":null}]"#
+        );
+        assert_eq!(Annotations::from_reader(&out[..]).unwrap(), annotations);
+    }
+
+    #[test]
+    fn apply_journal_test() {
+        let netlist = test_netlist();
+        let mut annotations = Annotations::new();
+        annotations.insert(ObjectPath::cell("top", "u_and"), json!(1));
+        annotations.insert(ObjectPath::net_bit("top", "a", 0), json!(2));
+
+        let mut rec = Recorder::new(netlist.modules["top"].clone());
+        rec.rename_cell("u_and", "and0").unwrap();
+        rec.rename_net("a", "a_in").unwrap();
+        annotations.apply_journal("top", rec.journal());
+        assert_eq!(
+            annotations.get(&ObjectPath::cell("top", "and0")),
+            Some(&json!(1))
+        );
+        assert_eq!(
+            annotations.get(&ObjectPath::net_bit("top", "a_in", 0)),
+            Some(&json!(2))
+        );
+
+        rec.remove_cell("and0").unwrap();
+        annotations.apply_journal("top", &rec.journal()[2..]);
+        assert_eq!(annotations.len(), 1);
+    }
+
+    #[test]
+    fn fold_into_attributes_test() {
+        let mut netlist = test_netlist();
+        let mut annotations = Annotations::new();
+        annotations.insert(ObjectPath::cell("top", "u_and"), json!(7));
+        annotations.insert(ObjectPath::net_bit("top", "a", 1), json!({ "cov": true }));
+        annotations
+            .fold_into_attributes(&mut netlist, "annot")
+            .unwrap();
+
+        let top = &netlist.modules["top"];
+        assert_eq!(top.cells["u_and"].attributes["annot"], AttributeVal::N(7));
+        assert_eq!(
+            top.netnames["a"].attributes["annot[1]"],
+            AttributeVal::S(r#"{"cov":true}"#.to_owned())
+        );
+        assert_eq!(Annotations::from_attributes(&netlist, "annot"), annotations);
+
+        annotations.insert(ObjectPath::cell("top", "gone"), json!(0));
+        assert!(annotations.fold_into_attributes(&mut netlist, "x").is_err());
+        assert!(!netlist.modules["top"].cells["u_and"]
+            .attributes
+            .contains_key("x"));
+    }
+}
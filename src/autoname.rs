@@ -0,0 +1,230 @@
+use std::fmt;
+
+/// A location in an HDL source file, as found in `src` attributes
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct SourceLocation {
+    /// Source file name
+    pub file: String,
+    /// First line of the location
+    pub start_line: usize,
+    /// First column of the location, if known
+    pub start_col: Option<usize>,
+    /// Last line of the location, if this is a range
+    pub end_line: Option<usize>,
+    /// Last column of the location, if this is a range
+    pub end_col: Option<usize>,
+}
+
+impl SourceLocation {
+    /// Parse a single location of the form `file:line`, `file:line.col` or
+    /// `file:line.col-line.col`
+    ///
+    /// Multiple locations joined with `|` are not accepted.
+    pub fn parse(s: &str) -> Option<SourceLocation> {
+        if s.contains('|') {
+            return None;
+        }
+        let (file, pos) = s.rsplit_once(':')?;
+        if file.is_empty() {
+            return None;
+        }
+
+        fn line_col(s: &str) -> Option<(usize, Option<usize>)> {
+            match s.split_once('.') {
+                Some((line, col)) => Some((line.parse().ok()?, Some(col.parse().ok()?))),
+                None => Some((s.parse().ok()?, None)),
+            }
+        }
+
+        let (start, end) = match pos.split_once('-') {
+            Some((start, end)) => (line_col(start)?, Some(line_col(end)?)),
+            None => (line_col(pos)?, None),
+        };
+
+        Some(SourceLocation {
+            file: file.to_owned(),
+            start_line: start.0,
+            start_col: start.1,
+            end_line: end.map(|x| x.0),
+            end_col: end.and_then(|x| x.1),
+        })
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.start_line)?;
+        if let Some(col) = self.start_col {
+            write!(f, ".{}", col)?;
+        }
+        if let Some(line) = self.end_line {
+            write!(f, "-{}", line)?;
+            if let Some(col) = self.end_col {
+                write!(f, ".{}", col)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The components of an auto-generated Yosys name such as `$xor$test.v:10$1_Y`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct AutoName {
+    /// Instance path prepended by `flatten`, outermost instance first
+    pub flatten_prefix: Vec<String>,
+    /// Pass or cell operator that generated the name, e.g. `xor` or `procdff`
+    ///
+    /// For `$auto$<file>.cc:<line>:<function>$<index>` names this is the name of the
+    /// Yosys source file, which is usually the name of the pass.
+    pub generator: String,
+    /// HDL source location embedded in the name
+    pub source: Option<SourceLocation>,
+    /// Uniquifying index
+    pub index: usize,
+    /// Port name appended to the name of a cell output wire, e.g. `Y` for `_Y`
+    pub port_suffix: Option<String>,
+}
+
+fn is_ident(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl AutoName {
+    /// Split an auto-generated name into its components
+    ///
+    /// Returns `None` for public names and for auto-generated names with a shape
+    /// that isn't recognized.
+    pub fn parse(name: &str) -> Option<AutoName> {
+        let mut flatten_prefix = Vec::new();
+        let mut rest = name;
+        if let Some(flattened) = rest.strip_prefix("$flatten") {
+            rest = flattened;
+            while let Some(inst) = rest.strip_prefix('\\') {
+                let (inst, remaining) = inst.split_once('.')?;
+                flatten_prefix.push(inst.to_owned());
+                rest = remaining;
+            }
+            if flatten_prefix.is_empty() {
+                return None;
+            }
+        }
+
+        let parts = rest.strip_prefix('$')?.split('$').collect::<Vec<_>>();
+        if parts.len() < 2 || parts.len() > 3 || !is_ident(parts[0]) {
+            return None;
+        }
+
+        let last = parts[parts.len() - 1];
+        let digits_end = last
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(last.len());
+        let index = last[..digits_end].parse().ok()?;
+        let port_suffix = match &last[digits_end..] {
+            "" => None,
+            suffix => {
+                let suffix = suffix.strip_prefix('_')?;
+                if !is_ident(suffix) {
+                    return None;
+                }
+                Some(suffix.to_owned())
+            }
+        };
+
+        let (generator, source) = if parts.len() == 2 {
+            (parts[0].to_owned(), None)
+        } else if parts[0] == "auto" {
+            let (file, _) = parts[1].split_once(".cc:")?;
+            if !is_ident(file) {
+                return None;
+            }
+            (file.to_owned(), None)
+        } else {
+            (parts[0].to_owned(), Some(SourceLocation::parse(parts[1])?))
+        };
+
+        Some(AutoName {
+            flatten_prefix,
+            generator,
+            source,
+            index,
+            port_suffix,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_location_test() {
+        let loc = SourceLocation::parse("test-for-json.v:10.12-10.17").unwrap();
+        assert_eq!(loc.file, "test-for-json.v");
+        assert_eq!(loc.start_line, 10);
+        assert_eq!(loc.start_col, Some(12));
+        assert_eq!(loc.end_line, Some(10));
+        assert_eq!(loc.end_col, Some(17));
+        assert_eq!(loc.to_string(), "test-for-json.v:10.12-10.17");
+
+        let loc = SourceLocation::parse("a.v:5").unwrap();
+        assert_eq!(loc.file, "a.v");
+        assert_eq!(loc.start_line, 5);
+        assert_eq!(loc.start_col, None);
+        assert_eq!(loc.end_line, None);
+        assert_eq!(loc.to_string(), "a.v:5");
+
+        assert_eq!(SourceLocation::parse("a.v:1.1-2.2|b.v:3.3-4.4"), None);
+        assert_eq!(SourceLocation::parse("a.v"), None);
+        assert_eq!(SourceLocation::parse(":5"), None);
+        assert_eq!(SourceLocation::parse("a.v:x"), None);
+    }
+
+    #[test]
+    fn autoname_test() {
+        let n = AutoName::parse("$procdff$1234").unwrap();
+        assert_eq!(n.generator, "procdff");
+        assert_eq!(n.index, 1234);
+        assert_eq!(n.source, None);
+        assert_eq!(n.port_suffix, None);
+        assert!(n.flatten_prefix.is_empty());
+
+        let n = AutoName::parse("$xor$test-for-json.v:10$1").unwrap();
+        assert_eq!(n.generator, "xor");
+        assert_eq!(n.source.unwrap().to_string(), "test-for-json.v:10");
+        assert_eq!(n.index, 1);
+        assert_eq!(n.port_suffix, None);
+
+        let n = AutoName::parse("$xor$test-for-json.v:10$1_Y").unwrap();
+        assert_eq!(n.generator, "xor");
+        assert_eq!(n.index, 1);
+        assert_eq!(n.port_suffix.as_deref(), Some("Y"));
+
+        let n = AutoName::parse("$logic_not$top.v:22$8_Y").unwrap();
+        assert_eq!(n.generator, "logic_not");
+        assert_eq!(n.source.unwrap().start_line, 22);
+
+        let n = AutoName::parse("$flatten\\u_core.$and$a.v:5$7").unwrap();
+        assert_eq!(n.flatten_prefix, ["u_core"]);
+        assert_eq!(n.generator, "and");
+        assert_eq!(n.source.unwrap().file, "a.v");
+        assert_eq!(n.index, 7);
+
+        let n = AutoName::parse("$flatten\\u_soc.\\u_dma.$procmux$42").unwrap();
+        assert_eq!(n.flatten_prefix, ["u_soc", "u_dma"]);
+        assert_eq!(n.generator, "procmux");
+        assert_eq!(n.index, 42);
+
+        let n = AutoName::parse("$auto$opt_dff.cc:764:run$123").unwrap();
+        assert_eq!(n.generator, "opt_dff");
+        assert_eq!(n.source, None);
+        assert_eq!(n.index, 123);
+
+        assert_eq!(AutoName::parse("a"), None);
+        assert_eq!(AutoName::parse("\\a"), None);
+        assert_eq!(AutoName::parse("$0\\q[7:0]"), None);
+        assert_eq!(AutoName::parse("$abc$1234$new_n56_"), None);
+        assert_eq!(AutoName::parse("$memrd$\\mem$test.v:12$5"), None);
+        assert_eq!(AutoName::parse("$flatten\\u_core.a"), None);
+        assert_eq!(AutoName::parse("$xor$test.v:10$1_Y[0]"), None);
+    }
+}
@@ -0,0 +1,363 @@
+//! Generate source code that embeds a netlist
+
+use crate::{BitVal, Cell, Module, SpecialBit};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+
+/// Options for `to_rust_const`
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct RustConstOptions {
+    /// Value that `x` and `z` constants read as in the generated `eval`
+    pub x_value: bool,
+}
+
+/// The operation `eval` performs for a cell type
+fn eval_kind(cell: &Cell) -> Option<&'static str> {
+    let kind = match cell.cell_type.as_str() {
+        "$pos" | "$_BUF_" => "Buf",
+        "$not" | "$_NOT_" => "Not",
+        "$and" | "$_AND_" => "And",
+        "$or" | "$_OR_" => "Or",
+        "$xor" | "$_XOR_" => "Xor",
+        "$xnor" | "$_XNOR_" => "Xnor",
+        "$_NAND_" => "Nand",
+        "$_NOR_" => "Nor",
+        "$mux" | "$_MUX_" => "Mux",
+        "$lut" => "Lut",
+        _ => return None,
+    };
+
+    // Inputs narrower than the output are zero-extended, which is wrong for signed
+    // cells
+    let y_width = cell.connections.get("Y").map_or(0, |y| y.len());
+    let sign_extended = ["A", "B"].iter().any(|port| {
+        let signed = cell
+            .parameters
+            .get(&format!("{}_SIGNED", port))
+            .and_then(|x| x.to_number())
+            .is_some_and(|x| x != 0);
+        signed
+            && cell
+                .connections
+                .get(*port)
+                .is_some_and(|x| x.len() < y_width)
+    });
+    if sign_extended || (kind == "Lut" && cell.connections.get("A").map_or(0, |a| a.len()) > 6) {
+        return None;
+    }
+    Some(kind)
+}
+
+fn signal(bit: &BitVal) -> String {
+    match bit {
+        BitVal::N(n) => n.to_string(),
+        BitVal::S(SpecialBit::_0) => "0".to_owned(),
+        BitVal::S(SpecialBit::_1) => "1".to_owned(),
+        BitVal::S(SpecialBit::X) => "SIGNAL_X".to_owned(),
+        BitVal::S(SpecialBit::Z) => "SIGNAL_Z".to_owned(),
+    }
+}
+
+fn signals(bits: &[BitVal]) -> String {
+    let bits = bits.iter().map(signal).collect::<Vec<_>>();
+    format!("&[{}]", bits.join(", "))
+}
+
+/// Order the cells so that every cell comes after the cells driving its inputs, or
+/// return `None` if there is a loop
+fn eval_order<'a>(cells: &[(&'a String, &'a Cell)]) -> Option<Vec<(&'a String, &'a Cell)>> {
+    let mut driver = HashMap::new();
+    for (i, (_, cell)) in cells.iter().enumerate() {
+        for bit in cell.connections.get("Y").into_iter().flatten() {
+            if let BitVal::N(n) = bit {
+                driver.insert(*n, i);
+            }
+        }
+    }
+
+    let mut readers = vec![Vec::new(); cells.len()];
+    let mut pending = vec![0; cells.len()];
+    for (i, (_, cell)) in cells.iter().enumerate() {
+        for (port, bits) in &cell.connections {
+            if port == "Y" {
+                continue;
+            }
+            for bit in bits {
+                if let Some(&d) = driver.get(&match bit {
+                    BitVal::N(n) => *n,
+                    BitVal::S(_) => continue,
+                }) {
+                    readers[d].push(i);
+                    pending[i] += 1;
+                }
+            }
+        }
+    }
+
+    let mut ready = (0..cells.len())
+        .filter(|&i| pending[i] == 0)
+        .collect::<VecDeque<_>>();
+    let mut order = Vec::new();
+    while let Some(i) = ready.pop_front() {
+        order.push(cells[i]);
+        for &r in &readers[i] {
+            pending[r] -= 1;
+            if pending[r] == 0 {
+                ready.push_back(r);
+            }
+        }
+    }
+    (order.len() == cells.len()).then_some(order)
+}
+
+const EVAL: &str = r#"
+fn connection(cell: &CellEntry, port: &str) -> &'static [u32] {
+    let mut i = 0;
+    while i < cell.connections.len() {
+        if cell.connections[i].0 == port {
+            return cell.connections[i].1;
+        }
+        i += 1;
+    }
+    &[]
+}
+
+fn get(s: &[bool; NUM_SIGNALS], bits: &[u32], i: usize) -> bool {
+    match bits.get(i) {
+        None | Some(0) => false,
+        Some(1) => true,
+        Some(&b) if b >= SIGNAL_Z => X_VALUE,
+        Some(&b) => s[b as usize],
+    }
+}
+
+/// Evaluate the module; bit `i` of each value is bit `i` of the port
+pub fn eval(inputs: &[u64; NUM_INPUTS]) -> [u64; NUM_OUTPUTS] {
+    let mut s = [false; NUM_SIGNALS];
+    for (k, (_, bits)) in INPUTS.iter().enumerate() {
+        for (i, &b) in bits.iter().enumerate() {
+            if b > 1 && b < SIGNAL_Z {
+                s[b as usize] = (inputs[k] >> i) & 1 != 0;
+            }
+        }
+    }
+    for cell in CELLS {
+        let (a, b, sel, y) = (
+            connection(cell, "A"),
+            connection(cell, "B"),
+            connection(cell, "S"),
+            connection(cell, "Y"),
+        );
+        for (i, &out) in y.iter().enumerate() {
+            let (av, bv) = (get(&s, a, i), get(&s, b, i));
+            let v = match cell.kind {
+                CellKind::Buf => av,
+                CellKind::Not => !av,
+                CellKind::And => av & bv,
+                CellKind::Or => av | bv,
+                CellKind::Xor => av ^ bv,
+                CellKind::Xnor => !(av ^ bv),
+                CellKind::Nand => !(av & bv),
+                CellKind::Nor => !(av | bv),
+                CellKind::Mux => if get(&s, sel, 0) { bv } else { av },
+                CellKind::Lut => {
+                    let mut idx = 0;
+                    for j in 0..a.len() {
+                        if get(&s, a, j) {
+                            idx |= 1 << j;
+                        }
+                    }
+                    (cell.lut_init >> idx) & 1 != 0
+                }
+                CellKind::Other => false,
+            };
+            if out > 1 && out < SIGNAL_Z {
+                s[out as usize] = v;
+            }
+        }
+    }
+    let mut outputs = [0; NUM_OUTPUTS];
+    for (k, (_, bits)) in OUTPUTS.iter().enumerate() {
+        for i in 0..bits.len() {
+            if get(&s, bits, i) {
+                outputs[k] |= 1 << i;
+            }
+        }
+    }
+    outputs
+}
+"#;
+
+/// Generate Rust source containing `module` as constant tables
+///
+/// The output defines `INPUTS` and `OUTPUTS` (port names and signal numbers), `CELLS`
+/// (type, connections, and the `LUT` parameter of `$lut` cells as a `u64`), and,
+/// when every cell is a supported combinational cell (bitwise logic, muxes, the
+/// matching gate-level cells, and `$lut`) with no loops and every port is at most 64
+/// bits wide, an `eval` function. Otherwise a comment explains why `eval` is
+/// missing. The code only uses `core` and has no inner attributes, so it can be
+/// pulled in with `include!`.
+pub fn to_rust_const(module: &Module, opts: &RustConstOptions) -> String {
+    let mut out = String::new();
+    let mut cells = module.cells.iter().collect::<Vec<_>>();
+    cells.sort_by_key(|(name, _)| name.as_str());
+
+    let mut ports = module.ports.iter().collect::<Vec<_>>();
+    ports.sort_by_key(|(name, _)| name.as_str());
+    let inputs = ports
+        .iter()
+        .filter(|(_, port)| port.direction.drives_net_inside())
+        .collect::<Vec<_>>();
+    let outputs = ports
+        .iter()
+        .filter(|(_, port)| port.direction.drives_net_from_instance())
+        .collect::<Vec<_>>();
+
+    let mut no_eval = None;
+    if let Some((name, cell)) = cells.iter().find(|(_, cell)| eval_kind(cell).is_none()) {
+        no_eval = Some(format!(
+            "cell `{}` of type `{}` is not supported",
+            name, cell.cell_type
+        ));
+    } else if let Some((name, _)) = ports.iter().find(|(_, port)| port.bits.len() > 64) {
+        no_eval = Some(format!("port `{}` is wider than 64 bits", name));
+    } else if let Some(order) = eval_order(&cells) {
+        cells = order;
+    } else {
+        no_eval = Some("the module contains a combinational loop".to_owned());
+    }
+
+    let num_signals = module
+        .ports
+        .values()
+        .map(|p| &p.bits)
+        .chain(module.cells.values().flat_map(|c| c.connections.values()))
+        .flatten()
+        .filter_map(|bit| match bit {
+            BitVal::N(n) => Some(n + 1),
+            BitVal::S(_) => None,
+        })
+        .max()
+        .unwrap_or(2);
+
+    writeln!(out, "// Generated by yosys-netlist-json. Do not edit.").unwrap();
+    out.push_str(
+        r#"
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellKind {
+    Buf,
+    Not,
+    And,
+    Or,
+    Xor,
+    Xnor,
+    Nand,
+    Nor,
+    Mux,
+    Lut,
+    Other,
+}
+
+pub struct CellEntry {
+    pub name: &'static str,
+    pub cell_type: &'static str,
+    pub kind: CellKind,
+    pub connections: &'static [(&'static str, &'static [u32])],
+    pub lut_init: u64,
+}
+
+/// Signal number used for `x` constants; 0 and 1 are the constants 0 and 1
+pub const SIGNAL_X: u32 = 0xffff_ffff;
+/// Signal number used for `z` constants
+pub const SIGNAL_Z: u32 = 0xffff_fffe;
+"#,
+    );
+    writeln!(out, "pub const X_VALUE: bool = {};", opts.x_value).unwrap();
+    writeln!(out, "pub const NUM_SIGNALS: usize = {};", num_signals).unwrap();
+    writeln!(out, "pub const NUM_INPUTS: usize = {};", inputs.len()).unwrap();
+    writeln!(out, "pub const NUM_OUTPUTS: usize = {};", outputs.len()).unwrap();
+
+    for (name, ports) in [("INPUTS", &inputs), ("OUTPUTS", &outputs)] {
+        writeln!(out, "\npub const {}: &[(&str, &[u32])] = &[", name).unwrap();
+        for (port_name, port) in ports.iter() {
+            writeln!(out, "    ({:?}, {}),", port_name, signals(&port.bits)).unwrap();
+        }
+        writeln!(out, "];").unwrap();
+    }
+
+    writeln!(out, "\npub const CELLS: &[CellEntry] = &[").unwrap();
+    for (name, cell) in &cells {
+        let mut connections = cell.connections.iter().collect::<Vec<_>>();
+        connections.sort_by_key(|(port, _)| port.as_str());
+        let lut_init = match cell.cell_type.as_str() {
+            "$lut" => cell
+                .parameters
+                .get("LUT")
+                .and_then(|x| x.to_number())
+                .unwrap_or(0),
+            _ => 0,
+        };
+        writeln!(out, "    CellEntry {{").unwrap();
+        writeln!(out, "        name: {:?},", name).unwrap();
+        writeln!(out, "        cell_type: {:?},", cell.cell_type).unwrap();
+        writeln!(
+            out,
+            "        kind: CellKind::{},",
+            eval_kind(cell).unwrap_or("Other")
+        )
+        .unwrap();
+        writeln!(out, "        connections: &[").unwrap();
+        for (port, bits) in connections {
+            writeln!(out, "            ({:?}, {}),", port, signals(bits)).unwrap();
+        }
+        writeln!(out, "        ],").unwrap();
+        writeln!(out, "        lut_init: {:#x},", lut_init).unwrap();
+        writeln!(out, "    }},").unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    match no_eval {
+        None => out.push_str(EVAL),
+        Some(reason) => writeln!(out, "\n// No `eval` function: {}", reason).unwrap(),
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netlist;
+
+    #[test]
+    fn to_rust_const_test() {
+        let netlist = netlist! {
+            module top {
+                input a[2];
+                output y;
+                wire t;
+                cell "$not" inv { A: t, Y: y }
+                cell "$lut" lut { A: a, Y: t } { WIDTH: 2, LUT: "1000" }
+            }
+        };
+        let code = to_rust_const(&netlist.modules["top"], &RustConstOptions::default());
+        assert!(
+            code.contains("pub const INPUTS: &[(&str, &[u32])] = &[\n    (\"a\", &[2, 3]),\n];")
+        );
+        assert!(code.contains("lut_init: 0x8,"));
+        assert!(code.contains("pub fn eval("));
+        // `lut` drives `inv`, so it is evaluated first
+        assert!(code.find("name: \"lut\"").unwrap() < code.find("name: \"inv\"").unwrap());
+
+        let netlist = netlist! {
+            module top {
+                input clk;
+                input d;
+                output q;
+                cell "$dff" r { CLK: clk, D: d, Q: q }
+            }
+        };
+        let code = to_rust_const(&netlist.modules["top"], &RustConstOptions::default());
+        assert!(!code.contains("pub fn eval("));
+        assert!(code.contains("// No `eval` function: cell `r` of type `$dff` is not supported"));
+    }
+}
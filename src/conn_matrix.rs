@@ -0,0 +1,270 @@
+use crate::timing::is_sequential;
+use crate::{BitVal, Endpoint, Module};
+use serde_derive::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{self, Write};
+
+/// Options for `Module::instance_connectivity_with`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConnMatrixOptions {
+    /// Only include these instances, instead of every cell whose type doesn't start
+    /// with `$`
+    pub instances: Option<Vec<String>>,
+    /// Follow nets through combinational `$` cells to find instances connected
+    /// through glue logic
+    pub through_glue: bool,
+}
+
+/// How many net bits flow between each pair of instances, returned by
+/// `Module::instance_connectivity`
+///
+/// Rows are the driving instance and columns the consuming one, both in the order
+/// of `instances`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct ConnMatrix {
+    /// Instance names, sorted
+    pub instances: Vec<String>,
+    /// Cell type of each instance
+    pub cell_types: Vec<String>,
+    /// Number of output bits of the row instance that are inputs of the column
+    /// instance
+    pub direct: Vec<Vec<usize>>,
+    /// Number of output bits of the row instance that only reach the column instance
+    /// through glue logic; all zero unless glue logic was traced
+    pub via_glue: Vec<Vec<usize>>,
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+impl ConnMatrix {
+    /// The direct and glue bit counts from instance `from` to instance `to`
+    pub fn get(&self, from: &str, to: &str) -> Option<(usize, usize)> {
+        let from = self.instances.iter().position(|name| name == from)?;
+        let to = self.instances.iter().position(|name| name == to)?;
+        Some((self.direct[from][to], self.via_glue[from][to]))
+    }
+
+    /// Write the matrix as CSV, with `name (type)` row and column headers
+    ///
+    /// Each entry is the number of direct bits, plus the glue bits if `include_glue`
+    /// is set.
+    pub fn write_csv<W: Write>(&self, mut writer: W, include_glue: bool) -> io::Result<()> {
+        let headers = self
+            .instances
+            .iter()
+            .zip(&self.cell_types)
+            .map(|(name, cell_type)| csv_field(&format!("{} ({})", name, cell_type)))
+            .collect::<Vec<_>>();
+        writeln!(writer, "driver \\ sink,{}", headers.join(","))?;
+        for (i, header) in headers.iter().enumerate() {
+            let row = (0..self.instances.len())
+                .map(|j| {
+                    let glue = if include_glue { self.via_glue[i][j] } else { 0 };
+                    (self.direct[i][j] + glue).to_string()
+                })
+                .collect::<Vec<_>>();
+            writeln!(writer, "{},{}", header, row.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl Module {
+    /// Count the net bits flowing directly between each pair of cells that
+    /// instantiate user modules (cells whose type doesn't start with `$`)
+    pub fn instance_connectivity(&self) -> ConnMatrix {
+        self.instance_connectivity_with(&ConnMatrixOptions::default())
+    }
+
+    /// Count the net bits flowing between each pair of instances
+    ///
+    /// A bit counts once for each instance that uses it as an input, even if it
+    /// goes to several of its ports. Only cell ports listed in `port_directions` are
+    /// followed. Glue logic is any `$` cell other than a flip-flop or latch.
+    pub fn instance_connectivity_with(&self, opts: &ConnMatrixOptions) -> ConnMatrix {
+        let mut instances = match &opts.instances {
+            Some(names) => names
+                .iter()
+                .filter(|name| self.cells.contains_key(*name))
+                .cloned()
+                .collect(),
+            None => self
+                .cells
+                .iter()
+                .filter(|(_, cell)| !cell.cell_type.starts_with('$'))
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>(),
+        };
+        instances.sort();
+        instances.dedup();
+        let position = instances
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), i))
+            .collect::<HashMap<_, _>>();
+        let is_glue = |cell: &str| {
+            let cell_type = &self.cells[cell].cell_type;
+            !position.contains_key(cell) && cell_type.starts_with('$') && !is_sequential(cell_type)
+        };
+
+        let index = self.connectivity();
+        let n = instances.len();
+        let mut matrix = ConnMatrix {
+            cell_types: instances
+                .iter()
+                .map(|name| self.cells[name].cell_type.clone())
+                .collect(),
+            instances: instances.clone(),
+            direct: vec![vec![0; n]; n],
+            via_glue: vec![vec![0; n]; n],
+        };
+
+        for (from, name) in instances.iter().enumerate() {
+            let cell = &self.cells[name];
+            for (port, bits) in &cell.connections {
+                let is_output = cell
+                    .port_directions
+                    .get(port)
+                    .is_some_and(|dir| dir.drives_net_from_instance());
+                if !is_output {
+                    continue;
+                }
+                for bit in bits {
+                    let BitVal::N(signal) = *bit else {
+                        continue;
+                    };
+
+                    let mut direct = BTreeSet::new();
+                    let mut glue = BTreeSet::new();
+                    let mut seen = HashSet::from([signal]);
+                    let mut queue = vec![(signal, false)];
+                    while let Some((signal, through_glue)) = queue.pop() {
+                        for sink in index.sinks(signal) {
+                            let Endpoint::CellPort { cell, .. } = *sink else {
+                                continue;
+                            };
+                            if let Some(&to) = position.get(cell) {
+                                if through_glue {
+                                    glue.insert(to);
+                                } else {
+                                    direct.insert(to);
+                                }
+                            } else if opts.through_glue && is_glue(cell) {
+                                let glue_cell = &self.cells[cell];
+                                for (port, bits) in &glue_cell.connections {
+                                    let is_output = glue_cell
+                                        .port_directions
+                                        .get(port)
+                                        .is_some_and(|dir| dir.drives_net_from_instance());
+                                    if !is_output {
+                                        continue;
+                                    }
+                                    for bit in bits {
+                                        if let BitVal::N(next) = *bit {
+                                            if seen.insert(next) {
+                                                queue.push((next, true));
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    for &to in &direct {
+                        matrix.direct[from][to] += 1;
+                    }
+                    for to in glue.difference(&direct) {
+                        matrix.via_glue[from][*to] += 1;
+                    }
+                }
+            }
+        }
+        matrix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netlist;
+
+    fn design() -> crate::Netlist {
+        let mut netlist = netlist! {
+            module top {
+                input a[4];
+                output y[4];
+                wire x[4];
+                wire g[2];
+                wire r;
+                cell "producer" p { i: a, o: x }
+                cell "consumer" c0 { i: x, o: y }
+                cell "$not" inv { A: x[0], Y: g[0] }
+                cell "$and" and { A: g[0], B: x[1], Y: g[1] }
+                cell "$dff" ff { CLK: a[0], D: x[2], Q: r }
+                cell "consumer" c1 { i: x, o: y }
+            }
+            module producer {
+                input i[4];
+                output o[4];
+            }
+            module consumer {
+                input i[4];
+                output o[4];
+            }
+        };
+        // c1.i is { g[1], r, x[3], x[3] }
+        let c1 = netlist
+            .modules
+            .get_mut("top")
+            .unwrap()
+            .cells
+            .get_mut("c1")
+            .unwrap();
+        c1.connections.insert(
+            "i".to_owned(),
+            vec![BitVal::N(15), BitVal::N(16), BitVal::N(13), BitVal::N(13)],
+        );
+        netlist
+    }
+
+    #[test]
+    fn instance_connectivity_test() {
+        let netlist = design();
+        let top = &netlist.modules["top"];
+
+        let matrix = top.instance_connectivity();
+        assert_eq!(matrix.instances, ["c0", "c1", "p"]);
+        assert_eq!(matrix.cell_types, ["consumer", "consumer", "producer"]);
+        assert_eq!(matrix.get("p", "c0"), Some((4, 0)));
+        assert_eq!(matrix.get("p", "c1"), Some((1, 0)));
+        assert_eq!(matrix.get("c0", "p"), Some((0, 0)));
+        assert_eq!(matrix.get("p", "ff"), None);
+
+        let matrix = top.instance_connectivity_with(&ConnMatrixOptions {
+            instances: Some(vec!["p".to_owned(), "c1".to_owned()]),
+            through_glue: true,
+        });
+        assert_eq!(matrix.instances, ["c1", "p"]);
+        // x[0] and x[1] reach c1 through the gates; x[2] stops at the flip-flop
+        assert_eq!(matrix.get("p", "c1"), Some((1, 2)));
+
+        let mut csv = Vec::new();
+        matrix.write_csv(&mut csv, true).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "driver \\ sink,c1 (consumer),p (producer)\n\
+             c1 (consumer),0,0\n\
+             p (producer),3,0\n"
+        );
+
+        let json = serde_json::to_value(&matrix).unwrap();
+        assert_eq!(json["direct"][1][0], 1);
+    }
+}
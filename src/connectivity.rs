@@ -0,0 +1,1036 @@
+use crate::timing::is_sequential;
+use crate::{
+    BitVal, Cell, CutError, Module, NetlistVisitor, Netname, Port, PortDirection, SignalSet,
+    Simulator, SpecialBit,
+};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// One end of a connection to a signal
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum Endpoint<'a> {
+    /// A bit of a cell connection
+    CellPort {
+        cell: &'a str,
+        port: &'a str,
+        bit: usize,
+    },
+    /// A bit of a module port
+    ModulePort { port: &'a str, bit: usize },
+}
+
+impl fmt::Display for Endpoint<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::CellPort { cell, port, bit } => {
+                write!(f, "cell `{}` port `{}[{}]`", cell, port, bit)
+            }
+            Endpoint::ModulePort { port, bit } => write!(f, "port `{}[{}]`", port, bit),
+        }
+    }
+}
+
+/// A bit of a cell connection: cell name, port name, and bit index
+pub type CellPortBit<'a> = (&'a str, &'a str, usize);
+
+/// A bit of a module port: port name and bit index
+pub type ModulePortBit<'a> = (&'a str, usize);
+
+/// How a signal in a module is driven, as returned by `Module::compute_net_classes`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum NetClass {
+    /// Driven by a module input or inout port
+    ModuleInput,
+    /// Drives a module output port
+    ModuleOutput,
+    /// Driven only by cells whose inputs are all constant, such as tie cells
+    Constant,
+    /// Driven by a cell inside the module
+    InternalDriven,
+    /// Not driven by anything
+    Undriven,
+}
+
+/// Drivers and sinks of every signal in a module, as returned by `Module::connectivity`
+///
+/// Module input ports and cell output ports are drivers; module output ports and
+/// cell input ports are sinks. Inout ports are both. Cell ports without an entry in
+/// `port_directions` are not included.
+///
+/// This is the definition of drivers and sinks used by the whole crate, including
+/// `invariants`, `Lint`, and `Netlist::verify_no_multi_drivers`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConnectivityIndex<'a> {
+    drivers: HashMap<usize, Vec<Endpoint<'a>>>,
+    sinks: HashMap<usize, Vec<Endpoint<'a>>>,
+    /// Endpoints of inout ports, which are both drivers and sinks
+    inouts: HashSet<Endpoint<'a>>,
+}
+
+impl<'a> ConnectivityIndex<'a> {
+    fn add(
+        &mut self,
+        bits: &[BitVal],
+        endpoint: impl Fn(usize) -> Endpoint<'a>,
+        driver: bool,
+        sink: bool,
+    ) {
+        for (i, bit) in bits.iter().enumerate() {
+            if let BitVal::N(n) = *bit {
+                if driver {
+                    self.drivers.entry(n).or_default().push(endpoint(i));
+                }
+                if sink {
+                    self.sinks.entry(n).or_default().push(endpoint(i));
+                }
+                if driver && sink {
+                    self.inouts.insert(endpoint(i));
+                }
+            }
+        }
+    }
+
+    /// Everything driving `signal`, sorted
+    pub fn drivers(&self, signal: usize) -> &[Endpoint<'a>] {
+        self.drivers.get(&signal).map_or(&[], |x| x.as_slice())
+    }
+
+    /// Everything driven by `signal`, sorted
+    pub fn sinks(&self, signal: usize) -> &[Endpoint<'a>] {
+        self.sinks.get(&signal).map_or(&[], |x| x.as_slice())
+    }
+
+    /// Drivers of `signal` that aren't inout ports, sorted
+    ///
+    /// Tri-state buses legitimately have several inout drivers, but any other driver
+    /// has to be the only one on its signal.
+    pub fn exclusive_drivers(&self, signal: usize) -> impl Iterator<Item = &Endpoint<'a>> {
+        self.drivers(signal)
+            .iter()
+            .filter(|driver| !self.inouts.contains(*driver))
+    }
+
+    /// Signals with more than one driver that isn't an inout port, sorted
+    pub fn multiply_driven_signals(&self) -> Vec<usize> {
+        let mut signals = self
+            .driven_signals()
+            .filter(|&n| self.exclusive_drivers(n).nth(1).is_some())
+            .collect::<Vec<_>>();
+        signals.sort_unstable();
+        signals
+    }
+
+    /// Number of sinks of `signal`
+    pub fn fanout(&self, signal: usize) -> usize {
+        self.sinks(signal).len()
+    }
+
+    /// Iterate over every signal with at least one driver
+    pub fn driven_signals(&self) -> impl Iterator<Item = usize> + '_ {
+        self.drivers.keys().copied()
+    }
+
+    /// Iterate over every signal with at least one sink
+    pub fn sunk_signals(&self) -> impl Iterator<Item = usize> + '_ {
+        self.sinks.keys().copied()
+    }
+}
+
+impl Module {
+    /// Build an index of the drivers and sinks of every signal in this module
+    pub fn connectivity(&self) -> ConnectivityIndex<'_> {
+        let mut index = ConnectivityIndex::default();
+
+        for (port_name, port) in &self.ports {
+            let dir = port.direction;
+            index.add(
+                &port.bits,
+                |bit| Endpoint::ModulePort {
+                    port: port_name,
+                    bit,
+                },
+                dir.drives_net_inside(),
+                dir.flipped().drives_net_inside(),
+            );
+        }
+        for (cell_name, cell) in &self.cells {
+            for (conn_name, bits) in &cell.connections {
+                let Some(dir) = cell.port_directions.get(conn_name) else {
+                    continue;
+                };
+                index.add(
+                    bits,
+                    |bit| Endpoint::CellPort {
+                        cell: cell_name,
+                        port: conn_name,
+                        bit,
+                    },
+                    dir.drives_net_from_instance(),
+                    dir.flipped().drives_net_from_instance(),
+                );
+            }
+        }
+
+        for endpoints in index.drivers.values_mut().chain(index.sinks.values_mut()) {
+            endpoints.sort();
+        }
+        index
+    }
+
+    /// Map each bit of every input (or inout) port to the cell ports it reaches
+    ///
+    /// Keys are `(port_name, bit_index)`; values are `(cell_name, cell_port_name)`
+    /// pairs, sorted and without duplicates. Bits that reach no cell are left out.
+    /// Like `connectivity`, this only sees cell ports listed in `port_directions`.
+    pub fn port_fanout_to_cells(&self) -> HashMap<(&str, usize), Vec<(&str, &str)>> {
+        let index = self.connectivity();
+        let mut fanout = HashMap::new();
+        for (port_name, port) in &self.ports {
+            if !port.direction.drives_net_inside() {
+                continue;
+            }
+            for (i, bit) in port.bits.iter().enumerate() {
+                let BitVal::N(n) = *bit else {
+                    continue;
+                };
+                let mut cells = index
+                    .sinks(n)
+                    .iter()
+                    .filter_map(|endpoint| match *endpoint {
+                        Endpoint::CellPort { cell, port, .. } => Some((cell, port)),
+                        Endpoint::ModulePort { .. } => None,
+                    })
+                    .collect::<Vec<_>>();
+                cells.dedup();
+                if !cells.is_empty() {
+                    fanout.insert((port_name.as_str(), i), cells);
+                }
+            }
+        }
+        fanout
+    }
+
+    /// Classify every signal used in this module by what drives it
+    ///
+    /// A signal that fits several classes gets the first one listed in `NetClass`, so
+    /// a port feeding straight through to an output is a `ModuleInput`. Like
+    /// `connectivity`, this only sees cell ports listed in `port_directions`.
+    pub fn compute_net_classes(&self) -> HashMap<usize, NetClass> {
+        let index = self.connectivity();
+        let mut classes = HashMap::new();
+        self.accept(&mut SignalCollector(|n| {
+            classes.entry(n).or_insert(NetClass::Undriven);
+        }));
+
+        for (&n, class) in classes.iter_mut() {
+            let drivers = index.drivers(n);
+            *class = if drivers
+                .iter()
+                .any(|d| matches!(d, Endpoint::ModulePort { .. }))
+            {
+                NetClass::ModuleInput
+            } else if index
+                .sinks(n)
+                .iter()
+                .any(|s| matches!(s, Endpoint::ModulePort { .. }))
+            {
+                NetClass::ModuleOutput
+            } else if drivers.is_empty() {
+                NetClass::Undriven
+            } else if drivers.iter().all(|d| match *d {
+                Endpoint::CellPort { cell, .. } => has_constant_inputs(&self.cells[cell]),
+                Endpoint::ModulePort { .. } => false,
+            }) {
+                NetClass::Constant
+            } else {
+                NetClass::InternalDriven
+            };
+        }
+        classes
+    }
+
+    /// Find the signals crossing between two groups of cells
+    ///
+    /// Returns the signals driven by `group_a` and used as inputs by `group_b`, then
+    /// those driven by `group_b` and used by `group_a`. Only cell ports listed in
+    /// `port_directions` are considered; inout ports count as both.
+    pub fn nets_at_cut(
+        &self,
+        group_a: &[&str],
+        group_b: &[&str],
+    ) -> Result<(SignalSet, SignalSet), CutError> {
+        let signals = |group: &[&str], outputs: bool| {
+            let mut signals = SignalSet::new();
+            for &name in group {
+                let cell = self
+                    .cells
+                    .get(name)
+                    .ok_or_else(|| CutError::CellNotFound(name.to_owned()))?;
+                for (port, bits) in &cell.connections {
+                    let Some(dir) = cell.port_directions.get(port) else {
+                        continue;
+                    };
+                    let matches = match dir {
+                        PortDirection::Input => !outputs,
+                        PortDirection::Output => outputs,
+                        PortDirection::InOut => true,
+                    };
+                    if matches {
+                        signals.extend(bits.iter().filter_map(|bit| match *bit {
+                            BitVal::N(n) => Some(n),
+                            BitVal::S(_) => None,
+                        }));
+                    }
+                }
+            }
+            Ok(signals)
+        };
+
+        let a_to_b = &signals(group_a, true)? & &signals(group_b, false)?;
+        let b_to_a = &signals(group_b, true)? & &signals(group_a, false)?;
+        Ok((a_to_b, b_to_a))
+    }
+
+    /// For each flip-flop cell, list the combinational cells in its fanin cone, sorted
+    ///
+    /// Flip-flops and latches are told apart with `Cell::sequential_kind`, so
+    /// gate-level cells such as `$_DFF_P_` are included. The cone is followed back
+    /// from every input of the flip-flop, including its clock, and stops at the outputs
+    /// of flip-flops and latches. Only cell ports listed in `port_directions` are
+    /// followed.
+    pub fn compute_register_input_cones(&self) -> HashMap<&str, Vec<&str>> {
+        let index = self.connectivity();
+        let input_signals = |cell: &Cell| {
+            cell.connections
+                .iter()
+                .filter(|(port, _)| {
+                    cell.port_directions
+                        .get(*port)
+                        .is_some_and(|dir| *dir != PortDirection::Output)
+                })
+                .flat_map(|(_, bits)| bits)
+                .filter_map(|bit| match *bit {
+                    BitVal::N(n) => Some(n),
+                    BitVal::S(_) => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut cones = HashMap::new();
+        for (name, register) in self.registers() {
+            let mut cone = HashSet::new();
+            let mut seen = HashSet::new();
+            let mut queue = input_signals(register);
+            while let Some(signal) = queue.pop() {
+                if !seen.insert(signal) {
+                    continue;
+                }
+                for driver in index.drivers(signal) {
+                    let Endpoint::CellPort { cell, .. } = *driver else {
+                        continue;
+                    };
+                    let driver_cell = &self.cells[cell];
+                    if driver_cell.sequential_kind().is_none() && cone.insert(cell) {
+                        queue.extend(input_signals(driver_cell));
+                    }
+                }
+            }
+            let mut cone = cone.into_iter().collect::<Vec<_>>();
+            cone.sort();
+            cones.insert(name, cone);
+        }
+        cones
+    }
+
+    /// Find signals whose only driver is a combinational cell with nothing but
+    /// constants on its inputs, and the constant each one carries
+    ///
+    /// Only cell types supported by `Simulator` can be evaluated; other cells are
+    /// skipped. Like `connectivity`, this only sees cell ports listed in
+    /// `port_directions`.
+    pub fn constant_driving_signals(&self) -> HashMap<usize, SpecialBit> {
+        let index = self.connectivity();
+        let mut constants = HashMap::new();
+        for (name, cell) in &self.cells {
+            if is_sequential(&cell.cell_type) || !has_constant_inputs(cell) {
+                continue;
+            }
+            let single = Module {
+                cells: HashMap::from([(name.clone(), cell.clone())]),
+                ..Default::default()
+            };
+            let Ok(mut sim) = Simulator::new(&single) else {
+                continue;
+            };
+            sim.settle();
+
+            for (port, bits) in &cell.connections {
+                let is_output = cell
+                    .port_directions
+                    .get(port)
+                    .is_some_and(|dir| dir.drives_net_from_instance());
+                if !is_output {
+                    continue;
+                }
+                for bit in bits {
+                    if let BitVal::N(n) = *bit {
+                        if index.drivers(n).len() == 1 {
+                            constants.insert(n, sim.signal(n));
+                        }
+                    }
+                }
+            }
+        }
+        constants
+    }
+
+    /// Find the cell port bit driving each bit of every output (or inout) port
+    ///
+    /// Keys are `(port_name, bit_index)`; values are `(cell_name, cell_port_name,
+    /// bit_index)`, or `None` if the bit is a constant, comes from a module input, or
+    /// isn't driven by a cell port listed in `port_directions`. If several cells
+    /// drive a bit, the first in sorted order is given.
+    pub fn output_driven_by(&self) -> HashMap<(&str, usize), Option<CellPortBit<'_>>> {
+        let index = self.connectivity();
+        let mut drivers = HashMap::new();
+        for (port_name, port) in &self.ports {
+            if !port.direction.drives_net_from_instance() {
+                continue;
+            }
+            for (i, bit) in port.bits.iter().enumerate() {
+                let driver = match *bit {
+                    BitVal::N(n) => index
+                        .drivers(n)
+                        .iter()
+                        .find_map(|endpoint| match *endpoint {
+                            Endpoint::CellPort { cell, port, bit } => Some((cell, port, bit)),
+                            Endpoint::ModulePort { .. } => None,
+                        }),
+                    BitVal::S(_) => None,
+                };
+                drivers.insert((port_name.as_str(), i), driver);
+            }
+        }
+        drivers
+    }
+
+    /// Find input port bits that are also bits of an output port, i.e. inputs wired
+    /// straight through the module to an output
+    ///
+    /// Returns `((input_port, bit_index), (output_port, bit_index))` pairs, sorted.
+    /// Inout ports are not considered.
+    pub fn input_driven_by_module_output(&self) -> Vec<(ModulePortBit<'_>, ModulePortBit<'_>)> {
+        let mut outputs: HashMap<usize, Vec<ModulePortBit>> = HashMap::new();
+        for (name, port) in &self.ports {
+            if port.direction == PortDirection::Output {
+                for (i, bit) in port.bits.iter().enumerate() {
+                    if let BitVal::N(n) = *bit {
+                        outputs.entry(n).or_default().push((name, i));
+                    }
+                }
+            }
+        }
+
+        let mut pairs = Vec::new();
+        for (name, port) in &self.ports {
+            if port.direction != PortDirection::Input {
+                continue;
+            }
+            for (i, bit) in port.bits.iter().enumerate() {
+                let BitVal::N(n) = *bit else {
+                    continue;
+                };
+                for &output in outputs.get(&n).into_iter().flatten() {
+                    pairs.push(((name.as_str(), i), output));
+                }
+            }
+        }
+        pairs.sort();
+        pairs
+    }
+
+    /// Find signals that are different numbers for the same net
+    ///
+    /// Yosys gives directly connected wires the same signal number, so different
+    /// numbers can only be aliases when a port and the netname of the same name
+    /// disagree about a bit. Such signals are grouped, transitively, and each is mapped
+    /// to the smallest signal in its group. Signals that are their own canonical
+    /// signal are not in the map.
+    pub fn net_alias_map(&self) -> HashMap<usize, usize> {
+        fn find(parent: &mut HashMap<usize, usize>, n: usize) -> usize {
+            let p = *parent.get(&n).unwrap_or(&n);
+            if p == n {
+                return n;
+            }
+            let root = find(parent, p);
+            parent.insert(n, root);
+            root
+        }
+
+        let mut parent = HashMap::new();
+        for (name, port) in &self.ports {
+            let Some(netname) = self.netnames.get(name) else {
+                continue;
+            };
+            for (a, b) in port.bits.iter().zip(&netname.bits) {
+                if let (&BitVal::N(a), &BitVal::N(b)) = (a, b) {
+                    let (a, b) = (find(&mut parent, a), find(&mut parent, b));
+                    if a != b {
+                        parent.insert(a.max(b), a.min(b));
+                    }
+                }
+            }
+        }
+
+        let signals = parent.keys().copied().collect::<Vec<_>>();
+        signals
+            .into_iter()
+            .map(|n| (n, find(&mut parent, n)))
+            .filter(|&(n, root)| n != root)
+            .collect()
+    }
+
+    /// Alias groups from `net_alias_map` in which more than one signal has a driver
+    /// (other than an inout port), each sorted, with the groups sorted
+    ///
+    /// Coalescing such a group with `coalesce_aliases` makes a multiply driven net.
+    pub fn multi_driver_alias_groups(&self) -> Vec<Vec<usize>> {
+        let mut groups = HashMap::<usize, Vec<usize>>::new();
+        for (n, canonical) in self.net_alias_map() {
+            groups
+                .entry(canonical)
+                .or_insert_with(|| vec![canonical])
+                .push(n);
+        }
+
+        let index = self.connectivity();
+        let mut conflicts = groups
+            .into_values()
+            .filter(|group| {
+                group
+                    .iter()
+                    .filter(|&&n| index.exclusive_drivers(n).next().is_some())
+                    .nth(1)
+                    .is_some()
+            })
+            .map(|mut group| {
+                group.sort_unstable();
+                group
+            })
+            .collect::<Vec<_>>();
+        conflicts.sort_unstable();
+        conflicts
+    }
+
+    /// Replace every signal in `net_alias_map` with its canonical signal, in ports,
+    /// cell connections, and netnames
+    ///
+    /// Returns the number of bits replaced. Groups listed by
+    /// `multi_driver_alias_groups` are merged too, into multiply driven nets.
+    pub fn coalesce_aliases(&mut self) -> usize {
+        let aliases = self.net_alias_map();
+        let mut count = 0;
+        self.for_each_bit_mut(|bit| {
+            if let BitVal::N(n) = bit {
+                if let Some(&canonical) = aliases.get(n) {
+                    *n = canonical;
+                    count += 1;
+                }
+            }
+        });
+        count
+    }
+
+    /// Rough depth of selector logic: the largest number of multiplexer cells (see
+    /// `Cell::is_mux`) on any path from an input port or register output to an output
+    /// port or register input
+    ///
+    /// Paths follow cell ports listed in `port_directions` and stop at flip-flops and
+    /// latches. Paths through a combinational loop are only counted up to the loop.
+    pub fn count_mux_levels(&self) -> usize {
+        let signals = |bits: &[BitVal]| {
+            bits.iter()
+                .filter_map(|bit| match *bit {
+                    BitVal::N(n) => Some(n),
+                    BitVal::S(_) => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Largest number of multiplexers on a path to each signal reached so far
+        let mut levels = HashMap::new();
+        let mut ends = Vec::new();
+        for port in self.ports.values() {
+            if port.direction.drives_net_inside() {
+                levels.extend(signals(&port.bits).into_iter().map(|n| (n, 0)));
+            }
+            if port.direction.flipped().drives_net_inside() {
+                ends.extend(signals(&port.bits));
+            }
+        }
+
+        // Edges from every input bit of a combinational cell to every output bit, with
+        // a weight of 1 through a multiplexer
+        let mut edges = HashMap::<usize, Vec<(usize, usize)>>::new();
+        let mut in_degree = HashMap::<usize, usize>::new();
+        for cell in self.cells.values() {
+            let mut inputs = Vec::new();
+            let mut outputs = Vec::new();
+            for (port, bits) in &cell.connections {
+                match cell.port_directions.get(port) {
+                    Some(dir) if dir.drives_net_from_instance() => outputs.extend(signals(bits)),
+                    Some(dir) if dir.flipped().drives_net_from_instance() => {
+                        inputs.extend(signals(bits))
+                    }
+                    _ => {}
+                }
+            }
+            if cell.sequential_kind().is_some() {
+                levels.extend(outputs.into_iter().map(|n| (n, 0)));
+                ends.extend(inputs);
+                continue;
+            }
+            for &from in &inputs {
+                for &to in &outputs {
+                    edges
+                        .entry(from)
+                        .or_default()
+                        .push((to, cell.is_mux() as usize));
+                    *in_degree.entry(to).or_default() += 1;
+                }
+            }
+        }
+
+        // Longest paths in topological order; signals on a loop never become ready
+        let mut ready = edges
+            .keys()
+            .copied()
+            .filter(|n| !in_degree.contains_key(n))
+            .collect::<Vec<_>>();
+        while let Some(from) = ready.pop() {
+            let level = levels.get(&from).copied();
+            for &(to, weight) in edges.get(&from).into_iter().flatten() {
+                if let Some(level) = level {
+                    let to_level = levels.entry(to).or_insert(0);
+                    *to_level = (*to_level).max(level + weight);
+                }
+                let degree = in_degree.get_mut(&to).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push(to);
+                }
+            }
+        }
+
+        ends.iter()
+            .filter_map(|n| levels.get(n))
+            .max()
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// Whether every input bit of a cell is a constant
+fn has_constant_inputs(cell: &Cell) -> bool {
+    cell.connections.iter().all(|(port, bits)| {
+        let is_input = cell
+            .port_directions
+            .get(port)
+            .is_some_and(|dir| dir.flipped().drives_net_from_instance());
+        !is_input || bits.iter().all(|bit| matches!(bit, BitVal::S(_)))
+    })
+}
+
+/// Calls a closure with every signal number in a module
+struct SignalCollector<F>(F);
+
+impl<F: FnMut(usize)> NetlistVisitor for SignalCollector<F> {
+    fn visit_port(&mut self, _name: &str, port: &Port) {
+        self.add(&port.bits);
+    }
+
+    fn visit_connection(&mut self, _cell: &str, _port: &str, bits: &[BitVal]) {
+        self.add(bits);
+    }
+
+    fn visit_netname(&mut self, _name: &str, netname: &Netname) {
+        self.add(&netname.bits);
+    }
+}
+
+impl<F: FnMut(usize)> SignalCollector<F> {
+    fn add(&mut self, bits: &[BitVal]) {
+        for bit in bits {
+            if let BitVal::N(n) = *bit {
+                (self.0)(n);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn connectivity_test() {
+        let netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                inout io;
+                cell "$not" not { A: a, Y: y }
+                cell "mystery" unknown { X: a }
+            }
+        };
+        let index = netlist.modules.get("top").unwrap().connectivity();
+
+        assert_eq!(
+            index.drivers(2),
+            [Endpoint::ModulePort { port: "a", bit: 0 }]
+        );
+        assert_eq!(
+            index.sinks(2),
+            [Endpoint::CellPort {
+                cell: "not",
+                port: "A",
+                bit: 0
+            }]
+        );
+        assert_eq!(
+            index.drivers(3),
+            [Endpoint::CellPort {
+                cell: "not",
+                port: "Y",
+                bit: 0
+            }]
+        );
+        assert_eq!(index.sinks(3), [Endpoint::ModulePort { port: "y", bit: 0 }]);
+        assert_eq!(
+            index.drivers(4),
+            [Endpoint::ModulePort { port: "io", bit: 0 }]
+        );
+        assert_eq!(
+            index.sinks(4),
+            [Endpoint::ModulePort { port: "io", bit: 0 }]
+        );
+        assert_eq!(index.fanout(2), 1);
+        assert_eq!(index.fanout(5), 0);
+    }
+
+    #[test]
+    fn net_classes_test() {
+        let netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                wire t;
+                wire one;
+                wire floating;
+                cell "$not" inv { A: a, Y: t }
+                cell "$and" and { A: t, B: one, Y: y }
+                cell "$not" tie { A: "0", Y: one }
+            }
+        };
+        let classes = netlist.modules.get("top").unwrap().compute_net_classes();
+        assert_eq!(classes.len(), 5);
+        assert_eq!(classes[&2], NetClass::ModuleInput);
+        assert_eq!(classes[&3], NetClass::ModuleOutput);
+        assert_eq!(classes[&4], NetClass::InternalDriven);
+        assert_eq!(classes[&5], NetClass::Constant);
+        assert_eq!(classes[&6], NetClass::Undriven);
+    }
+
+    #[test]
+    fn mux_levels_test() {
+        let netlist = netlist! {
+            module top {
+                input a[3];
+                input s[2];
+                input d;
+                output y;
+                output z;
+                wire m;
+                wire n;
+                cell "$mux" mux1 { A: a[0], B: a[1], S: s[0], Y: m }
+                cell "$not" inv { A: m, Y: n }
+                cell "$mux" mux2 { A: n, B: a[2], S: s[1], Y: y }
+                cell "$not" other { A: d, Y: z }
+            }
+        };
+        assert_eq!(netlist.modules["top"].count_mux_levels(), 2);
+
+        let netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                cell "$not" inv { A: a, Y: y }
+            }
+        };
+        assert_eq!(netlist.modules["top"].count_mux_levels(), 0);
+    }
+
+    #[test]
+    fn mux_levels_register_test() {
+        // Two muxes before the register and one after, so the register resets the count
+        let netlist = netlist! {
+            module top {
+                input clk;
+                input a[4];
+                input s[3];
+                output y;
+                wire m;
+                wire n;
+                wire q;
+                cell "$mux" mux1 { A: a[0], B: a[1], S: s[0], Y: m }
+                cell "$mux" mux2 { A: m, B: a[2], S: s[1], Y: n }
+                cell "$dff" r { CLK: clk, D: n, Q: q }
+                cell "$mux" mux3 { A: q, B: a[3], S: s[2], Y: y }
+            }
+        };
+        assert_eq!(netlist.modules["top"].count_mux_levels(), 2);
+    }
+
+    #[test]
+    fn mux_levels_loop_test() {
+        // `x` and `z` feed each other, so paths through them stop there and only `n2`
+        // counts towards `w`, whichever order signals are visited in
+        let build = || {
+            netlist! {
+                module top {
+                    input a[2];
+                    input s[2];
+                    output y;
+                    output w;
+                    wire p;
+                    wire x;
+                    wire z;
+                    cell "$mux" pre { A: a[0], B: a[1], S: s[0], Y: p }
+                    cell "$mux" g1 { A: p, B: z, S: s[1], Y: x }
+                    cell "$mux" g2 { A: a[1], B: x, S: s[0], Y: z }
+                    cell "$not" n1 { A: x, Y: y }
+                    cell "$mux" n2 { A: z, B: a[0], S: s[1], Y: w }
+                }
+            }
+        };
+        for _ in 0..20 {
+            assert_eq!(build().modules["top"].count_mux_levels(), 1);
+        }
+    }
+
+    #[test]
+    fn mux_levels_long_chain_test() {
+        // A recursive walk overflows the test thread's stack on a chain this long
+        let mut builder = crate::testutil::NetlistBuilder::new();
+        builder
+            .module("top")
+            .wire("top", "s", 1, Some(PortDirection::Input))
+            .wire("top", "w0", 1, Some(PortDirection::Input));
+        for i in 1..=20_000 {
+            let direction = (i == 20_000).then_some(PortDirection::Output);
+            builder.wire("top", &format!("w{}", i), 1, direction).cell(
+                "top",
+                &format!("m{}", i),
+                "$mux",
+                &[
+                    ("A", crate::testutil::Conn::Wire(&format!("w{}", i - 1))),
+                    ("B", crate::testutil::Conn::Wire("s")),
+                    ("S", crate::testutil::Conn::Wire("s")),
+                    ("Y", crate::testutil::Conn::Wire(&format!("w{}", i))),
+                ],
+                Vec::new(),
+            );
+        }
+        let netlist = builder.build();
+        let top = &netlist.modules["top"];
+        assert_eq!(top.count_mux_levels(), 20_000);
+    }
+
+    #[test]
+    fn loopback_test() {
+        let netlist = netlist! {
+            module top {
+                input a[2];
+                input b;
+                output y[3];
+                output z;
+            }
+        };
+        let mut top = netlist.modules["top"].clone();
+        top.ports.get_mut("y").unwrap().bits[2] = BitVal::N(3);
+        top.ports.get_mut("z").unwrap().bits[0] = BitVal::N(3);
+        assert_eq!(
+            top.input_driven_by_module_output(),
+            [(("a", 1), ("y", 2)), (("a", 1), ("z", 0))]
+        );
+        assert_eq!(netlist.modules["top"].input_driven_by_module_output(), []);
+    }
+
+    #[test]
+    fn nets_at_cut_test() {
+        // a is 2, y is 3, t is 4, f is 5
+        let netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                wire t;
+                wire f;
+                cell "$and" g0 { A: a, B: f, Y: t }
+                cell "$not" g1 { A: t, Y: y }
+                cell "$buf" g2 { A: y, Y: f }
+            }
+        };
+        let top = &netlist.modules["top"];
+        assert_eq!(
+            top.nets_at_cut(&["g0"], &["g1"]),
+            Ok((SignalSet::from([4]), SignalSet::new()))
+        );
+        assert_eq!(
+            top.nets_at_cut(&["g0"], &["g1", "g2"]),
+            Ok((SignalSet::from([4]), SignalSet::from([5])))
+        );
+        assert_eq!(
+            top.nets_at_cut(&["g0"], &["g3"]),
+            Err(CutError::CellNotFound("g3".to_owned()))
+        );
+    }
+
+    #[test]
+    fn register_input_cones_test() {
+        let netlist = netlist! {
+            module top {
+                input clk;
+                input a;
+                input b;
+                output q;
+                wire t[3];
+                wire r;
+                wire s;
+                wire u;
+                cell "$not" n0 { A: a, Y: t[0] }
+                cell "$and" g0 { A: t[0], B: b, Y: t[1] }
+                cell "$xor" g1 { A: t[1], B: r, Y: t[2] }
+                cell "$dff" ff0 { CLK: clk, D: t[2], Q: q }
+                cell "$dff" ff1 { CLK: clk, D: a, Q: s }
+                cell "$not" n1 { A: s, Y: r }
+                cell "$or" unused { A: a, B: b, Y: u }
+            }
+        };
+        let cones = netlist.modules["top"].compute_register_input_cones();
+        assert_eq!(cones.len(), 2);
+        // The cone of ff0 goes through n1 but stops at ff1
+        assert_eq!(cones["ff0"], ["g0", "g1", "n0", "n1"]);
+        assert_eq!(cones["ff1"], [] as [&str; 0]);
+
+        // Techmapped flip-flops and latches
+        let netlist = netlist! {
+            module top {
+                input clk;
+                input a;
+                output q;
+                wire t[2];
+                wire l;
+                cell "$_DLATCH_P_" lat { E: clk, D: a, Q: l }
+                cell "$_NOT_" n0 { A: l, Y: t[0] }
+                cell "$_AND_" g0 { A: t[0], B: a, Y: t[1] }
+                cell "$_DFF_P_" ff0 { C: clk, D: t[1], Q: q }
+            }
+        };
+        let cones = netlist.modules["top"].compute_register_input_cones();
+        assert_eq!(cones.len(), 1);
+        assert_eq!(cones["ff0"], ["g0", "n0"]);
+    }
+
+    #[test]
+    fn constant_driving_signals_test() {
+        // t is 4, u is 5 and 6, v is 7, w is 8
+        let netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                wire t;
+                wire u[2];
+                wire v;
+                wire w;
+                cell "$not" zero { A: "1", Y: t }
+                cell "$xor" x { A: "10", B: "11", Y: u }
+                cell "$and" g { A: "1", B: a, Y: y }
+                cell "$not" d0 { A: "0", Y: v }
+                cell "$not" d1 { A: "1", Y: v }
+                cell "$dff" r { CLK: "0", D: "1", Q: w }
+                cell "$not" xin { A: "x", Y: w }
+            }
+        };
+        let constants = netlist.modules["top"].constant_driving_signals();
+        assert_eq!(
+            constants,
+            HashMap::from([
+                (4, SpecialBit::_0),
+                (5, SpecialBit::_1),
+                (6, SpecialBit::_0),
+            ])
+        );
+    }
+
+    #[test]
+    fn net_alias_map_test() {
+        let netlist = netlist! {
+            module top {
+                input a[2];
+                input b;
+                output y[2];
+                wire t;
+                cell "$and" u { A: a[0], B: b, Y: t }
+            }
+        };
+        let mut top = netlist.modules["top"].clone();
+        assert!(top.net_alias_map().is_empty());
+
+        // a[1] is 3, b is 4, t is 7
+        top.netnames.get_mut("a").unwrap().bits[1] = BitVal::N(7);
+        top.netnames.get_mut("b").unwrap().bits[0] = BitVal::N(7);
+        top.netnames.get_mut("y").unwrap().bits[1] = BitVal::S(SpecialBit::_0);
+        let aliases = top.net_alias_map();
+        assert_eq!(aliases.len(), 2);
+        assert_eq!(aliases[&4], 3);
+        assert_eq!(aliases[&7], 3);
+    }
+
+    #[test]
+    fn coalesce_aliases_test() {
+        let netlist = netlist! {
+            module top {
+                input a[2];
+                input b;
+                output y;
+                wire t;
+                cell "$and" u { A: a[1], B: b, Y: t }
+                cell "$not" v { A: t, Y: y }
+            }
+        };
+        let mut top = netlist.modules["top"].clone();
+        assert_eq!(top.coalesce_aliases(), 0);
+
+        // b is 4, driven by its port, and 8 is only read by v
+        top.netnames.get_mut("b").unwrap().bits[0] = BitVal::N(8);
+        top.cells
+            .get_mut("v")
+            .unwrap()
+            .connections
+            .get_mut("A")
+            .unwrap()[0] = BitVal::N(8);
+        assert!(top.multi_driver_alias_groups().is_empty());
+
+        // a[1] is 3, driven by its port, and t is 6, driven by u
+        top.netnames.get_mut("a").unwrap().bits[1] = BitVal::N(6);
+        assert_eq!(top.multi_driver_alias_groups(), [vec![3, 6]]);
+
+        // netname b, v.A, u.Y, netnames a[1] and t
+        assert_eq!(top.coalesce_aliases(), 5);
+        assert!(top.net_alias_map().is_empty());
+        assert_eq!(top.netnames["b"].bits, [BitVal::N(4)]);
+        assert_eq!(top.cells["v"].connections["A"], [BitVal::N(4)]);
+        assert_eq!(top.cells["u"].connections["Y"], [BitVal::N(3)]);
+        assert_eq!(top.netnames["t"].bits, [BitVal::N(3)]);
+    }
+}
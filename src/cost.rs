@@ -0,0 +1,436 @@
+use crate::search::glob_match;
+use crate::{Error, Module, Netlist};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Read;
+
+/// The kind of FPGA resource a cell type uses
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceClass {
+    /// Lookup tables and other combinational logic
+    Lut,
+    /// Flip-flops and latches
+    Ff,
+    /// Block RAM
+    Bram,
+    /// Multipliers and DSP blocks
+    Dsp,
+}
+
+/// Cost of one cell of a type in a `CostTable`
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CellCost {
+    /// Cost of one cell, in whatever unit the table uses
+    pub cost: f64,
+    /// Resource the cell uses, if any
+    #[serde(default)]
+    pub class: Option<ResourceClass>,
+}
+
+/// Costs of cell types, for estimating the area of a design
+///
+/// The JSON form maps cell types to costs, e.g.
+/// `{ "$lut": { "cost": 1, "class": "lut" } }`. Keys containing `*` or `?` are glob
+/// patterns; exact names are tried first, then globs with the longest first. Cell
+/// types that match nothing get `default_cost` and no class.
+///
+/// A table for a generic 4-LUT FPGA after techmapping with `synth -lut 4`:
+///
+/// ```
+/// use yosys_netlist_json::{CostTable, Netlist, ResourceClass};
+///
+/// let table = CostTable::from_slice(br#"{
+///     "$lut":     { "cost": 1, "class": "lut" },
+///     "$_DFF_*":  { "cost": 1, "class": "ff" },
+///     "$_DFFE_*": { "cost": 1, "class": "ff" },
+///     "$_SDFF*":  { "cost": 1, "class": "ff" },
+///     "$_DLATCH*": { "cost": 1, "class": "ff" },
+///     "$mem*":    { "cost": 16, "class": "bram" },
+///     "$mul":     { "cost": 20, "class": "dsp" },
+///     "$_NOT_":   { "cost": 0 },
+///     "$_BUF_":   { "cost": 0 }
+/// }"#, 1.0).unwrap();
+///
+/// let netlist = Netlist::from_slice(br#"{ "modules": { "top": { "cells": {
+///     "l": { "type": "$lut", "connections": {} },
+///     "r": { "type": "$_DFF_P_", "connections": {} },
+///     "x": { "type": "$_AND_", "connections": {} }
+/// } } } }"#).unwrap();
+/// let report = netlist.estimate_cost("top", &table).unwrap();
+/// assert_eq!(report.total, 3.0);
+/// assert_eq!(report.by_class[&Some(ResourceClass::Ff)].cells, 1);
+/// assert_eq!(report.defaulted, ["$_AND_"]);
+/// ```
+///
+/// Before techmapping, counting each 2-input gate as a LUT gives an upper bound:
+///
+/// ```json
+/// {
+///     "$_AND_": { "cost": 1, "class": "lut" }, "$_OR_": { "cost": 1, "class": "lut" },
+///     "$_XOR_": { "cost": 1, "class": "lut" }, "$_MUX_": { "cost": 1, "class": "lut" },
+///     "$_NOT_": { "cost": 0 },
+///     "$_*FF*": { "cost": 1, "class": "ff" }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CostTable {
+    /// Costs of cell types matched by exact name
+    pub exact: HashMap<String, CellCost>,
+    /// Costs of cell types matched by glob pattern, tried in order after `exact`
+    pub globs: Vec<(String, CellCost)>,
+    /// Cost of cell types not in the table
+    pub default_cost: f64,
+}
+
+impl CostTable {
+    /// Create an empty table where every cell costs `default_cost`
+    pub fn new(default_cost: f64) -> Self {
+        CostTable {
+            default_cost,
+            ..Default::default()
+        }
+    }
+
+    fn from_map(map: HashMap<String, CellCost>, default_cost: f64) -> Self {
+        let mut table = CostTable::new(default_cost);
+        for (name, cost) in map {
+            if name.contains(['*', '?']) {
+                table.globs.push((name, cost));
+            } else {
+                table.exact.insert(name, cost);
+            }
+        }
+        table
+            .globs
+            .sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        table
+    }
+
+    /// Read a table from JSON, giving unlisted cell types `default_cost`
+    pub fn from_reader<R: Read>(reader: R, default_cost: f64) -> Result<Self, serde_json::Error> {
+        Ok(CostTable::from_map(
+            serde_json::from_reader(reader)?,
+            default_cost,
+        ))
+    }
+
+    /// Read a table from a slice of JSON, giving unlisted cell types `default_cost`
+    pub fn from_slice(input: &[u8], default_cost: f64) -> Result<Self, serde_json::Error> {
+        Ok(CostTable::from_map(
+            serde_json::from_slice(input)?,
+            default_cost,
+        ))
+    }
+
+    /// Set the cost of a cell type
+    pub fn set(&mut self, cell_type: &str, cost: f64, class: Option<ResourceClass>) {
+        self.exact
+            .insert(cell_type.to_owned(), CellCost { cost, class });
+    }
+
+    /// Add a glob pattern, tried after all earlier ones
+    pub fn add_glob(&mut self, pattern: &str, cost: f64, class: Option<ResourceClass>) {
+        self.globs
+            .push((pattern.to_owned(), CellCost { cost, class }));
+    }
+
+    /// Look up the cost of a cell type, or `None` if it isn't in the table
+    pub fn get(&self, cell_type: &str) -> Option<&CellCost> {
+        self.exact.get(cell_type).or_else(|| {
+            self.globs
+                .iter()
+                .find(|(pattern, _)| glob_match(pattern, cell_type, false))
+                .map(|(_, cost)| cost)
+        })
+    }
+}
+
+/// Number and cost of the cells of one resource class in a `CostReport`
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ClassCost {
+    /// Number of cells
+    pub cells: usize,
+    /// Total cost of the cells
+    pub cost: f64,
+}
+
+/// Cost of one module in a `CostReport`
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ModuleCost {
+    /// Number of times the module appears below the top module, including the top
+    /// module itself
+    pub instances: usize,
+    /// Cost of the module's own cells, for one instance
+    pub cost: f64,
+    /// Cost of one instance including the submodules it instantiates
+    pub hierarchical_cost: f64,
+    /// Bits of the module's own memories, for one instance, saturating at `usize::MAX`
+    pub memory_bits: usize,
+}
+
+/// Estimated cost of a module, returned by `Module::estimate_cost` and
+/// `Netlist::estimate_cost`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CostReport {
+    /// Total cost, counting every instance of a submodule
+    pub total: f64,
+    /// Cells and cost of each resource class; `None` collects cells without a class
+    pub by_class: BTreeMap<Option<ResourceClass>, ClassCost>,
+    /// Cost of each module in the hierarchy; empty for `Module::estimate_cost`
+    pub by_module: BTreeMap<String, ModuleCost>,
+    /// Total width times size of all memories, counting every instance, saturating at
+    /// `usize::MAX`
+    pub memory_bits: usize,
+    /// Cell types that weren't in the table and got the default cost, sorted
+    pub defaulted: Vec<String>,
+}
+
+/// Add the cells and memories of `module` to `report` `instances` times, skipping
+/// cells for which `skip` returns true. Returns the cost and memory bits of one
+/// instance.
+fn add_module(
+    report: &mut CostReport,
+    defaulted: &mut BTreeSet<String>,
+    module: &Module,
+    table: &CostTable,
+    instances: usize,
+    skip: impl Fn(&str) -> bool,
+) -> (f64, usize) {
+    let mut cost = 0.0;
+    for cell in module.cells.values() {
+        if skip(&cell.cell_type) {
+            continue;
+        }
+        let cell_cost = match table.get(&cell.cell_type) {
+            Some(cell_cost) => *cell_cost,
+            None => {
+                defaulted.insert(cell.cell_type.clone());
+                CellCost {
+                    cost: table.default_cost,
+                    class: None,
+                }
+            }
+        };
+        let class = report.by_class.entry(cell_cost.class).or_default();
+        class.cells += instances;
+        class.cost += cell_cost.cost * instances as f64;
+        cost += cell_cost.cost;
+    }
+    let memory_bits = module
+        .memories
+        .values()
+        .map(|memory| memory.total_bits().unwrap_or(usize::MAX))
+        .fold(0, usize::saturating_add);
+
+    report.total += cost * instances as f64;
+    report.memory_bits = report
+        .memory_bits
+        .saturating_add(memory_bits.saturating_mul(instances));
+    (cost, memory_bits)
+}
+
+impl Module {
+    /// Estimate the cost of the cells in this module from `table`
+    ///
+    /// Instances of other modules are looked up in the table like any other cell;
+    /// use `Netlist::estimate_cost` to count their contents instead.
+    pub fn estimate_cost(&self, table: &CostTable) -> CostReport {
+        let mut report = CostReport::default();
+        let mut defaulted = BTreeSet::new();
+        add_module(&mut report, &mut defaulted, self, table, 1, |_| false);
+        report.defaulted = defaulted.into_iter().collect();
+        report
+    }
+}
+
+impl Netlist {
+    /// Estimate the cost of module `top` and everything below it from `table`
+    ///
+    /// Instances of modules of this netlist are not looked up in the table. Their
+    /// contents are counted once per instance instead.
+    pub fn estimate_cost(&self, top: &str, table: &CostTable) -> Result<CostReport, Error> {
+        let (top, _) = self
+            .modules
+            .get_key_value(top)
+            .ok_or_else(|| Error::ModuleNotFound(top.to_owned()))?;
+
+        // Parents come before children, so instance counts are final when reached
+        let order = self.modules_in_reverse_dependency_order()?;
+        let mut instances = HashMap::new();
+        instances.insert(top.as_str(), 1);
+        let mut report = CostReport::default();
+        let mut defaulted = BTreeSet::new();
+        for &name in &order {
+            let Some(&count) = instances.get(name.as_str()) else {
+                continue;
+            };
+            let module = &self.modules[name];
+            let (cost, memory_bits) = add_module(
+                &mut report,
+                &mut defaulted,
+                module,
+                table,
+                count,
+                |cell_type| self.modules.contains_key(cell_type),
+            );
+            report.by_module.insert(
+                name.clone(),
+                ModuleCost {
+                    instances: count,
+                    cost,
+                    hierarchical_cost: cost,
+                    memory_bits,
+                },
+            );
+            for (submodule, n) in self.submodule_counts(module) {
+                *instances.entry(submodule).or_insert(0) += count * n;
+            }
+        }
+
+        // Children come before parents, so their hierarchical costs are final
+        for name in order.iter().rev() {
+            let Some(&ModuleCost { cost, .. }) = report.by_module.get(name.as_str()) else {
+                continue;
+            };
+            let below = self
+                .submodule_counts(&self.modules[name.as_str()])
+                .into_iter()
+                .map(|(submodule, n)| report.by_module[submodule].hierarchical_cost * n as f64)
+                .sum::<f64>();
+            report
+                .by_module
+                .get_mut(name.as_str())
+                .unwrap()
+                .hierarchical_cost = cost + below;
+        }
+
+        report.defaulted = defaulted.into_iter().collect();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{netlist, Memory};
+
+    fn table() -> CostTable {
+        let mut table = CostTable::from_slice(
+            br#"{
+                "$lut": { "cost": 1, "class": "lut" },
+                "$_DFF_*": { "cost": 1, "class": "ff" },
+                "$_DFF_P_": { "cost": 1.5, "class": "ff" },
+                "$*": { "cost": 0.5 }
+            }"#,
+            2.0,
+        )
+        .unwrap();
+        table.set("$mul", 10.0, Some(ResourceClass::Dsp));
+        table
+    }
+
+    #[test]
+    fn table_lookup_test() {
+        let table = table();
+        assert_eq!(table.get("$_DFF_P_").unwrap().cost, 1.5);
+        assert_eq!(
+            table.get("$_DFF_N_").unwrap().class,
+            Some(ResourceClass::Ff)
+        );
+        assert_eq!(table.get("$and").unwrap().cost, 0.5);
+        assert_eq!(table.get("SB_LUT4"), None);
+        assert_eq!(table.globs[0].0, "$_DFF_*");
+    }
+
+    #[test]
+    fn estimate_cost_test() {
+        let mut netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                cell "half" h0 { a: a, y: y }
+                cell "half" h1 { a: a, y: y }
+                cell "$mul" m { A: a, B: a, Y: y }
+            }
+            module half {
+                input a;
+                output y;
+                cell "quarter" q0 { a: a, y: y }
+                cell "quarter" q1 { a: a, y: y }
+                cell "$lut" l { A: a, Y: y }
+            }
+            module quarter {
+                input a;
+                output y;
+                cell "$_DFF_N_" r { D: a, Q: y }
+                cell "SB_LUT4" l { I0: a, O: y }
+            }
+            module unused {
+                cell "$lut" l { }
+            }
+        };
+        netlist.modules.get_mut("quarter").unwrap().memories.insert(
+            "mem".to_owned(),
+            Memory {
+                hide_name: 0,
+                attributes: HashMap::new(),
+                width: 8,
+                size: 4,
+                start_offset: 0,
+                extra: HashMap::new(),
+            },
+        );
+        let table = table();
+
+        let flat = netlist.modules["half"].estimate_cost(&table);
+        assert_eq!(flat.total, 5.0);
+        assert_eq!(flat.defaulted, ["quarter"]);
+        assert!(flat.by_module.is_empty());
+
+        let report = netlist.estimate_cost("top", &table).unwrap();
+        // 4 quarters at 3, 2 halves at 1, and the multiplier
+        assert_eq!(report.total, 24.0);
+        assert_eq!(report.memory_bits, 4 * 32);
+        assert_eq!(report.defaulted, ["SB_LUT4"]);
+        assert_eq!(
+            report.by_class[&Some(ResourceClass::Lut)],
+            ClassCost {
+                cells: 2,
+                cost: 2.0
+            }
+        );
+        assert_eq!(report.by_class[&Some(ResourceClass::Ff)].cells, 4);
+        assert_eq!(report.by_class[&None].cost, 8.0);
+        assert_eq!(
+            report.by_module["quarter"],
+            ModuleCost {
+                instances: 4,
+                cost: 3.0,
+                hierarchical_cost: 3.0,
+                memory_bits: 32,
+            }
+        );
+        assert_eq!(report.by_module["half"].hierarchical_cost, 7.0);
+        assert_eq!(report.by_module["top"].hierarchical_cost, report.total);
+        assert!(!report.by_module.contains_key("unused"));
+
+        assert!(matches!(
+            netlist.estimate_cost("missing", &table),
+            Err(Error::ModuleNotFound(_))
+        ));
+
+        // A memory too big to count saturates instead of overflowing
+        let mem = netlist
+            .modules
+            .get_mut("quarter")
+            .unwrap()
+            .memories
+            .get_mut("mem")
+            .unwrap();
+        mem.size = usize::MAX / 2;
+        let report = netlist.estimate_cost("top", &table).unwrap();
+        assert_eq!(report.memory_bits, usize::MAX);
+        assert_eq!(report.by_module["quarter"].memory_bits, usize::MAX);
+    }
+}
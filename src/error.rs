@@ -0,0 +1,511 @@
+use crate::ObjectPath;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Errors returned by operations on a netlist
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The named module does not exist in the netlist
+    ModuleNotFound(String),
+    /// The module hierarchy contains a cycle through the named module
+    HierarchyCycle(String),
+    /// Reading or writing a file failed
+    Io(std::io::Error),
+    /// Serializing or deserializing JSON failed
+    Json(serde_json::Error),
+    /// Two modules would be written to the same file, ignoring case
+    FileNameCollision(String, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ModuleNotFound(name) => write!(f, "module `{}` not found", name),
+            Error::HierarchyCycle(name) => {
+                write!(f, "module hierarchy contains a cycle through `{}`", name)
+            }
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Json(err) => write!(f, "{}", err),
+            Error::FileNameCollision(a, b) => {
+                write!(f, "modules `{}` and `{}` map to the same file name", a, b)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+/// The module hierarchy contains a cycle, so the modules can't be put in dependency order
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CycleError {
+    /// Modules that are part of a cycle or instantiate a module that is, sorted by name
+    pub modules: Vec<String>,
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "module hierarchy contains a cycle involving {}",
+            self.modules.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for CycleError {}
+
+impl From<CycleError> for Error {
+    fn from(err: CycleError) -> Self {
+        Error::HierarchyCycle(err.modules.into_iter().next().unwrap_or_default())
+    }
+}
+
+/// Errors returned by `Netlist::clone_module`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CloneError {
+    /// The module to copy does not exist
+    SourceNotFound,
+    /// A module with the new name already exists
+    DuplicateName,
+}
+
+impl fmt::Display for CloneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CloneError::SourceNotFound => write!(f, "source module not found"),
+            CloneError::DuplicateName => write!(f, "a module with this name already exists"),
+        }
+    }
+}
+
+impl std::error::Error for CloneError {}
+
+/// Errors returned by `Module::split_wide_ports`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SplitError {
+    /// The module does not exist
+    ModuleNotFound,
+    /// The port does not exist
+    PortNotFound,
+    /// The port has fewer than two bits
+    NotMultiBit,
+    /// A port or netname with the name of one of the new ports already exists
+    NameConflict(String),
+    /// An instance connects the port with a different number of bits than it has
+    WidthMismatch {
+        /// Module containing the instance
+        module: String,
+        /// Name of the instance
+        cell: String,
+    },
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitError::ModuleNotFound => write!(f, "module not found"),
+            SplitError::PortNotFound => write!(f, "port not found"),
+            SplitError::NotMultiBit => write!(f, "port has fewer than two bits"),
+            SplitError::NameConflict(name) => write!(f, "`{}` already exists", name),
+            SplitError::WidthMismatch { module, cell } => write!(
+                f,
+                "instance `{}` in module `{}` connects the port with the wrong width",
+                cell, module
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SplitError {}
+
+/// Errors returned by `Module::merge_1bit_ports`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MergeError {
+    /// The module does not exist
+    ModuleNotFound,
+    /// No ports were given
+    NoPorts,
+    /// The named port does not exist
+    PortNotFound(String),
+    /// The named port does not have exactly one bit
+    NotSingleBit(String),
+    /// The named port appears more than once
+    DuplicatePort(String),
+    /// The ports don't all have the same direction
+    DirectionMismatch,
+    /// A port with the new name already exists
+    NameConflict,
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::ModuleNotFound => write!(f, "module not found"),
+            MergeError::NoPorts => write!(f, "no ports to merge"),
+            MergeError::PortNotFound(name) => write!(f, "port `{}` not found", name),
+            MergeError::NotSingleBit(name) => write!(f, "port `{}` is not a 1-bit port", name),
+            MergeError::DuplicatePort(name) => write!(f, "port `{}` is listed twice", name),
+            MergeError::DirectionMismatch => write!(f, "ports have different directions"),
+            MergeError::NameConflict => write!(f, "a port with the new name already exists"),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// Errors returned when a `Recorder` edit can't be applied
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EditError {
+    /// The named cell does not exist
+    CellNotFound(String),
+    /// A cell with this name already exists
+    DuplicateCell(String),
+    /// The named netname does not exist
+    NetNotFound(String),
+    /// A netname with this name already exists
+    DuplicateNet(String),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::CellNotFound(name) => write!(f, "cell `{}` not found", name),
+            EditError::DuplicateCell(name) => write!(f, "cell `{}` already exists", name),
+            EditError::NetNotFound(name) => write!(f, "netname `{}` not found", name),
+            EditError::DuplicateNet(name) => write!(f, "netname `{}` already exists", name),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// Errors returned by `Annotations::attach` and `Annotations::fold_into_attributes`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AnnotationError {
+    /// These annotation paths don't name anything in the netlist
+    Unresolved(Vec<ObjectPath>),
+}
+
+impl fmt::Display for AnnotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnnotationError::Unresolved(paths) => {
+                write!(f, "{} annotation paths don't resolve", paths.len())?;
+                if let Some(path) = paths.first() {
+                    write!(f, ", first is {}", path)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnnotationError {}
+
+/// Errors returned by `Simulator`
+#[derive(Debug)]
+pub enum SimError {
+    /// The module contains a cell type the simulator can't evaluate
+    UnsupportedCell { cell: String, cell_type: String },
+    /// The named port does not exist, or has the wrong direction
+    UnknownPort(String),
+    /// A value isn't a binary string of the port's width
+    BadValue { port: String, value: String },
+    /// A line of a vector file couldn't be parsed
+    Parse { line: usize, message: String },
+    /// Reading or writing a vector file failed
+    Io(std::io::Error),
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimError::UnsupportedCell { cell, cell_type } => {
+                write!(f, "cell `{}` has unsupported type `{}`", cell, cell_type)
+            }
+            SimError::UnknownPort(name) => write!(f, "port `{}` not found", name),
+            SimError::BadValue { port, value } => {
+                write!(f, "bad value `{}` for port `{}`", value, port)
+            }
+            SimError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            SimError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SimError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SimError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SimError {
+    fn from(err: std::io::Error) -> Self {
+        SimError::Io(err)
+    }
+}
+
+/// Errors returned by `Netlist::remove_module_and_refs`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RemoveError {
+    /// The named module does not exist in the netlist
+    ModuleNotFound(String),
+}
+
+impl fmt::Display for RemoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoveError::ModuleNotFound(name) => write!(f, "module `{}` not found", name),
+        }
+    }
+}
+
+impl std::error::Error for RemoveError {}
+
+/// Errors returned by `Netlist::from_reader_tracked` and
+/// `Netlist::from_slice_tracked`, with the location of the problem in the JSON
+#[derive(Debug)]
+pub struct ParseError {
+    path: Option<serde_path_to_error::Path>,
+    source: serde_json::Error,
+}
+
+impl ParseError {
+    /// Where in the JSON the error happened, such as
+    /// `modules.cpu.cells.$and$foo.connections.A[3]`
+    ///
+    /// Iterate over it for the individual map keys and array indices. This is `None`
+    /// for errors after the end of the netlist object, such as trailing characters.
+    pub fn path(&self) -> Option<&serde_path_to_error::Path> {
+        self.path.as_ref()
+    }
+
+    /// The underlying error, which has the line and column
+    pub fn inner(&self) -> &serde_json::Error {
+        &self.source
+    }
+
+    /// Discard the path
+    pub fn into_inner(self) -> serde_json::Error {
+        self.source
+    }
+}
+
+impl From<serde_path_to_error::Error<serde_json::Error>> for ParseError {
+    fn from(err: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        ParseError {
+            path: Some(err.path().clone()),
+            source: err.into_inner(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(err: serde_json::Error) -> Self {
+        ParseError {
+            path: None,
+            source: err,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path, self.source),
+            None => write!(f, "{}", self.source),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Errors returned by `Netlist::from_slice_simd`
+#[cfg(feature = "simd-json")]
+#[derive(Debug)]
+pub enum SimdError {
+    /// Parsing with simd-json failed
+    Simd(simd_json::Error),
+    /// The CPU has no SIMD support, and parsing with serde_json instead failed
+    Json(serde_json::Error),
+}
+
+#[cfg(feature = "simd-json")]
+impl fmt::Display for SimdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimdError::Simd(err) => write!(f, "{}", err),
+            SimdError::Json(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[cfg(feature = "simd-json")]
+impl std::error::Error for SimdError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SimdError::Simd(err) => Some(err),
+            SimdError::Json(err) => Some(err),
+        }
+    }
+}
+
+/// Errors returned by `Netlist::from_path` and `Netlist::to_path`, with the path of
+/// the file involved
+#[derive(Debug)]
+pub enum PathError {
+    /// Opening, reading, or writing the file failed
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The file isn't a valid netlist, or serializing failed
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+impl PathError {
+    /// The file the error is about
+    pub fn path(&self) -> &Path {
+        match self {
+            PathError::Io { path, .. } | PathError::Json { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            PathError::Json { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for PathError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PathError::Io { source, .. } => Some(source),
+            PathError::Json { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Errors returned by `Module::add_netname_for_bits`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InsertError {
+    /// A netname with this name already exists
+    DuplicateNet(String),
+}
+
+impl fmt::Display for InsertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertError::DuplicateNet(name) => write!(f, "netname `{}` already exists", name),
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
+
+/// Errors returned by `Module::nets_at_cut`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CutError {
+    /// The named cell does not exist
+    CellNotFound(String),
+}
+
+impl fmt::Display for CutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CutError::CellNotFound(name) => write!(f, "cell `{}` not found", name),
+        }
+    }
+}
+
+impl std::error::Error for CutError {}
+
+/// Errors returned by `Module::flip_port_direction`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FlipError {
+    /// The named port does not exist
+    PortNotFound(String),
+}
+
+impl fmt::Display for FlipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlipError::PortNotFound(name) => write!(f, "port `{}` not found", name),
+        }
+    }
+}
+
+impl std::error::Error for FlipError {}
+
+/// An instance of a module that `Netlist::retain_modules` would remove
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct DanglingInstance {
+    /// Module containing the cell
+    pub module: String,
+    /// Name of the cell
+    pub cell: String,
+    /// The module it instantiates
+    pub cell_type: String,
+}
+
+/// Returned by `Netlist::retain_modules` when a kept module instantiates a removed one
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DanglingRefs {
+    /// The offending instances, sorted by module and cell
+    pub instances: Vec<DanglingInstance>,
+}
+
+impl fmt::Display for DanglingRefs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} instances refer to removed modules",
+            self.instances.len()
+        )?;
+        if let Some(inst) = self.instances.first() {
+            write!(
+                f,
+                ", first is `{}` ({}) in `{}`",
+                inst.cell, inst.cell_type, inst.module
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DanglingRefs {}
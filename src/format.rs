@@ -0,0 +1,414 @@
+use crate::{AttributeVal, BitVal, Cell, Memory, Module, Netlist, Netname, Port, PortDirection};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_derive::Serialize;
+use serde_json::ser::Formatter;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+/// Wraps a JSON value so that it serializes with the keys of every object in
+/// lexicographic order
+///
+/// The keys are sorted explicitly, so the order doesn't depend on whether
+/// `serde_json` was built with `preserve_order`.
+pub(crate) struct SortedValue<'a>(pub(crate) &'a Value);
+
+impl Serialize for SortedValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(&SortedValue(item))?;
+                }
+                seq.end()
+            }
+            Value::Object(object) => {
+                let mut entries = object.iter().collect::<Vec<_>>();
+                entries.sort_unstable_by_key(|(key, _)| *key);
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, &SortedValue(value))?;
+                }
+                map.end()
+            }
+            scalar => scalar.serialize(serializer),
+        }
+    }
+}
+
+fn sorted<'a, V, T: 'a>(
+    map: &'a HashMap<String, V>,
+    f: impl Fn(&'a V) -> T,
+) -> BTreeMap<&'a str, T> {
+    map.iter().map(|(k, v)| (k.as_str(), f(v))).collect()
+}
+
+fn sorted_extra(extra: &HashMap<String, Value>) -> BTreeMap<&str, SortedValue<'_>> {
+    sorted(extra, SortedValue)
+}
+
+/// A netlist borrowed with every map sorted by key
+///
+/// It serializes to the same fields as `Netlist`, but in the order `yosys write_json`
+/// writes them, e.g. a port's `offset` before its `bits`.
+#[derive(Serialize)]
+pub(crate) struct OrderedNetlist<'a> {
+    creator: &'a str,
+    modules: BTreeMap<&'a str, OrderedModule<'a>>,
+    #[serde(flatten)]
+    extra: BTreeMap<&'a str, SortedValue<'a>>,
+}
+
+impl<'a> OrderedNetlist<'a> {
+    pub(crate) fn new(netlist: &'a Netlist) -> Self {
+        OrderedNetlist {
+            creator: &netlist.creator,
+            modules: sorted(&netlist.modules, OrderedModule::new),
+            extra: sorted_extra(&netlist.extra),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OrderedModule<'a> {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    attributes: BTreeMap<&'a str, &'a AttributeVal>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    parameter_default_values: BTreeMap<&'a str, &'a AttributeVal>,
+    ports: BTreeMap<&'a str, OrderedPort<'a>>,
+    cells: BTreeMap<&'a str, OrderedCell<'a>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    memories: BTreeMap<&'a str, OrderedMemory<'a>>,
+    netnames: BTreeMap<&'a str, OrderedNetname<'a>>,
+    #[serde(flatten)]
+    extra: BTreeMap<&'a str, SortedValue<'a>>,
+}
+
+impl<'a> OrderedModule<'a> {
+    fn new(module: &'a Module) -> Self {
+        OrderedModule {
+            attributes: sorted(&module.attributes, |x| x),
+            parameter_default_values: sorted(&module.parameter_default_values, |x| x),
+            ports: sorted(&module.ports, OrderedPort::new),
+            cells: sorted(&module.cells, OrderedCell::new),
+            memories: sorted(&module.memories, OrderedMemory::new),
+            netnames: sorted(&module.netnames, OrderedNetname::new),
+            extra: sorted_extra(&module.extra),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OrderedPort<'a> {
+    direction: PortDirection,
+    #[serde(skip_serializing_if = "crate::is_zero")]
+    offset: usize,
+    #[serde(skip_serializing_if = "crate::is_zero")]
+    upto: usize,
+    #[serde(skip_serializing_if = "crate::is_zero")]
+    signed: usize,
+    bits: &'a [BitVal],
+    #[serde(flatten)]
+    extra: BTreeMap<&'a str, SortedValue<'a>>,
+}
+
+impl<'a> OrderedPort<'a> {
+    fn new(port: &'a Port) -> Self {
+        OrderedPort {
+            direction: port.direction,
+            offset: port.offset,
+            upto: port.upto,
+            signed: port.signed,
+            bits: &port.bits,
+            extra: sorted_extra(&port.extra),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OrderedCell<'a> {
+    hide_name: usize,
+    #[serde(rename = "type")]
+    cell_type: &'a str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    parameters: BTreeMap<&'a str, &'a AttributeVal>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    attributes: BTreeMap<&'a str, &'a AttributeVal>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    port_directions: BTreeMap<&'a str, &'a PortDirection>,
+    connections: BTreeMap<&'a str, &'a Vec<BitVal>>,
+    #[serde(flatten)]
+    extra: BTreeMap<&'a str, SortedValue<'a>>,
+}
+
+impl<'a> OrderedCell<'a> {
+    fn new(cell: &'a Cell) -> Self {
+        OrderedCell {
+            hide_name: cell.hide_name,
+            cell_type: &cell.cell_type,
+            parameters: sorted(&cell.parameters, |x| x),
+            attributes: sorted(&cell.attributes, |x| x),
+            port_directions: sorted(&cell.port_directions, |x| x),
+            connections: sorted(&cell.connections, |x| x),
+            extra: sorted_extra(&cell.extra),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OrderedMemory<'a> {
+    hide_name: usize,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    attributes: BTreeMap<&'a str, &'a AttributeVal>,
+    width: usize,
+    start_offset: usize,
+    size: usize,
+    #[serde(flatten)]
+    extra: BTreeMap<&'a str, SortedValue<'a>>,
+}
+
+impl<'a> OrderedMemory<'a> {
+    fn new(memory: &'a Memory) -> Self {
+        OrderedMemory {
+            hide_name: memory.hide_name,
+            attributes: sorted(&memory.attributes, |x| x),
+            width: memory.width,
+            start_offset: memory.start_offset,
+            size: memory.size,
+            extra: sorted_extra(&memory.extra),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OrderedNetname<'a> {
+    hide_name: usize,
+    bits: &'a [BitVal],
+    #[serde(skip_serializing_if = "crate::is_zero")]
+    offset: usize,
+    #[serde(skip_serializing_if = "crate::is_zero")]
+    upto: usize,
+    #[serde(skip_serializing_if = "crate::is_zero")]
+    signed: usize,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    attributes: BTreeMap<&'a str, &'a AttributeVal>,
+    #[serde(flatten)]
+    extra: BTreeMap<&'a str, SortedValue<'a>>,
+}
+
+impl<'a> OrderedNetname<'a> {
+    fn new(netname: &'a Netname) -> Self {
+        OrderedNetname {
+            hide_name: netname.hide_name,
+            bits: &netname.bits,
+            offset: netname.offset,
+            upto: netname.upto,
+            signed: netname.signed,
+            attributes: sorted(&netname.attributes, |x| x),
+            extra: sorted_extra(&netname.extra),
+        }
+    }
+}
+
+/// Serialize `value` to `writer` with sorted object keys, laid out by `formatter`
+pub(crate) fn write_sorted<T, W, F>(value: &T, writer: W, formatter: F) -> serde_json::Result<()>
+where
+    T: ?Sized + Serialize,
+    W: Write,
+    F: Formatter,
+{
+    let value = serde_json::to_value(value)?;
+    let mut serializer = serde_json::Serializer::with_formatter(writer, formatter);
+    SortedValue(&value).serialize(&mut serializer)
+}
+
+/// A `serde_json` formatter that lays out JSON the way `yosys write_json` does
+///
+/// Objects are indented by 2 spaces per level, with the closing brace on its own
+/// line even when empty. Arrays, which only hold bits in a netlist, stay on one line
+/// as `[ 2, 3, 4 ]`.
+#[derive(Clone, Debug, Default)]
+pub struct YosysFormatter {
+    depth: usize,
+}
+
+impl YosysFormatter {
+    /// Create a formatter for a top-level value
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn indent<W: ?Sized + Write>(&self, writer: &mut W) -> io::Result<()> {
+        for _ in 0..self.depth {
+            writer.write_all(b"  ")?;
+        }
+        Ok(())
+    }
+}
+
+impl Formatter for YosysFormatter {
+    fn begin_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b"[")
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b" ]")
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        writer.write_all(if first { b" " } else { b", " })
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, _writer: &mut W) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.depth += 1;
+        writer.write_all(b"{")
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.depth -= 1;
+        writer.write_all(b"\n")?;
+        self.indent(writer)?;
+        writer.write_all(b"}")
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        writer.write_all(if first { b"\n" } else { b",\n" })?;
+        self.indent(writer)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b": ")
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, _writer: &mut W) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Netlist {
+    /// Serialize to a writer laid out like `yosys write_json` output, with
+    /// `YosysFormatter`
+    ///
+    /// Fields are written in the order Yosys writes them, and the entries of every map
+    /// are sorted by key as Yosys does, so the output is the same from run to run.
+    pub fn to_writer_yosys_style<W: Write>(&self, mut writer: W) -> Result<(), serde_json::Error> {
+        let mut serializer =
+            serde_json::Serializer::with_formatter(&mut writer, YosysFormatter::new());
+        OrderedNetlist::new(self).serialize(&mut serializer)?;
+        writer.write_all(b"\n").map_err(serde_json::Error::io)
+    }
+
+    /// Serialize to a String laid out like `yosys write_json` output
+    pub fn to_string_yosys_style(&self) -> Result<String, serde_json::Error> {
+        let mut out = Vec::new();
+        self.to_writer_yosys_style(&mut out)?;
+        // serde_json only writes valid UTF-8
+        Ok(String::from_utf8(out).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BitVal, Module, Port, PortDirection};
+    use std::collections::HashMap;
+
+    #[test]
+    fn yosys_style_test() {
+        let module = Module {
+            ports: HashMap::from([(
+                "a".to_owned(),
+                Port {
+                    direction: PortDirection::Input,
+                    bits: vec![BitVal::N(2), BitVal::N(3)],
+                    offset: 0,
+                    upto: 0,
+                    signed: 0,
+                    extra: HashMap::new(),
+                },
+            )]),
+            ..Default::default()
+        };
+        let netlist = Netlist {
+            creator: "test".to_owned(),
+            modules: HashMap::from([("top".to_owned(), module)]),
+            extra: HashMap::new(),
+        };
+        assert_eq!(
+            netlist.to_string_yosys_style().unwrap(),
+            r#"{
+  "creator": "test",
+  "modules": {
+    "top": {
+      "ports": {
+        "a": {
+          "direction": "input",
+          "bits": [ 2, 3 ]
+        }
+      },
+      "cells": {
+      },
+      "netnames": {
+      }
+    }
+  }
+}
+"#
+        );
+    }
+
+    #[test]
+    fn ordered_netlist_same_fields_test() {
+        // Every field, including unknown ones, must survive the reordering
+        let netlist = Netlist::from_slice(
+            br#"{
+                "creator": "c",
+                "top_extra": { "z": 1, "a": [2] },
+                "modules": {
+                    "m": {
+                        "attributes": { "top": 1 },
+                        "parameter_default_values": { "W": 8 },
+                        "ports": {
+                            "p": { "direction": "inout", "bits": [2, 3], "offset": 1,
+                                   "upto": 1, "signed": 1, "port_extra": 1 }
+                        },
+                        "cells": {
+                            "c": { "hide_name": 1, "type": "$and", "parameters": { "A": 1 },
+                                   "attributes": { "src": "x" },
+                                   "port_directions": { "Y": "output" },
+                                   "connections": { "Y": [2] }, "cell_extra": null }
+                        },
+                        "memories": {
+                            "mem": { "hide_name": 0, "attributes": {}, "width": 2, "size": 4,
+                                     "start_offset": 1, "memory_extra": "x" }
+                        },
+                        "netnames": {
+                            "p": { "hide_name": 0, "bits": [2, 3], "offset": 1, "upto": 1,
+                                   "signed": 1, "attributes": { "keep": 1 },
+                                   "netname_extra": true }
+                        },
+                        "module_extra": 1
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(OrderedNetlist::new(&netlist)).unwrap(),
+            netlist.to_value().unwrap()
+        );
+    }
+}
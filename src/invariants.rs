@@ -0,0 +1,475 @@
+//! Cheap consistency checks, meant to be asserted after every change when fuzzing
+//! transforms
+//!
+//! Checks of drivers and readers build one `ConnectivityIndex`, so they agree with
+//! the rest of the crate on what drives a signal; the others make one pass over the
+//! objects they look at. Every check stops at the first violation. Checks of a single
+//! module leave `InvariantViolation::module` empty; `all` fills it in.
+
+use crate::{Cell, CellLibrary, Module, Netlist};
+use std::fmt;
+
+/// What is wrong, as found by one of the checks in this module
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ViolationKind {
+    /// A signal is read by a cell input or an output port but nothing drives it
+    DanglingBit { signal: usize, reader: String },
+    /// A port has no netname of the same name with the same bits
+    MissingPortNetname { port: String },
+    /// A signal has more than one driver
+    MultipleDrivers {
+        signal: usize,
+        first: String,
+        second: String,
+    },
+    /// A cell type isn't an internal (`$`) cell, a module of the netlist, or a cell of
+    /// the library
+    UnresolvedInstance { cell: String, cell_type: String },
+    /// An instance connects a port that its module doesn't have
+    UnknownInstancePort { cell: String, port: String },
+    /// An instance leaves a port of its module unconnected
+    MissingInstancePort { cell: String, port: String },
+    /// An instance connects a port with the wrong number of bits
+    InstanceWidthMismatch {
+        cell: String,
+        port: String,
+        expected: usize,
+        found: usize,
+    },
+    /// An instance of a library cell doesn't set a required parameter
+    MissingParameter { cell: String, parameter: String },
+}
+
+/// A failed invariant check
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct InvariantViolation {
+    /// Module the violation is in, if known
+    pub module: Option<String>,
+    /// What is wrong
+    pub kind: ViolationKind,
+}
+
+impl From<ViolationKind> for InvariantViolation {
+    fn from(kind: ViolationKind) -> Self {
+        InvariantViolation { module: None, kind }
+    }
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(module) = &self.module {
+            write!(f, "in module `{}`: ", module)?;
+        }
+        match &self.kind {
+            ViolationKind::DanglingBit { signal, reader } => {
+                write!(f, "signal {} is read by {} but not driven", signal, reader)
+            }
+            ViolationKind::MissingPortNetname { port } => {
+                write!(f, "port `{}` has no matching netname", port)
+            }
+            ViolationKind::MultipleDrivers {
+                signal,
+                first,
+                second,
+            } => write!(
+                f,
+                "signal {} is driven by both {} and {}",
+                signal, first, second
+            ),
+            ViolationKind::UnresolvedInstance { cell, cell_type } => {
+                write!(f, "cell `{}` has unknown type `{}`", cell, cell_type)
+            }
+            ViolationKind::UnknownInstancePort { cell, port } => {
+                write!(f, "cell `{}` connects unknown port `{}`", cell, port)
+            }
+            ViolationKind::MissingInstancePort { cell, port } => {
+                write!(f, "cell `{}` doesn't connect port `{}`", cell, port)
+            }
+            ViolationKind::InstanceWidthMismatch {
+                cell,
+                port,
+                expected,
+                found,
+            } => write!(
+                f,
+                "cell `{}` connects {} bits to port `{}` which has {}",
+                cell, found, port, expected
+            ),
+            ViolationKind::MissingParameter { cell, parameter } => {
+                write!(f, "cell `{}` doesn't set parameter `{}`", cell, parameter)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
+
+/// Check that every signal read by a cell input or an output port has a driver
+///
+/// Drivers and readers are those of `Module::connectivity`, so inout ports count as
+/// both and cell ports without `port_directions` are ignored. The lowest undriven
+/// signal is reported.
+pub fn no_dangling_bits(module: &Module) -> Result<(), InvariantViolation> {
+    let index = module.connectivity();
+    let dangling = index
+        .sunk_signals()
+        .filter(|&n| index.drivers(n).is_empty())
+        .min();
+    match dangling {
+        Some(n) => Err(ViolationKind::DanglingBit {
+            signal: n,
+            reader: index.sinks(n)[0].to_string(),
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// Check that every port has a netname with the same name and bits
+///
+/// The first offending port by name is reported.
+pub fn ports_have_netnames(module: &Module) -> Result<(), InvariantViolation> {
+    let missing = module
+        .ports
+        .iter()
+        .filter(|(name, port)| module.netnames.get(*name).map(|n| &n.bits) != Some(&port.bits))
+        .map(|(name, _)| name)
+        .min();
+    match missing {
+        Some(name) => Err(ViolationKind::MissingPortNetname { port: name.clone() }.into()),
+        None => Ok(()),
+    }
+}
+
+/// Check that no signal is driven by more than one input port or cell output
+///
+/// Drivers are those of `ConnectivityIndex::exclusive_drivers`, so inout ports are
+/// ignored, since tri-state buses legitimately have several drivers. The lowest
+/// multiply driven signal is reported.
+pub fn unique_driver_per_bit(module: &Module) -> Result<(), InvariantViolation> {
+    let index = module.connectivity();
+    let Some(&signal) = index.multiply_driven_signals().first() else {
+        return Ok(());
+    };
+    let mut drivers = index.exclusive_drivers(signal).map(ToString::to_string);
+    Err(ViolationKind::MultipleDrivers {
+        signal,
+        first: drivers.next().unwrap_or_default(),
+        second: drivers.next().unwrap_or_default(),
+    }
+    .into())
+}
+
+/// Check that every cell is either an internal (`$`) cell or an instance of a module
+/// of the netlist, and that instances only connect ports of that module with the
+/// right widths
+pub fn instances_resolve(netlist: &Netlist) -> Result<(), InvariantViolation> {
+    instances_resolve_with(netlist, None)
+}
+
+/// Like `instances_resolve`, but also accept instances of cells in `library`, which
+/// must only connect ports of the library cell with the right widths and must set
+/// the required parameters
+///
+/// Modules of the netlist take precedence over library cells of the same name.
+/// Modules, cells, and ports are checked in name order, and the first violation is
+/// reported.
+pub fn instances_resolve_with(
+    netlist: &Netlist,
+    library: Option<&CellLibrary>,
+) -> Result<(), InvariantViolation> {
+    let mut modules = netlist.modules.iter().collect::<Vec<_>>();
+    modules.sort_by_key(|(name, _)| name.as_str());
+    for (module_name, module) in modules {
+        let violation = |kind| InvariantViolation {
+            module: Some(module_name.clone()),
+            kind,
+        };
+        let mut cells = module.cells.iter().collect::<Vec<_>>();
+        cells.sort_by_key(|(name, _)| name.as_str());
+        for (cell_name, cell) in cells {
+            // `None` if the port doesn't exist, `Some(None)` if its width depends on a
+            // parameter that isn't set
+            let port_width = |port: &str| -> Option<Option<usize>> {
+                if let Some(target) = netlist.modules.get(&cell.cell_type) {
+                    return target.ports.get(port).map(|p| Some(p.bits.len()));
+                }
+                let lib_cell = library?.get(&cell.cell_type)?;
+                lib_cell.ports.get(port)?;
+                Some(lib_cell.port_width(port, cell))
+            };
+
+            let lib_cell = library.and_then(|lib| lib.get(&cell.cell_type));
+            if !netlist.modules.contains_key(&cell.cell_type) {
+                let Some(lib_cell) = lib_cell else {
+                    if cell.cell_type.starts_with('$') {
+                        continue;
+                    }
+                    return Err(violation(ViolationKind::UnresolvedInstance {
+                        cell: cell_name.clone(),
+                        cell_type: cell.cell_type.clone(),
+                    }));
+                };
+                if let Some(param) = lib_cell
+                    .parameters
+                    .iter()
+                    .find(|p| !cell.parameters.contains_key(*p))
+                {
+                    return Err(violation(ViolationKind::MissingParameter {
+                        cell: cell_name.clone(),
+                        parameter: param.clone(),
+                    }));
+                }
+            }
+
+            if let Some(kind) = connection_violations(cell_name, cell, port_width).next() {
+                return Err(violation(kind));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Connections of `cell` to ports that don't exist or have a different width, in port
+/// name order
+///
+/// `port_width` gives the width of a port of the cell type: `None` if the port doesn't
+/// exist, `Some(None)` if the width is unknown.
+pub(crate) fn connection_violations<'a, F>(
+    cell_name: &'a str,
+    cell: &'a Cell,
+    port_width: F,
+) -> impl Iterator<Item = ViolationKind> + 'a
+where
+    F: Fn(&str) -> Option<Option<usize>> + 'a,
+{
+    let mut connections = cell.connections.iter().collect::<Vec<_>>();
+    connections.sort_by_key(|(name, _)| name.as_str());
+    connections.into_iter().filter_map(move |(name, bits)| {
+        let Some(width) = port_width(name) else {
+            return Some(ViolationKind::UnknownInstancePort {
+                cell: cell_name.to_owned(),
+                port: name.clone(),
+            });
+        };
+        width.filter(|&width| width != bits.len()).map(|width| {
+            ViolationKind::InstanceWidthMismatch {
+                cell: cell_name.to_owned(),
+                port: name.clone(),
+                expected: width,
+                found: bits.len(),
+            }
+        })
+    })
+}
+
+/// Run every check on every module, in name order
+pub fn all(netlist: &Netlist) -> Result<(), InvariantViolation> {
+    let mut modules = netlist.modules.iter().collect::<Vec<_>>();
+    modules.sort_by_key(|(name, _)| name.as_str());
+    for (name, module) in modules {
+        no_dangling_bits(module)
+            .and_then(|_| ports_have_netnames(module))
+            .and_then(|_| unique_driver_per_bit(module))
+            .map_err(|mut err| {
+                err.module = Some(name.clone());
+                err
+            })?;
+    }
+    instances_resolve(netlist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netlist;
+    use crate::BitVal;
+
+    fn good_netlist() -> Netlist {
+        netlist! {
+            module top {
+                input a[2];
+                output y;
+                wire t;
+                cell "$and" u_and { A: a[0], B: a[1], Y: t }
+                cell "inv" u_inv { i: t, o: y }
+            }
+            module inv {
+                input i;
+                output o;
+                cell "$not" u { A: i, Y: o }
+            }
+        }
+    }
+
+    #[test]
+    fn all_pass_test() {
+        assert_eq!(all(&good_netlist()), Ok(()));
+    }
+
+    #[test]
+    fn violations_test() {
+        let mut netlist = good_netlist();
+        let top = netlist.modules.get_mut("top").unwrap();
+        top.cells.remove("u_and");
+        let err = no_dangling_bits(top).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ViolationKind::DanglingBit {
+                signal: 5,
+                reader: "cell `u_inv` port `i[0]`".to_owned()
+            }
+        );
+        assert_eq!(
+            all(&netlist).unwrap_err().to_string(),
+            "in module `top`: signal 5 is read by cell `u_inv` port `i[0]` but not driven"
+        );
+
+        let mut netlist = good_netlist();
+        let top = netlist.modules.get_mut("top").unwrap();
+        top.netnames.remove("y");
+        assert_eq!(
+            ports_have_netnames(top).unwrap_err().kind,
+            ViolationKind::MissingPortNetname {
+                port: "y".to_owned()
+            }
+        );
+
+        let mut netlist = good_netlist();
+        let inv = netlist.modules.get_mut("inv").unwrap();
+        let u = inv.cells["u"].clone();
+        inv.cells.insert("u2".to_owned(), u);
+        assert!(matches!(
+            unique_driver_per_bit(inv).unwrap_err().kind,
+            ViolationKind::MultipleDrivers { signal: 3, .. }
+        ));
+
+        let mut netlist = good_netlist();
+        let u_inv = netlist
+            .modules
+            .get_mut("top")
+            .unwrap()
+            .cells
+            .get_mut("u_inv")
+            .unwrap();
+        u_inv.connections.get_mut("i").unwrap().push(BitVal::N(2));
+        assert_eq!(
+            instances_resolve(&netlist).unwrap_err().kind,
+            ViolationKind::InstanceWidthMismatch {
+                cell: "u_inv".to_owned(),
+                port: "i".to_owned(),
+                expected: 1,
+                found: 2
+            }
+        );
+
+        netlist.modules.remove("inv");
+        assert!(matches!(
+            instances_resolve(&netlist).unwrap_err().kind,
+            ViolationKind::UnresolvedInstance { .. }
+        ));
+    }
+
+    #[test]
+    fn first_violation_test() {
+        let mut netlist = good_netlist();
+        let top = netlist.modules.get_mut("top").unwrap();
+        top.netnames.remove("y");
+        top.netnames.remove("a");
+        assert_eq!(
+            ports_have_netnames(top).unwrap_err().kind,
+            ViolationKind::MissingPortNetname {
+                port: "a".to_owned()
+            }
+        );
+
+        let mut netlist = good_netlist();
+        let top = netlist.modules.get_mut("top").unwrap();
+        let mut u_inv = top.cells["u_inv"].clone();
+        u_inv.connections.get_mut("i").unwrap().push(BitVal::N(2));
+        u_inv.connections.get_mut("o").unwrap().push(BitVal::N(2));
+        top.cells.insert("u_inv".to_owned(), u_inv.clone());
+        top.cells.insert("u_inv0".to_owned(), u_inv);
+        let mut zzz = top.cells["u_and"].clone();
+        zzz.cell_type = "zzz".to_owned();
+        top.cells.insert("a_zzz".to_owned(), zzz);
+        netlist
+            .modules
+            .insert("a".to_owned(), netlist.modules["top"].clone());
+        assert_eq!(
+            instances_resolve(&netlist).unwrap_err(),
+            InvariantViolation {
+                module: Some("a".to_owned()),
+                kind: ViolationKind::UnresolvedInstance {
+                    cell: "a_zzz".to_owned(),
+                    cell_type: "zzz".to_owned()
+                }
+            }
+        );
+
+        netlist.modules.remove("a");
+        netlist
+            .modules
+            .get_mut("top")
+            .unwrap()
+            .cells
+            .remove("a_zzz");
+        assert_eq!(
+            instances_resolve(&netlist).unwrap_err().kind,
+            ViolationKind::InstanceWidthMismatch {
+                cell: "u_inv".to_owned(),
+                port: "i".to_owned(),
+                expected: 1,
+                found: 2
+            }
+        );
+    }
+
+    #[test]
+    fn library_instances_test() {
+        let mut netlist = netlist! {
+            module top {
+                input a[4];
+                output y;
+                cell "SB_LUT4" lut { I0: a[0], I1: a[1], I2: a[2], I3: a[3], O: y }
+            }
+        };
+        let lib = CellLibrary::builtin();
+        assert!(instances_resolve(&netlist).is_err());
+        assert_eq!(
+            instances_resolve_with(&netlist, Some(&lib))
+                .unwrap_err()
+                .kind,
+            ViolationKind::MissingParameter {
+                cell: "lut".to_owned(),
+                parameter: "LUT_INIT".to_owned()
+            }
+        );
+
+        let lut = netlist
+            .modules
+            .get_mut("top")
+            .unwrap()
+            .cells
+            .get_mut("lut")
+            .unwrap();
+        lut.parameters
+            .insert("LUT_INIT".to_owned(), crate::AttributeVal::N(0x8000));
+        assert_eq!(instances_resolve_with(&netlist, Some(&lib)), Ok(()));
+
+        let lut = netlist
+            .modules
+            .get_mut("top")
+            .unwrap()
+            .cells
+            .get_mut("lut")
+            .unwrap();
+        lut.connections.insert("I4".to_owned(), vec![BitVal::N(2)]);
+        assert!(matches!(
+            instances_resolve_with(&netlist, Some(&lib))
+                .unwrap_err()
+                .kind,
+            ViolationKind::UnknownInstancePort { .. }
+        ));
+    }
+}
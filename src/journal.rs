@@ -0,0 +1,556 @@
+use crate::{format, AttributeVal, BitVal, Cell, EditError, Module};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hasher;
+use std::io::{self, Write};
+
+/// One reversible change made through a `Recorder`
+///
+/// Each edit stores enough of the previous state to be undone. A list of edits can
+/// be serialized and replayed onto a fresh copy of the original module with
+/// `Edit::replay`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Edit {
+    /// A cell was added
+    AddCell { name: String, cell: Cell },
+    /// A cell was removed
+    RemoveCell { name: String, cell: Cell },
+    /// A cell was renamed
+    RenameCell { from: String, to: String },
+    /// A netname was renamed
+    RenameNet { from: String, to: String },
+    /// Signals were renumbered
+    RemapBits {
+        /// Old to new signal numbers
+        mapping: BTreeMap<usize, usize>,
+        /// Position (in `for_each_bit_mut` order) and old signal number of every bit
+        /// that was changed
+        previous: Vec<(usize, usize)>,
+    },
+    /// An attribute was set or removed
+    SetAttribute {
+        /// Cell the attribute is on, or `None` for a module attribute
+        cell: Option<String>,
+        name: String,
+        /// New value, or `None` if the attribute was removed
+        value: Option<AttributeVal>,
+        /// Value before the edit, or `None` if the attribute wasn't set
+        previous: Option<AttributeVal>,
+    },
+}
+
+fn attributes_mut<'a>(
+    module: &'a mut Module,
+    cell: &Option<String>,
+) -> Result<&'a mut HashMap<String, AttributeVal>, EditError> {
+    match cell {
+        None => Ok(&mut module.attributes),
+        Some(cell) => module
+            .cells
+            .get_mut(cell)
+            .map(|c| &mut c.attributes)
+            .ok_or_else(|| EditError::CellNotFound(cell.clone())),
+    }
+}
+
+fn set_or_remove(
+    attributes: &mut HashMap<String, AttributeVal>,
+    name: &str,
+    value: &Option<AttributeVal>,
+) {
+    match value {
+        Some(value) => attributes.insert(name.to_owned(), value.clone()),
+        None => attributes.remove(name),
+    };
+}
+
+fn add_cell(module: &mut Module, name: &str, cell: &Cell) -> Result<(), EditError> {
+    if module.cells.contains_key(name) {
+        return Err(EditError::DuplicateCell(name.to_owned()));
+    }
+    module.cells.insert(name.to_owned(), cell.clone());
+    Ok(())
+}
+
+fn remove_cell(module: &mut Module, name: &str) -> Result<Cell, EditError> {
+    module
+        .cells
+        .remove(name)
+        .ok_or_else(|| EditError::CellNotFound(name.to_owned()))
+}
+
+fn rename_cell(module: &mut Module, from: &str, to: &str) -> Result<(), EditError> {
+    if module.cells.contains_key(to) {
+        return Err(EditError::DuplicateCell(to.to_owned()));
+    }
+    let cell = remove_cell(module, from)?;
+    module.cells.insert(to.to_owned(), cell);
+    Ok(())
+}
+
+fn rename_net(module: &mut Module, from: &str, to: &str) -> Result<(), EditError> {
+    if module.netnames.contains_key(to) {
+        return Err(EditError::DuplicateNet(to.to_owned()));
+    }
+    let netname = module
+        .netnames
+        .remove(from)
+        .ok_or_else(|| EditError::NetNotFound(from.to_owned()))?;
+    module.netnames.insert(to.to_owned(), netname);
+    Ok(())
+}
+
+/// Renumber signals, returning the position and old number of every changed bit
+fn remap_bits(module: &mut Module, mapping: &BTreeMap<usize, usize>) -> Vec<(usize, usize)> {
+    let mut previous = Vec::new();
+    let mut idx = 0;
+    module.for_each_bit_mut(|bit| {
+        if let BitVal::N(n) = bit {
+            if let Some(&new) = mapping.get(n) {
+                if new != *n {
+                    previous.push((idx, *n));
+                    *n = new;
+                }
+            }
+        }
+        idx += 1;
+    });
+    previous
+}
+
+impl Edit {
+    /// Apply this edit to a module
+    pub fn apply(&self, module: &mut Module) -> Result<(), EditError> {
+        match self {
+            Edit::AddCell { name, cell } => add_cell(module, name, cell),
+            Edit::RemoveCell { name, .. } => remove_cell(module, name).map(|_| ()),
+            Edit::RenameCell { from, to } => rename_cell(module, from, to),
+            Edit::RenameNet { from, to } => rename_net(module, from, to),
+            Edit::RemapBits { mapping, .. } => {
+                remap_bits(module, mapping);
+                Ok(())
+            }
+            Edit::SetAttribute {
+                cell, name, value, ..
+            } => {
+                set_or_remove(attributes_mut(module, cell)?, name, value);
+                Ok(())
+            }
+        }
+    }
+
+    /// Undo this edit on a module it was just applied to
+    pub fn revert(&self, module: &mut Module) -> Result<(), EditError> {
+        match self {
+            Edit::AddCell { name, .. } => remove_cell(module, name).map(|_| ()),
+            Edit::RemoveCell { name, cell } => add_cell(module, name, cell),
+            Edit::RenameCell { from, to } => rename_cell(module, to, from),
+            Edit::RenameNet { from, to } => rename_net(module, to, from),
+            Edit::RemapBits { previous, .. } => {
+                let mut previous = previous.iter().peekable();
+                let mut idx = 0;
+                module.for_each_bit_mut(|bit| {
+                    if let Some(&&(pos, old)) = previous.peek() {
+                        if pos == idx {
+                            *bit = BitVal::N(old);
+                            previous.next();
+                        }
+                    }
+                    idx += 1;
+                });
+                Ok(())
+            }
+            Edit::SetAttribute {
+                cell,
+                name,
+                previous,
+                ..
+            } => {
+                set_or_remove(attributes_mut(module, cell)?, name, previous);
+                Ok(())
+            }
+        }
+    }
+
+    /// Apply a journal of edits, in order, to a module
+    pub fn replay(module: &mut Module, edits: &[Edit]) -> Result<(), EditError> {
+        edits.iter().try_for_each(|edit| edit.apply(module))
+    }
+}
+
+/// Feeds everything written to it into a hasher
+struct HashWriter<'a>(&'a mut DefaultHasher);
+
+impl Write for HashWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash of the contents of a module, independent of map iteration order
+fn module_hash(module: &Module) -> Result<u64, serde_json::Error> {
+    let mut hasher = DefaultHasher::new();
+    format::write_sorted(
+        module,
+        HashWriter(&mut hasher),
+        serde_json::ser::CompactFormatter,
+    )?;
+    Ok(hasher.finish())
+}
+
+/// A point in the edit history of a `Recorder`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Checkpoint {
+    position: usize,
+    /// `None` if the module couldn't be serialized for hashing
+    hash: Option<u64>,
+}
+
+/// Wraps a module and records every change made through its methods so that it can
+/// be undone and redone
+///
+/// Only changes made through the `Recorder` methods are recorded. Changes made
+/// directly to the module through `module_mut` bypass the journal and will break
+/// undo; `verify_checkpoint` can be used to detect them.
+#[derive(Clone, Debug, Default)]
+pub struct Recorder {
+    module: Module,
+    edits: Vec<Edit>,
+    /// Number of edits in `edits` currently applied; the rest can be redone
+    position: usize,
+}
+
+impl Recorder {
+    /// Start recording changes to a module
+    pub fn new(module: Module) -> Self {
+        Recorder {
+            module,
+            edits: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// The module in its current state
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// Mutable access to the module, bypassing the journal
+    ///
+    /// Changes made this way are not recorded and can't be undone.
+    pub fn module_mut(&mut self) -> &mut Module {
+        &mut self.module
+    }
+
+    /// Stop recording and return the module
+    pub fn into_inner(self) -> Module {
+        self.module
+    }
+
+    /// The edits currently applied, oldest first
+    pub fn journal(&self) -> &[Edit] {
+        &self.edits[..self.position]
+    }
+
+    fn record(&mut self, edit: Edit) -> Result<(), EditError> {
+        edit.apply(&mut self.module)?;
+        self.edits.truncate(self.position);
+        self.edits.push(edit);
+        self.position += 1;
+        Ok(())
+    }
+
+    /// Add a cell
+    pub fn add_cell(&mut self, name: &str, cell: Cell) -> Result<(), EditError> {
+        self.record(Edit::AddCell {
+            name: name.to_owned(),
+            cell,
+        })
+    }
+
+    /// Remove a cell, returning it
+    pub fn remove_cell(&mut self, name: &str) -> Result<Cell, EditError> {
+        let cell = self
+            .module
+            .cells
+            .get(name)
+            .cloned()
+            .ok_or_else(|| EditError::CellNotFound(name.to_owned()))?;
+        self.record(Edit::RemoveCell {
+            name: name.to_owned(),
+            cell: cell.clone(),
+        })?;
+        Ok(cell)
+    }
+
+    /// Rename a cell
+    pub fn rename_cell(&mut self, from: &str, to: &str) -> Result<(), EditError> {
+        self.record(Edit::RenameCell {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        })
+    }
+
+    /// Rename a netname
+    pub fn rename_net(&mut self, from: &str, to: &str) -> Result<(), EditError> {
+        self.record(Edit::RenameNet {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        })
+    }
+
+    /// Renumber signals in every port, cell connection, and netname
+    ///
+    /// Returns the number of bits changed.
+    pub fn remap_bits(&mut self, mapping: &HashMap<usize, usize>) -> usize {
+        let mapping = mapping.iter().map(|(&k, &v)| (k, v)).collect();
+        let previous = remap_bits(&mut self.module, &mapping);
+        let changed = previous.len();
+        self.edits.truncate(self.position);
+        self.edits.push(Edit::RemapBits { mapping, previous });
+        self.position += 1;
+        changed
+    }
+
+    /// Set an attribute on a cell, or on the module if `cell` is `None`
+    ///
+    /// Passing `None` as the value removes the attribute.
+    pub fn set_attribute(
+        &mut self,
+        cell: Option<&str>,
+        name: &str,
+        value: Option<AttributeVal>,
+    ) -> Result<(), EditError> {
+        let cell = cell.map(|c| c.to_owned());
+        let previous = attributes_mut(&mut self.module, &cell)?.get(name).cloned();
+        self.record(Edit::SetAttribute {
+            cell,
+            name: name.to_owned(),
+            value,
+            previous,
+        })
+    }
+
+    /// Whether there is an edit to undo
+    pub fn can_undo(&self) -> bool {
+        self.position > 0
+    }
+
+    /// Whether there is an undone edit to redo
+    pub fn can_redo(&self) -> bool {
+        self.position < self.edits.len()
+    }
+
+    /// Undo the most recent edit, returning false if there is none
+    pub fn undo(&mut self) -> Result<bool, EditError> {
+        if !self.can_undo() {
+            return Ok(false);
+        }
+        self.edits[self.position - 1].revert(&mut self.module)?;
+        self.position -= 1;
+        Ok(true)
+    }
+
+    /// Redo the most recently undone edit, returning false if there is none
+    ///
+    /// Making a new edit discards everything that could be redone.
+    pub fn redo(&mut self) -> Result<bool, EditError> {
+        if !self.can_redo() {
+            return Ok(false);
+        }
+        self.edits[self.position].apply(&mut self.module)?;
+        self.position += 1;
+        Ok(true)
+    }
+
+    /// Remember the current state
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            position: self.position,
+            hash: module_hash(&self.module).ok(),
+        }
+    }
+
+    /// Undo or redo edits until the history is back at `checkpoint`
+    ///
+    /// Returns false, without changing anything, if the checkpoint is past the end
+    /// of the redo history.
+    pub fn restore(&mut self, checkpoint: &Checkpoint) -> Result<bool, EditError> {
+        if checkpoint.position > self.edits.len() {
+            return Ok(false);
+        }
+        while self.position > checkpoint.position {
+            self.undo()?;
+        }
+        while self.position < checkpoint.position {
+            self.redo()?;
+        }
+        Ok(true)
+    }
+
+    /// Whether the module is at the same point in history as `checkpoint` and has the
+    /// same contents
+    ///
+    /// Returns false if the module was changed without going through the journal, or
+    /// if its contents couldn't be hashed.
+    pub fn verify_checkpoint(&self, checkpoint: &Checkpoint) -> bool {
+        self.position == checkpoint.position
+            && checkpoint.hash.is_some()
+            && module_hash(&self.module).ok() == checkpoint.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Netlist;
+
+    fn test_module() -> Module {
+        let netlist = Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+                "top": {
+                  "attributes": { "top": 1 },
+                  "ports": {
+                    "a": { "direction": "input", "bits": [ 2, 3 ] },
+                    "y": { "direction": "output", "bits": [ 4, 5 ] }
+                  },
+                  "cells": {
+                    "inv": {
+                      "type": "$not",
+                      "connections": { "A": [ 2, 3 ], "Y": [ 4, 5 ] }
+                    }
+                  },
+                  "netnames": {
+                    "a": { "bits": [ 2, 3 ] },
+                    "y": { "bits": [ 4, 5 ] }
+                  }
+                }
+              }
+            }"#,
+        )
+        .unwrap();
+        netlist.modules["top"].clone()
+    }
+
+    #[test]
+    fn undo_redo_test() {
+        let original = test_module();
+        let mut rec = Recorder::new(original.clone());
+        let start = rec.checkpoint();
+
+        rec.rename_cell("inv", "u_inv").unwrap();
+        rec.set_attribute(Some("u_inv"), "keep", Some(AttributeVal::N(1)))
+            .unwrap();
+        rec.set_attribute(None, "top", None).unwrap();
+        assert_eq!(rec.remap_bits(&[(2, 10), (4, 11)].into_iter().collect()), 6);
+        rec.rename_net("a", "a_in").unwrap();
+        let cell = rec.remove_cell("u_inv").unwrap();
+        let edited = rec.module().clone();
+
+        assert_eq!(cell.attributes.get("keep"), Some(&AttributeVal::N(1)));
+        assert!(!edited.attributes.contains_key("top"));
+        assert_eq!(edited.ports["a"].bits, [BitVal::N(10), BitVal::N(3)]);
+        assert_eq!(edited.netnames["a_in"].bits, [BitVal::N(10), BitVal::N(3)]);
+        assert!(edited.cells.is_empty());
+
+        assert_eq!(
+            rec.rename_cell("missing", "x"),
+            Err(EditError::CellNotFound("missing".to_owned()))
+        );
+        assert_eq!(rec.add_cell("inv", cell.clone()), Ok(()));
+        assert_eq!(
+            rec.add_cell("inv", cell),
+            Err(EditError::DuplicateCell("inv".to_owned()))
+        );
+        assert_eq!(rec.journal().len(), 7);
+
+        assert!(rec.undo().unwrap());
+        assert_eq!(rec.module(), &edited);
+        let end = rec.checkpoint();
+
+        assert!(rec.restore(&start).unwrap());
+        assert_eq!(rec.module(), &original);
+        assert!(rec.verify_checkpoint(&start));
+        assert!(!rec.undo().unwrap());
+
+        assert!(rec.redo().unwrap());
+        assert!(rec.module().cells.contains_key("u_inv"));
+        assert!(rec.restore(&end).unwrap());
+        assert_eq!(rec.module(), &edited);
+        assert!(rec.verify_checkpoint(&end));
+
+        // A new edit discards the redo history
+        assert!(rec.can_redo());
+        rec.rename_net("y", "y_out").unwrap();
+        assert!(!rec.can_redo());
+        assert!(!rec
+            .restore(&Checkpoint {
+                position: 8,
+                hash: None
+            })
+            .unwrap());
+    }
+
+    #[test]
+    fn module_hash_order_test() {
+        let keys = ["src", "keep", "a", "zzz", "init", "b"];
+        let mut forward = test_module();
+        let mut backward = test_module();
+        for key in keys {
+            forward.set_attribute(key, AttributeVal::N(1));
+        }
+        for key in keys.iter().rev() {
+            backward.set_attribute(*key, AttributeVal::N(1));
+        }
+        assert_eq!(forward, backward);
+        assert_eq!(
+            module_hash(&forward).unwrap(),
+            module_hash(&backward).unwrap()
+        );
+        assert_ne!(
+            module_hash(&forward).unwrap(),
+            module_hash(&test_module()).unwrap()
+        );
+    }
+
+    #[test]
+    fn replay_test() {
+        let original = test_module();
+        let mut rec = Recorder::new(original.clone());
+        rec.rename_cell("inv", "u_inv").unwrap();
+        rec.remap_bits(&[(3, 2)].into_iter().collect());
+        rec.set_attribute(
+            Some("u_inv"),
+            "src",
+            Some(AttributeVal::S("a.v:1".to_owned())),
+        )
+        .unwrap();
+
+        let json = serde_json::to_string(rec.journal()).unwrap();
+        let edits: Vec<Edit> = serde_json::from_str(&json).unwrap();
+        assert_eq!(edits, rec.journal());
+
+        let mut copy = original;
+        Edit::replay(&mut copy, &edits).unwrap();
+        assert_eq!(&copy, rec.module());
+    }
+
+    #[test]
+    fn verify_checkpoint_test() {
+        let mut rec = Recorder::new(test_module());
+        let checkpoint = rec.checkpoint();
+        assert!(rec.verify_checkpoint(&checkpoint));
+
+        rec.module_mut().netnames.remove("y");
+        assert!(!rec.verify_checkpoint(&checkpoint));
+    }
+}
@@ -1,6 +1,89 @@
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{hash_map, BTreeMap, BTreeSet, HashMap};
 use std::io::{Read, Write};
+use std::ops::Range;
+
+mod annotations;
+mod autoname;
+pub mod codegen;
+mod conn_matrix;
+mod connectivity;
+mod cost;
+mod error;
+mod format;
+pub mod invariants;
+mod journal;
+mod library;
+mod lint;
+mod parse;
+mod pass;
+mod paths;
+mod query;
+mod search;
+mod sim;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
+mod timing;
+mod undef;
+mod visit;
+
+pub use annotations::{Annotations, ObjectPath};
+pub use autoname::{AutoName, SourceLocation};
+pub use conn_matrix::{ConnMatrix, ConnMatrixOptions};
+pub use connectivity::{CellPortBit, ConnectivityIndex, Endpoint, ModulePortBit, NetClass};
+pub use cost::{CellCost, ClassCost, CostReport, CostTable, ModuleCost, ResourceClass};
+#[cfg(feature = "simd-json")]
+pub use error::SimdError;
+pub use error::{
+    AnnotationError, CloneError, CutError, CycleError, DanglingInstance, DanglingRefs, EditError,
+    Error, FlipError, InsertError, MergeError, ParseError, PathError, RemoveError, SimError,
+    SplitError,
+};
+pub use format::YosysFormatter;
+pub use journal::{Checkpoint, Edit, Recorder};
+pub use library::{CellLibrary, LibraryCell, LibraryPort, LibraryWidth};
+pub use lint::{Lint, LintFinding, LintReport, LintRule, RuleFindings, Severity, WAIVE_ATTRIBUTE};
+pub use parse::{ModuleAction, ParseOptions};
+pub use pass::{
+    CompactSignalIdsPass, Merge1BitPortsPass, NetlistPass, PassContext, PassError, PassManager,
+    PassReport, PassRun, PassStats, SplitWidePortsPass, StripAttributesPass,
+};
+pub use paths::{NetPath, NetPathHop, PathOptions};
+pub use query::{Comparison, Query, Selection};
+pub use search::{NamePattern, NetMatch, NetSearchOptions};
+pub use sim::{Simulator, VectorFormat, VectorMismatch, VectorReport, VectorResult};
+pub use timing::{DelayTable, PathKind, Timing, TimingHop, TimingPath};
+pub use undef::{UndefBit, UndefFilter, UndefLocation, UndefParameter, UndefReport};
+pub use visit::{AttributeOwner, NetlistVisitor, NetlistVisitorMut};
+
+/// Kind of storage element a Yosys cell type is
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum SequentialKind {
+    /// An edge-triggered flip-flop, e.g. `$dff` or `$_DFFE_PP_`
+    Register,
+    /// A level-sensitive latch, e.g. `$dlatch`, `$sr` or `$_DLATCH_P_`
+    Latch,
+}
+
+impl SequentialKind {
+    /// Classify a cell type, coarse-grain or gate-level; `None` for anything that
+    /// holds no state
+    ///
+    /// This is the classifier used throughout the crate to decide where
+    /// combinational logic ends.
+    pub fn of_cell_type(cell_type: &str) -> Option<SequentialKind> {
+        let name = cell_type.strip_prefix('$')?;
+        let name = name.trim_start_matches('_').to_ascii_lowercase();
+        let starts_with = |prefixes: &[&str]| prefixes.iter().any(|x| name.starts_with(x));
+        if starts_with(&["dff", "adff", "aldff", "sdff", "ff"]) {
+            Some(SequentialKind::Register)
+        } else if starts_with(&["dlatch", "adlatch"]) || name == "sr" || name.starts_with("sr_") {
+            Some(SequentialKind::Latch)
+        } else {
+            None
+        }
+    }
+}
 
 /// Legal values for the direction of a port on a module
 #[derive(Copy, Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
@@ -13,6 +96,52 @@ pub enum PortDirection {
     InOut,
 }
 
+impl PortDirection {
+    /// The Verilog keyword for this direction: `input`, `output`, or `inout`
+    ///
+    /// These are the same strings used in the JSON format.
+    pub fn to_verilog(&self) -> &'static str {
+        match self {
+            PortDirection::Input => "input",
+            PortDirection::Output => "output",
+            PortDirection::InOut => "inout",
+        }
+    }
+
+    /// The direction as seen from the other side of the port
+    ///
+    /// Input and output are swapped, inout stays inout.
+    pub fn flipped(&self) -> PortDirection {
+        match self {
+            PortDirection::Input => PortDirection::Output,
+            PortDirection::Output => PortDirection::Input,
+            PortDirection::InOut => PortDirection::InOut,
+        }
+    }
+
+    /// Whether a module port with this direction drives its net inside the module body
+    ///
+    /// An input port brings a value in from outside, so it acts as a driver for the
+    /// logic inside the module. An output port is driven by the module's own logic.
+    pub fn drives_net_inside(&self) -> bool {
+        match self {
+            PortDirection::Input | PortDirection::InOut => true,
+            PortDirection::Output => false,
+        }
+    }
+
+    /// Whether a cell port with this direction drives the net it is connected to
+    ///
+    /// This is the view from the module containing the cell, where an output of the
+    /// instantiated module drives the connected net and an input is a sink.
+    pub fn drives_net_from_instance(&self) -> bool {
+        match self {
+            PortDirection::Output | PortDirection::InOut => true,
+            PortDirection::Input => false,
+        }
+    }
+}
+
 /// Special constant bit values
 #[derive(Copy, Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
 pub enum SpecialBit {
@@ -39,16 +168,16 @@ impl slog::Value for SpecialBit {
         serializer: &mut dyn slog::Serializer,
     ) -> slog::Result {
         match self {
-            &SpecialBit::_0 => serializer.emit_str(key, "0"),
-            &SpecialBit::_1 => serializer.emit_str(key, "1"),
-            &SpecialBit::X => serializer.emit_str(key, "x"),
-            &SpecialBit::Z => serializer.emit_str(key, "z"),
+            SpecialBit::_0 => serializer.emit_str(key, "0"),
+            SpecialBit::_1 => serializer.emit_str(key, "1"),
+            SpecialBit::X => serializer.emit_str(key, "x"),
+            SpecialBit::Z => serializer.emit_str(key, "z"),
         }
     }
 }
 
 /// A number representing a single bit of a wire
-#[derive(Copy, Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Serialize, Debug, Eq, PartialEq, Hash)]
 #[serde(untagged)]
 pub enum BitVal {
     /// An actual signal number
@@ -57,6 +186,46 @@ pub enum BitVal {
     S(SpecialBit),
 }
 
+// Written by hand instead of derived as untagged, which would buffer every bit and
+// give an error that doesn't say what the bad value was
+impl<'de> serde::Deserialize<'de> for BitVal {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BitValVisitor;
+
+        impl serde::de::Visitor<'_> for BitValVisitor {
+            type Value = BitVal;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(r#"a signal number or one of "0", "1", "x", "z""#)
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<BitVal, E> {
+                usize::try_from(v)
+                    .map(BitVal::N)
+                    .map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(v), &self))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<BitVal, E> {
+                usize::try_from(v)
+                    .map(BitVal::N)
+                    .map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(v), &self))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<BitVal, E> {
+                Ok(BitVal::S(match v {
+                    "0" => SpecialBit::_0,
+                    "1" => SpecialBit::_1,
+                    "x" => SpecialBit::X,
+                    "z" => SpecialBit::Z,
+                    _ => return Err(E::invalid_value(serde::de::Unexpected::Str(v), &self)),
+                }))
+            }
+        }
+
+        deserializer.deserialize_any(BitValVisitor)
+    }
+}
+
 #[cfg(feature = "slog")]
 impl slog::Value for BitVal {
     fn serialize(
@@ -66,14 +235,17 @@ impl slog::Value for BitVal {
         serializer: &mut dyn slog::Serializer,
     ) -> slog::Result {
         match self {
-            &BitVal::N(n) => serializer.emit_usize(key, n),
-            &BitVal::S(s) => s.serialize(record, key, serializer),
+            BitVal::N(n) => serializer.emit_usize(key, *n),
+            BitVal::S(s) => s.serialize(record, key, serializer),
         }
     }
 }
 
+/// A sorted set of signal numbers
+pub type SignalSet = BTreeSet<usize>;
+
 /// The value of an attribute/parameter
-#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Serialize, Debug, Eq, PartialEq, Hash)]
 #[serde(untagged)]
 pub enum AttributeVal {
     /// Numeric attribute value
@@ -82,13 +254,85 @@ pub enum AttributeVal {
     S(String),
 }
 
+// Written by hand for the same reasons as for `BitVal`
+impl<'de> serde::Deserialize<'de> for AttributeVal {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AttributeValVisitor;
+
+        impl AttributeValVisitor {
+            fn found<E: serde::de::Error>(what: &str) -> E {
+                E::custom(format_args!(
+                    "expected integer or string parameter value, found {}",
+                    what
+                ))
+            }
+        }
+
+        impl<'de> serde::de::Visitor<'de> for AttributeValVisitor {
+            type Value = AttributeVal;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("integer or string parameter value")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<AttributeVal, E> {
+                usize::try_from(v)
+                    .map(AttributeVal::N)
+                    .map_err(|_| Self::found("integer out of range"))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<AttributeVal, E> {
+                usize::try_from(v)
+                    .map(AttributeVal::N)
+                    .map_err(|_| Self::found("negative integer"))
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, _v: f64) -> Result<AttributeVal, E> {
+                Err(Self::found("floating point number"))
+            }
+
+            fn visit_bool<E: serde::de::Error>(self, _v: bool) -> Result<AttributeVal, E> {
+                Err(Self::found("boolean"))
+            }
+
+            fn visit_unit<E: serde::de::Error>(self) -> Result<AttributeVal, E> {
+                Err(Self::found("null"))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<AttributeVal, E> {
+                Ok(AttributeVal::S(v.to_owned()))
+            }
+
+            fn visit_string<E: serde::de::Error>(self, v: String) -> Result<AttributeVal, E> {
+                Ok(AttributeVal::S(v))
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                _seq: A,
+            ) -> Result<AttributeVal, A::Error> {
+                Err(Self::found("array"))
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                _map: A,
+            ) -> Result<AttributeVal, A::Error> {
+                Err(Self::found("object"))
+            }
+        }
+
+        deserializer.deserialize_any(AttributeValVisitor)
+    }
+}
+
 impl AttributeVal {
     pub fn to_number(&self) -> Option<usize> {
         match self {
-            &AttributeVal::N(n) => Some(n),
-            &AttributeVal::S(ref s) => {
+            AttributeVal::N(n) => Some(*n),
+            AttributeVal::S(s) => {
                 // If it's an empty string, the value was zero
-                if s.len() == 0 {
+                if s.is_empty() {
                     Some(0)
                 } else {
                     usize::from_str_radix(s, 2).ok()
@@ -99,9 +343,9 @@ impl AttributeVal {
 
     pub fn to_string_if_string(&self) -> Option<&str> {
         match self {
-            &AttributeVal::N(_) => None,
-            &AttributeVal::S(ref s) => {
-                if s.len() == 0 {
+            AttributeVal::N(_) => None,
+            AttributeVal::S(s) => {
+                if s.is_empty() {
                     // If it's an empty string then it wasn't originally a string
                     None
                 } else if s
@@ -121,6 +365,62 @@ impl AttributeVal {
             }
         }
     }
+
+    /// Interpret this value as a boolean, accepting only the literal values 1 and 0
+    ///
+    /// Any other value (including multi-bit binary strings) returns `None`.
+    pub fn as_bool_explicit(&self) -> Option<bool> {
+        match self {
+            AttributeVal::N(1) => Some(true),
+            AttributeVal::N(0) => Some(false),
+            AttributeVal::S(s) if s == "1" => Some(true),
+            AttributeVal::S(s) if s == "0" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Convert to a canonical form, so that values meaning the same thing compare equal
+    ///
+    /// Binary strings of only `0` and `1` that fit in a `usize` become numbers, and the
+    /// trailing space Yosys adds to string values is dropped unless the string would
+    /// then look like a binary string. Binary strings containing `x` or `z` are kept.
+    pub fn normalize(&self) -> AttributeVal {
+        let is_bits = |s: &str| s.chars().all(|c| matches!(c, '0' | '1' | 'x' | 'z'));
+        match self {
+            AttributeVal::N(n) => AttributeVal::N(*n),
+            AttributeVal::S(s) if is_bits(s) => match self.to_number() {
+                Some(n) => AttributeVal::N(n),
+                None => AttributeVal::S(s.clone()),
+            },
+            AttributeVal::S(s) => match s.strip_suffix(' ') {
+                Some(stripped) if !is_bits(stripped) => AttributeVal::S(stripped.to_owned()),
+                _ => AttributeVal::S(s.clone()),
+            },
+        }
+    }
+
+    /// Whether two values are equal after `normalize`
+    pub fn normalized_eq(&self, other: &AttributeVal) -> bool {
+        self.normalize() == other.normalize()
+    }
+}
+
+impl From<usize> for AttributeVal {
+    fn from(n: usize) -> Self {
+        AttributeVal::N(n)
+    }
+}
+
+impl From<&str> for AttributeVal {
+    fn from(s: &str) -> Self {
+        AttributeVal::S(s.to_owned())
+    }
+}
+
+impl From<String> for AttributeVal {
+    fn from(s: String) -> Self {
+        AttributeVal::S(s)
+    }
 }
 
 #[cfg(feature = "slog")]
@@ -132,8 +432,8 @@ impl slog::Value for AttributeVal {
         serializer: &mut dyn slog::Serializer,
     ) -> slog::Result {
         match self {
-            &AttributeVal::N(n) => serializer.emit_usize(key, n),
-            &AttributeVal::S(ref s) => serializer.emit_str(key, s),
+            AttributeVal::N(n) => serializer.emit_usize(key, *n),
+            AttributeVal::S(s) => serializer.emit_str(key, s),
         }
     }
 }
@@ -147,16 +447,19 @@ pub struct Netlist {
     /// A map from module names to module objects contained in this .json file
     #[serde(default)]
     pub modules: HashMap<String, Module>,
+    /// Fields this crate doesn't know about, kept so that they survive a round trip
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
 /// Represents one module in the Yosys hierarchy
 #[derive(Clone, Serialize, Deserialize, Debug, Default, Eq, PartialEq)]
 pub struct Module {
     /// Module attributes (Verilog `(* attr *)`)
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub attributes: HashMap<String, AttributeVal>,
     /// Module parameter (Verilog `parameter`) default values
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub parameter_default_values: HashMap<String, AttributeVal>,
     /// Module ports (interfaces to other modules)
     #[serde(default)]
@@ -165,11 +468,14 @@ pub struct Module {
     #[serde(default)]
     pub cells: HashMap<String, Cell>,
     /// Module memories
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub memories: HashMap<String, Memory>,
     /// Module netnames (names of wires in this module)
     #[serde(default)]
     pub netnames: HashMap<String, Netname>,
+    /// Fields this crate doesn't know about, kept so that they survive a round trip
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
 /// Represents a port on a module
@@ -180,14 +486,70 @@ pub struct Port {
     /// Bit value(s) representing the wire(s) connected to this port
     pub bits: Vec<BitVal>,
     /// Bit offset for mapping to HDL bit numbering
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub offset: usize,
     /// Whether or not HDL bit numbering is MSB-first
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub upto: usize,
     /// Whether or not HDL considers value signed
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub signed: usize,
+    /// Fields this crate doesn't know about, kept so that they survive a round trip
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// Ordering used when listing the ports of a module
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PortOrder {
+    /// Sort by port name
+    Alphabetical,
+    /// Inputs first, then outputs, then inouts, each sorted by name
+    ByDirection,
+    /// Sort by the numeric value of the given attribute on the port's netname
+    ///
+    /// Ports without the attribute are placed after all other ports, sorted by name.
+    ByAttribute(String),
+}
+
+/// Complete description of one port of a module
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PortDesc {
+    /// Port name
+    pub name: String,
+    /// Port direction
+    pub direction: PortDirection,
+    /// Number of bits in the port
+    pub width: usize,
+    /// HDL index of the most significant (leftmost) bit
+    pub msb: usize,
+    /// HDL index of the least significant (rightmost) bit
+    pub lsb: usize,
+    /// Whether or not HDL considers value signed
+    pub signed: bool,
+    /// Bit value(s) representing the wire(s) connected to this port
+    pub bits: Vec<BitVal>,
+}
+
+impl PortDesc {
+    fn new(name: &str, port: &Port) -> Self {
+        let width = port.bits.len();
+        let high = port.offset + width.saturating_sub(1);
+        let (msb, lsb) = if port.upto != 0 {
+            (port.offset, high)
+        } else {
+            (high, port.offset)
+        };
+        Self {
+            name: name.to_owned(),
+            direction: port.direction,
+            width,
+            msb,
+            lsb,
+            signed: port.signed != 0,
+            bits: port.bits.clone(),
+        }
+    }
 }
 
 /// Represents a cell in a module
@@ -200,16 +562,19 @@ pub struct Cell {
     #[serde(rename = "type")]
     pub cell_type: String,
     /// Parameters specified on this cell
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub parameters: HashMap<String, AttributeVal>,
     /// Attributes specified on this cell
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub attributes: HashMap<String, AttributeVal>,
     /// The direction of the ports on this cell
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub port_directions: HashMap<String, PortDirection>,
     /// Bit value(s) representing the wire(s) connected to the inputs/outputs of this cell
     pub connections: HashMap<String, Vec<BitVal>>,
+    /// Fields this crate doesn't know about, kept so that they survive a round trip
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
 /// Represents a memory in a module
@@ -219,7 +584,7 @@ pub struct Memory {
     #[serde(default)]
     pub hide_name: usize,
     /// Attributes for this memory
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub attributes: HashMap<String, AttributeVal>,
     /// Memory width
     pub width: usize,
@@ -228,6 +593,173 @@ pub struct Memory {
     /// Lowest valid memory address
     #[serde(default)]
     pub start_offset: usize,
+    /// Fields this crate doesn't know about, kept so that they survive a round trip
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// A problem with the address of a memory port cell found by
+/// `Module::validate_memory_ports`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum MemoryPortIssue {
+    /// The `ABITS` parameter is too small to address every word of the memory
+    AbitsTooSmall {
+        cell: String,
+        memory: String,
+        abits: usize,
+        required: usize,
+    },
+    /// The `ADDR` connection is too narrow to reach the highest address of the memory
+    AddressTooNarrow {
+        cell: String,
+        memory: String,
+        width: usize,
+        required: usize,
+    },
+}
+
+/// The cells accessing one memory, as returned by `Module::memory_ports`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemoryPorts<'a> {
+    /// `$memrd` and `$memrd_v2` cells, sorted by name
+    pub read: Vec<(&'a str, &'a Cell)>,
+    /// `$memwr` and `$memwr_v2` cells, sorted by name
+    pub write: Vec<(&'a str, &'a Cell)>,
+    /// `$meminit` and `$meminit_v2` cells, sorted by name
+    pub init: Vec<(&'a str, &'a Cell)>,
+}
+
+/// The cells accessing every memory of a module, as returned by `Module::memory_map`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemoryMap<'a> {
+    /// Ports of each memory in the module
+    pub memories: HashMap<&'a str, MemoryPorts<'a>>,
+    /// Memory cells whose `MEMID` doesn't match any memory, sorted by name
+    pub orphans: Vec<&'a str>,
+}
+
+/// A problem with the parameters of a cell found by `Module::check_parameter_consistency`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ParameterWarning {
+    /// The cell has a parameter that its cell type doesn't use
+    Unrecognized { cell: String, parameter: String },
+    /// The cell is missing a parameter that its cell type requires
+    Missing { cell: String, parameter: String },
+}
+
+/// A cell whose `port_directions` disagree with the module it instantiates, found by
+/// `Netlist::verify_module_interface_consistency`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct InterfaceError {
+    /// Module containing the cell
+    pub module: String,
+    /// Name of the cell
+    pub cell: String,
+    /// Port with the wrong direction
+    pub port: String,
+    /// Direction of the port on the instantiated module
+    pub expected: PortDirection,
+    /// Direction recorded on the cell
+    pub found: PortDirection,
+}
+
+/// A signal with several drivers, found by `Netlist::verify_no_multi_drivers`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MultiDriverError {
+    /// Module containing the signal
+    pub module: String,
+    /// The signal number
+    pub signal: usize,
+    /// Each driver, such as ``cell `u0` port `Y[0]` `` or ``port `a[0]` ``, sorted
+    pub drivers: Vec<String>,
+}
+
+/// Options for `Netlist::merge_subset`
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct SubsetMergeOptions {
+    /// Keep each replaced module under a new name (`<name>$old`, or `<name>$old2` and
+    /// so on if that is taken) instead of discarding it
+    pub keep_old: bool,
+}
+
+/// Result of `Netlist::merge_subset`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SubsetMergeReport {
+    /// Whether the modules were merged; `false` if there were interface breaks
+    pub merged: bool,
+    /// Existing modules that were replaced, sorted
+    pub replaced: Vec<String>,
+    /// Modules that did not exist before, sorted
+    pub added: Vec<String>,
+    /// Old and new names of the replaced modules that were kept, sorted
+    pub kept_old: Vec<(String, String)>,
+    /// Instances in untouched modules whose `port_directions` disagree with the
+    /// modified modules
+    pub interface_errors: Vec<InterfaceError>,
+    /// Instances in untouched modules that connect ports the modified modules don't
+    /// have, with the wrong width, or leave new ports unconnected, sorted
+    pub instance_errors: Vec<invariants::InvariantViolation>,
+}
+
+/// Attribute set by `Netlist::remove_module_and_refs` on cells that instantiated the
+/// removed module, holding the module's name
+pub const TOMBSTONE_ATTRIBUTE: &str = "removed_module";
+
+/// What `Netlist::retain_modules` removed
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RemovedSummary {
+    /// Names of the removed modules, sorted
+    pub modules: Vec<String>,
+    /// Total number of cells in the removed modules
+    pub cells: usize,
+    /// Removed modules that are still instantiated by a kept module, sorted; only
+    /// non-empty if dangling references were allowed
+    pub dangling_cell_types: Vec<String>,
+}
+
+// Yosys only writes `offset`, `upto`, and `signed` when they're set
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// Sort the keys of every object in a JSON value, recursively
+fn sort_object_keys(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Array(values) => values.iter_mut().for_each(sort_object_keys),
+        serde_json::Value::Object(map) => {
+            let mut entries = std::mem::take(map).into_iter().collect::<Vec<_>>();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (_, value) in &mut entries {
+                sort_object_keys(value);
+            }
+            map.extend(entries);
+        }
+        _ => {}
+    }
+}
+
+/// Parameters of the internal Yosys cell types that are checked by
+/// `Module::check_parameter_consistency`
+fn expected_parameters(cell_type: &str) -> Option<&'static [&'static str]> {
+    const UNARY: &[&str] = &["A_SIGNED", "A_WIDTH", "Y_WIDTH"];
+    const BINARY: &[&str] = &["A_SIGNED", "A_WIDTH", "B_SIGNED", "B_WIDTH", "Y_WIDTH"];
+
+    Some(match cell_type {
+        "$not" | "$pos" | "$neg" | "$reduce_and" | "$reduce_or" | "$reduce_xor"
+        | "$reduce_xnor" | "$reduce_bool" | "$logic_not" => UNARY,
+        "$and" | "$or" | "$xor" | "$xnor" | "$shl" | "$shr" | "$sshl" | "$sshr" | "$shift"
+        | "$shiftx" | "$lt" | "$le" | "$eq" | "$ne" | "$eqx" | "$nex" | "$ge" | "$gt" | "$add"
+        | "$sub" | "$mul" | "$div" | "$mod" | "$divfloor" | "$modfloor" | "$pow" | "$logic_and"
+        | "$logic_or" => BINARY,
+        "$mux" | "$tribuf" => &["WIDTH"],
+        "$pmux" | "$bmux" | "$demux" => &["WIDTH", "S_WIDTH"],
+        "$dff" => &["WIDTH", "CLK_POLARITY"],
+        "$dffe" => &["WIDTH", "CLK_POLARITY", "EN_POLARITY"],
+        "$adff" => &["WIDTH", "CLK_POLARITY", "ARST_POLARITY", "ARST_VALUE"],
+        "$sdff" => &["WIDTH", "CLK_POLARITY", "SRST_POLARITY", "SRST_VALUE"],
+        "$dlatch" => &["WIDTH", "EN_POLARITY"],
+        _ => return None,
+    })
 }
 
 /// Represents the name of a net in a module
@@ -239,17 +771,105 @@ pub struct Netname {
     /// Bit value(s) that should be given this name
     pub bits: Vec<BitVal>,
     /// Bit offset for mapping to HDL bit numbering
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub offset: usize,
     /// Whether or not HDL bit numbering is MSB-first
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub upto: usize,
     /// Whether or not HDL considers value signed
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub signed: usize,
     /// Attributes for this netname
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub attributes: HashMap<String, AttributeVal>,
+    /// Fields this crate doesn't know about, kept so that they survive a round trip
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// Accessors shared by objects that give HDL bit numbering to a vector of bits
+///
+/// This is implemented by both `Port` and `Netname`.
+pub trait HdlVector {
+    /// Bit value(s), least significant bit first
+    fn bits(&self) -> &[BitVal];
+    /// Bit offset for mapping to HDL bit numbering
+    fn offset(&self) -> usize;
+    /// Whether or not HDL bit numbering is MSB-first
+    fn is_upto(&self) -> bool;
+
+    /// Number of bits
+    fn width(&self) -> usize {
+        self.bits().len()
+    }
+
+    /// Whether this has more than one bit
+    fn is_bus(&self) -> bool {
+        self.width() > 1
+    }
+
+    /// Convert an index into `bits()` into the HDL index of that bit
+    fn to_hdl_index(&self, idx: usize) -> isize {
+        if self.is_upto() {
+            self.offset() as isize + self.width() as isize - 1 - idx as isize
+        } else {
+            self.offset() as isize + idx as isize
+        }
+    }
+
+    /// Look up a bit by its HDL index
+    fn bit(&self, hdl_index: isize) -> Option<BitVal> {
+        let idx = if self.is_upto() {
+            self.offset() as isize + self.width() as isize - 1 - hdl_index
+        } else {
+            hdl_index - self.offset() as isize
+        };
+        usize::try_from(idx)
+            .ok()
+            .and_then(|idx| self.bits().get(idx).copied())
+    }
+
+    /// Iterate over `(hdl_index, bit)` pairs in declaration (left to right) order
+    fn iter_hdl_indexed(&self) -> impl Iterator<Item = (isize, BitVal)> + '_ {
+        self.bits()
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(idx, &bit)| (self.to_hdl_index(idx), bit))
+    }
+}
+
+impl HdlVector for Port {
+    fn bits(&self) -> &[BitVal] {
+        &self.bits
+    }
+    fn offset(&self) -> usize {
+        self.offset
+    }
+    fn is_upto(&self) -> bool {
+        self.upto != 0
+    }
+}
+
+impl HdlVector for Netname {
+    fn bits(&self) -> &[BitVal] {
+        &self.bits
+    }
+    fn offset(&self) -> usize {
+        self.offset
+    }
+    fn is_upto(&self) -> bool {
+        self.upto != 0
+    }
+}
+
+/// Read netlist data from a string containing a Yosys .json file
+impl std::str::FromStr for Netlist {
+    type Err = serde_json::Error;
+
+    fn from_str(input: &str) -> Result<Netlist, serde_json::Error> {
+        serde_json::from_str(input)
+    }
 }
 
 impl Netlist {
@@ -258,6 +878,7 @@ impl Netlist {
         Self {
             creator: creator.to_owned(),
             modules: HashMap::new(),
+            extra: HashMap::new(),
         }
     }
 
@@ -271,6 +892,17 @@ impl Netlist {
         serde_json::from_slice(input)
     }
 
+    /// Read netlist data from a JSON value, e.g. one that was put together from
+    /// several files
+    pub fn from_value(value: serde_json::Value) -> Result<Netlist, serde_json::Error> {
+        serde_json::from_value(value)
+    }
+
+    /// Serialize to a JSON value
+    pub fn to_value(&self) -> Result<serde_json::Value, serde_json::Error> {
+        serde_json::to_value(self)
+    }
+
     /// Serialize to a String
     pub fn to_string(&self) -> Result<String, serde_json::Error> {
         serde_json::to_string(self)
@@ -280,88 +912,1830 @@ impl Netlist {
     pub fn to_writer<W: Write>(&self, writer: W) -> Result<(), serde_json::Error> {
         serde_json::to_writer(writer, self)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Serialize to a String with the keys of every object in lexicographic order
+    ///
+    /// The output is the same for equal netlists, whatever order their maps were
+    /// filled in. Struct fields are sorted along with the map keys.
+    pub fn to_string_sorted(&self) -> Result<String, serde_json::Error> {
+        let mut out = Vec::new();
+        self.to_writer_sorted(&mut out)?;
+        // serde_json only writes valid UTF-8
+        Ok(String::from_utf8(out).unwrap())
+    }
 
-    #[test]
-    fn super_empty_json() {
-        let result = Netlist::from_slice(
-            br#"
-            {}"#,
-        )
-        .unwrap();
-        assert_eq!(result.creator, "");
-        assert_eq!(result.modules.len(), 0);
+    /// Serialize to a writer with the keys of every object in lexicographic order
+    pub fn to_writer_sorted<W: Write>(&self, writer: W) -> Result<(), serde_json::Error> {
+        format::write_sorted(self, writer, serde_json::ser::CompactFormatter)
     }
 
-    #[test]
-    fn empty_json() {
-        let result = Netlist::from_slice(
-            br#"
-            {
-              "creator": "this is a test",
-              "modules": {
-              }
-            }"#,
-        )
-        .unwrap();
-        assert_eq!(result.creator, "this is a test");
-        assert_eq!(result.modules.len(), 0);
+    /// Serialize to a pretty-printed String, indenting by `indent` spaces (default 2)
+    pub fn to_string_pretty(&self, indent: Option<usize>) -> Result<String, serde_json::Error> {
+        let mut out = Vec::new();
+        self.to_writer_pretty(&mut out, indent)?;
+        // serde_json only writes valid UTF-8
+        Ok(String::from_utf8(out).unwrap())
     }
 
-    #[test]
-    fn empty_json_2() {
-        let result = Netlist::from_slice(
-            br#"
-            {
-              "modules": {
-              }
-            }"#,
-        )
-        .unwrap();
-        assert_eq!(result.creator, "");
-        assert_eq!(result.modules.len(), 0);
+    /// Serialize to a writer as pretty-printed JSON, indenting by `indent` spaces
+    /// (default 2)
+    pub fn to_writer_pretty<W: Write>(
+        &self,
+        writer: W,
+        indent: Option<usize>,
+    ) -> Result<(), serde_json::Error> {
+        let indent = " ".repeat(indent.unwrap_or(2));
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+        let mut serializer = serde_json::Serializer::with_formatter(writer, formatter);
+        serde::Serialize::serialize(self, &mut serializer)
     }
 
-    #[test]
-    fn bit_values_test() {
-        let result = Netlist::from_slice(
-            br#"
-            {
-              "modules": {
-                "mymodule": {
-                  "cells": {
-                    "mycell": {
-                      "type": "celltype",
-                      "connections": {
-                        "IN": [ "x", 0, "z", 234, "1", "0" ]
-                      }
-                    }
-                  }
-                }
-              }
-            }"#,
-        )
-        .unwrap();
-        assert_eq!(
-            result
-                .modules
-                .get("mymodule")
-                .unwrap()
-                .cells
-                .get("mycell")
-                .unwrap()
-                .connections
-                .get("IN")
-                .unwrap(),
-            &vec![
-                BitVal::S(SpecialBit::X),
-                BitVal::N(0),
-                BitVal::S(SpecialBit::Z),
-                BitVal::N(234),
+    /// Whether `creator` looks like it was written by Yosys: `Yosys ` followed by a
+    /// version number such as `0.14+51`
+    pub fn verify_creator_format(&self) -> bool {
+        let Some(version) = self.creator.strip_prefix("Yosys ") else {
+            return false;
+        };
+        let version = version.split(' ').next().unwrap_or("");
+        let mut parts = version.split(['.', '+']);
+        parts.clone().count() >= 2
+            && parts.all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+    }
+
+    /// Read netlist data from the file at `path`
+    pub fn from_path(path: impl AsRef<std::path::Path>) -> Result<Netlist, PathError> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path).map_err(|source| PathError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        Self::from_reader(std::io::BufReader::new(file)).map_err(|source| PathError::Json {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    /// Write netlist data to the file at `path`, replacing it if it exists
+    pub fn to_path(&self, path: impl AsRef<std::path::Path>) -> Result<(), PathError> {
+        let path = path.as_ref();
+        let io_error = |source| PathError::Io {
+            path: path.to_owned(),
+            source,
+        };
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path).map_err(io_error)?);
+        self.to_writer(&mut writer)
+            .map_err(|source| PathError::Json {
+                path: path.to_owned(),
+                source,
+            })?;
+        writer.flush().map_err(io_error)
+    }
+
+    /// Write each module to `{dir}/{file name}.json`, as a netlist containing just
+    /// that module, returning the number of files written
+    ///
+    /// `dir` is created if it doesn't exist. File names come from
+    /// `split_file_name`, so every file stays inside `dir`. If two modules would get
+    /// the same file name, ignoring case, nothing is written and
+    /// `Error::FileNameCollision` is returned.
+    pub fn write_split(&self, dir: impl AsRef<std::path::Path>) -> Result<usize, Error> {
+        let mut files = BTreeMap::new();
+        let mut names = self.modules.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let file_name = Self::split_file_name(name);
+            if let Some(other) = files.insert(file_name.to_lowercase(), name) {
+                return Err(Error::FileNameCollision(other.clone(), name.clone()));
+            }
+        }
+
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        for (name, module) in &self.modules {
+            let single = Netlist {
+                creator: self.creator.clone(),
+                modules: HashMap::from([(name.clone(), module.clone())]),
+                extra: self.extra.clone(),
+            };
+            let file_name = format!("{}.json", Self::split_file_name(name));
+            let file = std::fs::File::create(dir.join(file_name))?;
+            let mut writer = std::io::BufWriter::new(file);
+            single.to_writer(&mut writer)?;
+            writer.flush()?;
+        }
+        Ok(self.modules.len())
+    }
+
+    /// The file name, without `.json`, that `write_split` uses for a module
+    ///
+    /// ASCII letters, digits, `_`, `-` and `.` are kept, except for a leading `.`.
+    /// Every other byte of the name is written as `%XX` in hex, so `$paramod\foo\W=8`
+    /// becomes `%24paramod%5Cfoo%5CW%3D8`. Different module names always give
+    /// different file names.
+    pub fn split_file_name(module_name: &str) -> String {
+        let mut out = String::with_capacity(module_name.len());
+        for (i, byte) in module_name.bytes().enumerate() {
+            let keep = byte.is_ascii_alphanumeric()
+                || byte == b'_'
+                || byte == b'-'
+                || (byte == b'.' && i != 0);
+            if keep {
+                out.push(byte as char);
+            } else {
+                out.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        out
+    }
+
+    /// Count the cells of each type across every module, with
+    /// `Module::count_by_type`
+    ///
+    /// Each module is counted once, however many times it is instantiated.
+    pub fn cell_count_globally_by_type(&self) -> HashMap<&str, usize> {
+        let mut counts = HashMap::new();
+        for module in self.modules.values() {
+            for (cell_type, count) in module.count_by_type() {
+                *counts.entry(cell_type).or_insert(0) += count;
+            }
+        }
+        counts
+    }
+
+    /// Get the module `name`, adding an empty module if there isn't one
+    pub fn get_or_create_module(&mut self, name: &str) -> &mut Module {
+        self.modules.entry(name.to_owned()).or_default()
+    }
+
+    /// Copy the module `src` and add the copy as `new_name`
+    pub fn clone_module(&mut self, src: &str, new_name: &str) -> Result<&mut Module, CloneError> {
+        if self.modules.contains_key(new_name) {
+            return Err(CloneError::DuplicateName);
+        }
+        let module = self
+            .modules
+            .get(src)
+            .ok_or(CloneError::SourceNotFound)?
+            .clone();
+        Ok(self.modules.entry(new_name.to_owned()).or_insert(module))
+    }
+
+    /// Split a multi-bit port of module `module` into 1-bit ports with
+    /// `Module::split_wide_ports`, and update the connections of every instance of the
+    /// module to match
+    ///
+    /// Nothing is changed if an instance connects the port with a different width.
+    pub fn split_wide_ports(
+        &mut self,
+        module: &str,
+        port_name: &str,
+    ) -> Result<Vec<String>, SplitError> {
+        let width = self
+            .modules
+            .get(module)
+            .ok_or(SplitError::ModuleNotFound)?
+            .ports
+            .get(port_name)
+            .map(|port| port.bits.len());
+        if let Some(width) = width {
+            for (parent_name, parent) in &self.modules {
+                for (cell_name, cell) in &parent.cells {
+                    let conn = cell.connections.get(port_name);
+                    if cell.cell_type == module && conn.is_some_and(|x| x.len() != width) {
+                        return Err(SplitError::WidthMismatch {
+                            module: parent_name.clone(),
+                            cell: cell_name.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        let new_ports = self
+            .modules
+            .get_mut(module)
+            .ok_or(SplitError::ModuleNotFound)?
+            .split_wide_ports(port_name)?;
+
+        for parent in self.modules.values_mut() {
+            for cell in parent.cells.values_mut() {
+                if cell.cell_type != module {
+                    continue;
+                }
+                if let Some(bits) = cell.connections.remove(port_name) {
+                    for (new_port, bit) in new_ports.iter().zip(bits) {
+                        cell.connections.insert(new_port.clone(), vec![bit]);
+                    }
+                }
+                if let Some(dir) = cell.port_directions.remove(port_name) {
+                    for new_port in &new_ports {
+                        cell.port_directions.insert(new_port.clone(), dir);
+                    }
+                }
+            }
+        }
+        Ok(new_ports)
+    }
+
+    /// Merge 1-bit ports of module `module` into a bus with `Module::merge_1bit_ports`,
+    /// and update the connections of every instance of the module to match
+    ///
+    /// Ports that an instance leaves unconnected are connected to `x` on the bus.
+    pub fn merge_1bit_ports(
+        &mut self,
+        module: &str,
+        new_name: &str,
+        ports: &[&str],
+    ) -> Result<(), MergeError> {
+        self.modules
+            .get_mut(module)
+            .ok_or(MergeError::ModuleNotFound)?
+            .merge_1bit_ports(new_name, ports)?;
+
+        for parent in self.modules.values_mut() {
+            for cell in parent.cells.values_mut() {
+                if cell.cell_type != module {
+                    continue;
+                }
+                // Unconnected ports become `x` so that later bits keep their index
+                if ports
+                    .iter()
+                    .any(|port| cell.connections.contains_key(*port))
+                {
+                    let bits = ports
+                        .iter()
+                        .flat_map(|port| {
+                            cell.connections
+                                .remove(*port)
+                                .unwrap_or_else(|| vec![BitVal::S(SpecialBit::X)])
+                        })
+                        .collect();
+                    cell.connections.insert(new_name.to_owned(), bits);
+                }
+                let mut dir = None;
+                for &port in ports {
+                    dir = cell.port_directions.remove(port).or(dir);
+                }
+                if let Some(dir) = dir {
+                    cell.port_directions.insert(new_name.to_owned(), dir);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sort the keys of every JSON object kept in `extra` fields
+    ///
+    /// Only those objects keep an order (with serde_json's `preserve_order` feature);
+    /// the other maps are `HashMap`s, and bit vectors are never reordered because
+    /// the order of bits is significant everywhere it appears. Signal numbers are left
+    /// alone; use `Module::compact_signal_ids` to renumber them as a separate step.
+    /// Use `to_string_canonical` for byte-for-byte stable output.
+    pub fn canonicalize(&mut self) {
+        let mut extras = vec![&mut self.extra];
+        for module in self.modules.values_mut() {
+            extras.push(&mut module.extra);
+            extras.extend(module.ports.values_mut().map(|x| &mut x.extra));
+            extras.extend(module.cells.values_mut().map(|x| &mut x.extra));
+            extras.extend(module.memories.values_mut().map(|x| &mut x.extra));
+            extras.extend(module.netnames.values_mut().map(|x| &mut x.extra));
+        }
+        for value in extras.into_iter().flat_map(HashMap::values_mut) {
+            sort_object_keys(value);
+        }
+    }
+
+    /// Serialize a canonicalized copy of the netlist with sorted keys
+    ///
+    /// Equal netlists give exactly the same bytes, whatever order their maps were
+    /// filled in.
+    pub fn to_string_canonical(&self) -> Result<String, serde_json::Error> {
+        let mut canonical = self.clone();
+        canonical.canonicalize();
+        canonical.to_string_sorted()
+    }
+
+    /// Add missing port netnames to every module with `Module::ensure_port_netnames`
+    ///
+    /// Returns the total number of netnames created.
+    pub fn ensure_port_netnames(&mut self) -> usize {
+        self.modules
+            .values_mut()
+            .map(Module::ensure_port_netnames)
+            .sum()
+    }
+
+    /// Number of modules
+    pub fn module_count(&self) -> usize {
+        self.modules.len()
+    }
+
+    /// Whether the netlist contains any modules
+    pub fn has_any_modules(&self) -> bool {
+        !self.modules.is_empty()
+    }
+
+    /// Guess the name of the top module
+    ///
+    /// This is the module with a non-zero `top` attribute, which `yosys hierarchy -top`
+    /// sets. Failing that, it's the only module that isn't a blackbox and isn't
+    /// instantiated by another module. Returns `None` if there's no single answer.
+    pub fn top_module(&self) -> Option<&str> {
+        let marked = self
+            .modules
+            .iter()
+            .filter(|(_, module)| {
+                module
+                    .attributes
+                    .get("top")
+                    .and_then(AttributeVal::to_number)
+                    .is_some_and(|top| top != 0)
+            })
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        let candidates = if marked.is_empty() {
+            let instantiated = self
+                .modules
+                .values()
+                .flat_map(|module| self.submodule_counts(module).into_keys())
+                .collect::<BTreeSet<_>>();
+            self.modules
+                .iter()
+                .filter(|(name, module)| {
+                    !instantiated.contains(name.as_str())
+                        && !module.attributes.contains_key("blackbox")
+                })
+                .map(|(name, _)| name.as_str())
+                .collect()
+        } else {
+            marked
+        };
+        match candidates[..] {
+            [top] => Some(top),
+            _ => None,
+        }
+    }
+
+    /// Whether `top_module` finds a top module
+    pub fn has_top_module(&self) -> bool {
+        self.top_module().is_some()
+    }
+
+    /// Count the instances of each module of this netlist that are directly instantiated
+    /// inside `module`
+    fn submodule_counts(&self, module: &Module) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for cell in module.cells.values() {
+            if let Some((name, _)) = self.modules.get_key_value(&cell.cell_type) {
+                *counts.entry(name.as_str()).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    /// List module names so that every module comes after all modules it instantiates
+    ///
+    /// Modules that could go in either order are sorted by name, so the result is
+    /// deterministic.
+    pub fn modules_in_dependency_order(&self) -> Result<Vec<&String>, CycleError> {
+        let mut deps = self
+            .modules
+            .iter()
+            .map(|(name, module)| {
+                let submodules = self
+                    .submodule_counts(module)
+                    .into_keys()
+                    .collect::<BTreeSet<_>>();
+                (name.as_str(), submodules)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let mut order = Vec::with_capacity(deps.len());
+        while let Some(name) = deps
+            .iter()
+            .find(|(_, submodules)| submodules.is_empty())
+            .map(|(&name, _)| name)
+        {
+            deps.remove(name);
+            for submodules in deps.values_mut() {
+                submodules.remove(name);
+            }
+            order.push(self.modules.get_key_value(name).unwrap().0);
+        }
+
+        if !deps.is_empty() {
+            return Err(CycleError {
+                modules: deps.into_keys().map(|name| name.to_owned()).collect(),
+            });
+        }
+        Ok(order)
+    }
+
+    /// List module names so that every module comes before all modules it instantiates
+    ///
+    /// This is the reverse of `modules_in_dependency_order`.
+    pub fn modules_in_reverse_dependency_order(&self) -> Result<Vec<&String>, CycleError> {
+        let mut order = self.modules_in_dependency_order()?;
+        order.reverse();
+        Ok(order)
+    }
+
+    /// Draw the module instantiation hierarchy below `root` as a tree
+    ///
+    /// Each line shows a module name followed by the number of times it is
+    /// instantiated in its parent.
+    pub fn print_hierarchy(&self, root: &str) -> Result<String, Error> {
+        fn visit<'a>(
+            netlist: &'a Netlist,
+            name: &'a str,
+            prefix: &str,
+            stack: &mut Vec<&'a str>,
+            out: &mut String,
+        ) -> Result<(), Error> {
+            if stack.contains(&name) {
+                return Err(Error::HierarchyCycle(name.to_owned()));
+            }
+            stack.push(name);
+
+            let counts = netlist.submodule_counts(&netlist.modules[name]);
+            for (i, (submodule, count)) in counts.iter().enumerate() {
+                let last = i == counts.len() - 1;
+                out.push('\n');
+                out.push_str(prefix);
+                out.push_str(if last { "└── " } else { "├── " });
+                out.push_str(submodule);
+                out.push_str(&format!(" x{}", count));
+                let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+                visit(netlist, submodule, &prefix, stack, out)?;
+            }
+
+            stack.pop();
+            Ok(())
+        }
+
+        let (root, _) = self
+            .modules
+            .get_key_value(root)
+            .ok_or_else(|| Error::ModuleNotFound(root.to_owned()))?;
+        let mut out = root.clone();
+        visit(self, root, "", &mut Vec::new(), &mut out)?;
+        Ok(out)
+    }
+
+    /// Check that the `port_directions` of every instance of a module of this netlist
+    /// match the directions of that module's ports
+    ///
+    /// Ports missing from either side are not reported. Errors are sorted by module,
+    /// cell, and port.
+    pub fn verify_module_interface_consistency(&self) -> Vec<InterfaceError> {
+        let mut errors = Vec::new();
+        for (module_name, module) in &self.modules {
+            interface_errors(module_name, module, &self.modules, &mut errors);
+        }
+        errors.sort_by(|a, b| (&a.module, &a.cell, &a.port).cmp(&(&b.module, &b.cell, &b.port)));
+        errors
+    }
+
+    /// Check that no signal in any module has more than one driver
+    ///
+    /// Drivers are as defined by `ConnectivityIndex::exclusive_drivers`: module input
+    /// ports and cell outputs, but not inout ports, so tri-state buses aren't reported.
+    /// This is the same rule as `invariants::unique_driver_per_bit`. Errors are sorted
+    /// by module and signal.
+    pub fn verify_no_multi_drivers(&self) -> Vec<MultiDriverError> {
+        let mut errors = Vec::new();
+        for (module_name, module) in &self.modules {
+            let index = module.connectivity();
+            for signal in index.multiply_driven_signals() {
+                errors.push(MultiDriverError {
+                    module: module_name.clone(),
+                    signal,
+                    drivers: index
+                        .exclusive_drivers(signal)
+                        .map(ToString::to_string)
+                        .collect(),
+                });
+            }
+        }
+        errors.sort_by(|a, b| (&a.module, a.signal).cmp(&(&b.module, b.signal)));
+        errors
+    }
+
+    /// Replace modules with the versions in `modified`, which is usually a subset of
+    /// this design that was exported and rewritten by another tool
+    ///
+    /// Modules of `modified` that don't exist here are added. Before anything is
+    /// changed, instances of the modified modules inside the untouched modules are
+    /// checked the same way as `verify_module_interface_consistency` and
+    /// `invariants::instances_resolve`. Ports that an instance leaves unconnected are
+    /// only reported if the old version of the module didn't have them. If anything
+    /// disagrees, nothing is merged and the report lists the breaks.
+    pub fn merge_subset(
+        &mut self,
+        modified: Netlist,
+        opts: SubsetMergeOptions,
+    ) -> SubsetMergeReport {
+        let mut report = SubsetMergeReport::default();
+        for (module_name, module) in &self.modules {
+            if !modified.modules.contains_key(module_name) {
+                interface_errors(
+                    module_name,
+                    module,
+                    &modified.modules,
+                    &mut report.interface_errors,
+                );
+                self.instance_port_errors(
+                    module_name,
+                    module,
+                    &modified.modules,
+                    &mut report.instance_errors,
+                );
+            }
+        }
+        if !report.interface_errors.is_empty() || !report.instance_errors.is_empty() {
+            report
+                .interface_errors
+                .sort_by(|a, b| (&a.module, &a.cell, &a.port).cmp(&(&b.module, &b.cell, &b.port)));
+            report.instance_errors.sort_by_key(ToString::to_string);
+            return report;
+        }
+
+        for (name, module) in modified.modules {
+            match self.modules.insert(name.clone(), module) {
+                Some(old) => {
+                    if opts.keep_old {
+                        let mut old_name = format!("{}$old", name);
+                        let mut i = 2;
+                        while self.modules.contains_key(&old_name) {
+                            old_name = format!("{}$old{}", name, i);
+                            i += 1;
+                        }
+                        self.modules.insert(old_name.clone(), old);
+                        report.kept_old.push((name.clone(), old_name));
+                    }
+                    report.replaced.push(name);
+                }
+                None => report.added.push(name),
+            }
+        }
+        report.merged = true;
+        report.replaced.sort();
+        report.added.sort();
+        report.kept_old.sort();
+        report
+    }
+
+    /// Check instances in `module` of the modules in `targets` for ports that are
+    /// unknown, have the wrong width, or are new and unconnected
+    fn instance_port_errors(
+        &self,
+        module_name: &str,
+        module: &Module,
+        targets: &HashMap<String, Module>,
+        errors: &mut Vec<invariants::InvariantViolation>,
+    ) {
+        for (cell_name, cell) in &module.cells {
+            let Some(target) = targets.get(&cell.cell_type) else {
+                continue;
+            };
+            let port_width = |port: &str| target.ports.get(port).map(|p| Some(p.bits.len()));
+            let old = self.modules.get(&cell.cell_type);
+            let missing = target
+                .ports
+                .keys()
+                .filter(|port| !cell.connections.contains_key(*port))
+                .filter(|port| !old.is_some_and(|old| old.ports.contains_key(*port)))
+                .map(|port| invariants::ViolationKind::MissingInstancePort {
+                    cell: cell_name.clone(),
+                    port: port.clone(),
+                });
+            errors.extend(
+                invariants::connection_violations(cell_name, cell, port_width)
+                    .chain(missing)
+                    .map(|kind| invariants::InvariantViolation {
+                        module: Some(module_name.to_owned()),
+                        kind,
+                    }),
+            );
+        }
+    }
+
+    /// Remove the named module, returning it if it existed
+    ///
+    /// Cells instantiating the module are left alone; see `remove_module_and_refs`.
+    pub fn remove_module(&mut self, name: &str) -> Option<Module> {
+        self.modules.remove(name)
+    }
+
+    /// Remove the named module and mark every cell that instantiated it
+    ///
+    /// The cells are kept, with the `TOMBSTONE_ATTRIBUTE` attribute set to the name of
+    /// the removed module, so they can be found and replaced later.
+    pub fn remove_module_and_refs(&mut self, name: &str) -> Result<Module, RemoveError> {
+        let removed = self
+            .modules
+            .remove(name)
+            .ok_or_else(|| RemoveError::ModuleNotFound(name.to_owned()))?;
+        for module in self.modules.values_mut() {
+            for cell in module.cells.values_mut() {
+                if cell.cell_type == name {
+                    cell.attributes
+                        .insert(TOMBSTONE_ATTRIBUTE.to_owned(), AttributeVal::from(name));
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Remove every module for which `keep` returns false
+    ///
+    /// If a kept module instantiates a removed one, nothing is removed and the
+    /// offending instances are returned, unless `allow_dangling` is set. Then the
+    /// modules are removed anyway and the dangling cell types are listed in the
+    /// summary, for example so that blackboxes can be created for them.
+    pub fn retain_modules(
+        &mut self,
+        keep: impl Fn(&str, &Module) -> bool,
+        allow_dangling: bool,
+    ) -> Result<RemovedSummary, DanglingRefs> {
+        let removed = self
+            .modules
+            .iter()
+            .filter(|(name, module)| !keep(name, module))
+            .map(|(name, _)| name.clone())
+            .collect::<BTreeSet<_>>();
+
+        let mut instances = Vec::new();
+        for (module_name, module) in &self.modules {
+            if removed.contains(module_name) {
+                continue;
+            }
+            for (cell_name, cell) in &module.cells {
+                if removed.contains(&cell.cell_type) {
+                    instances.push(DanglingInstance {
+                        module: module_name.clone(),
+                        cell: cell_name.clone(),
+                        cell_type: cell.cell_type.clone(),
+                    });
+                }
+            }
+        }
+        if !instances.is_empty() && !allow_dangling {
+            instances.sort_by(|a, b| (&a.module, &a.cell).cmp(&(&b.module, &b.cell)));
+            return Err(DanglingRefs { instances });
+        }
+
+        let mut summary = RemovedSummary::default();
+        for name in removed {
+            summary.cells += self.modules.remove(&name).unwrap().cells.len();
+            summary.modules.push(name);
+        }
+        summary.dangling_cell_types = instances
+            .into_iter()
+            .map(|inst| inst.cell_type)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        Ok(summary)
+    }
+
+    /// Find the ports in every module that have the attribute `key`
+    ///
+    /// Ports don't carry attributes themselves, so the attributes of the netname with
+    /// the same name as the port are used. Returns `(module, port name, port)`,
+    /// sorted by module and port name.
+    pub fn find_ports_by_attribute<'a>(&'a self, key: &str) -> Vec<(&'a str, &'a str, &'a Port)> {
+        let mut found = Vec::new();
+        for (module_name, module) in &self.modules {
+            for (port_name, port) in &module.ports {
+                if module
+                    .netnames
+                    .get(port_name)
+                    .is_some_and(|netname| netname.attributes.contains_key(key))
+                {
+                    found.push((module_name.as_str(), port_name.as_str(), port));
+                }
+            }
+        }
+        found.sort_by_key(|&(module, port, _)| (module, port));
+        found
+    }
+
+    /// Find the memories in every module that have the attribute `key`
+    ///
+    /// Returns `(module, memory name, memory)`, sorted by module and memory name.
+    pub fn find_memories_by_attribute<'a>(
+        &'a self,
+        key: &str,
+    ) -> Vec<(&'a str, &'a str, &'a Memory)> {
+        let mut found = Vec::new();
+        for (module_name, module) in &self.modules {
+            for (memory_name, memory) in &module.memories {
+                if memory.attributes.contains_key(key) {
+                    found.push((module_name.as_str(), memory_name.as_str(), memory));
+                }
+            }
+        }
+        found.sort_by_key(|&(module, memory, _)| (module, memory));
+        found
+    }
+}
+
+/// Check instances in `module` of the modules in `targets` for port direction mismatches
+fn interface_errors(
+    module_name: &str,
+    module: &Module,
+    targets: &HashMap<String, Module>,
+    errors: &mut Vec<InterfaceError>,
+) {
+    for (cell_name, cell) in &module.cells {
+        let Some(target) = targets.get(&cell.cell_type) else {
+            continue;
+        };
+        for (port_name, &found) in &cell.port_directions {
+            let Some(port) = target.ports.get(port_name) else {
+                continue;
+            };
+            if port.direction != found {
+                errors.push(InterfaceError {
+                    module: module_name.to_owned(),
+                    cell: cell_name.clone(),
+                    port: port_name.clone(),
+                    expected: port.direction,
+                    found,
+                });
+            }
+        }
+    }
+}
+
+impl Module {
+    /// Create a blackbox module with only the ports of this module
+    ///
+    /// Netnames belonging to ports are kept, everything else (cells, memories, other
+    /// netnames, parameters) is dropped.
+    pub fn extract_interface(&self) -> Module {
+        let mut module = Module {
+            ports: self.ports.clone(),
+            netnames: self
+                .netnames
+                .iter()
+                .filter(|(name, _)| self.ports.contains_key(*name))
+                .map(|(name, netname)| (name.clone(), netname.clone()))
+                .collect(),
+            ..Default::default()
+        };
+        module
+            .attributes
+            .insert("blackbox".to_owned(), AttributeVal::N(1));
+        module
+    }
+
+    /// List the ports of this module in the requested order
+    pub fn interface_ordered(&self, order: PortOrder) -> Vec<PortDesc> {
+        let mut ports = self
+            .ports
+            .iter()
+            .map(|(name, port)| PortDesc::new(name, port))
+            .collect::<Vec<_>>();
+
+        match order {
+            PortOrder::Alphabetical => ports.sort_by(|a, b| a.name.cmp(&b.name)),
+            PortOrder::ByDirection => {
+                let dir_idx = |dir: PortDirection| match dir {
+                    PortDirection::Input => 0,
+                    PortDirection::Output => 1,
+                    PortDirection::InOut => 2,
+                };
+                ports.sort_by(|a, b| {
+                    (dir_idx(a.direction), &a.name).cmp(&(dir_idx(b.direction), &b.name))
+                })
+            }
+            PortOrder::ByAttribute(attr) => {
+                let attr_idx = |name: &str| {
+                    self.netnames
+                        .get(name)
+                        .and_then(|netname| netname.attributes.get(&attr))
+                        .and_then(|val| val.to_number())
+                        .unwrap_or(usize::MAX)
+                };
+                ports
+                    .sort_by(|a, b| (attr_idx(&a.name), &a.name).cmp(&(attr_idx(&b.name), &b.name)))
+            }
+        }
+
+        ports
+    }
+
+    /// Iterate over all netnames with more than one bit
+    pub fn buses(&self) -> impl Iterator<Item = (&str, &Netname)> {
+        self.netnames
+            .iter()
+            .filter(|(_, netname)| netname.is_bus())
+            .map(|(name, netname)| (name.as_str(), netname))
+    }
+
+    /// All netnames with `hide_name == 0`, sorted by name
+    pub fn all_nets_named(&self) -> Vec<(&str, &Netname)> {
+        let mut nets = self
+            .netnames
+            .iter()
+            .filter(|(_, netname)| netname.hide_name == 0)
+            .map(|(name, netname)| (name.as_str(), netname))
+            .collect::<Vec<_>>();
+        nets.sort_by_key(|(name, _)| *name);
+        nets
+    }
+
+    /// Iterate over all netnames with exactly one bit
+    pub fn scalars(&self) -> impl Iterator<Item = (&str, &Netname)> {
+        self.netnames
+            .iter()
+            .filter(|(_, netname)| netname.width() == 1)
+            .map(|(name, netname)| (name.as_str(), netname))
+    }
+
+    /// Iterate over all flip-flop cells, as identified by `Cell::is_register`
+    pub fn registers(&self) -> impl Iterator<Item = (&str, &Cell)> {
+        self.cells
+            .iter()
+            .filter(|(_, cell)| cell.is_register())
+            .map(|(name, cell)| (name.as_str(), cell))
+    }
+
+    /// Iterate over all latch cells, as identified by `Cell::is_latch`
+    pub fn latches(&self) -> impl Iterator<Item = (&str, &Cell)> {
+        self.cells
+            .iter()
+            .filter(|(_, cell)| cell.is_latch())
+            .map(|(name, cell)| (name.as_str(), cell))
+    }
+
+    /// Iterate over the cells whose type matches a glob `pattern`, with
+    /// `Cell::matches_pattern`
+    pub fn cells_matching_pattern<'a>(
+        &'a self,
+        pattern: &str,
+    ) -> impl Iterator<Item = (&'a str, &'a Cell)> {
+        let pattern = pattern.to_owned();
+        self.cells
+            .iter()
+            .filter(move |(_, cell)| cell.matches_pattern(&pattern))
+            .map(|(name, cell)| (name.as_str(), cell))
+    }
+
+    /// Number of flip-flop cells
+    pub fn register_count(&self) -> usize {
+        self.registers().count()
+    }
+
+    /// Number of latch cells
+    pub fn latch_count(&self) -> usize {
+        self.latches().count()
+    }
+
+    /// Signals connected to any of `ports` of a flip-flop cell
+    fn register_port_signals(&self, ports: &[&str]) -> SignalSet {
+        self.registers()
+            .flat_map(|(_, cell)| {
+                cell.connections
+                    .iter()
+                    .filter(|(port, _)| ports.contains(&port.as_str()))
+                    .flat_map(|(_, bits)| bits)
+            })
+            .filter_map(|bit| match *bit {
+                BitVal::N(n) => Some(n),
+                BitVal::S(_) => None,
+            })
+            .collect()
+    }
+
+    /// Signals connected to the `CLK` or `C` port of a flip-flop cell
+    pub fn clock_signals(&self) -> SignalSet {
+        self.register_port_signals(&["CLK", "C"])
+    }
+
+    /// Signals connected to the `ARST` or `SRST` port of a flip-flop cell, or the `R`
+    /// port of a gate-level one
+    pub fn reset_signals(&self) -> SignalSet {
+        self.register_port_signals(&["ARST", "SRST", "R"])
+    }
+
+    /// Signals connected to the `EN` or `CE` port of a flip-flop cell, or the `E` port
+    /// of a gate-level one
+    pub fn enable_signals(&self) -> SignalSet {
+        self.register_port_signals(&["EN", "CE", "E"])
+    }
+
+    /// Collect the read, write, and init cells referencing the memory `name`
+    pub fn memory_ports(&self, name: &str) -> MemoryPorts<'_> {
+        let mut ports = MemoryPorts::default();
+        for (cell_name, cell) in &self.cells {
+            if cell.memid() == Some(name) {
+                ports.push(cell_name, cell);
+            }
+        }
+        ports.sort();
+        ports
+    }
+
+    /// Collect the read, write, and init cells of every memory in this module
+    ///
+    /// Memories without any cells referencing them get an empty entry.
+    pub fn memory_map(&self) -> MemoryMap<'_> {
+        let mut map = MemoryMap {
+            memories: self
+                .memories
+                .keys()
+                .map(|name| (name.as_str(), MemoryPorts::default()))
+                .collect(),
+            orphans: Vec::new(),
+        };
+        for (cell_name, cell) in &self.cells {
+            if MemoryPorts::kind(cell).is_none() {
+                continue;
+            }
+            match cell.memid().and_then(|memid| map.memories.get_mut(memid)) {
+                Some(ports) => ports.push(cell_name, cell),
+                None => map.orphans.push(cell_name),
+            }
+        }
+        for ports in map.memories.values_mut() {
+            ports.sort();
+        }
+        map.orphans.sort();
+        map
+    }
+
+    /// Check the address width of memory read/write port cells against their memories
+    ///
+    /// Cells that reference a memory which doesn't exist are ignored.
+    pub fn validate_memory_ports(&self) -> Vec<MemoryPortIssue> {
+        let mut issues = Vec::new();
+        for (cell_name, cell) in &self.cells {
+            if !matches!(
+                cell.cell_type.as_str(),
+                "$memrd" | "$memrd_v2" | "$memwr" | "$memwr_v2"
+            ) {
+                continue;
+            }
+            let Some((memory_name, memory)) = cell
+                .memid()
+                .and_then(|memid| self.memories.get_key_value(memid))
+            else {
+                continue;
+            };
+
+            let required = memory.addr_bits();
+            if let Some(abits) = cell.parameters.get("ABITS").and_then(|x| x.to_number()) {
+                if abits < required {
+                    issues.push(MemoryPortIssue::AbitsTooSmall {
+                        cell: cell_name.clone(),
+                        memory: memory_name.clone(),
+                        abits,
+                        required,
+                    });
+                }
+            }
+
+            let required = match memory.addr_range() {
+                Some(range) => bits_needed(range.end.saturating_sub(1)),
+                None => usize::BITS as usize,
+            };
+            if let Some(addr) = cell.connections.get("ADDR") {
+                if addr.len() < required {
+                    issues.push(MemoryPortIssue::AddressTooNarrow {
+                        cell: cell_name.clone(),
+                        memory: memory_name.clone(),
+                        width: addr.len(),
+                        required,
+                    });
+                }
+            }
+        }
+        issues
+    }
+
+    /// Call `f` on every bit of every port, cell connection, and netname
+    ///
+    /// Objects are visited in the order of `Module::accept_mut` (ports, then cells,
+    /// then netnames, each sorted by name) so the traversal is deterministic.
+    fn for_each_bit_mut(&mut self, f: impl FnMut(&mut BitVal)) {
+        self.accept_mut(&mut visit::BitVisitor(f));
+    }
+
+    /// All signals used by a port, cell connection, or netname
+    pub fn all_signal_ids(&self) -> SignalSet {
+        let ports = self.ports.values().map(|port| &port.bits);
+        let cells = self
+            .cells
+            .values()
+            .flat_map(|cell| cell.connections.values());
+        let netnames = self.netnames.values().map(|netname| &netname.bits);
+        ports
+            .chain(cells)
+            .chain(netnames)
+            .flatten()
+            .filter_map(|bit| match *bit {
+                BitVal::N(n) => Some(n),
+                BitVal::S(_) => None,
+            })
+            .collect()
+    }
+
+    /// Signals that aren't covered by any netname, hidden or not
+    pub fn signals_without_names(&self) -> SignalSet {
+        let mut signals = self.all_signal_ids();
+        for bit in self.netnames.values().flat_map(|netname| &netname.bits) {
+            if let BitVal::N(n) = bit {
+                signals.remove(n);
+            }
+        }
+        signals
+    }
+
+    /// Renumber all signals into a dense range starting at 2
+    ///
+    /// Numbers are assigned in order of first use, visiting ports, cells, and netnames
+    /// sorted by name, so modules that only differ in signal numbering end up
+    /// identical. Returns the mapping from old to new signal numbers.
+    pub fn compact_signal_ids(&mut self) -> HashMap<usize, usize> {
+        let mut mapping = HashMap::new();
+        self.for_each_bit_mut(|bit| {
+            if let BitVal::N(n) = bit {
+                let next = mapping.len() + 2;
+                *n = *mapping.entry(*n).or_insert(next);
+            }
+        });
+        mapping
+    }
+
+    /// Compare two modules, ignoring differences in signal numbering
+    ///
+    /// Plain `==` already ignores map ordering but treats renumbered signals as
+    /// different. This compares copies of both modules after `compact_signal_ids`.
+    pub fn is_structurally_equal(&self, other: &Module) -> bool {
+        let mut a = self.clone();
+        let mut b = other.clone();
+        a.compact_signal_ids();
+        b.compact_signal_ids();
+        a == b
+    }
+
+    /// Replace a multi-bit port with one 1-bit port per bit, named `{port_name}[i]` with
+    /// `i` the HDL index of the bit
+    ///
+    /// A netname with the same name as the port and the same width is split the same
+    /// way. Returns the names of the new ports, least significant bit first. Instances
+    /// of this module in other modules are not updated; use
+    /// `Netlist::split_wide_ports` for that.
+    pub fn split_wide_ports(&mut self, port_name: &str) -> Result<Vec<String>, SplitError> {
+        let port = self.ports.get(port_name).ok_or(SplitError::PortNotFound)?;
+        if port.bits.len() < 2 {
+            return Err(SplitError::NotMultiBit);
+        }
+        let new_ports = (0..port.bits.len())
+            .map(|i| format!("{}[{}]", port_name, port.to_hdl_index(i)))
+            .collect::<Vec<_>>();
+        let split_netname = self
+            .netnames
+            .get(port_name)
+            .is_some_and(|netname| netname.bits.len() == port.bits.len());
+        if let Some(name) = new_ports.iter().find(|x| {
+            self.ports.contains_key(*x) || (split_netname && self.netnames.contains_key(*x))
+        }) {
+            return Err(SplitError::NameConflict(name.clone()));
+        }
+
+        if split_netname {
+            let netname = self.netnames.remove(port_name).unwrap();
+            for (name, bit) in new_ports.iter().zip(&netname.bits) {
+                self.netnames.insert(
+                    name.clone(),
+                    Netname {
+                        hide_name: netname.hide_name,
+                        bits: vec![*bit],
+                        offset: 0,
+                        upto: 0,
+                        signed: 0,
+                        attributes: netname.attributes.clone(),
+                        extra: netname.extra.clone(),
+                    },
+                );
+            }
+        }
+
+        let port = self.ports.remove(port_name).unwrap();
+        for (name, bit) in new_ports.iter().zip(port.bits) {
+            self.ports.insert(
+                name.clone(),
+                Port {
+                    direction: port.direction,
+                    bits: vec![bit],
+                    offset: 0,
+                    upto: 0,
+                    signed: 0,
+                    extra: port.extra.clone(),
+                },
+            );
+        }
+        Ok(new_ports)
+    }
+
+    /// Replace several 1-bit ports with a single port `new_name`
+    ///
+    /// The first port in `ports` becomes the least significant bit. This is the
+    /// inverse of `split_wide_ports`. Instances of this module in other modules are
+    /// not updated; use `Netlist::merge_1bit_ports` for that.
+    pub fn merge_1bit_ports(&mut self, new_name: &str, ports: &[&str]) -> Result<(), MergeError> {
+        let mut direction = None;
+        let mut bits = Vec::with_capacity(ports.len());
+        for (i, &name) in ports.iter().enumerate() {
+            if ports[..i].contains(&name) {
+                return Err(MergeError::DuplicatePort(name.to_owned()));
+            }
+            let port = self
+                .ports
+                .get(name)
+                .ok_or_else(|| MergeError::PortNotFound(name.to_owned()))?;
+            if port.bits.len() != 1 {
+                return Err(MergeError::NotSingleBit(name.to_owned()));
+            }
+            if *direction.get_or_insert(port.direction) != port.direction {
+                return Err(MergeError::DirectionMismatch);
+            }
+            bits.push(port.bits[0]);
+        }
+        let direction = direction.ok_or(MergeError::NoPorts)?;
+        if self.ports.contains_key(new_name) && !ports.contains(&new_name) {
+            return Err(MergeError::NameConflict);
+        }
+
+        for name in ports {
+            self.ports.remove(*name);
+        }
+        self.ports.insert(
+            new_name.to_owned(),
+            Port {
+                direction,
+                bits,
+                offset: 0,
+                upto: 0,
+                signed: 0,
+                extra: HashMap::new(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Find cell ports with the same direction that are connected to exactly the same
+    /// bits, which can be left over from synthesis
+    ///
+    /// Nothing is removed; this returns `(cell, port_a, port_b)` with `port_a` before
+    /// `port_b`, sorted. Empty connections are ignored.
+    pub fn remove_duplicate_connections(&self) -> Vec<(String, String, String)> {
+        let mut duplicates = Vec::new();
+        for (cell_name, cell) in &self.cells {
+            let mut ports = cell
+                .connections
+                .iter()
+                .filter(|(_, bits)| !bits.is_empty())
+                .collect::<Vec<_>>();
+            ports.sort_by_key(|(name, _)| name.as_str());
+            for (i, (port_a, bits_a)) in ports.iter().enumerate() {
+                for (port_b, bits_b) in &ports[i + 1..] {
+                    if bits_a == bits_b
+                        && cell.port_directions.get(*port_a) == cell.port_directions.get(*port_b)
+                    {
+                        duplicates.push((cell_name.clone(), (*port_a).clone(), (*port_b).clone()));
+                    }
+                }
+            }
+        }
+        duplicates.sort();
+        duplicates
+    }
+
+    /// Count the cells of each type in this module
+    pub fn count_by_type(&self) -> HashMap<&str, usize> {
+        let mut counts = HashMap::new();
+        for cell in self.cells.values() {
+            *counts.entry(cell.cell_type.as_str()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// A one-line summary of this module, named `name`, for messages, e.g.
+    /// `top [3 ports, 1 cell]`
+    pub fn short_description(&self, name: &str) -> String {
+        let plural =
+            |n: usize, what: &str| format!("{} {}{}", n, what, if n == 1 { "" } else { "s" });
+        format!(
+            "{} [{}, {}]",
+            name,
+            plural(self.ports.len(), "port"),
+            plural(self.cells.len(), "cell")
+        )
+    }
+
+    /// Look up a cell by name
+    pub fn cell(&self, name: &str) -> Option<&Cell> {
+        self.cells.get(name)
+    }
+
+    /// Look up a port by name
+    pub fn port(&self, name: &str) -> Option<&Port> {
+        self.ports.get(name)
+    }
+
+    /// Look up a memory by name
+    pub fn memory(&self, name: &str) -> Option<&Memory> {
+        self.memories.get(name)
+    }
+
+    /// Look up a netname by name
+    pub fn netname(&self, name: &str) -> Option<&Netname> {
+        self.netnames.get(name)
+    }
+
+    /// Add a cell, replacing and returning any cell of the same name
+    pub fn set_cell(&mut self, name: impl Into<String>, cell: Cell) -> Option<Cell> {
+        self.cells.insert(name.into(), cell)
+    }
+
+    /// Add a port, replacing and returning any port of the same name
+    pub fn set_port(&mut self, name: impl Into<String>, port: Port) -> Option<Port> {
+        self.ports.insert(name.into(), port)
+    }
+
+    /// Swap a port between input and output, returning its old direction
+    ///
+    /// Inout ports stay inout. Instances of this module aren't updated.
+    pub fn flip_port_direction(&mut self, port_name: &str) -> Result<PortDirection, FlipError> {
+        let port = self
+            .ports
+            .get_mut(port_name)
+            .ok_or_else(|| FlipError::PortNotFound(port_name.to_owned()))?;
+        let old = port.direction;
+        port.direction = old.flipped();
+        Ok(old)
+    }
+
+    /// Add a memory, replacing and returning any memory of the same name
+    pub fn set_memory(&mut self, name: impl Into<String>, memory: Memory) -> Option<Memory> {
+        self.memories.insert(name.into(), memory)
+    }
+
+    /// Add a netname, replacing and returning any netname of the same name
+    pub fn set_netname(&mut self, name: impl Into<String>, netname: Netname) -> Option<Netname> {
+        self.netnames.insert(name.into(), netname)
+    }
+
+    /// Add a visible netname for `bits`, failing if the name is already taken
+    pub fn add_netname_for_bits(
+        &mut self,
+        name: impl Into<String>,
+        bits: Vec<BitVal>,
+    ) -> Result<&mut Netname, InsertError> {
+        match self.netnames.entry(name.into()) {
+            hash_map::Entry::Occupied(entry) => Err(InsertError::DuplicateNet(entry.key().clone())),
+            hash_map::Entry::Vacant(entry) => Ok(entry.insert(Netname {
+                hide_name: 0,
+                bits,
+                offset: 0,
+                upto: 0,
+                signed: 0,
+                attributes: HashMap::new(),
+                extra: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Set `hide_name` on every cell
+    pub fn set_hide_name_all_cells(&mut self, value: usize) {
+        for cell in self.cells.values_mut() {
+            cell.hide_name = value;
+        }
+    }
+
+    /// Set `hide_name` on every netname
+    pub fn set_hide_name_all_netnames(&mut self, value: usize) {
+        for netname in self.netnames.values_mut() {
+            netname.hide_name = value;
+        }
+    }
+
+    /// Set a module attribute, replacing any previous value
+    pub fn set_attribute(&mut self, key: impl Into<String>, value: AttributeVal) {
+        self.attributes.insert(key.into(), value);
+    }
+
+    /// Get a module attribute
+    pub fn get_attribute(&self, key: &str) -> Option<&AttributeVal> {
+        self.attributes.get(key)
+    }
+
+    /// Remove a module attribute, returning its value if it was set
+    pub fn remove_attribute(&mut self, key: &str) -> Option<AttributeVal> {
+        self.attributes.remove(key)
+    }
+
+    /// Add a netname for every port that doesn't have one of the same name
+    ///
+    /// The netname gets the port's bits, `offset`, `upto`, and `signed`, and is not
+    /// hidden. Existing netnames are left alone even if their bits differ. Returns
+    /// the number of netnames created.
+    pub fn ensure_port_netnames(&mut self) -> usize {
+        let mut count = 0;
+        for (name, port) in &self.ports {
+            if self.netnames.contains_key(name) {
+                continue;
+            }
+            self.netnames.insert(
+                name.clone(),
+                Netname {
+                    hide_name: 0,
+                    bits: port.bits.clone(),
+                    offset: port.offset,
+                    upto: port.upto,
+                    signed: port.signed,
+                    attributes: HashMap::new(),
+                    extra: HashMap::new(),
+                },
+            );
+            count += 1;
+        }
+        count
+    }
+
+    /// Remove all attributes from this module and its cells, memories, and netnames
+    ///
+    /// Returns the number of attributes removed.
+    pub fn strip_attributes(&mut self) -> usize {
+        fn strip(attributes: &mut HashMap<String, AttributeVal>) -> usize {
+            let count = attributes.len();
+            attributes.clear();
+            count
+        }
+
+        strip(&mut self.attributes)
+            + self
+                .cells
+                .values_mut()
+                .map(|cell| strip(&mut cell.attributes))
+                .sum::<usize>()
+            + self
+                .memories
+                .values_mut()
+                .map(|memory| strip(&mut memory.attributes))
+                .sum::<usize>()
+            + self
+                .netnames
+                .values_mut()
+                .map(|netname| strip(&mut netname.attributes))
+                .sum::<usize>()
+    }
+
+    /// Sum of `Cell::estimated_area` over all cells in this module
+    pub fn total_estimated_area(&self) -> usize {
+        self.cells.values().map(|cell| cell.estimated_area()).sum()
+    }
+
+    /// Check the parameters of internal cells against the parameters their types use
+    ///
+    /// Only the common combinational and flip-flop cell types are checked; cells of
+    /// other types (including instances of user modules) are skipped. Warnings are
+    /// sorted by cell type, then parameter name, then cell name.
+    pub fn check_parameter_consistency(&self) -> Vec<ParameterWarning> {
+        let mut warnings = Vec::new();
+        for (cell_name, cell) in &self.cells {
+            let Some(expected) = expected_parameters(&cell.cell_type) else {
+                continue;
+            };
+            for parameter in cell.parameters.keys() {
+                if !expected.contains(&parameter.as_str()) {
+                    warnings.push(ParameterWarning::Unrecognized {
+                        cell: cell_name.clone(),
+                        parameter: parameter.clone(),
+                    });
+                }
+            }
+            for &parameter in expected {
+                if !cell.parameters.contains_key(parameter) {
+                    warnings.push(ParameterWarning::Missing {
+                        cell: cell_name.clone(),
+                        parameter: parameter.to_owned(),
+                    });
+                }
+            }
+        }
+        warnings.sort_by_cached_key(|warning| {
+            let (ParameterWarning::Unrecognized { cell, parameter }
+            | ParameterWarning::Missing { cell, parameter }) = warning;
+            (
+                self.cells[cell].cell_type.clone(),
+                parameter.clone(),
+                cell.clone(),
+            )
+        });
+        warnings
+    }
+
+    /// Map from port name to port direction, in the same shape as `Cell::port_directions`
+    pub fn port_directions_map(&self) -> HashMap<&str, PortDirection> {
+        self.ports
+            .iter()
+            .map(|(name, port)| (name.as_str(), port.direction))
+            .collect()
+    }
+}
+
+impl Port {
+    /// Whether this port has exactly one bit
+    pub fn is_scalar(&self) -> bool {
+        self.bits.len() == 1
+    }
+
+    /// Whether this port has more than one bit
+    pub fn is_vector(&self) -> bool {
+        self.bits.len() > 1
+    }
+
+    /// Number of bits in this port
+    pub fn bit_count(&self) -> usize {
+        self.bits.len()
+    }
+}
+
+impl Cell {
+    /// Deep-copy this cell, renumbering signals in its connections according to `mapping`
+    ///
+    /// Signals not present in `mapping` are left unchanged.
+    pub fn clone_with_new_signals(&self, mapping: &HashMap<usize, usize>) -> Cell {
+        let mut cell = self.clone();
+        for bits in cell.connections.values_mut() {
+            for bit in bits.iter_mut() {
+                if let BitVal::N(n) = bit {
+                    if let Some(&new_n) = mapping.get(n) {
+                        *n = new_n;
+                    }
+                }
+            }
+        }
+        cell
+    }
+
+    /// Get the connection to `port`, inserting `default_bits` if it isn't connected
+    pub fn get_or_insert_connection(
+        &mut self,
+        port: &str,
+        default_bits: Vec<BitVal>,
+    ) -> &mut Vec<BitVal> {
+        self.connections
+            .entry(port.to_owned())
+            .or_insert(default_bits)
+    }
+
+    /// Get the parameter `name`, inserting `default` if it isn't set
+    pub fn get_or_default_parameter(
+        &mut self,
+        name: &str,
+        default: AttributeVal,
+    ) -> &mut AttributeVal {
+        self.parameters.entry(name.to_owned()).or_insert(default)
+    }
+
+    /// Convert the port names in `connections` and `port_directions` to upper case (or
+    /// lower case if `uppercase` is false)
+    ///
+    /// A key is left alone if the converted name is already in use. Returns the
+    /// number of keys renamed.
+    pub fn normalize_port_names(&mut self, uppercase: bool) -> usize {
+        fn rename<V>(map: &mut HashMap<String, V>, uppercase: bool) -> usize {
+            let keys = map.keys().cloned().collect::<Vec<_>>();
+            let mut count = 0;
+            for key in keys {
+                let new_key = if uppercase {
+                    key.to_uppercase()
+                } else {
+                    key.to_lowercase()
+                };
+                if new_key != key && !map.contains_key(&new_key) {
+                    let val = map.remove(&key).unwrap();
+                    map.insert(new_key, val);
+                    count += 1;
+                }
+            }
+            count
+        }
+
+        rename(&mut self.connections, uppercase) + rename(&mut self.port_directions, uppercase)
+    }
+
+    /// Whether this is a multiplexer (`$mux`, `$pmux`, or the gate-level `$_MUX_`)
+    pub fn is_mux(&self) -> bool {
+        matches!(self.cell_type.as_str(), "$mux" | "$pmux" | "$_MUX_")
+    }
+
+    /// The select (`S`) connection of a multiplexer, or `None` if this isn't one
+    pub fn mux_select_port(&self) -> Option<&[BitVal]> {
+        if !self.is_mux() {
+            return None;
+        }
+        self.connections.get("S").map(|x| x.as_slice())
+    }
+
+    /// Whether this is a `$lut` cell
+    pub fn is_lut(&self) -> bool {
+        self.cell_type == "$lut"
+    }
+
+    /// Rough estimate of the size of this cell, computed as the total number of
+    /// connected bits
+    pub fn estimated_area(&self) -> usize {
+        self.total_connection_bits()
+    }
+
+    /// A one-line summary of this cell for messages: the type and the width of each
+    /// connection, e.g. `$xor (A:8, B:8, Y:8)`
+    ///
+    /// The instance name isn't included, so callers can place it where they need it;
+    /// `_name` only keeps the signature the same as `Module::short_description`.
+    pub fn short_description(&self, _name: &str) -> String {
+        let mut ports = self.connections.iter().collect::<Vec<_>>();
+        ports.sort_by_key(|(port, _)| port.as_str());
+        let ports = ports
+            .iter()
+            .map(|(port, bits)| format!("{}:{}", port, bits.len()))
+            .collect::<Vec<_>>();
+        if ports.is_empty() {
+            self.cell_type.clone()
+        } else {
+            format!("{} ({})", self.cell_type, ports.join(", "))
+        }
+    }
+
+    /// Width of the widest connection, or 0 if the cell has no connections
+    pub fn max_connection_width(&self) -> usize {
+        self.connections
+            .values()
+            .map(|bits| bits.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Sum of the widths of all connections
+    pub fn total_connection_bits(&self) -> usize {
+        self.connections.values().map(|bits| bits.len()).sum()
+    }
+
+    /// What kind of storage element the cell is, with `SequentialKind::of_cell_type`
+    pub fn sequential_kind(&self) -> Option<SequentialKind> {
+        SequentialKind::of_cell_type(&self.cell_type)
+    }
+
+    /// Whether the cell is a Yosys flip-flop, coarse-grain or gate-level
+    pub fn is_register(&self) -> bool {
+        self.sequential_kind() == Some(SequentialKind::Register)
+    }
+
+    /// Remove all parameters whose name starts with `$`, returning how many were
+    /// removed
+    pub fn strip_internal_parameters(&mut self) -> usize {
+        let before = self.parameters.len();
+        self.parameters.retain(|key, _| !key.starts_with('$'));
+        before - self.parameters.len()
+    }
+
+    /// Remove all attributes whose name starts with `$`, returning how many were
+    /// removed
+    pub fn strip_internal_attributes(&mut self) -> usize {
+        let before = self.attributes.len();
+        self.attributes.retain(|key, _| !key.starts_with('$'));
+        before - self.attributes.len()
+    }
+
+    /// Iterate over the connected ports, with their direction if it's known
+    pub fn ports_iter(&self) -> impl Iterator<Item = (&str, Option<PortDirection>, &[BitVal])> {
+        self.connections.iter().map(|(name, bits)| {
+            (
+                name.as_str(),
+                self.port_directions.get(name).copied(),
+                bits.as_slice(),
+            )
+        })
+    }
+
+    /// The connection of the clock port (`CLK` or `C`) of a flip-flop cell
+    pub fn clock_port(&self) -> Option<&[BitVal]> {
+        if !self.is_register() {
+            return None;
+        }
+        self.connections
+            .get("CLK")
+            .or_else(|| self.connections.get("C"))
+            .map(Vec::as_slice)
+    }
+
+    /// The connection of the `D` port of a flip-flop cell
+    pub fn data_port(&self) -> Option<&[BitVal]> {
+        self.register_connection("D")
+    }
+
+    /// The connection of the `Q` port of a flip-flop cell
+    pub fn output_port(&self) -> Option<&[BitVal]> {
+        self.register_connection("Q")
+    }
+
+    fn register_connection(&self, port: &str) -> Option<&[BitVal]> {
+        if !self.is_register() {
+            return None;
+        }
+        self.connections.get(port).map(Vec::as_slice)
+    }
+
+    /// Whether the cell type matches a glob `pattern`, where `*` matches any run of
+    /// characters and `?` any single character
+    pub fn matches_pattern(&self, pattern: &str) -> bool {
+        search::glob_match(pattern, &self.cell_type, false)
+    }
+
+    /// Whether the cell is a Yosys latch, coarse-grain or gate-level
+    pub fn is_latch(&self) -> bool {
+        self.sequential_kind() == Some(SequentialKind::Latch)
+    }
+
+    /// Name of the memory referenced by the `MEMID` parameter of a memory cell
+    ///
+    /// The name is returned in the same form as the keys of `Module::memories`.
+    pub fn memid(&self) -> Option<&str> {
+        let memid = self.parameters.get("MEMID")?.to_string_if_string()?;
+        Some(memid.strip_prefix('\\').unwrap_or(memid))
+    }
+
+    /// Split the name of this cell into its components if it was auto-generated
+    ///
+    /// Always returns `None` if `hide_name` isn't set.
+    pub fn autoname(&self, name: &str) -> Option<AutoName> {
+        if self.hide_name == 0 {
+            return None;
+        }
+        AutoName::parse(name)
+    }
+}
+
+enum MemoryCellKind {
+    Read,
+    Write,
+    Init,
+}
+
+impl<'a> MemoryPorts<'a> {
+    fn kind(cell: &Cell) -> Option<MemoryCellKind> {
+        match cell.cell_type.as_str() {
+            "$memrd" | "$memrd_v2" => Some(MemoryCellKind::Read),
+            "$memwr" | "$memwr_v2" => Some(MemoryCellKind::Write),
+            "$meminit" | "$meminit_v2" => Some(MemoryCellKind::Init),
+            _ => None,
+        }
+    }
+
+    fn push(&mut self, name: &'a str, cell: &'a Cell) {
+        match Self::kind(cell) {
+            Some(MemoryCellKind::Read) => self.read.push((name, cell)),
+            Some(MemoryCellKind::Write) => self.write.push((name, cell)),
+            Some(MemoryCellKind::Init) => self.init.push((name, cell)),
+            None => {}
+        }
+    }
+
+    fn sort(&mut self) {
+        self.read.sort_by_key(|x| x.0);
+        self.write.sort_by_key(|x| x.0);
+        self.init.sort_by_key(|x| x.0);
+    }
+}
+
+/// Number of bits needed to represent the value `n`
+fn bits_needed(n: usize) -> usize {
+    (usize::BITS - n.leading_zeros()) as usize
+}
+
+impl Memory {
+    /// Number of address bits needed to select every word of this memory
+    pub fn addr_bits(&self) -> usize {
+        bits_needed(self.size.saturating_sub(1))
+    }
+
+    /// Range of valid addresses, or `None` if its end doesn't fit in a `usize`
+    pub fn addr_range(&self) -> Option<Range<usize>> {
+        Some(self.start_offset..self.start_offset.checked_add(self.size)?)
+    }
+
+    /// Total number of bits stored in this memory, or `None` if it doesn't fit in a
+    /// `usize`
+    pub fn total_bits(&self) -> Option<usize> {
+        self.width.checked_mul(self.size)
+    }
+
+    /// Whether `addr` is a valid address for this memory
+    pub fn contains_addr(&self, addr: usize) -> bool {
+        addr.checked_sub(self.start_offset)
+            .is_some_and(|index| index < self.size)
+    }
+}
+
+impl Netname {
+    /// Split the name of this net into its components if it was auto-generated
+    ///
+    /// Always returns `None` if `hide_name` isn't set.
+    pub fn autoname(&self, name: &str) -> Option<AutoName> {
+        if self.hide_name == 0 {
+            return None;
+        }
+        AutoName::parse(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn super_empty_json() {
+        let result = Netlist::from_slice(
+            br#"
+            {}"#,
+        )
+        .unwrap();
+        assert_eq!(result.creator, "");
+        assert_eq!(result.modules.len(), 0);
+    }
+
+    #[test]
+    fn empty_json() {
+        let result = Netlist::from_slice(
+            br#"
+            {
+              "creator": "this is a test",
+              "modules": {
+              }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(result.creator, "this is a test");
+        assert_eq!(result.modules.len(), 0);
+    }
+
+    #[test]
+    fn empty_json_2() {
+        let result = Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+              }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(result.creator, "");
+        assert_eq!(result.modules.len(), 0);
+    }
+
+    #[test]
+    fn bit_values_test() {
+        let result = Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+                "mymodule": {
+                  "cells": {
+                    "mycell": {
+                      "type": "celltype",
+                      "connections": {
+                        "IN": [ "x", 0, "z", 234, "1", "0" ]
+                      }
+                    }
+                  }
+                }
+              }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            result
+                .modules
+                .get("mymodule")
+                .unwrap()
+                .cells
+                .get("mycell")
+                .unwrap()
+                .connections
+                .get("IN")
+                .unwrap(),
+            &vec![
+                BitVal::S(SpecialBit::X),
+                BitVal::N(0),
+                BitVal::S(SpecialBit::Z),
+                BitVal::N(234),
                 BitVal::S(SpecialBit::_1),
                 BitVal::S(SpecialBit::_0)
             ]
@@ -369,42 +2743,2381 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
-    fn invalid_bit_value_test() {
-        Netlist::from_slice(
-            br#"
-            {
-              "modules": {
-                "mymodule": {
-                  "cells": {
-                    "mycell": {
-                      "type": "celltype",
-                      "connections": {
-                        "IN": [ "w" ]
-                      }
-                    }
-                  }
-                }
-              }
-            }"#,
-        )
-        .unwrap();
+    #[should_panic]
+    fn invalid_bit_value_test() {
+        Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+                "mymodule": {
+                  "cells": {
+                    "mycell": {
+                      "type": "celltype",
+                      "connections": {
+                        "IN": [ "w" ]
+                      }
+                    }
+                  }
+                }
+              }
+            }"#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn invalid_bit_value_message_test() {
+        let err = serde_json::from_str::<Vec<BitVal>>(r#"[2, "w"]"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"invalid value: string "w", expected a signal number or one of "0", "1", "x", "z" at line 1 column 7"#
+        );
+        let err = serde_json::from_str::<BitVal>("-1").unwrap_err();
+        assert!(err.to_string().starts_with("invalid value: integer `-1`"));
+        let err = serde_json::from_str::<BitVal>("1.5").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("invalid type: floating point `1.5`"));
+
+        let err = Netlist::from_slice(
+            br#"{"modules": {"m": {"cells": {"c": {"type": "t", "connections": {"IN": ["w"]}}}}}}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains(r#"string "w""#));
+
+        assert_eq!(
+            serde_json::from_str::<Vec<BitVal>>(r#"[0, "0", "1", "x", "z", 7]"#).unwrap(),
+            [
+                BitVal::N(0),
+                BitVal::S(SpecialBit::_0),
+                BitVal::S(SpecialBit::_1),
+                BitVal::S(SpecialBit::X),
+                BitVal::S(SpecialBit::Z),
+                BitVal::N(7),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_attribute_value_message_test() {
+        let parse = |json: &str| serde_json::from_str::<AttributeVal>(json);
+        assert_eq!(parse("12").unwrap(), AttributeVal::N(12));
+        assert_eq!(parse(r#""01""#).unwrap(), AttributeVal::from("01"));
+        assert_eq!(parse(r#""a\"b""#).unwrap(), AttributeVal::from("a\"b"));
+
+        for (json, found) in [
+            ("[1, 2]", "array"),
+            (r#"{"a": 1}"#, "object"),
+            ("true", "boolean"),
+            ("null", "null"),
+            ("1.5", "floating point number"),
+            ("-3", "negative integer"),
+        ] {
+            let err = parse(json).unwrap_err();
+            assert!(
+                err.to_string().starts_with(&format!(
+                    "expected integer or string parameter value, found {}",
+                    found
+                )),
+                "{}",
+                err
+            );
+        }
+
+        let err = Netlist::from_slice(
+            br#"{"modules": {"m": {"cells": {"c": {"type": "t", "connections": {},
+                "parameters": {"WIDTH": [8]}}}}}}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("found array"));
+    }
+
+    #[test]
+    fn normalize_test() {
+        let eq = |a: AttributeVal, b: AttributeVal| a.normalized_eq(&b);
+        assert!(eq(
+            AttributeVal::N(8),
+            AttributeVal::from("00000000000000000000000000001000")
+        ));
+        assert!(eq(AttributeVal::from("0101"), AttributeVal::from("101")));
+        assert!(eq(AttributeVal::from(""), AttributeVal::N(0)));
+        assert!(eq(
+            AttributeVal::from("top.v:1.2-3.4 "),
+            AttributeVal::from("top.v:1.2-3.4")
+        ));
+        assert!(!eq(AttributeVal::from("01x"), AttributeVal::from("1x")));
+        assert!(!eq(AttributeVal::from("101 "), AttributeVal::N(5)));
+        assert!(!eq(AttributeVal::from("abc"), AttributeVal::from("abd")));
+
+        assert_eq!(AttributeVal::from("0110").normalize(), AttributeVal::N(6));
+        assert_eq!(
+            AttributeVal::from("xz").normalize(),
+            AttributeVal::from("xz")
+        );
+        assert_eq!(
+            AttributeVal::from("101 ").normalize(),
+            AttributeVal::from("101 ")
+        );
+        let long = "1".repeat(100);
+        assert_eq!(
+            AttributeVal::from(long.as_str()).normalize(),
+            AttributeVal::from(long)
+        );
+        for value in [
+            AttributeVal::from("a "),
+            AttributeVal::from("0011"),
+            AttributeVal::N(3),
+        ] {
+            assert_eq!(value.normalize().normalize(), value.normalize());
+        }
+    }
+
+    #[test]
+    fn attribute_value_test() {
+        let result = Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+                "mymodule": {
+                  "cells": {
+                    "mycell": {
+                      "type": "celltype",
+                      "parameters": {
+                        "testparam": 123
+                      },
+                      "connections": {}
+                    }
+                  }
+                }
+              }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            result
+                .modules
+                .get("mymodule")
+                .unwrap()
+                .cells
+                .get("mycell")
+                .unwrap()
+                .parameters
+                .get("testparam")
+                .unwrap(),
+            &AttributeVal::N(123)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_attribute_value_test() {
+        Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+                "mymodule": {
+                  "cells": {
+                    "mycell": {
+                      "type": "celltype",
+                      "parameters": {
+                        "testparam": [123]
+                      },
+                      "connections": {}
+                    }
+                  }
+                }
+              }
+            }"#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn port_direction_semantics_test() {
+        use PortDirection::*;
+
+        assert_eq!(Input.flipped(), Output);
+        assert_eq!(Output.flipped(), Input);
+        assert_eq!(InOut.flipped(), InOut);
+
+        assert!(Input.drives_net_inside());
+        assert!(!Output.drives_net_inside());
+        assert!(InOut.drives_net_inside());
+
+        assert!(!Input.drives_net_from_instance());
+        assert!(Output.drives_net_from_instance());
+        assert!(InOut.drives_net_from_instance());
+
+        // Flipping swaps the two views, except for inout which is both in both
+        for dir in [Input, Output, InOut] {
+            assert_eq!(dir.flipped().flipped(), dir);
+            assert_eq!(
+                dir.drives_net_inside(),
+                dir.flipped().drives_net_from_instance()
+            );
+        }
+    }
+
+    #[test]
+    fn port_direction_to_verilog_test() {
+        for (dir, name) in [
+            (PortDirection::Input, "input"),
+            (PortDirection::Output, "output"),
+            (PortDirection::InOut, "inout"),
+        ] {
+            assert_eq!(dir.to_verilog(), name);
+            assert_eq!(
+                serde_json::to_string(&dir).unwrap(),
+                format!("\"{}\"", name)
+            );
+        }
+    }
+
+    #[test]
+    fn attribute_bool_explicit_test() {
+        assert_eq!(AttributeVal::N(1).as_bool_explicit(), Some(true));
+        assert_eq!(AttributeVal::N(0).as_bool_explicit(), Some(false));
+        assert_eq!(AttributeVal::N(2).as_bool_explicit(), None);
+        assert_eq!(AttributeVal::S("1".into()).as_bool_explicit(), Some(true));
+        assert_eq!(AttributeVal::S("0".into()).as_bool_explicit(), Some(false));
+        assert_eq!(AttributeVal::S("".into()).as_bool_explicit(), None);
+        assert_eq!(AttributeVal::S("01".into()).as_bool_explicit(), None);
+        assert_eq!(
+            AttributeVal::S("00000000000000000000000000000001".into()).as_bool_explicit(),
+            None
+        );
+        assert_eq!(AttributeVal::S("true".into()).as_bool_explicit(), None);
+    }
+
+    const INTEGRATION_JSON: &[u8] = br#"{
+  "creator": "Yosys 0.14+51 (git sha1 286caa09b, gcc 9.3.0-13 -fPIC -Os)",
+  "modules": {
+    "test": {
+      "attributes": {
+        "cells_not_processed": "00000000000000000000000000000001",
+        "src": "test-for-json.v:1.1-12.10"
+      },
+      "parameter_default_values": {
+        "TESTPARAM": "00000000000000001010010001010101"
+      },
+      "ports": {
+        "a": {
+          "direction": "input",
+          "offset": 1,
+          "bits": [ 2, 3, 4, 5, 6, 7, 8, 9 ]
+        },
+        "b": {
+          "direction": "input",
+          "upto": 1,
+          "bits": [ 10, 11, 12, 13, 14, 15, 16, 17 ]
+        },
+        "o": {
+          "direction": "output",
+          "bits": [ 18, 19, 20, 21, 22, 23, 24, 25 ]
+        }
+      },
+      "cells": {
+        "$xor$test-for-json.v:10$1": {
+          "hide_name": 1,
+          "type": "$xor",
+          "parameters": {
+            "A_SIGNED": "00000000000000000000000000000000",
+            "A_WIDTH": "00000000000000000000000000001000",
+            "B_SIGNED": "00000000000000000000000000000000",
+            "B_WIDTH": "00000000000000000000000000001000",
+            "Y_WIDTH": "00000000000000000000000000001000"
+          },
+          "attributes": {
+            "src": "test-for-json.v:10.12-10.17"
+          },
+          "port_directions": {
+            "A": "input",
+            "B": "input",
+            "Y": "output"
+          },
+          "connections": {
+            "A": [ 2, 3, 4, 5, 6, 7, 8, 9 ],
+            "B": [ 10, 11, 12, 13, 14, 15, 16, 17 ],
+            "Y": [ 18, 19, 20, 21, 22, 23, 24, 25 ]
+          }
+        }
+      },
+      "memories": {
+        "testmemory": {
+          "hide_name": 0,
+          "attributes": {
+            "src": "test-for-json.v:8.12-8.22"
+          },
+          "width": 8,
+          "start_offset": 1,
+          "size": 1111
+        }
+      },
+      "netnames": {
+        "$xor$test-for-json.v:10$1_Y": {
+          "hide_name": 1,
+          "bits": [ 18, 19, 20, 21, 22, 23, 24, 25 ],
+          "attributes": {
+            "src": "test-for-json.v:10.12-10.17"
+          }
+        },
+        "a": {
+          "hide_name": 0,
+          "bits": [ 2, 3, 4, 5, 6, 7, 8, 9 ],
+          "offset": 1,
+          "attributes": {
+            "src": "test-for-json.v:2.17-2.18"
+          }
+        },
+        "b": {
+          "hide_name": 0,
+          "bits": [ 10, 11, 12, 13, 14, 15, 16, 17 ],
+          "upto": 1,
+          "attributes": {
+            "src": "test-for-json.v:3.17-3.18"
+          }
+        },
+        "o": {
+          "hide_name": 0,
+          "bits": [ 18, 19, 20, 21, 22, 23, 24, 25 ],
+          "attributes": {
+            "src": "test-for-json.v:4.18-4.19"
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+    fn integration_netlist() -> Netlist {
+        Netlist::from_slice(INTEGRATION_JSON).unwrap()
+    }
+
+    #[test]
+    fn integration_test() {
+        let result = integration_netlist();
+
+        assert_eq!(
+            result.creator,
+            "Yosys 0.14+51 (git sha1 286caa09b, gcc 9.3.0-13 -fPIC -Os)"
+        );
+        let mod_test = result.modules.get("test").unwrap();
+
+        assert_eq!(
+            mod_test
+                .parameter_default_values
+                .get("TESTPARAM")
+                .unwrap()
+                .to_number()
+                .unwrap(),
+            42069
+        );
+
+        assert_eq!(mod_test.ports.get("a").unwrap().offset, 1);
+        assert_eq!(mod_test.ports.get("b").unwrap().upto, 1);
+        assert_eq!(mod_test.ports.get("o").unwrap().offset, 0);
+        assert_eq!(mod_test.ports.get("o").unwrap().upto, 0);
+
+        assert_eq!(mod_test.memories.get("testmemory").unwrap().size, 1111);
+    }
+
+    #[test]
+    fn yosys_style_fixture_test() {
+        // Yosys writes map entries sorted, so the output matches byte for byte
+        let netlist = integration_netlist();
+        let output = netlist.to_string_yosys_style().unwrap();
+        assert_eq!(output, std::str::from_utf8(INTEGRATION_JSON).unwrap());
+        assert_eq!(Netlist::from_slice(output.as_bytes()).unwrap(), netlist);
+    }
+
+    #[cfg(feature = "simd-json")]
+    #[test]
+    fn simd_integration_test() {
+        let mut input = INTEGRATION_JSON.to_vec();
+        assert_eq!(
+            Netlist::from_slice_simd(&mut input).unwrap(),
+            integration_netlist()
+        );
+    }
+
+    #[test]
+    fn extract_interface_test() {
+        let netlist = integration_netlist();
+        let mod_test = netlist.modules.get("test").unwrap();
+        let iface = mod_test.extract_interface();
+
+        assert_eq!(iface.ports.len(), mod_test.ports.len());
+        for (name, port) in &mod_test.ports {
+            assert_eq!(iface.ports.get(name).unwrap().bits.len(), port.bits.len());
+        }
+        assert!(iface.cells.is_empty());
+        assert!(iface.memories.is_empty());
+        assert_eq!(iface.netnames.len(), 3);
+        assert!(!iface.netnames.contains_key("$xor$test-for-json.v:10$1_Y"));
+        assert_eq!(
+            iface.attributes.get("blackbox").unwrap(),
+            &AttributeVal::N(1)
+        );
+    }
+
+    #[test]
+    fn interface_ordered_test() {
+        let mut netlist = integration_netlist();
+        let mod_test = netlist.modules.get_mut("test").unwrap();
+
+        let ports = mod_test.interface_ordered(PortOrder::Alphabetical);
+        let names = ports.iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "o"]);
+        assert_eq!(ports[0].width, 8);
+        assert_eq!((ports[0].msb, ports[0].lsb), (8, 1));
+        assert_eq!((ports[1].msb, ports[1].lsb), (0, 7));
+        assert_eq!((ports[2].msb, ports[2].lsb), (7, 0));
+        assert_eq!(ports[2].direction, PortDirection::Output);
+        assert!(!ports[2].signed);
+        assert_eq!(ports[2].bits, mod_test.ports.get("o").unwrap().bits);
+
+        mod_test.ports.get_mut("a").unwrap().direction = PortDirection::Output;
+        let ports = mod_test.interface_ordered(PortOrder::ByDirection);
+        let names = ports.iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["b", "a", "o"]);
+
+        for (name, idx) in [("o", 0), ("a", 1)] {
+            mod_test
+                .netnames
+                .get_mut(name)
+                .unwrap()
+                .attributes
+                .insert("port_order".to_owned(), AttributeVal::N(idx));
+        }
+        let ports = mod_test.interface_ordered(PortOrder::ByAttribute("port_order".to_owned()));
+        let names = ports.iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["o", "a", "b"]);
+    }
+
+    #[test]
+    fn clone_with_new_signals_test() {
+        let netlist = integration_netlist();
+        let cell = netlist
+            .modules
+            .get("test")
+            .unwrap()
+            .cells
+            .get("$xor$test-for-json.v:10$1")
+            .unwrap();
+
+        let mapping = [(2, 102), (17, 117), (25, 125)].into_iter().collect();
+        let clone = cell.clone_with_new_signals(&mapping);
+
+        assert_eq!(clone.connections.get("A").unwrap()[0], BitVal::N(102));
+        assert_eq!(clone.connections.get("A").unwrap()[1], BitVal::N(3));
+        assert_eq!(clone.connections.get("B").unwrap()[7], BitVal::N(117));
+        assert_eq!(clone.connections.get("Y").unwrap()[7], BitVal::N(125));
+        assert_eq!(clone.cell_type, cell.cell_type);
+        assert_eq!(clone.parameters, cell.parameters);
+
+        assert_eq!(cell.connections.get("A").unwrap()[0], BitVal::N(2));
+        assert_eq!(cell.connections.get("Y").unwrap()[7], BitVal::N(25));
+    }
+
+    #[test]
+    fn autoname_test() {
+        let netlist = integration_netlist();
+        let mod_test = netlist.modules.get("test").unwrap();
+
+        let name = "$xor$test-for-json.v:10$1";
+        let autoname = mod_test.cells.get(name).unwrap().autoname(name).unwrap();
+        assert_eq!(autoname.generator, "xor");
+        assert_eq!(autoname.index, 1);
+
+        let name = "$xor$test-for-json.v:10$1_Y";
+        let autoname = mod_test.netnames.get(name).unwrap().autoname(name).unwrap();
+        assert_eq!(autoname.port_suffix.as_deref(), Some("Y"));
+
+        assert_eq!(mod_test.netnames.get("a").unwrap().autoname("a"), None);
+    }
+
+    #[test]
+    fn port_directions_map_test() {
+        let netlist = integration_netlist();
+        let dirs = netlist.modules.get("test").unwrap().port_directions_map();
+
+        assert_eq!(dirs.len(), 3);
+        assert_eq!(dirs.get("a"), Some(&PortDirection::Input));
+        assert_eq!(dirs.get("b"), Some(&PortDirection::Input));
+        assert_eq!(dirs.get("o"), Some(&PortDirection::Output));
+    }
+
+    #[test]
+    fn hdl_vector_test() {
+        let mut netlist = integration_netlist();
+        let mod_test = netlist.modules.get_mut("test").unwrap();
+
+        // a is [8:1]
+        let a = mod_test.netnames.get("a").unwrap();
+        assert_eq!(a.width(), 8);
+        assert!(a.is_bus());
+        assert_eq!(a.bit(1), Some(BitVal::N(2)));
+        assert_eq!(a.bit(8), Some(BitVal::N(9)));
+        assert_eq!(a.bit(0), None);
+        assert_eq!(a.bit(9), None);
+        let hdl = a.iter_hdl_indexed().collect::<Vec<_>>();
+        assert_eq!(hdl.first(), Some(&(8, BitVal::N(9))));
+        assert_eq!(hdl.last(), Some(&(1, BitVal::N(2))));
+
+        // b is [0:7]
+        let b = mod_test.netnames.get("b").unwrap();
+        assert_eq!(b.bit(0), Some(BitVal::N(17)));
+        assert_eq!(b.bit(7), Some(BitVal::N(10)));
+        let hdl = b.iter_hdl_indexed().collect::<Vec<_>>();
+        assert_eq!(hdl.first(), Some(&(0, BitVal::N(17))));
+        assert_eq!(hdl.last(), Some(&(7, BitVal::N(10))));
+
+        // Ports share the same implementation
+        let port_b = mod_test.ports.get("b").unwrap();
+        assert_eq!(port_b.bit(0), b.bit(0));
+
+        assert_eq!(mod_test.buses().count(), 4);
+        assert_eq!(mod_test.scalars().count(), 0);
+
+        mod_test.netnames.get_mut("o").unwrap().bits.truncate(1);
+        let scalars = mod_test.scalars().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(scalars, ["o"]);
+        assert_eq!(mod_test.buses().count(), 3);
+    }
+
+    #[test]
+    fn print_hierarchy_test() {
+        let netlist = integration_netlist();
+        assert_eq!(netlist.print_hierarchy("test").unwrap(), "test");
+        assert!(matches!(
+            netlist.print_hierarchy("nonexistent"),
+            Err(Error::ModuleNotFound(_))
+        ));
+
+        let netlist = Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+                "top": {
+                  "cells": {
+                    "u0": { "type": "mid", "connections": {} },
+                    "u1": { "type": "mid", "connections": {} },
+                    "u2": { "type": "leaf", "connections": {} },
+                    "u3": { "type": "$and", "connections": {} }
+                  }
+                },
+                "mid": {
+                  "cells": {
+                    "u0": { "type": "leaf", "connections": {} }
+                  }
+                },
+                "leaf": {}
+              }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            netlist.print_hierarchy("top").unwrap(),
+            "top\n├── leaf x1\n└── mid x2\n    └── leaf x1"
+        );
+
+        let netlist = Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+                "a": { "cells": { "u0": { "type": "b", "connections": {} } } },
+                "b": { "cells": { "u0": { "type": "a", "connections": {} } } }
+              }
+            }"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            netlist.print_hierarchy("a"),
+            Err(Error::HierarchyCycle(_))
+        ));
+    }
+
+    #[test]
+    fn memory_addressing_test() {
+        let mut mem = Memory {
+            hide_name: 0,
+            attributes: HashMap::new(),
+            width: 8,
+            size: 0,
+            start_offset: 0,
+            extra: HashMap::new(),
+        };
+        assert_eq!(mem.addr_bits(), 0);
+        assert_eq!(mem.addr_range(), Some(0..0));
+        assert_eq!(mem.total_bits(), Some(0));
+        assert!(!mem.contains_addr(0));
+
+        mem.size = 1;
+        assert_eq!(mem.addr_bits(), 0);
+        assert!(mem.contains_addr(0));
+        assert!(!mem.contains_addr(1));
+
+        mem.size = 16;
+        assert_eq!(mem.addr_bits(), 4);
+        mem.size = 17;
+        assert_eq!(mem.addr_bits(), 5);
+
+        // Sizes from untrusted JSON mustn't overflow
+        mem.start_offset = usize::MAX - 1;
+        mem.size = usize::MAX / 4;
+        assert_eq!(mem.addr_range(), None);
+        assert_eq!(mem.total_bits(), None);
+        assert!(mem.contains_addr(usize::MAX));
+        assert!(!mem.contains_addr(0));
+        mem.start_offset = 0;
+        mem.width = 4;
+        assert_eq!(mem.total_bits(), Some(usize::MAX / 4 * 4));
+
+        let netlist = integration_netlist();
+        let mem = netlist
+            .modules
+            .get("test")
+            .unwrap()
+            .memories
+            .get("testmemory")
+            .unwrap();
+        assert_eq!(mem.addr_bits(), 11);
+        assert_eq!(mem.addr_range(), Some(1..1112));
+        assert_eq!(mem.total_bits(), Some(8888));
+        assert!(!mem.contains_addr(0));
+        assert!(mem.contains_addr(1111));
+        assert!(!mem.contains_addr(1112));
+    }
+
+    #[test]
+    fn validate_memory_ports_test() {
+        let netlist = Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+                "top": {
+                  "memories": {
+                    "mem": { "width": 8, "size": 16, "start_offset": 16 }
+                  },
+                  "cells": {
+                    "rd_ok": {
+                      "type": "$memrd",
+                      "parameters": { "MEMID": "\\mem", "ABITS": 5 },
+                      "connections": { "ADDR": [ 2, 3, 4, 5, 6 ] }
+                    },
+                    "rd_bad": {
+                      "type": "$memrd_v2",
+                      "parameters": { "MEMID": "\\mem", "ABITS": 3 },
+                      "connections": { "ADDR": [ 2, 3, 4 ] }
+                    },
+                    "wr_narrow": {
+                      "type": "$memwr_v2",
+                      "parameters": { "MEMID": "\\mem", "ABITS": 4 },
+                      "connections": { "ADDR": [ 2, 3, 4, 5 ] }
+                    },
+                    "other": {
+                      "type": "$memrd",
+                      "parameters": { "MEMID": "\\nonexistent", "ABITS": 1 },
+                      "connections": { "ADDR": [ 2 ] }
+                    }
+                  }
+                }
+              }
+            }"#,
+        )
+        .unwrap();
+        let mut issues = netlist.modules.get("top").unwrap().validate_memory_ports();
+        issues.sort_by_key(|issue| format!("{:?}", issue));
+        assert_eq!(
+            issues,
+            [
+                MemoryPortIssue::AbitsTooSmall {
+                    cell: "rd_bad".to_owned(),
+                    memory: "mem".to_owned(),
+                    abits: 3,
+                    required: 4,
+                },
+                MemoryPortIssue::AddressTooNarrow {
+                    cell: "rd_bad".to_owned(),
+                    memory: "mem".to_owned(),
+                    width: 3,
+                    required: 5,
+                },
+                MemoryPortIssue::AddressTooNarrow {
+                    cell: "wr_narrow".to_owned(),
+                    memory: "mem".to_owned(),
+                    width: 4,
+                    required: 5,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parameter_consistency_test() {
+        let mut netlist = integration_netlist();
+        let mod_test = netlist.modules.get_mut("test").unwrap();
+        assert_eq!(mod_test.check_parameter_consistency(), []);
+
+        let cell = mod_test.cells.get_mut("$xor$test-for-json.v:10$1").unwrap();
+        cell.parameters.remove("B_SIGNED");
+        cell.parameters
+            .insert("FANCY_MODE".to_owned(), AttributeVal::N(1));
+        let mut xor2 = cell.clone();
+        xor2.parameters.remove("A_WIDTH");
+        mod_test.cells.insert("xor2".to_owned(), xor2);
+        let mut and = mod_test.cells["$xor$test-for-json.v:10$1"].clone();
+        and.cell_type = "$and".to_owned();
+        and.parameters.remove("A_WIDTH");
+        and.parameters
+            .insert("B_SIGNED".to_owned(), AttributeVal::N(0));
+        and.parameters.remove("FANCY_MODE");
+        mod_test.cells.insert("and2".to_owned(), and);
+        assert_eq!(
+            mod_test.check_parameter_consistency(),
+            [
+                ParameterWarning::Missing {
+                    cell: "and2".to_owned(),
+                    parameter: "A_WIDTH".to_owned()
+                },
+                ParameterWarning::Missing {
+                    cell: "xor2".to_owned(),
+                    parameter: "A_WIDTH".to_owned()
+                },
+                ParameterWarning::Missing {
+                    cell: "$xor$test-for-json.v:10$1".to_owned(),
+                    parameter: "B_SIGNED".to_owned()
+                },
+                ParameterWarning::Missing {
+                    cell: "xor2".to_owned(),
+                    parameter: "B_SIGNED".to_owned()
+                },
+                ParameterWarning::Unrecognized {
+                    cell: "$xor$test-for-json.v:10$1".to_owned(),
+                    parameter: "FANCY_MODE".to_owned()
+                },
+                ParameterWarning::Unrecognized {
+                    cell: "xor2".to_owned(),
+                    parameter: "FANCY_MODE".to_owned()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn memory_ports_test() {
+        let netlist = Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+                "top": {
+                  "memories": {
+                    "mem": { "width": 8, "size": 16 },
+                    "unused": { "width": 8, "size": 16 }
+                  },
+                  "cells": {
+                    "rd1": { "type": "$memrd_v2", "parameters": { "MEMID": "\\mem" }, "connections": {} },
+                    "rd0": { "type": "$memrd", "parameters": { "MEMID": "\\mem" }, "connections": {} },
+                    "wr": { "type": "$memwr_v2", "parameters": { "MEMID": "\\mem" }, "connections": {} },
+                    "init": { "type": "$meminit_v2", "parameters": { "MEMID": "\\mem" }, "connections": {} },
+                    "lost": { "type": "$memrd_v2", "parameters": { "MEMID": "\\gone" }, "connections": {} },
+                    "logic": { "type": "$and", "connections": {} }
+                  }
+                }
+              }
+            }"#,
+        )
+        .unwrap();
+        let top = netlist.modules.get("top").unwrap();
+
+        let ports = top.memory_ports("mem");
+        let names = |x: &[(&str, &Cell)]| x.iter().map(|x| x.0.to_owned()).collect::<Vec<_>>();
+        assert_eq!(names(&ports.read), ["rd0", "rd1"]);
+        assert_eq!(names(&ports.write), ["wr"]);
+        assert_eq!(names(&ports.init), ["init"]);
+        assert_eq!(top.memory_ports("unused"), MemoryPorts::default());
+
+        let map = top.memory_map();
+        assert_eq!(map.memories.len(), 2);
+        assert_eq!(map.memories.get("mem"), Some(&ports));
+        assert_eq!(map.memories.get("unused"), Some(&MemoryPorts::default()));
+        assert_eq!(map.orphans, ["lost"]);
+    }
+
+    #[test]
+    fn estimated_area_test() {
+        let netlist = integration_netlist();
+        let mod_test = netlist.modules.get("test").unwrap();
+        let cell = mod_test.cells.get("$xor$test-for-json.v:10$1").unwrap();
+        assert_eq!(cell.estimated_area(), 24);
+        assert_eq!(mod_test.total_estimated_area(), 24);
+        assert_eq!(Module::default().total_estimated_area(), 0);
+    }
+
+    #[test]
+    fn dependency_order_test() {
+        let netlist = Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+                "top": {
+                  "cells": {
+                    "u0": { "type": "mid", "connections": {} },
+                    "u1": { "type": "bbox", "connections": {} }
+                  }
+                },
+                "mid": {
+                  "cells": {
+                    "u0": { "type": "leaf", "connections": {} },
+                    "u1": { "type": "$and", "connections": {} }
+                  }
+                },
+                "leaf": {},
+                "bbox": { "attributes": { "blackbox": 1 } }
+              }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            netlist.modules_in_dependency_order().unwrap(),
+            ["bbox", "leaf", "mid", "top"]
+        );
+        assert_eq!(
+            netlist.modules_in_reverse_dependency_order().unwrap(),
+            ["top", "mid", "leaf", "bbox"]
+        );
+
+        let netlist = Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+                "top": { "cells": { "u0": { "type": "a", "connections": {} } } },
+                "a": { "cells": { "u0": { "type": "b", "connections": {} } } },
+                "b": { "cells": { "u0": { "type": "a", "connections": {} } } },
+                "leaf": {}
+              }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            netlist.modules_in_dependency_order(),
+            Err(CycleError {
+                modules: vec!["a".to_owned(), "b".to_owned(), "top".to_owned()]
+            })
+        );
+    }
+
+    #[test]
+    fn strip_attributes_test() {
+        let mut netlist = integration_netlist();
+        let mod_test = netlist.modules.get_mut("test").unwrap();
+
+        assert_eq!(mod_test.strip_attributes(), 8);
+        assert!(mod_test.attributes.is_empty());
+        assert!(mod_test.cells.values().all(|x| x.attributes.is_empty()));
+        assert!(mod_test.memories.values().all(|x| x.attributes.is_empty()));
+        assert!(mod_test.netnames.values().all(|x| x.attributes.is_empty()));
+        assert_eq!(mod_test.strip_attributes(), 0);
+    }
+
+    #[test]
+    fn clone_module_test() {
+        let mut netlist = integration_netlist();
+
+        assert_eq!(
+            netlist.clone_module("nonexistent", "copy"),
+            Err(CloneError::SourceNotFound)
+        );
+        assert_eq!(
+            netlist.clone_module("test", "test"),
+            Err(CloneError::DuplicateName)
+        );
+
+        let copy = netlist.clone_module("test", "copy").unwrap();
+        copy.cells.clear();
+        copy.ports.get_mut("a").unwrap().bits.clear();
+
+        let original = netlist.modules.get("test").unwrap();
+        assert_eq!(original.cells.len(), 1);
+        assert_eq!(original.ports.get("a").unwrap().bits.len(), 8);
+        assert_eq!(netlist.modules.get("copy").unwrap().cells.len(), 0);
+    }
+
+    #[test]
+    fn compact_signal_ids_test() {
+        let mut netlist = integration_netlist();
+        let mod_test = netlist.modules.get_mut("test").unwrap();
+
+        let mapping = mod_test.compact_signal_ids();
+        assert_eq!(mapping.len(), 24);
+        // Port a is visited first
+        assert_eq!(mapping.get(&2), Some(&2));
+        assert_eq!(mod_test.ports.get("a").unwrap().bits[0], BitVal::N(2));
+        assert_eq!(mod_test.ports.get("o").unwrap().bits[7], BitVal::N(25));
+    }
+
+    #[test]
+    fn interface_consistency_test() {
+        let mut netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                cell "inv" u0 { i: a, o: y }
+                cell "inv" u1 { i: a, o: y }
+            }
+            module inv {
+                input i;
+                output o;
+            }
+        };
+        assert_eq!(netlist.verify_module_interface_consistency(), []);
+
+        let u1 = netlist
+            .modules
+            .get_mut("top")
+            .unwrap()
+            .cells
+            .get_mut("u1")
+            .unwrap();
+        u1.port_directions
+            .insert("o".to_owned(), PortDirection::Input);
+        u1.port_directions
+            .insert("extra".to_owned(), PortDirection::Input);
+        assert_eq!(
+            netlist.verify_module_interface_consistency(),
+            [InterfaceError {
+                module: "top".to_owned(),
+                cell: "u1".to_owned(),
+                port: "o".to_owned(),
+                expected: PortDirection::Output,
+                found: PortDirection::Input,
+            }]
+        );
+    }
+
+    #[test]
+    fn skip_empty_maps_test() {
+        let netlist = integration_netlist();
+        let json = netlist.to_string().unwrap();
+        assert_eq!(Netlist::from_slice(json.as_bytes()).unwrap(), netlist);
+
+        let netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                cell "$not" n { A: a, Y: y }
+            }
+        };
+        let json = netlist.to_string().unwrap();
+        assert!(!json.contains("\"parameters\""));
+        assert!(!json.contains("\"attributes\""));
+        assert!(!json.contains("\"memories\""));
+        assert!(json.contains("\"port_directions\""));
+        assert_eq!(Netlist::from_slice(json.as_bytes()).unwrap(), netlist);
+    }
+
+    #[test]
+    fn skip_zero_fields_test() {
+        let netlist = integration_netlist();
+        let value = serde_json::to_value(&netlist).unwrap();
+        let module = &value["modules"]["test"];
+        assert_eq!(module["ports"]["a"]["offset"], 1);
+        assert_eq!(module["netnames"]["b"]["upto"], 1);
+        for object in [&module["ports"]["o"], &module["netnames"]["o"]] {
+            for key in ["offset", "upto", "signed"] {
+                assert!(object.get(key).is_none(), "{}", key);
+            }
+        }
+        // Yosys always writes `hide_name`
+        assert_eq!(module["netnames"]["o"]["hide_name"], 0);
+        assert_eq!(module["memories"]["testmemory"]["hide_name"], 0);
+
+        let json = br#"{"modules": {"top": {"ports": {
+            "a": {"direction": "input", "bits": [2], "offset": 0, "upto": 0, "signed": 0}
+        }}}}"#;
+        let parsed = Netlist::from_slice(json).unwrap();
+        assert_eq!(parsed.modules["top"].ports["a"].offset, 0);
+        assert!(!parsed.to_string().unwrap().contains("offset"));
+    }
+
+    #[test]
+    fn extra_fields_test() {
+        let json = br#"{
+            "creator": "test",
+            "future_field": 1,
+            "modules": {
+                "top": {
+                    "future_field": "module",
+                    "ports": {
+                        "a": {"direction": "input", "bits": [2], "future_field": [1, 2]}
+                    },
+                    "cells": {
+                        "n": {
+                            "type": "$not",
+                            "connections": {"A": [2], "Y": [3]},
+                            "future_field": {"nested": true}
+                        }
+                    },
+                    "memories": {
+                        "m": {"width": 1, "size": 2, "future_field": null}
+                    },
+                    "netnames": {
+                        "a": {"bits": [2], "future_field": "net"}
+                    }
+                }
+            }
+        }"#;
+        let netlist = Netlist::from_slice(json).unwrap();
+        assert_eq!(netlist.extra["future_field"], 1);
+        let top = &netlist.modules["top"];
+        assert_eq!(top.extra["future_field"], "module");
+        assert_eq!(
+            top.ports["a"].extra["future_field"],
+            serde_json::json!([1, 2])
+        );
+        assert_eq!(top.cells["n"].extra["future_field"]["nested"], true);
+        assert!(top.memories["m"].extra["future_field"].is_null());
+        assert_eq!(top.netnames["a"].extra["future_field"], "net");
+
+        let value = serde_json::to_value(&netlist).unwrap();
+        let top = &value["modules"]["top"];
+        assert_eq!(value["future_field"], 1);
+        assert_eq!(top["future_field"], "module");
+        assert_eq!(top["ports"]["a"]["future_field"], serde_json::json!([1, 2]));
+        assert_eq!(top["cells"]["n"]["future_field"]["nested"], true);
+        assert!(top["memories"]["m"]
+            .as_object()
+            .unwrap()
+            .contains_key("future_field"));
+        assert_eq!(top["netnames"]["a"]["future_field"], "net");
+        assert_eq!(serde_json::from_value::<Netlist>(value).unwrap(), netlist);
+
+        assert!(integration_netlist().extra.is_empty());
+    }
+
+    #[test]
+    fn get_or_create_module_test() {
+        let mut netlist = integration_netlist();
+        let module = netlist.get_or_create_module("test");
+        assert_eq!(module.cells.len(), 1);
+        module.set_attribute("top", AttributeVal::N(1));
+        assert_eq!(netlist.modules["test"].attributes.len(), 3);
+
+        let module = netlist.get_or_create_module("new");
+        assert_eq!(*module, Module::default());
+        module.set_attribute("blackbox", AttributeVal::N(1));
+        assert_eq!(netlist.modules.len(), 2);
+        assert_eq!(netlist.get_or_create_module("new").attributes.len(), 1);
+    }
+
+    #[test]
+    fn count_by_type_test() {
+        let netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                cell "$not" n0 { A: a, Y: y }
+                cell "$and" g0 { A: a, B: a, Y: y }
+                cell "sub" s0 { a: a }
+                cell "sub" s1 { a: a }
+            }
+            module sub {
+                input a;
+                wire t;
+                cell "$not" n0 { A: a, Y: t }
+                cell "$not" n1 { A: t, Y: t }
+            }
+        };
+
+        let top = netlist.modules["top"].count_by_type();
+        assert_eq!(top.len(), 3);
+        assert_eq!(top["sub"], 2);
+
+        let counts = netlist.cell_count_globally_by_type();
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts["$not"], 3);
+        assert_eq!(counts["$and"], 1);
+        assert_eq!(counts["sub"], 2);
+        assert_eq!(Netlist::default().cell_count_globally_by_type().len(), 0);
+    }
+
+    #[test]
+    fn module_short_description_test() {
+        let netlist = integration_netlist();
+        assert_eq!(
+            netlist.modules["test"].short_description("test"),
+            "test [3 ports, 1 cell]"
+        );
+
+        let mut module = Module::default();
+        assert_eq!(module.short_description("m"), "m [0 ports, 0 cells]");
+        module.set_port("a", netlist.modules["test"].ports["a"].clone());
+        assert_eq!(module.short_description("m"), "m [1 port, 0 cells]");
+    }
+
+    #[test]
+    fn module_setters_test() {
+        let mut netlist = integration_netlist();
+        let module = netlist.modules.get_mut("test").unwrap();
+
+        let xor = module.cells["$xor$test-for-json.v:10$1"].clone();
+        let mut not = xor.clone();
+        not.cell_type = "$not".to_owned();
+        assert_eq!(module.set_cell("inv", not.clone()), None);
+        assert_eq!(module.set_cell("$xor$test-for-json.v:10$1", not), Some(xor));
+        assert_eq!(module.cells["$xor$test-for-json.v:10$1"].cell_type, "$not");
+        assert_eq!(module.cells.len(), 2);
+
+        let port = module.ports["a"].clone();
+        assert_eq!(module.set_port("c", port.clone()), None);
+        assert_eq!(module.set_port("a", port.clone()), Some(port));
+
+        let memory = module.memories["testmemory"].clone();
+        assert_eq!(module.set_memory("mem2", memory.clone()), None);
+        assert_eq!(
+            module.set_memory(String::from("testmemory"), memory.clone()),
+            Some(memory)
+        );
+
+        let netname = module.netnames["a"].clone();
+        assert_eq!(module.set_netname("c", netname.clone()), None);
+        assert_eq!(module.set_netname("a", netname.clone()), Some(netname));
+        assert_eq!(module.netnames.len(), 5);
+    }
+
+    #[test]
+    fn module_attribute_test() {
+        let mut netlist = integration_netlist();
+        let module = netlist.modules.get_mut("test").unwrap();
+        assert_eq!(
+            module.get_attribute("src"),
+            Some(&AttributeVal::S("test-for-json.v:1.1-12.10".to_owned()))
+        );
+
+        module.set_attribute("top", AttributeVal::N(1));
+        module.set_attribute(String::from("src"), "elsewhere".into());
+        assert_eq!(module.get_attribute("top"), Some(&AttributeVal::N(1)));
+        assert_eq!(module.get_attribute("src"), Some(&"elsewhere".into()));
+
+        assert_eq!(module.remove_attribute("top"), Some(AttributeVal::N(1)));
+        assert_eq!(module.remove_attribute("top"), None);
+        assert_eq!(module.get_attribute("top"), None);
+    }
+
+    #[test]
+    fn ensure_port_netnames_test() {
+        let mut netlist = Netlist::from_slice(
+            br#"{
+              "modules": {
+                "top": {
+                  "ports": {
+                    "a": { "direction": "input", "bits": [ 2, 3 ], "offset": 4, "upto": 1 },
+                    "y": { "direction": "output", "bits": [ 4 ], "signed": 1 }
+                  },
+                  "netnames": {
+                    "y": { "hide_name": 0, "bits": [ 4 ], "attributes": { "keep": 1 } }
+                  }
+                }
+              }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(netlist.ensure_port_netnames(), 1);
+        let top = &netlist.modules["top"];
+        let a = &top.netnames["a"];
+        assert_eq!(a.bits, [BitVal::N(2), BitVal::N(3)]);
+        assert_eq!((a.offset, a.upto, a.signed, a.hide_name), (4, 1, 0, 0));
+        assert_eq!(top.netnames["y"].signed, 0);
+        assert_eq!(top.netnames["y"].attributes.len(), 1);
+        assert!(invariants::ports_have_netnames(top).is_ok());
+
+        let before = netlist.clone();
+        assert_eq!(netlist.ensure_port_netnames(), 0);
+        assert_eq!(netlist, before);
+    }
+
+    #[test]
+    fn get_or_insert_test() {
+        let mut netlist = integration_netlist();
+        let cell = netlist
+            .modules
+            .get_mut("test")
+            .unwrap()
+            .cells
+            .get_mut("$xor$test-for-json.v:10$1")
+            .unwrap();
+
+        let a = cell.get_or_insert_connection("A", vec![BitVal::S(SpecialBit::_0)]);
+        assert_eq!(a.len(), 8);
+        assert_eq!(a[0], BitVal::N(2));
+        let en = cell.get_or_insert_connection("EN", vec![BitVal::S(SpecialBit::_1)]);
+        en.push(BitVal::N(30));
+        assert_eq!(
+            cell.connections["EN"],
+            [BitVal::S(SpecialBit::_1), BitVal::N(30)]
+        );
+
+        let width = cell.get_or_default_parameter("A_WIDTH", AttributeVal::N(1));
+        assert_eq!(
+            *width,
+            AttributeVal::S("00000000000000000000000000001000".to_owned())
+        );
+        *cell.get_or_default_parameter("NEW", AttributeVal::N(1)) = AttributeVal::N(2);
+        assert_eq!(cell.parameters["NEW"], AttributeVal::N(2));
+    }
+
+    #[test]
+    fn find_by_attribute_test() {
+        let mut netlist = integration_netlist();
+        let ports = netlist.find_ports_by_attribute("src");
+        let names = ports
+            .iter()
+            .map(|&(module, port, _)| (module, port))
+            .collect::<Vec<_>>();
+        assert_eq!(names, [("test", "a"), ("test", "b"), ("test", "o")]);
+        assert_eq!(ports[1].2.upto, 1);
+        assert_eq!(netlist.find_ports_by_attribute("keep"), []);
+
+        let memories = netlist.find_memories_by_attribute("src");
+        assert_eq!(memories.len(), 1);
+        assert_eq!(memories[0].0, "test");
+        assert_eq!(memories[0].1, "testmemory");
+        assert_eq!(memories[0].2.size, 1111);
+        assert_eq!(netlist.find_memories_by_attribute("keep"), []);
+
+        netlist
+            .modules
+            .get_mut("test")
+            .unwrap()
+            .netnames
+            .get_mut("b")
+            .unwrap()
+            .attributes
+            .insert("keep".to_owned(), AttributeVal::N(1));
+        let ports = netlist.find_ports_by_attribute("keep");
+        assert_eq!(ports.len(), 1);
+        assert_eq!(ports[0].1, "b");
+    }
+
+    #[test]
+    fn remove_module_test() {
+        let design = netlist! {
+            module top {
+                input a;
+                output y;
+                cell "inv" u0 { i: a, o: y }
+                cell "$not" n { A: a, Y: y }
+            }
+            module inv {
+                input i;
+                output o;
+            }
+        };
+
+        let mut netlist = design.clone();
+        let inv = netlist.remove_module("inv").unwrap();
+        assert_eq!(inv, design.modules["inv"]);
+        assert_eq!(netlist.remove_module("inv"), None);
+        assert!(netlist.modules["top"].cells["u0"].attributes.is_empty());
+
+        let mut netlist = design.clone();
+        let inv = netlist.remove_module_and_refs("inv").unwrap();
+        assert_eq!(inv, design.modules["inv"]);
+        let top = &netlist.modules["top"];
+        assert_eq!(
+            top.cells["u0"].attributes.get(TOMBSTONE_ATTRIBUTE),
+            Some(&AttributeVal::S("inv".to_owned()))
+        );
+        assert!(top.cells["n"].attributes.is_empty());
+        assert_eq!(
+            netlist.remove_module_and_refs("inv"),
+            Err(RemoveError::ModuleNotFound("inv".to_owned()))
+        );
+    }
+
+    #[test]
+    fn retain_modules_test() {
+        let design = netlist! {
+            module top {
+                input a;
+                output y;
+                cell "inv" u0 { i: a, o: y }
+                cell "inv" u1 { i: a, o: y }
+            }
+            module inv {
+                input i;
+                output o;
+                cell "$not" n { A: i, Y: o }
+            }
+            module unused {
+                cell "inv" u { }
+            }
+        };
+
+        let mut netlist = design.clone();
+        let summary = netlist
+            .retain_modules(|name, _| name != "unused", false)
+            .unwrap();
+        assert_eq!(summary.modules, ["unused"]);
+        assert_eq!(summary.cells, 1);
+        assert_eq!(summary.dangling_cell_types, [] as [String; 0]);
+        assert_eq!(netlist.modules.len(), 2);
+
+        let mut netlist = design.clone();
+        let err = netlist
+            .retain_modules(|_, module| module.cells.len() != 1, false)
+            .unwrap_err();
+        assert_eq!(
+            err.instances,
+            [
+                DanglingInstance {
+                    module: "top".to_owned(),
+                    cell: "u0".to_owned(),
+                    cell_type: "inv".to_owned(),
+                },
+                DanglingInstance {
+                    module: "top".to_owned(),
+                    cell: "u1".to_owned(),
+                    cell_type: "inv".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(netlist, design);
+
+        let summary = netlist
+            .retain_modules(|_, module| module.cells.len() != 1, true)
+            .unwrap();
+        assert_eq!(summary.modules, ["inv", "unused"]);
+        assert_eq!(summary.cells, 2);
+        assert_eq!(summary.dangling_cell_types, ["inv"]);
+        assert_eq!(netlist.modules.keys().collect::<Vec<_>>(), ["top"]);
+    }
+
+    #[test]
+    fn merge_subset_test() {
+        let design = netlist! {
+            module top {
+                input a;
+                output y;
+                wire t;
+                cell "inv" u0 { i: a, o: t }
+                cell "buf" u1 { i: t, o: y }
+            }
+            module inv {
+                input i;
+                output o;
+                cell "$not" n { A: i, Y: o }
+            }
+            module buf {
+                input i;
+                output o;
+            }
+        };
+        let modified = netlist! {
+            module inv {
+                input i;
+                output o;
+                wire t;
+                cell "$not" n0 { A: i, Y: t }
+                cell "$not" n1 { A: t, Y: o }
+                cell "$not" n2 { A: t, Y: o }
+            }
+            module extra {
+                input i;
+            }
+        };
+
+        let mut netlist = design.clone();
+        let report = netlist.merge_subset(modified.clone(), SubsetMergeOptions::default());
+        assert!(report.merged);
+        assert_eq!(report.replaced, ["inv"]);
+        assert_eq!(report.added, ["extra"]);
+        assert_eq!(report.interface_errors, []);
+        assert_eq!(netlist.modules["inv"], modified.modules["inv"]);
+        assert_eq!(netlist.modules["top"], design.modules["top"]);
+        assert_eq!(netlist.modules.len(), 4);
+
+        let mut netlist = design.clone();
+        netlist.clone_module("inv", "inv$old").unwrap();
+        let report = netlist.merge_subset(modified.clone(), SubsetMergeOptions { keep_old: true });
+        assert_eq!(report.kept_old, [("inv".to_owned(), "inv$old2".to_owned())]);
+        assert_eq!(netlist.modules["inv$old2"], design.modules["inv"]);
+
+        let mut broken = modified.clone();
+        broken
+            .modules
+            .get_mut("inv")
+            .unwrap()
+            .ports
+            .get_mut("o")
+            .unwrap()
+            .direction = PortDirection::Input;
+        let mut netlist = design.clone();
+        let report = netlist.merge_subset(broken, SubsetMergeOptions::default());
+        assert!(!report.merged);
+        assert_eq!(report.interface_errors.len(), 1);
+        assert_eq!(report.interface_errors[0].cell, "u0");
+        assert_eq!(netlist, design);
+
+        // A rewritten module that drops a port, widens another, and adds a third
+        let mut broken = modified;
+        let inv = broken.modules.get_mut("inv").unwrap();
+        let o = inv.ports.remove("o").unwrap();
+        inv.ports.get_mut("i").unwrap().bits.push(BitVal::N(100));
+        inv.ports.insert("o2".to_owned(), o);
+        let mut netlist = design.clone();
+        let report = netlist.merge_subset(broken, SubsetMergeOptions::default());
+        assert!(!report.merged);
+        assert_eq!(report.interface_errors, []);
+        let kinds = report
+            .instance_errors
+            .iter()
+            .map(|err| {
+                assert_eq!(err.module.as_deref(), Some("top"));
+                err.kind.clone()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                invariants::ViolationKind::InstanceWidthMismatch {
+                    cell: "u0".to_owned(),
+                    port: "i".to_owned(),
+                    expected: 2,
+                    found: 1
+                },
+                invariants::ViolationKind::UnknownInstancePort {
+                    cell: "u0".to_owned(),
+                    port: "o".to_owned()
+                },
+                invariants::ViolationKind::MissingInstancePort {
+                    cell: "u0".to_owned(),
+                    port: "o2".to_owned()
+                },
+            ]
+        );
+        assert_eq!(netlist, design);
+    }
+
+    #[test]
+    fn cell_short_description_test() {
+        let mut netlist = integration_netlist();
+        let cell = netlist
+            .modules
+            .get_mut("test")
+            .unwrap()
+            .cells
+            .get_mut("$xor$test-for-json.v:10$1")
+            .unwrap();
+        assert_eq!(cell.short_description("u1"), "$xor (A:8, B:8, Y:8)");
+
+        cell.connections.clear();
+        assert_eq!(cell.short_description("u1"), "$xor");
+    }
+
+    #[test]
+    fn connection_width_test() {
+        let mut netlist = integration_netlist();
+        let cell = netlist
+            .modules
+            .get_mut("test")
+            .unwrap()
+            .cells
+            .get_mut("$xor$test-for-json.v:10$1")
+            .unwrap();
+        assert_eq!(cell.max_connection_width(), 8);
+        assert_eq!(cell.total_connection_bits(), 24);
+
+        cell.connections.clear();
+        assert_eq!(cell.max_connection_width(), 0);
+        assert_eq!(cell.total_connection_bits(), 0);
+    }
+
+    #[test]
+    fn register_latch_test() {
+        let cell = |cell_type: &str| Cell {
+            hide_name: 0,
+            cell_type: cell_type.to_owned(),
+            parameters: HashMap::new(),
+            attributes: HashMap::new(),
+            port_directions: HashMap::new(),
+            connections: HashMap::new(),
+            extra: HashMap::new(),
+        };
+        for cell_type in [
+            "$dff", "$dffe", "$dffsr", "$dffsre", "$adff", "$adffe", "$aldff", "$aldffe", "$sdff",
+            "$sdffe", "$sdffce", "$ff",
+        ] {
+            assert!(cell(cell_type).is_register(), "{}", cell_type);
+            assert!(!cell(cell_type).is_latch(), "{}", cell_type);
+        }
+        for cell_type in [
+            "$_DFF_P_",
+            "$_DFFE_PN_",
+            "$_DFF_PN0_",
+            "$_SDFFE_PP0P_",
+            "$_ALDFF_P_",
+            "$_DFFSR_PPP_",
+            "$_FF_",
+        ] {
+            assert!(cell(cell_type).is_register(), "{}", cell_type);
+            assert!(!cell(cell_type).is_latch(), "{}", cell_type);
+        }
+        for cell_type in [
+            "$dlatch",
+            "$adlatch",
+            "$dlatchsr",
+            "$sr",
+            "$_DLATCH_P_",
+            "$_DLATCH_PN0_",
+            "$_SR_PP_",
+        ] {
+            assert!(cell(cell_type).is_latch(), "{}", cell_type);
+            assert!(!cell(cell_type).is_register(), "{}", cell_type);
+        }
+        for cell_type in ["$and", "$mem", "dff", "$sshr", "$sub", "$_MUX_"] {
+            assert!(!cell(cell_type).is_register(), "{}", cell_type);
+            assert!(!cell(cell_type).is_latch(), "{}", cell_type);
+        }
+    }
+
+    #[test]
+    fn registers_test() {
+        let netlist = integration_netlist();
+        let module = &netlist.modules["test"];
+        assert_eq!(module.registers().count(), 0);
+        assert_eq!(module.latches().count(), 0);
+
+        let netlist = netlist! {
+            module top {
+                input clk;
+                input d;
+                output q[3];
+                cell "$dff" r0 { CLK: clk, D: d, Q: q[0] }
+                cell "$adff" r1 { CLK: clk, ARST: d, D: d, Q: q[1] }
+                cell "$dlatch" l0 { EN: clk, D: d, Q: q[2] }
+                cell "$not" n { A: d, Y: q[2] }
+            }
+        };
+        let top = &netlist.modules["top"];
+        let mut registers = top.registers().map(|(name, _)| name).collect::<Vec<_>>();
+        registers.sort();
+        assert_eq!(registers, ["r0", "r1"]);
+        assert_eq!(top.register_count(), 2);
+        assert_eq!(
+            top.latches().map(|(name, _)| name).collect::<Vec<_>>(),
+            ["l0"]
+        );
+        assert_eq!(top.latch_count(), 1);
+    }
+
+    #[test]
+    fn clock_signals_test() {
+        // clk is 2, d is 3
+        let netlist = netlist! {
+            module top {
+                input clk;
+                input d;
+                output q;
+                cell "$dff" r { CLK: clk, D: d, Q: q }
+                cell "$and" g { A: clk, B: d, Y: q }
+            }
+        };
+        let top = &netlist.modules["top"];
+        assert_eq!(top.clock_signals(), SignalSet::from([2]));
+
+        // Techmapped flip-flops use `C`, `R` and `E`
+        let netlist = netlist! {
+            module top {
+                input clk;
+                input rst;
+                input en;
+                input d;
+                output q;
+                cell "$_DFFE_PN0P_" r { C: clk, R: rst, E: en, D: d, Q: q }
+            }
+        };
+        let top = &netlist.modules["top"];
+        assert_eq!(top.clock_signals(), SignalSet::from([2]));
+        assert_eq!(top.reset_signals(), SignalSet::from([3]));
+        assert_eq!(top.enable_signals(), SignalSet::from([4]));
+        let r = &top.cells["r"];
+        assert_eq!(r.clock_port(), Some(&[BitVal::N(2)][..]));
+        assert_eq!(r.data_port(), Some(&[BitVal::N(5)][..]));
+
+        let netlist = integration_netlist();
+        assert_eq!(netlist.modules["test"].clock_signals(), SignalSet::new());
+    }
+
+    #[test]
+    fn reset_enable_signals_test() {
+        // clk is 2, rst is 3, srst is 4, en is 5, d is 6
+        let netlist = netlist! {
+            module top {
+                input clk;
+                input rst;
+                input srst;
+                input en;
+                input d;
+                output q[4];
+                cell "$adff" r0 { CLK: clk, ARST: rst, D: d, Q: q[0] }
+                cell "$sdffce" r1 { CLK: clk, SRST: srst, EN: en, D: d, Q: q[1] }
+                cell "$dffe" r2 { CLK: clk, EN: en, D: d, Q: q[2] }
+                cell "$dlatch" l { EN: d, D: rst, Q: q[3] }
+            }
+        };
+        let top = &netlist.modules["top"];
+        assert_eq!(top.reset_signals(), SignalSet::from([3, 4]));
+        assert_eq!(top.enable_signals(), SignalSet::from([5]));
+        assert_eq!(top.clock_signals(), SignalSet::from([2]));
+    }
+
+    #[test]
+    fn register_ports_test() {
+        let netlist = netlist! {
+            module top {
+                input clk;
+                input d[2];
+                output q[2];
+                cell "$dff" r { CLK: clk, D: d, Q: q }
+                cell "$dlatch" l { EN: clk, D: d, Q: q }
+            }
+        };
+        let top = &netlist.modules["top"];
+        let r = &top.cells["r"];
+        assert_eq!(r.clock_port(), Some(&[BitVal::N(2)][..]));
+        assert_eq!(r.data_port(), Some(&[BitVal::N(3), BitVal::N(4)][..]));
+        assert_eq!(r.output_port(), Some(&[BitVal::N(5), BitVal::N(6)][..]));
+
+        let l = &top.cells["l"];
+        assert_eq!(l.clock_port(), None);
+        assert_eq!(l.data_port(), None);
+        assert_eq!(l.output_port(), None);
+    }
+
+    #[test]
+    fn flip_port_direction_test() {
+        let mut netlist = integration_netlist();
+        let module = netlist.modules.get_mut("test").unwrap();
+        assert_eq!(module.flip_port_direction("a"), Ok(PortDirection::Input));
+        assert_eq!(module.ports["a"].direction, PortDirection::Output);
+        assert_eq!(module.flip_port_direction("o"), Ok(PortDirection::Output));
+        assert_eq!(module.ports["o"].direction, PortDirection::Input);
+        assert_eq!(
+            module.flip_port_direction("missing"),
+            Err(FlipError::PortNotFound("missing".to_owned()))
+        );
+
+        module.ports.get_mut("b").unwrap().direction = PortDirection::InOut;
+        assert_eq!(module.flip_port_direction("b"), Ok(PortDirection::InOut));
+        assert_eq!(module.ports["b"].direction, PortDirection::InOut);
+    }
+
+    #[test]
+    fn ports_iter_test() {
+        let mut netlist = integration_netlist();
+        let cell = netlist
+            .modules
+            .get_mut("test")
+            .unwrap()
+            .cells
+            .get_mut("$xor$test-for-json.v:10$1")
+            .unwrap();
+        let mut ports = cell.ports_iter().collect::<Vec<_>>();
+        ports.sort_by_key(|(name, _, _)| *name);
+        assert_eq!(
+            ports
+                .iter()
+                .map(|(name, dir, bits)| (*name, *dir, bits.len()))
+                .collect::<Vec<_>>(),
+            [
+                ("A", Some(PortDirection::Input), 8),
+                ("B", Some(PortDirection::Input), 8),
+                ("Y", Some(PortDirection::Output), 8),
+            ]
+        );
+        assert_eq!(ports[0].2[0], BitVal::N(2));
+
+        cell.port_directions.remove("B");
+        assert!(cell
+            .ports_iter()
+            .any(|(name, dir, _)| name == "B" && dir.is_none()));
+    }
+
+    #[test]
+    fn all_nets_named_test() {
+        let netlist = integration_netlist();
+        let nets = netlist.modules["test"].all_nets_named();
+        assert_eq!(
+            nets.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+            ["a", "b", "o"]
+        );
+    }
+
+    #[test]
+    fn write_split_test() {
+        let mut netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                cell "inv" u0 { i: a, o: y }
+            }
+            module inv {
+                input i;
+                output o;
+                cell "$not" n { A: i, Y: o }
+            }
+        };
+        netlist.creator = "test".to_owned();
+        let dir = std::env::temp_dir().join(format!("write_split_test-{}", std::process::id()));
+
+        assert_eq!(netlist.write_split(&dir).unwrap(), 2);
+        for name in ["top", "inv"] {
+            let file = std::fs::File::open(dir.join(format!("{}.json", name))).unwrap();
+            let single = Netlist::from_reader(file).unwrap();
+            assert_eq!(single.creator, "test");
+            assert_eq!(single.modules.len(), 1);
+            assert_eq!(single.modules[name], netlist.modules[name]);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Names that aren't safe as file names are escaped
+        let mut netlist = Netlist::default();
+        for name in ["$paramod\\inv\\W=8", "../escape", "a/b", ".."] {
+            netlist.modules.insert(name.to_owned(), Module::default());
+        }
+        assert_eq!(netlist.write_split(&dir).unwrap(), 4);
+        let mut files = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                "%24paramod%5Cinv%5CW%3D8.json",
+                "%2E.%2Fescape.json",
+                "%2E..json",
+                "a%2Fb.json"
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Names that only differ in case would overwrite each other on some systems
+        let mut netlist = Netlist::default();
+        for name in ["inv", "INV"] {
+            netlist.modules.insert(name.to_owned(), Module::default());
+        }
+        assert!(matches!(
+            netlist.write_split(&dir),
+            Err(Error::FileNameCollision(a, b)) if a == "INV" && b == "inv"
+        ));
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn matches_pattern_test() {
+        let netlist = netlist! {
+            module top {
+                input clk;
+                input d;
+                output q[4];
+                cell "$dff" r0 { CLK: clk, D: d, Q: q[0] }
+                cell "$dffe" r1 { CLK: clk, EN: d, D: d, Q: q[1] }
+                cell "$dffsr" r2 { CLK: clk, D: d, Q: q[2] }
+                cell "$adff" r3 { CLK: clk, ARST: d, D: d, Q: q[3] }
+                cell "$not" n { A: d, Y: q[3] }
+            }
+        };
+        let top = &netlist.modules["top"];
+        assert!(top.cells["r0"].matches_pattern("$dff*"));
+        assert!(top.cells["r2"].matches_pattern("$dff??"));
+        assert!(!top.cells["r3"].matches_pattern("$dff*"));
+        assert!(top.cells["r3"].matches_pattern("*dff*"));
+
+        let mut names = top
+            .cells_matching_pattern("$dff*")
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["r0", "r1", "r2"]);
+        assert_eq!(top.cells_matching_pattern("$xor").count(), 0);
+    }
+
+    #[test]
+    fn to_string_pretty_test() {
+        let netlist = integration_netlist();
+
+        let pretty = netlist.to_string_pretty(None).unwrap();
+        assert!(pretty.contains("\n  \"modules\": {\n    \"test\": {"));
+        assert_eq!(Netlist::from_slice(pretty.as_bytes()).unwrap(), netlist);
+
+        let pretty = netlist.to_string_pretty(Some(4)).unwrap();
+        assert!(pretty.contains("\n    \"modules\": {\n        \"test\": {"));
+        assert_eq!(Netlist::from_slice(pretty.as_bytes()).unwrap(), netlist);
+
+        let mut out = Vec::new();
+        netlist.to_writer_pretty(&mut out, Some(0)).unwrap();
+        assert_eq!(Netlist::from_slice(&out).unwrap(), netlist);
+        assert!(out.windows(2).any(|w| w == b"\n\""));
+    }
+
+    #[test]
+    fn verify_no_multi_drivers_test() {
+        // a is 2, y is 3
+        let netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                cell "$not" n0 { A: a, Y: y }
+                cell "$not" n1 { A: a, Y: y }
+                cell "$tribuf" t { A: a, EN: a, Y: a }
+            }
+            module ok {
+                input a;
+                output y;
+                inout b;
+                cell "$not" n { A: a, Y: y }
+                cell "bus" u0 { p: b }
+                cell "bus" u1 { p: b }
+            }
+            module bus {
+                inout p;
+            }
+        };
+        // The input port `a` counts as a driver too
+        assert_eq!(
+            netlist.verify_no_multi_drivers(),
+            [
+                MultiDriverError {
+                    module: "top".to_owned(),
+                    signal: 2,
+                    drivers: vec!["cell `t` port `Y[0]`".to_owned(), "port `a[0]`".to_owned()],
+                },
+                MultiDriverError {
+                    module: "top".to_owned(),
+                    signal: 3,
+                    drivers: vec![
+                        "cell `n0` port `Y[0]`".to_owned(),
+                        "cell `n1` port `Y[0]`".to_owned()
+                    ],
+                }
+            ]
+        );
+        assert_eq!(integration_netlist().verify_no_multi_drivers(), []);
+    }
+
+    #[test]
+    fn remove_duplicate_connections_test() {
+        let netlist = netlist! {
+            module top {
+                input a[2];
+                input b;
+                output y[2];
+                cell "$and" g0 { A: a, B: a, Y: y }
+                cell "$and" g1 { A: a, B: b, Y: y }
+                cell "$buf" g2 { A: y, Y: y }
+                cell "sub" s { x: b, y: b, z: b }
+            }
+        };
+        assert_eq!(
+            netlist.modules["top"].remove_duplicate_connections(),
+            [
+                ("g0".to_owned(), "A".to_owned(), "B".to_owned()),
+                ("s".to_owned(), "x".to_owned(), "y".to_owned()),
+                ("s".to_owned(), "x".to_owned(), "z".to_owned()),
+                ("s".to_owned(), "y".to_owned(), "z".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn to_string_sorted_test() {
+        let keys = ["src", "keep", "a", "zzz", "init", "b"];
+        let build = |keys: &mut dyn Iterator<Item = &&str>| {
+            let mut netlist = integration_netlist();
+            let module = netlist.modules.get_mut("test").unwrap();
+            for key in keys {
+                module.set_attribute(*key, AttributeVal::from(*key));
+                module
+                    .netnames
+                    .get_mut("a")
+                    .unwrap()
+                    .attributes
+                    .insert(key.to_string(), AttributeVal::N(1));
+            }
+            netlist
+        };
+        let forward = build(&mut keys.iter());
+        let backward = build(&mut keys.iter().rev());
+        assert_eq!(forward, backward);
+
+        let sorted = forward.to_string_sorted().unwrap();
+        assert_eq!(sorted, backward.to_string_sorted().unwrap());
+        assert!(sorted.find("\"a\":\"a\"").unwrap() < sorted.find("\"zzz\":\"zzz\"").unwrap());
+        assert_eq!(Netlist::from_slice(sorted.as_bytes()).unwrap(), forward);
+
+        let mut out = Vec::new();
+        backward.to_writer_sorted(&mut out).unwrap();
+        assert_eq!(out, sorted.as_bytes());
+    }
+
+    #[test]
+    fn to_string_sorted_fields_test() {
+        // Struct fields are written in declaration order by `to_value`, so this only
+        // comes out sorted if the keys are sorted explicitly
+        let netlist = Netlist::from_slice(
+            br#"{
+                "creator": "c",
+                "modules": {
+                    "top": {
+                        "ports": { "b": { "direction": "input", "bits": [2] } },
+                        "cells": {},
+                        "netnames": { "b": { "hide_name": 0, "bits": [2] } }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            netlist.to_string_sorted().unwrap(),
+            concat!(
+                r#"{"creator":"c","modules":{"top":{"cells":{},"#,
+                r#""netnames":{"b":{"bits":[2],"hide_name":0}},"#,
+                r#""ports":{"b":{"bits":[2],"direction":"input"}}}}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn add_netname_for_bits_test() {
+        let mut netlist = integration_netlist();
+        let module = netlist.modules.get_mut("test").unwrap();
+
+        let netname = module
+            .add_netname_for_bits("a_low", vec![BitVal::N(2), BitVal::N(3)])
+            .unwrap();
+        netname.offset = 1;
+        let netname = &module.netnames["a_low"];
+        assert_eq!(netname.hide_name, 0);
+        assert_eq!(netname.bits, [BitVal::N(2), BitVal::N(3)]);
+        assert_eq!(netname.offset, 1);
+
+        assert_eq!(
+            module.add_netname_for_bits("a", vec![BitVal::N(2)]),
+            Err(InsertError::DuplicateNet("a".to_owned()))
+        );
+        assert_eq!(module.netnames["a"].bits.len(), 8);
+    }
+
+    #[test]
+    fn signals_without_names_test() {
+        let netlist = integration_netlist();
+        let module = &netlist.modules["test"];
+        assert_eq!(module.all_signal_ids(), (2..26).collect::<SignalSet>());
+        assert_eq!(module.signals_without_names(), SignalSet::new());
+
+        // a is 2, y is 3, t is 4
+        let mut netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                wire t;
+                cell "$not" n0 { A: a, Y: t }
+                cell "$not" n1 { A: t, Y: y }
+            }
+        };
+        let top = netlist.modules.get_mut("top").unwrap();
+        top.netnames.remove("t");
+        top.cells.get_mut("n1").unwrap().connections.insert(
+            "B".to_owned(),
+            vec![BitVal::N(9), BitVal::S(SpecialBit::_0)],
+        );
+        assert_eq!(top.all_signal_ids(), SignalSet::from([2, 3, 4, 9]));
+        assert_eq!(top.signals_without_names(), SignalSet::from([4, 9]));
+    }
+
+    #[test]
+    fn verify_creator_format_test() {
+        let mut netlist = integration_netlist();
+        assert!(netlist.verify_creator_format());
+        for creator in ["Yosys 0.9", "Yosys 0.38 (git sha1 543faed9c8c)"] {
+            netlist.creator = creator.to_owned();
+            assert!(netlist.verify_creator_format(), "{}", creator);
+        }
+        for creator in [
+            "",
+            "Yosys",
+            "Yosys ",
+            "Yosys x.y",
+            "Yosys 1",
+            "yosys 0.9",
+            "nextpnr",
+        ] {
+            netlist.creator = creator.to_owned();
+            assert!(!netlist.verify_creator_format(), "{}", creator);
+        }
+    }
+
+    #[test]
+    fn set_hide_name_all_test() {
+        let mut netlist = integration_netlist();
+        let module = netlist.modules.get_mut("test").unwrap();
+
+        module.set_hide_name_all_netnames(1);
+        assert!(module
+            .netnames
+            .values()
+            .all(|netname| netname.hide_name == 1));
+        assert_eq!(module.all_nets_named(), []);
+        module.set_hide_name_all_netnames(0);
+        assert_eq!(module.all_nets_named().len(), 4);
+
+        module.set_hide_name_all_cells(0);
+        assert!(module.cells.values().all(|cell| cell.hide_name == 0));
+        module.set_hide_name_all_cells(1);
+        assert!(module.cells.values().all(|cell| cell.hide_name == 1));
+        assert_eq!(module.memories["testmemory"].hide_name, 0);
+    }
+
+    #[test]
+    fn from_path_test() {
+        let dir = std::env::temp_dir().join(format!("from_path_test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let netlist = integration_netlist();
+        let path = dir.join("netlist.json");
+        netlist.to_path(&path).unwrap();
+        assert_eq!(Netlist::from_path(&path).unwrap(), netlist);
+
+        let missing = dir.join("missing.json");
+        let err = Netlist::from_path(&missing).unwrap_err();
+        assert!(matches!(err, PathError::Io { .. }));
+        assert_eq!(err.path(), missing);
+        assert!(err.to_string().starts_with(&missing.display().to_string()));
+
+        let malformed = dir.join("malformed.json");
+        std::fs::write(&malformed, "{\"modules\": [").unwrap();
+        let err = Netlist::from_path(&malformed).unwrap_err();
+        assert!(matches!(err, PathError::Json { .. }));
+        assert_eq!(err.path(), malformed);
+
+        let err = netlist
+            .to_path(dir.join("no-such-dir/out.json"))
+            .unwrap_err();
+        assert!(matches!(err, PathError::Io { .. }));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn module_accessors_test() {
+        let netlist = integration_netlist();
+        let module = &netlist.modules["test"];
+        assert_eq!(
+            module.cell("$xor$test-for-json.v:10$1").unwrap().cell_type,
+            "$xor"
+        );
+        assert_eq!(module.port("b").unwrap().upto, 1);
+        assert_eq!(module.memory("testmemory").unwrap().size, 1111);
+        assert_eq!(module.netname("o").unwrap().bits.len(), 8);
+
+        assert!(module.cell("a").is_none());
+        assert!(module.port("testmemory").is_none());
+        assert!(module.memory("a").is_none());
+        assert!(module.netname("missing").is_none());
+    }
+
+    #[test]
+    fn from_str_value_test() {
+        use std::str::FromStr;
+
+        let text = std::str::from_utf8(INTEGRATION_JSON).unwrap();
+        let netlist = Netlist::from_str(text).unwrap();
+        assert_eq!(netlist, integration_netlist());
+        assert_eq!(text.parse::<Netlist>().unwrap(), netlist);
+        assert!("{\"modules\": 1}".parse::<Netlist>().is_err());
+
+        let mut value = netlist.to_value().unwrap();
+        assert_eq!(value["modules"]["test"]["ports"]["a"]["offset"], 1);
+        let copy = value["modules"]["test"].clone();
+        value["modules"]["copy"] = copy;
+        let spliced = Netlist::from_value(value).unwrap();
+        assert_eq!(spliced.modules["copy"], netlist.modules["test"]);
+        assert!(Netlist::from_value(serde_json::json!([])).is_err());
+    }
+
+    #[test]
+    fn module_count_test() {
+        let empty = Netlist::default();
+        assert_eq!(empty.module_count(), 0);
+        assert!(!empty.has_any_modules());
+        assert!(!empty.has_top_module());
+
+        let netlist = integration_netlist();
+        assert_eq!(netlist.module_count(), 1);
+        assert!(netlist.has_any_modules());
+        assert_eq!(netlist.top_module(), Some("test"));
+
+        let mut netlist = netlist! {
+            module top {
+                input a;
+                cell "sub" u0 { a: a }
+            }
+            module sub {
+                input a;
+            }
+            module lib_cell {
+                input a;
+            }
+        };
+        assert_eq!(netlist.module_count(), 3);
+        assert_eq!(netlist.top_module(), None);
+        assert!(!netlist.has_top_module());
+
+        let lib_cell = netlist.modules.get_mut("lib_cell").unwrap();
+        lib_cell.set_attribute("blackbox", AttributeVal::N(1));
+        assert_eq!(netlist.top_module(), Some("top"));
+
+        let sub = netlist.modules.get_mut("sub").unwrap();
+        sub.set_attribute(
+            "top",
+            AttributeVal::from("00000000000000000000000000000001"),
+        );
+        assert_eq!(netlist.top_module(), Some("sub"));
+        assert!(netlist.has_top_module());
+    }
+
+    #[test]
+    fn strip_internal_test() {
+        let mut netlist = integration_netlist();
+        let cell = netlist
+            .modules
+            .get_mut("test")
+            .unwrap()
+            .cells
+            .get_mut("$xor$test-for-json.v:10$1")
+            .unwrap();
+        assert_eq!(cell.strip_internal_parameters(), 0);
+        assert_eq!(cell.strip_internal_attributes(), 0);
+
+        cell.parameters
+            .insert("$source_name".to_owned(), AttributeVal::from("x"));
+        cell.parameters
+            .insert("$flags".to_owned(), AttributeVal::N(3));
+        cell.attributes
+            .insert("$internal".to_owned(), AttributeVal::N(1));
+        assert_eq!(cell.strip_internal_parameters(), 2);
+        assert_eq!(cell.parameters.len(), 5);
+        assert!(cell.parameters.contains_key("A_WIDTH"));
+        assert_eq!(cell.strip_internal_attributes(), 1);
+        assert_eq!(cell.attributes.keys().collect::<Vec<_>>(), ["src"]);
     }
 
     #[test]
-    fn attribute_value_test() {
-        let result = Netlist::from_slice(
+    fn mux_lut_test() {
+        let netlist = netlist! {
+            module top {
+                input a[4];
+                input s[2];
+                output y;
+                cell "$pmux" pmux { A: a[0], B: a[1], S: s, Y: y }
+                cell "$_MUX_" mux { A: a[2], B: a[3], S: s[0], Y: y }
+                cell "$lut" lut { A: a, Y: y } { WIDTH: 4, LUT: "1000000000000000" }
+            }
+        };
+        let top = &netlist.modules["top"];
+
+        let pmux = &top.cells["pmux"];
+        assert!(pmux.is_mux());
+        assert!(!pmux.is_lut());
+        assert_eq!(pmux.mux_select_port(), Some(&top.ports["s"].bits[..]));
+
+        let mux = &top.cells["mux"];
+        assert!(mux.is_mux());
+        assert_eq!(mux.mux_select_port(), Some(&top.ports["s"].bits[..1]));
+
+        let lut = &top.cells["lut"];
+        assert!(lut.is_lut());
+        assert!(!lut.is_mux());
+        assert_eq!(lut.mux_select_port(), None);
+    }
+
+    #[test]
+    fn port_shape_test() {
+        let netlist = integration_netlist();
+        for port in netlist.modules.get("test").unwrap().ports.values() {
+            assert!(port.is_vector());
+            assert!(!port.is_scalar());
+            assert_eq!(port.bit_count(), 8);
+        }
+
+        let netlist = netlist! {
+            module top {
+                input clk;
+            }
+        };
+        let clk = &netlist.modules["top"].ports["clk"];
+        assert!(clk.is_scalar());
+        assert!(!clk.is_vector());
+    }
+
+    #[test]
+    fn net_classes_integration_test() {
+        let netlist = integration_netlist();
+        let classes = netlist.modules.get("test").unwrap().compute_net_classes();
+
+        assert_eq!(classes.len(), 24);
+        assert_eq!(classes[&2], NetClass::ModuleInput);
+        assert_eq!(classes[&17], NetClass::ModuleInput);
+        assert_eq!(classes[&18], NetClass::ModuleOutput);
+        assert_eq!(
+            classes
+                .values()
+                .filter(|&&c| c == NetClass::ModuleOutput)
+                .count(),
+            8
+        );
+    }
+
+    #[test]
+    fn output_driven_by_test() {
+        let netlist = integration_netlist();
+        let drivers = netlist.modules.get("test").unwrap().output_driven_by();
+
+        assert_eq!(drivers.len(), 8);
+        assert_eq!(
+            drivers[&("o", 0)],
+            Some(("$xor$test-for-json.v:10$1", "Y", 0))
+        );
+        assert_eq!(
+            drivers[&("o", 7)],
+            Some(("$xor$test-for-json.v:10$1", "Y", 7))
+        );
+
+        let netlist = netlist! {
+            module top {
+                input a;
+                output y[2];
+            }
+        };
+        let mut top = netlist.modules["top"].clone();
+        top.ports.get_mut("y").unwrap().bits = vec![BitVal::N(2), BitVal::S(SpecialBit::_1)];
+        let drivers = top.output_driven_by();
+        assert_eq!(drivers[&("y", 0)], None);
+        assert_eq!(drivers[&("y", 1)], None);
+    }
+
+    #[test]
+    fn port_fanout_to_cells_test() {
+        let netlist = integration_netlist();
+        let fanout = netlist.modules.get("test").unwrap().port_fanout_to_cells();
+
+        assert_eq!(
+            fanout.get(&("a", 0)).unwrap(),
+            &[("$xor$test-for-json.v:10$1", "A")]
+        );
+        assert_eq!(
+            fanout.get(&("b", 7)).unwrap(),
+            &[("$xor$test-for-json.v:10$1", "B")]
+        );
+        // Only input ports are included
+        assert_eq!(fanout.len(), 16);
+        assert!(!fanout.contains_key(&("o", 0)));
+    }
+
+    #[test]
+    fn canonicalize_test() {
+        let mut a = integration_netlist();
+        let mut b = integration_netlist();
+
+        // Fill a map of `b` in a different order, and an extra object too
+        let attributes = &mut b.modules.get_mut("test").unwrap().attributes;
+        let entries = attributes.drain().collect::<BTreeMap<_, _>>();
+        attributes.extend(entries.into_iter().rev());
+        a.extra.insert(
+            "meta".to_owned(),
+            serde_json::json!({ "x": 1, "y": [{ "b": 2, "a": 3 }] }),
+        );
+        let mut meta = serde_json::Map::new();
+        meta.insert("y".to_owned(), serde_json::json!([{ "a": 3, "b": 2 }]));
+        meta.insert("x".to_owned(), serde_json::json!(1));
+        b.extra.insert("meta".to_owned(), meta.into());
+
+        let canonical = a.to_string_canonical().unwrap();
+        assert_eq!(canonical, b.to_string_canonical().unwrap());
+
+        // Signal numbers are kept
+        let original = a.clone();
+        a.canonicalize();
+        b.canonicalize();
+        assert_eq!(a, original);
+        assert_eq!(a, b);
+        assert_eq!(a.to_string_sorted().unwrap(), canonical);
+
+        // Netlists that only differ in numbering need renumbering as a separate step
+        b.modules.get_mut("test").unwrap().for_each_bit_mut(|bit| {
+            if let BitVal::N(n) = bit {
+                *n = 1000 - *n;
+            }
+        });
+        assert_ne!(
+            a.to_string_canonical().unwrap(),
+            b.to_string_canonical().unwrap()
+        );
+        for netlist in [&mut a, &mut b] {
+            for module in netlist.modules.values_mut() {
+                module.compact_signal_ids();
+            }
+        }
+        assert_eq!(
+            a.to_string_canonical().unwrap(),
+            b.to_string_canonical().unwrap()
+        );
+    }
+
+    #[test]
+    fn structurally_equal_test() {
+        let netlist = integration_netlist();
+        let original = netlist.modules.get("test").unwrap();
+
+        let mut renumbered = original.clone();
+        renumbered.for_each_bit_mut(|bit| {
+            if let BitVal::N(n) = bit {
+                *n += 100;
+            }
+        });
+        assert_ne!(original, &renumbered);
+        assert!(original.is_structurally_equal(&renumbered));
+
+        // Swapping two bits is a real structural change
+        renumbered.ports.get_mut("a").unwrap().bits.swap(0, 1);
+        assert!(!original.is_structurally_equal(&renumbered));
+    }
+
+    #[test]
+    fn split_wide_ports_test() {
+        let mut netlist = Netlist::from_slice(
             br#"
             {
               "modules": {
-                "mymodule": {
+                "sub": {
+                  "ports": {
+                    "a": { "direction": "input", "bits": [ 2, 3, 4, 5 ] },
+                    "b": { "direction": "input", "bits": [ 6 ] }
+                  },
+                  "netnames": {
+                    "a": { "hide_name": 0, "bits": [ 2, 3, 4, 5 ], "attributes": { "keep": 1 } }
+                  }
+                },
+                "top": {
                   "cells": {
-                    "mycell": {
-                      "type": "celltype",
-                      "parameters": {
-                        "testparam": 123
-                      },
-                      "connections": {}
+                    "u0": {
+                      "type": "sub",
+                      "port_directions": { "a": "input", "b": "input" },
+                      "connections": { "a": [ 10, 11, "0", 13 ], "b": [ 14 ] }
                     }
                   }
                 }
@@ -412,36 +5125,70 @@ mod tests {
             }"#,
         )
         .unwrap();
+
+        let sub = netlist.modules.get_mut("sub").unwrap();
+        assert_eq!(sub.split_wide_ports("c"), Err(SplitError::PortNotFound));
+        assert_eq!(sub.split_wide_ports("b"), Err(SplitError::NotMultiBit));
+
+        // An instance connecting the wrong number of bits stops the split
+        let mut narrow = netlist.clone();
+        let u0 = narrow.modules.get_mut("top").unwrap().cells.get_mut("u0");
+        u0.unwrap().connections.get_mut("a").unwrap().pop();
         assert_eq!(
-            result
-                .modules
-                .get("mymodule")
-                .unwrap()
-                .cells
-                .get("mycell")
-                .unwrap()
-                .parameters
-                .get("testparam")
-                .unwrap(),
-            &AttributeVal::N(123)
+            narrow.split_wide_ports("sub", "a"),
+            Err(SplitError::WidthMismatch {
+                module: "top".to_owned(),
+                cell: "u0".to_owned()
+            })
+        );
+        assert!(narrow.modules.get("sub").unwrap().ports.contains_key("a"));
+
+        let new_ports = netlist.split_wide_ports("sub", "a").unwrap();
+        assert_eq!(new_ports, ["a[0]", "a[1]", "a[2]", "a[3]"]);
+
+        let sub = netlist.modules.get("sub").unwrap();
+        assert_eq!(sub.ports.len(), 5);
+        assert!(!sub.ports.contains_key("a"));
+        for (i, name) in new_ports.iter().enumerate() {
+            let port = sub.ports.get(name).unwrap();
+            assert_eq!(port.bits, [BitVal::N(2 + i)]);
+            assert_eq!(port.direction, PortDirection::Input);
+            let netname = sub.netnames.get(name).unwrap();
+            assert_eq!(netname.bits, port.bits);
+            assert_eq!(netname.attributes.get("keep"), Some(&AttributeVal::N(1)));
+        }
+        assert!(!sub.netnames.contains_key("a"));
+        assert_eq!(netlist.ensure_port_netnames(), 1);
+
+        let u0 = netlist.modules.get("top").unwrap().cells.get("u0").unwrap();
+        assert!(!u0.connections.contains_key("a"));
+        assert_eq!(
+            u0.connections.get("a[2]").unwrap(),
+            &[BitVal::S(SpecialBit::_0)]
         );
+        assert_eq!(u0.connections.get("a[3]").unwrap(), &[BitVal::N(13)]);
+        assert_eq!(u0.port_directions.get("a[0]"), Some(&PortDirection::Input));
     }
 
     #[test]
-    #[should_panic]
-    fn invalid_attribute_value_test() {
-        Netlist::from_slice(
+    fn merge_1bit_ports_test() {
+        let mut netlist = Netlist::from_slice(
             br#"
             {
               "modules": {
-                "mymodule": {
+                "sub": {
+                  "ports": {
+                    "a": { "direction": "input", "bits": [ 2, 3, 4, 5 ] },
+                    "b": { "direction": "output", "bits": [ 6 ] },
+                    "c": { "direction": "input", "bits": [ 7, 8 ] }
+                  }
+                },
+                "top": {
                   "cells": {
-                    "mycell": {
-                      "type": "celltype",
-                      "parameters": {
-                        "testparam": [123]
-                      },
-                      "connections": {}
+                    "u0": {
+                      "type": "sub",
+                      "port_directions": { "a": "input", "b": "output" },
+                      "connections": { "a": [ 10, 11, "0", 13 ], "b": [ 14 ] }
                     }
                   }
                 }
@@ -449,136 +5196,102 @@ mod tests {
             }"#,
         )
         .unwrap();
-    }
+        let original = netlist.clone();
 
-    #[test]
-    fn integration_test() {
-        let result = Netlist::from_slice(
-            br#"{
-  "creator": "Yosys 0.14+51 (git sha1 286caa09b, gcc 9.3.0-13 -fPIC -Os)",
-  "modules": {
-    "test": {
-      "attributes": {
-        "cells_not_processed": "00000000000000000000000000000001",
-        "src": "test-for-json.v:1.1-12.10"
-      },
-      "parameter_default_values": {
-        "TESTPARAM": "00000000000000001010010001010101"
-      },
-      "ports": {
-        "a": {
-          "direction": "input",
-          "offset": 1,
-          "bits": [ 2, 3, 4, 5, 6, 7, 8, 9 ]
-        },
-        "b": {
-          "direction": "input",
-          "upto": 1,
-          "bits": [ 10, 11, 12, 13, 14, 15, 16, 17 ]
-        },
-        "o": {
-          "direction": "output",
-          "bits": [ 18, 19, 20, 21, 22, 23, 24, 25 ]
-        }
-      },
-      "cells": {
-        "$xor$test-for-json.v:10$1": {
-          "hide_name": 1,
-          "type": "$xor",
-          "parameters": {
-            "A_SIGNED": "00000000000000000000000000000000",
-            "A_WIDTH": "00000000000000000000000000001000",
-            "B_SIGNED": "00000000000000000000000000000000",
-            "B_WIDTH": "00000000000000000000000000001000",
-            "Y_WIDTH": "00000000000000000000000000001000"
-          },
-          "attributes": {
-            "src": "test-for-json.v:10.12-10.17"
-          },
-          "port_directions": {
-            "A": "input",
-            "B": "input",
-            "Y": "output"
-          },
-          "connections": {
-            "A": [ 2, 3, 4, 5, 6, 7, 8, 9 ],
-            "B": [ 10, 11, 12, 13, 14, 15, 16, 17 ],
-            "Y": [ 18, 19, 20, 21, 22, 23, 24, 25 ]
-          }
-        }
-      },
-      "memories": {
-        "testmemory": {
-          "hide_name": 0,
-          "attributes": {
-            "src": "test-for-json.v:8.12-8.22"
-          },
-          "width": 8,
-          "start_offset": 1,
-          "size": 1111
-        }
-      },
-      "netnames": {
-        "$xor$test-for-json.v:10$1_Y": {
-          "hide_name": 1,
-          "bits": [ 18, 19, 20, 21, 22, 23, 24, 25 ],
-          "attributes": {
-            "src": "test-for-json.v:10.12-10.17"
-          }
-        },
-        "a": {
-          "hide_name": 0,
-          "bits": [ 2, 3, 4, 5, 6, 7, 8, 9 ],
-          "offset": 1,
-          "attributes": {
-            "src": "test-for-json.v:2.17-2.18"
-          }
-        },
-        "b": {
-          "hide_name": 0,
-          "bits": [ 10, 11, 12, 13, 14, 15, 16, 17 ],
-          "upto": 1,
-          "attributes": {
-            "src": "test-for-json.v:3.17-3.18"
-          }
-        },
-        "o": {
-          "hide_name": 0,
-          "bits": [ 18, 19, 20, 21, 22, 23, 24, 25 ],
-          "attributes": {
-            "src": "test-for-json.v:4.18-4.19"
-          }
-        }
-      }
-    }
-  }
-}
-"#,
-        )
-        .unwrap();
+        let new_ports = netlist.split_wide_ports("sub", "a").unwrap();
+        let new_ports = new_ports.iter().map(|x| x.as_str()).collect::<Vec<_>>();
 
+        let sub = netlist.modules.get_mut("sub").unwrap();
+        assert_eq!(sub.merge_1bit_ports("x", &[]), Err(MergeError::NoPorts));
         assert_eq!(
-            result.creator,
-            "Yosys 0.14+51 (git sha1 286caa09b, gcc 9.3.0-13 -fPIC -Os)"
+            sub.merge_1bit_ports("x", &["a[0]", "nope"]),
+            Err(MergeError::PortNotFound("nope".to_owned()))
+        );
+        assert_eq!(
+            sub.merge_1bit_ports("x", &["a[0]", "c"]),
+            Err(MergeError::NotSingleBit("c".to_owned()))
+        );
+        assert_eq!(
+            sub.merge_1bit_ports("x", &["a[0]", "b"]),
+            Err(MergeError::DirectionMismatch)
+        );
+        assert_eq!(
+            sub.merge_1bit_ports("c", &["a[0]", "a[1]"]),
+            Err(MergeError::NameConflict)
+        );
+        assert_eq!(
+            sub.merge_1bit_ports("x", &["a[0]", "a[1]", "a[0]"]),
+            Err(MergeError::DuplicatePort("a[0]".to_owned()))
         );
-        let mod_test = result.modules.get("test").unwrap();
 
+        let mut unconnected = netlist.clone();
+        netlist.merge_1bit_ports("sub", "a", &new_ports).unwrap();
+        assert_eq!(netlist, original);
+
+        // A port left unconnected on an instance keeps the later bits in place
+        let u0 = unconnected
+            .modules
+            .get_mut("top")
+            .unwrap()
+            .cells
+            .get_mut("u0")
+            .unwrap();
+        u0.connections.remove("a[1]");
+        unconnected
+            .merge_1bit_ports("sub", "a", &new_ports)
+            .unwrap();
+        let u0 = unconnected
+            .modules
+            .get("top")
+            .unwrap()
+            .cells
+            .get("u0")
+            .unwrap();
         assert_eq!(
-            mod_test
-                .parameter_default_values
-                .get("TESTPARAM")
-                .unwrap()
-                .to_number()
-                .unwrap(),
-            42069
+            u0.connections.get("a").unwrap(),
+            &[
+                BitVal::N(10),
+                BitVal::S(SpecialBit::X),
+                BitVal::S(SpecialBit::_0),
+                BitVal::N(13)
+            ]
         );
+    }
 
-        assert_eq!(mod_test.ports.get("a").unwrap().offset, 1);
-        assert_eq!(mod_test.ports.get("b").unwrap().upto, 1);
-        assert_eq!(mod_test.ports.get("o").unwrap().offset, 0);
-        assert_eq!(mod_test.ports.get("o").unwrap().upto, 0);
+    #[test]
+    fn normalize_port_names_test() {
+        let mut netlist = netlist! {
+            module top {
+                wire x;
+                wire y;
+                wire z;
+                cell "$and" u0 { a: x, b: y, Y: z }
+            }
+        };
+        let cell = netlist
+            .modules
+            .get_mut("top")
+            .unwrap()
+            .cells
+            .get_mut("u0")
+            .unwrap();
 
-        assert_eq!(mod_test.memories.get("testmemory").unwrap().size, 1111);
+        assert_eq!(cell.normalize_port_names(true), 4);
+        let mut names = cell.connections.keys().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["A", "B", "Y"]);
+        assert_eq!(cell.connections.get("A").unwrap(), &[BitVal::N(2)]);
+        assert_eq!(cell.port_directions.get("B"), Some(&PortDirection::Input));
+        assert_eq!(cell.normalize_port_names(true), 0);
+
+        assert_eq!(cell.normalize_port_names(false), 6);
+        assert!(cell.connections.contains_key("y"));
+
+        // Renaming never overwrites an existing port
+        cell.connections.insert("A".to_owned(), vec![BitVal::N(5)]);
+        assert_eq!(cell.normalize_port_names(true), 5);
+        assert_eq!(cell.connections.get("A").unwrap(), &[BitVal::N(5)]);
+        assert_eq!(cell.connections.get("a").unwrap(), &[BitVal::N(2)]);
     }
 
     #[test]
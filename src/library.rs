@@ -0,0 +1,159 @@
+use crate::{Cell, PortDirection};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+
+/// Width of a port in a `CellLibrary`
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LibraryWidth {
+    /// A fixed number of bits
+    Fixed(usize),
+    /// The value of the named cell parameter
+    Parameter(String),
+}
+
+/// A port of a cell in a `CellLibrary`
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct LibraryPort {
+    /// Port direction
+    pub direction: PortDirection,
+    /// Port width
+    pub width: LibraryWidth,
+}
+
+/// A cell type in a `CellLibrary`
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LibraryCell {
+    /// Ports of the cell
+    pub ports: HashMap<String, LibraryPort>,
+    /// Parameters every instance must set
+    #[serde(default)]
+    pub parameters: Vec<String>,
+}
+
+impl LibraryCell {
+    /// The width of `port` on `cell`, or `None` if the port doesn't exist or its
+    /// width parameter isn't set
+    pub fn port_width(&self, port: &str, cell: &Cell) -> Option<usize> {
+        match &self.ports.get(port)?.width {
+            LibraryWidth::Fixed(n) => Some(*n),
+            LibraryWidth::Parameter(param) => cell.parameters.get(param)?.to_number(),
+        }
+    }
+}
+
+/// Descriptions of cell types that aren't modules in the netlist, such as vendor
+/// primitives
+///
+/// A library is a JSON object mapping cell type names to a `ports` object (each port
+/// having a `direction` and a `width`, which is a number or the name of a
+/// parameter) and an optional list of required `parameters`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CellLibrary {
+    /// Cell types by name
+    pub cells: HashMap<String, LibraryCell>,
+}
+
+impl CellLibrary {
+    /// Create an empty library
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A library of common iCE40 and ECP5 primitives
+    pub fn builtin() -> Self {
+        Self::from_slice(include_bytes!("library/builtin.json")).unwrap()
+    }
+
+    /// Read a library description
+    pub fn from_reader<R: Read>(reader: R) -> Result<CellLibrary, serde_json::Error> {
+        serde_json::from_reader(reader)
+    }
+
+    /// Read a library description from a slice
+    pub fn from_slice(input: &[u8]) -> Result<CellLibrary, serde_json::Error> {
+        serde_json::from_slice(input)
+    }
+
+    /// Add the cells of `other`, replacing cells of the same name
+    pub fn merge(&mut self, other: CellLibrary) {
+        self.cells.extend(other.cells);
+    }
+
+    /// The description of a cell type
+    pub fn get(&self, cell_type: &str) -> Option<&LibraryCell> {
+        self.cells.get(cell_type)
+    }
+}
+
+impl Cell {
+    /// The direction of `port`, from `port_directions` or else from `library`
+    pub fn direction_of(&self, port: &str, library: Option<&CellLibrary>) -> Option<PortDirection> {
+        if let Some(dir) = self.port_directions.get(port) {
+            return Some(*dir);
+        }
+        Some(library?.get(&self.cell_type)?.ports.get(port)?.direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{netlist, AttributeVal};
+
+    #[test]
+    fn builtin_test() {
+        let lib = CellLibrary::builtin();
+        let lut = lib.get("SB_LUT4").unwrap();
+        assert_eq!(lut.ports["O"].direction, PortDirection::Output);
+        assert_eq!(lut.parameters, ["LUT_INIT"]);
+        assert!(lib.get("TRELLIS_FF").is_some());
+    }
+
+    #[test]
+    fn merge_and_direction_test() {
+        let mut lib = CellLibrary::builtin();
+        lib.merge(
+            CellLibrary::from_slice(
+                br#"
+                {
+                  "MY_FIFO": {
+                    "ports": {
+                      "DIN": { "direction": "input", "width": "WIDTH" },
+                      "DOUT": { "direction": "output", "width": "WIDTH" }
+                    },
+                    "parameters": [ "WIDTH", "DEPTH" ]
+                  },
+                  "SB_GB": { "ports": {} }
+                }"#,
+            )
+            .unwrap(),
+        );
+        assert!(lib.get("SB_GB").unwrap().ports.is_empty());
+
+        let netlist = netlist! {
+            module top {
+                input d[8];
+                output q[8];
+                cell "MY_FIFO" fifo { DIN: d, DOUT: q } { WIDTH: 8 }
+            }
+        };
+        let fifo = &netlist.modules["top"].cells["fifo"];
+        assert_eq!(fifo.direction_of("DOUT", None), None);
+        assert_eq!(
+            fifo.direction_of("DOUT", Some(&lib)),
+            Some(PortDirection::Output)
+        );
+        assert_eq!(fifo.direction_of("NOPE", Some(&lib)), None);
+
+        let desc = lib.get("MY_FIFO").unwrap();
+        assert_eq!(desc.port_width("DIN", fifo), Some(8));
+        let mut narrow = fifo.clone();
+        narrow
+            .parameters
+            .insert("WIDTH".to_owned(), AttributeVal::N(4));
+        assert_eq!(desc.port_width("DIN", &narrow), Some(4));
+    }
+}
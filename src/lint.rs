@@ -0,0 +1,819 @@
+use crate::invariants::{self, ViolationKind};
+use crate::timing::is_sequential;
+use crate::{
+    AttributeVal, BitVal, Endpoint, Module, Netlist, ObjectPath, SpecialBit, UndefLocation,
+};
+use serde_derive::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+/// Attribute listing the rules that don't apply to an object, separated by commas
+/// or spaces
+pub const WAIVE_ATTRIBUTE: &str = "lint_waive";
+
+/// How seriously a lint finding is taken
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Reported, but doesn't fail the run
+    Warn,
+    /// Fails the run
+    Deny,
+}
+
+/// One problem found by a lint rule
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+pub struct LintFinding {
+    /// The object the problem is on
+    pub object: ObjectPath,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl LintFinding {
+    fn new(object: ObjectPath, message: String) -> Self {
+        LintFinding { object, message }
+    }
+}
+
+/// A check run by `Lint`
+pub trait LintRule {
+    /// Identifier used with `Lint::deny`, `Lint::allow`, and the `lint_waive`
+    /// attribute
+    fn id(&self) -> &str;
+    /// Severity of the findings unless the rule is denied
+    fn default_severity(&self) -> Severity {
+        Severity::Warn
+    }
+    /// Find every problem in the netlist
+    fn check(&self, netlist: &Netlist) -> Vec<LintFinding>;
+}
+
+/// Findings of one rule in a `LintReport`
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+pub struct RuleFindings {
+    /// Severity of the rule
+    pub severity: Severity,
+    /// Findings that weren't waived, sorted
+    pub findings: Vec<LintFinding>,
+    /// Number of findings waived with the `lint_waive` attribute
+    pub waived: usize,
+}
+
+/// Result of `Lint::run`
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, Serialize)]
+pub struct LintReport {
+    /// Whether there are no findings of rules with `Severity::Deny`
+    pub passed: bool,
+    /// Findings of each rule that was run, by rule identifier
+    pub rules: BTreeMap<String, RuleFindings>,
+}
+
+impl LintReport {
+    /// Number of findings with the given severity
+    pub fn count(&self, severity: Severity) -> usize {
+        self.rules
+            .values()
+            .filter(|rule| rule.severity == severity)
+            .map(|rule| rule.findings.len())
+            .sum()
+    }
+
+    /// Every finding with the given severity, with the identifier of its rule
+    pub fn findings(&self, severity: Severity) -> impl Iterator<Item = (&str, &LintFinding)> {
+        self.rules
+            .iter()
+            .filter(move |(_, rule)| rule.severity == severity)
+            .flat_map(|(id, rule)| rule.findings.iter().map(move |f| (id.as_str(), f)))
+    }
+}
+
+/// Runs a set of lint rules over a netlist
+#[derive(Default)]
+pub struct Lint {
+    rules: Vec<Box<dyn LintRule>>,
+    denied: HashSet<String>,
+    allowed: HashSet<String>,
+}
+
+impl fmt::Debug for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lint")
+            .field(
+                "rules",
+                &self.rules.iter().map(|rule| rule.id()).collect::<Vec<_>>(),
+            )
+            .field("denied", &self.denied)
+            .field("allowed", &self.allowed)
+            .finish()
+    }
+}
+
+impl Lint {
+    /// Create a lint runner with no rules
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the built-in rules: `undriven-nets`, `multiple-drivers`, `latches`,
+    /// `combinational-loops`, `width-mismatches`, `x-constants`, `hide-name`, and
+    /// `unnamed-bits`
+    ///
+    /// `multiple-drivers` and `combinational-loops` are denied by default; the others
+    /// only warn.
+    pub fn with_default_rules(self) -> Self {
+        self.add_rule(UndrivenNets)
+            .add_rule(MultipleDrivers)
+            .add_rule(Latches)
+            .add_rule(CombinationalLoops)
+            .add_rule(WidthMismatches)
+            .add_rule(XConstants)
+            .add_rule(HideName)
+            .add_rule(UnnamedBits)
+    }
+
+    /// Add a rule
+    pub fn add_rule(mut self, rule: impl LintRule + 'static) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Make the findings of a rule fail the run
+    pub fn deny(mut self, id: &str) -> Self {
+        self.allowed.remove(id);
+        self.denied.insert(id.to_owned());
+        self
+    }
+
+    /// Don't run a rule
+    pub fn allow(mut self, id: &str) -> Self {
+        self.denied.remove(id);
+        self.allowed.insert(id.to_owned());
+        self
+    }
+
+    /// Run every rule that isn't allowed
+    pub fn run(&self, netlist: &Netlist) -> LintReport {
+        let mut report = LintReport {
+            passed: true,
+            rules: BTreeMap::new(),
+        };
+        for rule in &self.rules {
+            let id = rule.id();
+            if self.allowed.contains(id) {
+                continue;
+            }
+            let severity = if self.denied.contains(id) {
+                Severity::Deny
+            } else {
+                rule.default_severity()
+            };
+
+            let mut findings = rule.check(netlist);
+            findings.sort();
+            findings.dedup();
+            let total = findings.len();
+            findings.retain(|finding| !is_waived(netlist, &finding.object, id));
+            if severity == Severity::Deny && !findings.is_empty() {
+                report.passed = false;
+            }
+            report.rules.insert(
+                id.to_owned(),
+                RuleFindings {
+                    severity,
+                    waived: total - findings.len(),
+                    findings,
+                },
+            );
+        }
+        report
+    }
+}
+
+/// Whether the object or its module has a `lint_waive` attribute listing `id`
+fn is_waived(netlist: &Netlist, object: &ObjectPath, id: &str) -> bool {
+    let lists = |attributes: Option<&HashMap<String, AttributeVal>>| {
+        attributes
+            .and_then(|attributes| attributes.get(WAIVE_ATTRIBUTE))
+            .and_then(|val| val.to_string_if_string())
+            .is_some_and(|list| list.split([',', ' ']).any(|waived| waived.trim() == id))
+    };
+
+    let Some(module) = netlist.modules.get(object.module_name()) else {
+        return false;
+    };
+    let attributes = match object {
+        ObjectPath::Module { .. } => None,
+        ObjectPath::Cell { cell, .. } => module.cells.get(cell).map(|c| &c.attributes),
+        ObjectPath::Net { net, .. } => module.netnames.get(net).map(|n| &n.attributes),
+    };
+    lists(Some(&module.attributes)) || lists(attributes)
+}
+
+/// The netname bit for each signal, chosen the same way as the net names in timing
+/// reports
+fn net_paths(module_name: &str, module: &Module) -> HashMap<usize, ObjectPath> {
+    module
+        .signal_netname_bits()
+        .into_iter()
+        .map(|(n, (name, _, i))| (n, ObjectPath::net_bit(module_name, name, i)))
+        .collect()
+}
+
+fn sorted_modules(netlist: &Netlist) -> Vec<(&String, &Module)> {
+    let mut modules = netlist.modules.iter().collect::<Vec<_>>();
+    modules.sort_by_key(|(name, _)| *name);
+    modules
+}
+
+struct UndrivenNets;
+
+impl LintRule for UndrivenNets {
+    fn id(&self) -> &str {
+        "undriven-nets"
+    }
+
+    fn check(&self, netlist: &Netlist) -> Vec<LintFinding> {
+        let mut findings = Vec::new();
+        for (module_name, module) in sorted_modules(netlist) {
+            let index = module.connectivity();
+            let paths = net_paths(module_name, module);
+            for n in index.sunk_signals() {
+                if !index.drivers(n).is_empty() {
+                    continue;
+                }
+                let object = paths
+                    .get(&n)
+                    .cloned()
+                    .unwrap_or_else(|| ObjectPath::module(module_name));
+                let readers = index
+                    .sinks(n)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+                findings.push(LintFinding::new(
+                    object,
+                    format!(
+                        "signal {} is read by {} but not driven",
+                        n,
+                        readers.join(", ")
+                    ),
+                ));
+            }
+        }
+        findings
+    }
+}
+
+struct MultipleDrivers;
+
+impl LintRule for MultipleDrivers {
+    fn id(&self) -> &str {
+        "multiple-drivers"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Deny
+    }
+
+    fn check(&self, netlist: &Netlist) -> Vec<LintFinding> {
+        let mut findings = Vec::new();
+        for (module_name, module) in sorted_modules(netlist) {
+            let index = module.connectivity();
+            let paths = net_paths(module_name, module);
+            for n in index.multiply_driven_signals() {
+                let drivers = index
+                    .exclusive_drivers(n)
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+                let object = paths
+                    .get(&n)
+                    .cloned()
+                    .unwrap_or_else(|| ObjectPath::module(module_name));
+                findings.push(LintFinding::new(
+                    object,
+                    format!("signal {} is driven by {}", n, drivers.join(", ")),
+                ));
+            }
+        }
+        findings
+    }
+}
+
+struct Latches;
+
+impl LintRule for Latches {
+    fn id(&self) -> &str {
+        "latches"
+    }
+
+    fn check(&self, netlist: &Netlist) -> Vec<LintFinding> {
+        let mut findings = Vec::new();
+        for (module_name, module) in sorted_modules(netlist) {
+            for (cell_name, cell) in module.latches() {
+                findings.push(LintFinding::new(
+                    ObjectPath::cell(module_name, cell_name),
+                    format!("cell is a `{}` latch", cell.cell_type),
+                ));
+            }
+        }
+        findings
+    }
+}
+
+struct CombinationalLoops;
+
+impl LintRule for CombinationalLoops {
+    fn id(&self) -> &str {
+        "combinational-loops"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Deny
+    }
+
+    fn check(&self, netlist: &Netlist) -> Vec<LintFinding> {
+        let mut findings = Vec::new();
+        for (module_name, module) in sorted_modules(netlist) {
+            for cells in combinational_loops(module) {
+                findings.push(LintFinding::new(
+                    ObjectPath::cell(module_name, cells[0]),
+                    format!("combinational loop through {}", cells.join(", ")),
+                ));
+            }
+        }
+        findings
+    }
+}
+
+/// Groups of internal cells that feed each other without passing through a
+/// flip-flop or latch, each sorted by name
+fn combinational_loops(module: &Module) -> Vec<Vec<&str>> {
+    struct Tarjan<'a> {
+        edges: BTreeMap<&'a str, BTreeSet<&'a str>>,
+        index: HashMap<&'a str, usize>,
+        low: HashMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        on_stack: HashSet<&'a str>,
+        loops: Vec<Vec<&'a str>>,
+    }
+
+    impl<'a> Tarjan<'a> {
+        fn enter(&mut self, cell: &'a str, calls: &mut Vec<(&'a str, Vec<&'a str>)>) {
+            let i = self.index.len();
+            self.index.insert(cell, i);
+            self.low.insert(cell, i);
+            self.stack.push(cell);
+            self.on_stack.insert(cell);
+            // Reversed so that popping gives the targets in order
+            calls.push((cell, self.edges[cell].iter().rev().copied().collect()));
+        }
+
+        /// Visit everything reachable from `root`, with an explicit call stack so a
+        /// long chain of cells can't overflow the real one
+        fn visit(&mut self, root: &'a str) {
+            let mut calls = Vec::new();
+            self.enter(root, &mut calls);
+            while let Some((cell, next)) = calls.last_mut() {
+                let cell = *cell;
+                if let Some(to) = next.pop() {
+                    if !self.index.contains_key(to) {
+                        self.enter(to, &mut calls);
+                    } else if self.on_stack.contains(to) {
+                        self.low.insert(cell, self.low[cell].min(self.index[to]));
+                    }
+                    continue;
+                }
+
+                calls.pop();
+                if let Some(&(parent, _)) = calls.last() {
+                    self.low
+                        .insert(parent, self.low[parent].min(self.low[cell]));
+                }
+                if self.low[cell] == self.index[cell] {
+                    let mut group = Vec::new();
+                    while let Some(member) = self.stack.pop() {
+                        self.on_stack.remove(member);
+                        group.push(member);
+                        if member == cell {
+                            break;
+                        }
+                    }
+                    if group.len() > 1 || self.edges[cell].contains(cell) {
+                        group.sort();
+                        self.loops.push(group);
+                    }
+                }
+            }
+        }
+    }
+
+    let is_comb = |cell: &str| {
+        let cell_type = &module.cells[cell].cell_type;
+        cell_type.starts_with('$') && !is_sequential(cell_type)
+    };
+    let index = module.connectivity();
+    let mut edges = BTreeMap::new();
+    for (name, cell) in &module.cells {
+        if !is_comb(name) {
+            continue;
+        }
+        let targets: &mut BTreeSet<&str> = edges.entry(name.as_str()).or_default();
+        for (port, bits) in &cell.connections {
+            if !cell
+                .port_directions
+                .get(port)
+                .is_some_and(|dir| dir.drives_net_from_instance())
+            {
+                continue;
+            }
+            for bit in bits {
+                let BitVal::N(n) = *bit else {
+                    continue;
+                };
+                for sink in index.sinks(n) {
+                    if let Endpoint::CellPort { cell, .. } = *sink {
+                        if is_comb(cell) {
+                            targets.insert(cell);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        edges,
+        index: HashMap::new(),
+        low: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: HashSet::new(),
+        loops: Vec::new(),
+    };
+    let cells = tarjan.edges.keys().copied().collect::<Vec<_>>();
+    for cell in cells {
+        if !tarjan.index.contains_key(cell) {
+            tarjan.visit(cell);
+        }
+    }
+    tarjan.loops.sort();
+    tarjan.loops
+}
+
+struct WidthMismatches;
+
+impl LintRule for WidthMismatches {
+    fn id(&self) -> &str {
+        "width-mismatches"
+    }
+
+    fn check(&self, netlist: &Netlist) -> Vec<LintFinding> {
+        let mut findings = Vec::new();
+        for (module_name, module) in sorted_modules(netlist) {
+            for (cell_name, cell) in &module.cells {
+                // Instances are checked the same way as `invariants::instances_resolve`;
+                // internal cells against their `_WIDTH` parameters
+                let target = netlist.modules.get(&cell.cell_type);
+                let port_width = |port: &str| match target {
+                    Some(target) => target.ports.get(port).map(|p| Some(p.bits.len())),
+                    None => Some(
+                        cell.parameters
+                            .get(&format!("{}_WIDTH", port))
+                            .and_then(|val| val.to_number()),
+                    ),
+                };
+                for kind in invariants::connection_violations(cell_name, cell, port_width) {
+                    if let ViolationKind::InstanceWidthMismatch {
+                        port,
+                        expected,
+                        found,
+                        ..
+                    } = kind
+                    {
+                        let source = if target.is_some() {
+                            "the module port"
+                        } else {
+                            "its `_WIDTH` parameter"
+                        };
+                        findings.push(LintFinding::new(
+                            ObjectPath::cell(module_name, cell_name),
+                            format!(
+                                "port `{}` has {} bits but {} says {}",
+                                port, found, source, expected
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        findings
+    }
+}
+
+struct XConstants;
+
+impl LintRule for XConstants {
+    fn id(&self) -> &str {
+        "x-constants"
+    }
+
+    fn check(&self, netlist: &Netlist) -> Vec<LintFinding> {
+        let report = netlist.undef_report();
+        let mut findings = Vec::new();
+        for bit in report.bits {
+            let (object, place) = match bit.location {
+                UndefLocation::Cell { cell, port } => (
+                    ObjectPath::cell(&bit.module, &cell),
+                    format!("port `{}[{}]`", port, bit.bit),
+                ),
+                UndefLocation::Port { port } => {
+                    let object = if netlist.modules[&bit.module].netnames.contains_key(&port) {
+                        ObjectPath::net_bit(&bit.module, &port, bit.bit)
+                    } else {
+                        ObjectPath::module(&bit.module)
+                    };
+                    (object, format!("module port `{}[{}]`", port, bit.bit))
+                }
+            };
+            findings.push(LintFinding::new(
+                object,
+                format!(
+                    "constant `{}` on {}",
+                    if bit.value == SpecialBit::X { "x" } else { "z" },
+                    place
+                ),
+            ));
+        }
+        for param in report.parameters {
+            let object = match &param.cell {
+                Some(cell) => ObjectPath::cell(&param.module, cell),
+                None => ObjectPath::module(&param.module),
+            };
+            findings.push(LintFinding::new(
+                object,
+                format!("parameter `{}` is `{}`", param.parameter, param.value),
+            ));
+        }
+        findings
+    }
+}
+
+struct HideName;
+
+impl LintRule for HideName {
+    fn id(&self) -> &str {
+        "hide-name"
+    }
+
+    fn check(&self, netlist: &Netlist) -> Vec<LintFinding> {
+        let message = |name: &str, hide_name: usize| {
+            if name.starts_with('$') {
+                (hide_name == 0).then(|| "name starts with `$` but hide_name is 0".to_owned())
+            } else {
+                (hide_name != 0).then(|| "public name but hide_name is set".to_owned())
+            }
+        };
+
+        let mut findings = Vec::new();
+        for (module_name, module) in sorted_modules(netlist) {
+            for (name, cell) in &module.cells {
+                if let Some(message) = message(name, cell.hide_name) {
+                    findings.push(LintFinding::new(
+                        ObjectPath::cell(module_name, name),
+                        message,
+                    ));
+                }
+            }
+            for (name, netname) in &module.netnames {
+                if let Some(message) = message(name, netname.hide_name) {
+                    findings.push(LintFinding::new(
+                        ObjectPath::net(module_name, name),
+                        message,
+                    ));
+                }
+            }
+        }
+        findings
+    }
+}
+
+struct UnnamedBits;
+
+impl LintRule for UnnamedBits {
+    fn id(&self) -> &str {
+        "unnamed-bits"
+    }
+
+    fn check(&self, netlist: &Netlist) -> Vec<LintFinding> {
+        let mut findings = Vec::new();
+        for (module_name, module) in sorted_modules(netlist) {
+            let named = module
+                .netnames
+                .values()
+                .flat_map(|netname| &netname.bits)
+                .collect::<HashSet<_>>();
+            let mut cells = module.cells.iter().collect::<Vec<_>>();
+            cells.sort_by_key(|(name, _)| *name);
+            let mut seen = HashSet::new();
+            for (cell_name, cell) in cells {
+                let mut ports = cell.connections.iter().collect::<Vec<_>>();
+                ports.sort_by_key(|(name, _)| *name);
+                for (port, bits) in ports {
+                    for (i, bit) in bits.iter().enumerate() {
+                        if let BitVal::N(n) = bit {
+                            if !named.contains(bit) && seen.insert(*n) {
+                                findings.push(LintFinding::new(
+                                    ObjectPath::cell(module_name, cell_name),
+                                    format!(
+                                        "signal {} on port `{}[{}]` has no netname",
+                                        n, port, i
+                                    ),
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        findings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netlist;
+
+    fn design() -> Netlist {
+        let mut netlist = netlist! {
+            module top {
+                input a[2];
+                input clk;
+                output y[2];
+                wire t;
+                wire u;
+                wire q;
+                cell "$and" g1 { A: a[0], B: u, Y: t } { A_WIDTH: 1, B_WIDTH: 2, Y_WIDTH: 1 }
+                cell "$not" g2 { A: t, Y: u }
+                cell "$not" g3 { A: a[1], Y: y[0] }
+                cell "$not" g4 { A: a[1], Y: y[0] }
+                cell "$dlatch" l { EN: clk, D: t, Q: q }
+                cell "$dff" r { CLK: clk, D: q, Q: y[1] }
+            }
+        };
+        let top = netlist.modules.get_mut("top").unwrap();
+        // A bit with no netname, read but not driven
+        top.cells
+            .get_mut("g3")
+            .unwrap()
+            .connections
+            .insert("A".to_owned(), vec![BitVal::N(100)]);
+        top.cells
+            .get_mut("g4")
+            .unwrap()
+            .connections
+            .insert("B".to_owned(), vec![BitVal::S(SpecialBit::X)]);
+        top.netnames.get_mut("t").unwrap().hide_name = 1;
+        netlist
+    }
+
+    fn objects(report: &LintReport, rule: &str) -> Vec<String> {
+        report.rules[rule]
+            .findings
+            .iter()
+            .map(|finding| finding.object.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn long_combinational_loop_test() {
+        // Deep enough to overflow the stack if cells were visited recursively
+        let n = 20_000;
+        let mut builder = crate::testutil::NetlistBuilder::new();
+        builder.module("top");
+        for i in 0..n {
+            builder.wire("top", &format!("w{}", i), 1, None);
+        }
+        for i in 0..n {
+            builder.cell(
+                "top",
+                &format!("c{}", i),
+                "$not",
+                &[
+                    ("A", crate::testutil::Conn::Wire(&format!("w{}", i))),
+                    (
+                        "Y",
+                        crate::testutil::Conn::Wire(&format!("w{}", (i + 1) % n)),
+                    ),
+                ],
+                Vec::new(),
+            );
+        }
+        let mut netlist = builder.build();
+        let loops = combinational_loops(&netlist.modules["top"]);
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), n);
+
+        // Breaking the loop leaves a long chain with no loop in it
+        let top = netlist.modules.get_mut("top").unwrap();
+        top.cells.remove("c0");
+        assert!(combinational_loops(top).is_empty());
+    }
+
+    #[test]
+    fn default_rules_test() {
+        let netlist = design();
+        let report = Lint::new().with_default_rules().run(&netlist);
+        assert!(!report.passed);
+        assert_eq!(report.rules.len(), 8);
+
+        assert_eq!(objects(&report, "undriven-nets"), ["top"]);
+        assert_eq!(objects(&report, "multiple-drivers"), ["top/y/0"]);
+        assert_eq!(
+            report.rules["multiple-drivers"].findings[0].message,
+            "signal 5 is driven by cell `g3` port `Y[0]`, cell `g4` port `Y[0]`"
+        );
+        assert_eq!(objects(&report, "latches"), ["top/l"]);
+        assert_eq!(objects(&report, "combinational-loops"), ["top/g1"]);
+        assert_eq!(
+            report.rules["combinational-loops"].findings[0].message,
+            "combinational loop through g1, g2"
+        );
+        assert_eq!(objects(&report, "width-mismatches"), ["top/g1"]);
+        assert_eq!(objects(&report, "x-constants"), ["top/g4"]);
+        assert_eq!(objects(&report, "hide-name"), ["top/t"]);
+        assert_eq!(objects(&report, "unnamed-bits"), ["top/g3"]);
+
+        assert_eq!(report.count(Severity::Deny), 2);
+        assert_eq!(report.count(Severity::Warn), 6);
+        assert_eq!(
+            report
+                .findings(Severity::Deny)
+                .map(|(rule, _)| rule)
+                .collect::<Vec<_>>(),
+            ["combinational-loops", "multiple-drivers"]
+        );
+    }
+
+    #[test]
+    fn agrees_with_invariants_test() {
+        // An input port driven by a cell too, and a gate-level latch
+        let netlist = netlist! {
+            module top {
+                input a;
+                input en;
+                output q;
+                cell "$not" n { A: q, Y: a }
+                cell "$_DLATCH_P_" l { E: en, D: a, Q: q }
+            }
+        };
+        let report = Lint::new().with_default_rules().run(&netlist);
+        assert_eq!(
+            report.rules["multiple-drivers"].findings[0].message,
+            "signal 2 is driven by cell `n` port `Y[0]`, port `a[0]`"
+        );
+        assert!(invariants::unique_driver_per_bit(&netlist.modules["top"]).is_err());
+        assert_eq!(objects(&report, "latches"), ["top/l"]);
+    }
+
+    #[test]
+    fn deny_allow_waive_test() {
+        let mut netlist = design();
+        let lint = Lint::new()
+            .with_default_rules()
+            .allow("multiple-drivers")
+            .allow("combinational-loops")
+            .allow("unnamed-bits");
+        let report = lint.run(&netlist);
+        assert!(report.passed);
+        assert!(!report.rules.contains_key("unnamed-bits"));
+
+        let lint = lint.deny("latches");
+        assert!(!lint.run(&netlist).passed);
+
+        netlist
+            .modules
+            .get_mut("top")
+            .unwrap()
+            .cells
+            .get_mut("l")
+            .unwrap()
+            .attributes
+            .insert(
+                WAIVE_ATTRIBUTE.to_owned(),
+                AttributeVal::from("x-constants, latches"),
+            );
+        let report = lint.run(&netlist);
+        assert!(report.passed);
+        assert_eq!(report.rules["latches"].findings, []);
+        assert_eq!(report.rules["latches"].waived, 1);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["passed"], true);
+        assert_eq!(json["rules"]["latches"]["severity"], "deny");
+        assert_eq!(
+            json["rules"]["hide-name"]["findings"][0]["object"]["net"]["net"],
+            "t"
+        );
+    }
+}
@@ -0,0 +1,440 @@
+use crate::{Module, Netlist, ParseError};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, Visitor};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+
+/// What to do with a module while parsing, as decided by a `ParseOptions::module_filter`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ModuleAction {
+    /// Parse the module normally
+    Parse,
+    /// Skip the contents of the module but still add an empty module with its name
+    SkipBody,
+    /// Leave the module out of the netlist entirely
+    SkipEntirely,
+}
+
+type ModuleFilter<'a> = Box<dyn Fn(&str) -> ModuleAction + 'a>;
+
+/// Options controlling how a netlist is parsed
+#[derive(Default)]
+pub struct ParseOptions<'a> {
+    module_filter: Option<ModuleFilter<'a>>,
+    strict: bool,
+}
+
+impl fmt::Debug for ParseOptions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParseOptions")
+            .field("module_filter", &self.module_filter.is_some())
+            .field("strict", &self.strict)
+            .finish()
+    }
+}
+
+impl<'a> ParseOptions<'a> {
+    /// Default options, equivalent to `Netlist::from_slice`/`Netlist::from_reader`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `filter` with the name of each module before parsing it to decide whether
+    /// to parse or skip it
+    pub fn module_filter(mut self, filter: impl Fn(&str) -> ModuleAction + 'a) -> Self {
+        self.module_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Fail on any field this crate doesn't know about, instead of keeping it in the
+    /// `extra` map of the object it was found in
+    ///
+    /// Skipped module bodies aren't checked.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    fn module_action(&self, name: &str) -> ModuleAction {
+        match &self.module_filter {
+            Some(filter) => filter(name),
+            None => ModuleAction::Parse,
+        }
+    }
+}
+
+/// JSON paths of every field that ended up in an `extra` map, sorted
+fn unknown_fields(netlist: &Netlist) -> Vec<String> {
+    fn push<T>(paths: &mut Vec<String>, prefix: &str, extra: &HashMap<String, T>) {
+        paths.extend(extra.keys().map(|key| format!("{}{}", prefix, key)));
+    }
+
+    let mut paths = Vec::new();
+    push(&mut paths, "", &netlist.extra);
+    for (module_name, module) in &netlist.modules {
+        let prefix = format!("modules.{}.", module_name);
+        push(&mut paths, &prefix, &module.extra);
+        for (name, port) in &module.ports {
+            push(
+                &mut paths,
+                &format!("{}ports.{}.", prefix, name),
+                &port.extra,
+            );
+        }
+        for (name, cell) in &module.cells {
+            push(
+                &mut paths,
+                &format!("{}cells.{}.", prefix, name),
+                &cell.extra,
+            );
+        }
+        for (name, memory) in &module.memories {
+            push(
+                &mut paths,
+                &format!("{}memories.{}.", prefix, name),
+                &memory.extra,
+            );
+        }
+        for (name, netname) in &module.netnames {
+            push(
+                &mut paths,
+                &format!("{}netnames.{}.", prefix, name),
+                &netname.extra,
+            );
+        }
+    }
+    paths.sort();
+    paths
+}
+
+fn check_strict(netlist: Netlist, options: &ParseOptions) -> Result<Netlist, serde_json::Error> {
+    if options.strict {
+        let unknown = unknown_fields(&netlist);
+        if let Some(first) = unknown.first() {
+            return Err(de::Error::custom(format_args!(
+                "unknown field `{}` ({} unknown fields in total)",
+                first,
+                unknown.len()
+            )));
+        }
+    }
+    Ok(netlist)
+}
+
+struct NetlistSeed<'o, 'a>(&'o ParseOptions<'a>);
+
+impl<'de> DeserializeSeed<'de> for NetlistSeed<'_, '_> {
+    type Value = Netlist;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Netlist, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for NetlistSeed<'_, '_> {
+    type Value = Netlist;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a Yosys netlist object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Netlist, A::Error> {
+        let mut netlist = Netlist::default();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "creator" => netlist.creator = map.next_value()?,
+                "modules" => netlist.modules = map.next_value_seed(ModulesSeed(self.0))?,
+                _ => {
+                    let value = map.next_value()?;
+                    netlist.extra.insert(key, value);
+                }
+            }
+        }
+        Ok(netlist)
+    }
+}
+
+struct ModulesSeed<'o, 'a>(&'o ParseOptions<'a>);
+
+impl<'de> DeserializeSeed<'de> for ModulesSeed<'_, '_> {
+    type Value = HashMap<String, Module>;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ModulesSeed<'_, '_> {
+    type Value = HashMap<String, Module>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a map of modules")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut modules = HashMap::new();
+        while let Some(name) = map.next_key::<String>()? {
+            match self.0.module_action(&name) {
+                ModuleAction::Parse => {
+                    modules.insert(name, map.next_value()?);
+                }
+                ModuleAction::SkipBody => {
+                    map.next_value::<IgnoredAny>()?;
+                    modules.insert(name, Module::default());
+                }
+                ModuleAction::SkipEntirely => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(modules)
+    }
+}
+
+impl Netlist {
+    /// Read netlist data from a reader using the given options
+    pub fn from_reader_with<R: Read>(
+        reader: R,
+        options: &ParseOptions,
+    ) -> Result<Netlist, serde_json::Error> {
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        let netlist = NetlistSeed(options).deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        check_strict(netlist, options)
+    }
+
+    /// Read netlist data from a slice using the given options
+    pub fn from_slice_with(
+        input: &[u8],
+        options: &ParseOptions,
+    ) -> Result<Netlist, serde_json::Error> {
+        let mut deserializer = serde_json::Deserializer::from_slice(input);
+        let netlist = NetlistSeed(options).deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        check_strict(netlist, options)
+    }
+
+    /// Read netlist data from a reader, reporting where in the JSON any error happened
+    ///
+    /// This is slower than `from_reader`, since the path has to be tracked throughout.
+    pub fn from_reader_tracked<R: Read>(reader: R) -> Result<Netlist, ParseError> {
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        let netlist = serde_path_to_error::deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(netlist)
+    }
+
+    /// Read netlist data from a slice, reporting where in the JSON any error happened
+    ///
+    /// This is slower than `from_slice`, since the path has to be tracked throughout.
+    pub fn from_slice_tracked(input: &[u8]) -> Result<Netlist, ParseError> {
+        let mut deserializer = serde_json::Deserializer::from_slice(input);
+        let netlist = serde_path_to_error::deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(netlist)
+    }
+
+    /// Read netlist data from a slice with simd-json, which is much faster than
+    /// `from_slice` on large files
+    ///
+    /// simd-json uses `input` as scratch space, so its contents are garbage afterwards.
+    /// On CPUs without the SIMD instructions simd-json needs, this falls back to
+    /// `from_slice` and leaves `input` alone.
+    #[cfg(feature = "simd-json")]
+    pub fn from_slice_simd(input: &mut [u8]) -> Result<Netlist, crate::SimdError> {
+        if simd_json::Deserializer::algorithm() == simd_json::Implementation::Native {
+            return Self::from_slice(input).map_err(crate::SimdError::Json);
+        }
+        simd_json::serde::from_slice(input).map_err(crate::SimdError::Simd)
+    }
+
+    /// Read netlist data from a slice, failing on any field this crate doesn't know
+    /// about
+    ///
+    /// See `ParseOptions::strict`.
+    pub fn from_slice_strict(input: &[u8]) -> Result<Netlist, serde_json::Error> {
+        Self::from_slice_with(input, &ParseOptions::new().strict(true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTER_JSON: &[u8] = br#"
+    {
+      "creator": "filter test",
+      "unknown": [ 1, 2, 3 ],
+      "modules": {
+        "cpu_core": {
+          "ports": { "a": { "direction": "input", "bits": [ 2 ] } },
+          "cells": { "u0": { "type": "gpu_shader", "connections": {} } }
+        },
+        "gpu_shader": {
+          "ports": { "a": { "direction": "input", "bits": [ 2 ] } }
+        },
+        "debug_uart": {
+          "ports": { "a": { "direction": "input", "bits": [ "invalid" ] } }
+        }
+      }
+    }"#;
+
+    #[test]
+    fn parse_options_default_test() {
+        let netlist = Netlist::from_slice_with(
+            br#"{"creator": "x", "modules": {"m": {}}}"#,
+            &ParseOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            netlist,
+            Netlist::from_slice(br#"{"creator": "x", "modules": {"m": {}}}"#).unwrap()
+        );
+        assert!(Netlist::from_slice_with(FILTER_JSON, &ParseOptions::new()).is_err());
+    }
+
+    #[test]
+    fn module_filter_test() {
+        let options = ParseOptions::new().module_filter(|name| {
+            if name.starts_with("cpu") {
+                ModuleAction::Parse
+            } else if name.starts_with("gpu") {
+                ModuleAction::SkipBody
+            } else {
+                ModuleAction::SkipEntirely
+            }
+        });
+        let netlist = Netlist::from_slice_with(FILTER_JSON, &options).unwrap();
+        assert_eq!(netlist.creator, "filter test");
+        assert_eq!(netlist.extra["unknown"], serde_json::json!([1, 2, 3]));
+        assert_eq!(netlist.modules.len(), 2);
+        assert_eq!(netlist.modules.get("cpu_core").unwrap().cells.len(), 1);
+        assert_eq!(netlist.modules.get("gpu_shader"), Some(&Module::default()));
+        assert!(!netlist.modules.contains_key("debug_uart"));
+
+        let netlist = Netlist::from_reader_with(FILTER_JSON, &options).unwrap();
+        assert_eq!(netlist.modules.len(), 2);
+    }
+
+    #[test]
+    fn strict_test() {
+        let json = br#"{
+            "creator": "strict test",
+            "modules": {
+                "top": {
+                    "ports": { "a": { "direction": "input", "bits": [ 2 ] } },
+                    "cells": {
+                        "n": { "type": "$not", "connections": {}, "future_field": 1 }
+                    },
+                    "netnames": { "a": { "bits": [ 2 ], "future_field": 2 } }
+                }
+            }
+        }"#;
+        assert_eq!(
+            Netlist::from_slice(json).unwrap().modules["top"].cells["n"]
+                .extra
+                .len(),
+            1
+        );
+
+        let err = Netlist::from_slice_strict(json).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown field `modules.top.cells.n.future_field` (2 unknown fields in total)"
+        );
+
+        let options = ParseOptions::new()
+            .strict(true)
+            .module_filter(|name| match name {
+                "cpu_core" => ModuleAction::Parse,
+                _ => ModuleAction::SkipEntirely,
+            });
+        let err = Netlist::from_reader_with(FILTER_JSON, &options).unwrap_err();
+        assert!(err.to_string().starts_with("unknown field `unknown`"));
+
+        let options = ParseOptions::new()
+            .strict(true)
+            .module_filter(|name| match name {
+                "top" => ModuleAction::SkipBody,
+                _ => ModuleAction::Parse,
+            });
+        assert!(Netlist::from_slice_with(json, &options).is_ok());
+    }
+
+    #[test]
+    fn tracked_test() {
+        let json = br#"{
+            "modules": {
+                "cpu": {
+                    "cells": {
+                        "$and$foo": {
+                            "type": "$and",
+                            "connections": { "A": [ 2, 3, 4, "w" ] }
+                        }
+                    }
+                }
+            }
+        }"#;
+        let err = Netlist::from_slice_tracked(json).unwrap_err();
+        assert_eq!(
+            err.path().unwrap().to_string(),
+            "modules.cpu.cells.$and$foo.connections.A[3]"
+        );
+        assert!(err
+            .to_string()
+            .starts_with("modules.cpu.cells.$and$foo.connections.A[3]: "));
+        assert_eq!(err.inner().line(), 7);
+        assert_eq!(err.path().unwrap().iter().count(), 7);
+
+        let err = Netlist::from_reader_tracked(&json[..]).unwrap_err();
+        assert_eq!(
+            err.path().unwrap().to_string(),
+            "modules.cpu.cells.$and$foo.connections.A[3]"
+        );
+
+        let netlist = Netlist::from_slice_tracked(br#"{"modules": {"m": {}}}"#).unwrap();
+        assert_eq!(netlist.modules.len(), 1);
+        let err = Netlist::from_slice_tracked(br#"{"modules": {}} x"#).unwrap_err();
+        assert!(err.path().is_none());
+    }
+
+    #[cfg(feature = "simd-json")]
+    #[test]
+    fn simd_test() {
+        let json = br#"{
+            "creator": "simd test",
+            "future_field": { "a": [ 1, 2 ] },
+            "modules": {
+                "top": {
+                    "attributes": { "top": "00000000000000000000000000000001" },
+                    "ports": {
+                        "a": { "direction": "input", "bits": [ 2, 3 ], "offset": 1 },
+                        "y": { "direction": "output", "bits": [ 4, "x" ], "signed": 1 }
+                    },
+                    "cells": {
+                        "$and$x.v:1$1": {
+                            "hide_name": 1,
+                            "type": "$and",
+                            "parameters": { "A_WIDTH": 2, "B_WIDTH": "00000000000000000000000000000010" },
+                            "port_directions": { "A": "input", "B": "input", "Y": "output" },
+                            "connections": { "A": [ 2, 3 ], "B": [ "0", "1" ], "Y": [ 4, 5 ] }
+                        }
+                    },
+                    "memories": { "m": { "hide_name": 0, "width": 8, "start_offset": 0, "size": 4 } },
+                    "netnames": { "a": { "hide_name": 0, "bits": [ 2, 3 ], "attributes": { "src": "x.v:1 " } } }
+                }
+            }
+        }"#;
+        let expected = Netlist::from_slice(json).unwrap();
+        let mut input = json.to_vec();
+        assert_eq!(Netlist::from_slice_simd(&mut input).unwrap(), expected);
+
+        let mut input =
+            br#"{"modules": {"m": {"ports": {"a": {"direction": "input", "bits": ["w"]}}}}}"#
+                .to_vec();
+        assert!(Netlist::from_slice_simd(&mut input).is_err());
+    }
+}
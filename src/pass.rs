@@ -0,0 +1,548 @@
+use crate::{AttributeVal, Module, Netlist, Selection};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Information given to a pass about the module it is running on
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PassContext<'a> {
+    /// Name of the module being processed
+    pub module_name: &'a str,
+    /// Whether passes must leave objects with a `keep` attribute alone
+    pub honor_keep: bool,
+}
+
+impl PassContext<'_> {
+    /// Whether an object with these attributes must not be changed
+    pub fn is_kept(&self, attributes: &HashMap<String, AttributeVal>) -> bool {
+        self.honor_keep
+            && attributes
+                .get("keep")
+                .and_then(|x| x.to_number())
+                .is_some_and(|x| x != 0)
+    }
+}
+
+/// What a pass did to one module (or, when summed, to every module)
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct PassStats {
+    /// Number of changes made; zero means the pass didn't modify anything
+    pub changes: usize,
+}
+
+/// An error returned by a pass
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PassError {
+    /// Name of the pass that failed
+    pub pass: String,
+    /// Module the pass was running on
+    pub module: String,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl fmt::Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pass `{}` failed on module `{}`: {}",
+            self.pass, self.module, self.message
+        )
+    }
+}
+
+impl std::error::Error for PassError {}
+
+/// A transformation applied to modules one at a time, run by a `PassManager`
+pub trait NetlistPass {
+    /// Name of the pass, used in reports
+    fn name(&self) -> &str;
+    /// Transform one module
+    fn run_on_module(&mut self, m: &mut Module, ctx: &PassContext) -> Result<PassStats, PassError>;
+}
+
+/// Wraps `Module::strip_attributes`
+///
+/// When honoring `keep`, kept cells, memories, and netnames keep their attributes.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StripAttributesPass;
+
+impl NetlistPass for StripAttributesPass {
+    fn name(&self) -> &str {
+        "strip_attributes"
+    }
+
+    fn run_on_module(&mut self, m: &mut Module, ctx: &PassContext) -> Result<PassStats, PassError> {
+        if !ctx.honor_keep {
+            return Ok(PassStats {
+                changes: m.strip_attributes(),
+            });
+        }
+
+        let mut changes = 0;
+        let mut strip = |attributes: &mut HashMap<String, AttributeVal>| {
+            if !ctx.is_kept(attributes) {
+                changes += attributes.len();
+                attributes.clear();
+            }
+        };
+        strip(&mut m.attributes);
+        m.cells.values_mut().for_each(|x| strip(&mut x.attributes));
+        m.memories
+            .values_mut()
+            .for_each(|x| strip(&mut x.attributes));
+        m.netnames
+            .values_mut()
+            .for_each(|x| strip(&mut x.attributes));
+        Ok(PassStats { changes })
+    }
+}
+
+/// Wraps `Module::compact_signal_ids`
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CompactSignalIdsPass;
+
+impl NetlistPass for CompactSignalIdsPass {
+    fn name(&self) -> &str {
+        "compact_signal_ids"
+    }
+
+    fn run_on_module(
+        &mut self,
+        m: &mut Module,
+        _ctx: &PassContext,
+    ) -> Result<PassStats, PassError> {
+        let mapping = m.compact_signal_ids();
+        Ok(PassStats {
+            changes: mapping.iter().filter(|(old, new)| old != new).count(),
+        })
+    }
+}
+
+/// Wraps `Module::split_wide_ports`, splitting port `port` of every module that has
+/// it with more than one bit
+///
+/// Like the method it wraps, this doesn't update instances of the module; use
+/// `Netlist::split_wide_ports` for that.
+#[derive(Clone, Debug, Default)]
+pub struct SplitWidePortsPass {
+    /// Name of the port to split
+    pub port: String,
+}
+
+impl NetlistPass for SplitWidePortsPass {
+    fn name(&self) -> &str {
+        "split_wide_ports"
+    }
+
+    fn run_on_module(&mut self, m: &mut Module, ctx: &PassContext) -> Result<PassStats, PassError> {
+        let Some(port) = m.ports.get(&self.port) else {
+            return Ok(PassStats::default());
+        };
+        if port.bits.len() < 2 {
+            return Ok(PassStats::default());
+        }
+        let new_ports = m.split_wide_ports(&self.port).map_err(|e| PassError {
+            pass: self.name().to_owned(),
+            module: ctx.module_name.to_owned(),
+            message: e.to_string(),
+        })?;
+        Ok(PassStats {
+            changes: new_ports.len(),
+        })
+    }
+}
+
+/// Wraps `Module::merge_1bit_ports`, merging `ports` into `new_name` in every module
+/// that has all of them
+///
+/// Like the method it wraps, this doesn't update instances of the module; use
+/// `Netlist::merge_1bit_ports` for that.
+#[derive(Clone, Debug, Default)]
+pub struct Merge1BitPortsPass {
+    /// Name of the new port
+    pub new_name: String,
+    /// Ports to merge, least significant bit first
+    pub ports: Vec<String>,
+}
+
+impl NetlistPass for Merge1BitPortsPass {
+    fn name(&self) -> &str {
+        "merge_1bit_ports"
+    }
+
+    fn run_on_module(&mut self, m: &mut Module, ctx: &PassContext) -> Result<PassStats, PassError> {
+        if self.ports.is_empty() || !self.ports.iter().all(|x| m.ports.contains_key(x)) {
+            return Ok(PassStats::default());
+        }
+        let ports = self.ports.iter().map(String::as_str).collect::<Vec<_>>();
+        m.merge_1bit_ports(&self.new_name, &ports)
+            .map_err(|e| PassError {
+                pass: self.name().to_owned(),
+                module: ctx.module_name.to_owned(),
+                message: e.to_string(),
+            })?;
+        Ok(PassStats {
+            changes: ports.len(),
+        })
+    }
+}
+
+enum Step {
+    Pass(Box<dyn NetlistPass>),
+    FixedPoint {
+        passes: Vec<Box<dyn NetlistPass>>,
+        max_iterations: usize,
+    },
+}
+
+/// One pass run recorded by `PassManager::run`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PassRun {
+    /// Name of the pass
+    pub pass: String,
+    /// Iteration number (starting at 0) if the pass ran in a fixed-point group
+    pub iteration: Option<usize>,
+    /// Statistics summed over every processed module
+    pub stats: PassStats,
+    /// Time spent running the pass
+    pub duration: Duration,
+}
+
+/// Everything a `PassManager` did
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PassReport {
+    /// Every pass run, in order
+    pub runs: Vec<PassRun>,
+}
+
+impl PassReport {
+    /// Total number of changes made by all passes
+    pub fn total_changes(&self) -> usize {
+        self.runs.iter().map(|run| run.stats.changes).sum()
+    }
+}
+
+/// Runs a sequence of passes over the modules of a netlist
+#[derive(Default)]
+pub struct PassManager {
+    steps: Vec<Step>,
+    selection: Option<Selection>,
+    honor_keep: bool,
+}
+
+impl fmt::Debug for PassManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PassManager")
+            .field("steps", &self.steps.len())
+            .field("selection", &self.selection)
+            .field("honor_keep", &self.honor_keep)
+            .finish()
+    }
+}
+
+impl PassManager {
+    /// Create a pass manager with no passes that runs on every module
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a pass to the end of the sequence
+    pub fn add_pass(mut self, pass: impl NetlistPass + 'static) -> Self {
+        self.steps.push(Step::Pass(Box::new(pass)));
+        self
+    }
+
+    /// Add a group of passes that is repeated until it makes no changes, or until it
+    /// has run `max_iterations` times
+    pub fn add_fixed_point(
+        mut self,
+        passes: Vec<Box<dyn NetlistPass>>,
+        max_iterations: usize,
+    ) -> Self {
+        self.steps.push(Step::FixedPoint {
+            passes,
+            max_iterations,
+        });
+        self
+    }
+
+    /// Only run on the modules in `selection`
+    pub fn select(mut self, selection: Selection) -> Self {
+        self.selection = Some(selection);
+        self
+    }
+
+    /// Tell passes to leave objects with a `keep` attribute alone
+    pub fn honor_keep(mut self, honor_keep: bool) -> Self {
+        self.honor_keep = honor_keep;
+        self
+    }
+
+    fn run_pass(
+        &self,
+        pass: &mut dyn NetlistPass,
+        netlist: &mut Netlist,
+        iteration: Option<usize>,
+    ) -> Result<PassRun, PassError> {
+        let mut module_names = netlist
+            .modules
+            .keys()
+            .filter(|name| match &self.selection {
+                Some(selection) => selection.contains_module(name),
+                None => true,
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        module_names.sort();
+
+        let start = Instant::now();
+        let mut stats = PassStats::default();
+        for module_name in &module_names {
+            let ctx = PassContext {
+                module_name,
+                honor_keep: self.honor_keep,
+            };
+            let module = netlist.modules.get_mut(module_name).unwrap();
+            stats.changes += pass.run_on_module(module, &ctx)?.changes;
+        }
+        Ok(PassRun {
+            pass: pass.name().to_owned(),
+            iteration,
+            stats,
+            duration: start.elapsed(),
+        })
+    }
+
+    fn run_steps(
+        &self,
+        steps: &mut [Step],
+        netlist: &mut Netlist,
+        report: &mut PassReport,
+    ) -> Result<(), PassError> {
+        for step in steps {
+            match step {
+                Step::Pass(pass) => {
+                    report
+                        .runs
+                        .push(self.run_pass(pass.as_mut(), netlist, None)?)
+                }
+                Step::FixedPoint {
+                    passes,
+                    max_iterations,
+                } => {
+                    for iteration in 0..*max_iterations {
+                        let mut changes = 0;
+                        for pass in passes.iter_mut() {
+                            let run = self.run_pass(pass.as_mut(), netlist, Some(iteration))?;
+                            changes += run.stats.changes;
+                            report.runs.push(run);
+                        }
+                        if changes == 0 {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run every pass on the selected modules, in order
+    pub fn run(&mut self, netlist: &mut Netlist) -> Result<PassReport, PassError> {
+        let mut report = PassReport::default();
+        let mut steps = std::mem::take(&mut self.steps);
+        let result = self.run_steps(&mut steps, netlist, &mut report);
+        self.steps = steps;
+        result.map(|()| report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BitVal, Query};
+
+    const PASS_JSON: &[u8] = br#"
+    {
+      "modules": {
+        "a": {
+          "attributes": { "src": "a.v:1" },
+          "cells": {
+            "c0": { "type": "$not", "attributes": { "keep": 1, "src": "a.v:2" }, "connections": { "A": [ 10 ], "Y": [ 20 ] } },
+            "c1": { "type": "$not", "attributes": { "src": "a.v:3" }, "connections": { "A": [ 20 ], "Y": [ 30 ] } }
+          }
+        },
+        "b": {
+          "attributes": { "src": "b.v:1" }
+        }
+      }
+    }"#;
+
+    /// Removes one cell per run until none are left
+    struct RemoveOneCell;
+
+    impl NetlistPass for RemoveOneCell {
+        fn name(&self) -> &str {
+            "remove_one_cell"
+        }
+
+        fn run_on_module(
+            &mut self,
+            m: &mut Module,
+            _ctx: &PassContext,
+        ) -> Result<PassStats, PassError> {
+            let Some(name) = m.cells.keys().min().cloned() else {
+                return Ok(PassStats::default());
+            };
+            m.cells.remove(&name);
+            Ok(PassStats { changes: 1 })
+        }
+    }
+
+    struct Fail;
+
+    impl NetlistPass for Fail {
+        fn name(&self) -> &str {
+            "fail"
+        }
+
+        fn run_on_module(
+            &mut self,
+            _m: &mut Module,
+            ctx: &PassContext,
+        ) -> Result<PassStats, PassError> {
+            Err(PassError {
+                pass: self.name().to_owned(),
+                module: ctx.module_name.to_owned(),
+                message: "always fails".to_owned(),
+            })
+        }
+    }
+
+    #[test]
+    fn pass_manager_test() {
+        let mut netlist = Netlist::from_slice(PASS_JSON).unwrap();
+        let report = PassManager::new()
+            .honor_keep(true)
+            .add_pass(StripAttributesPass)
+            .add_pass(CompactSignalIdsPass)
+            .run(&mut netlist)
+            .unwrap();
+
+        assert_eq!(report.runs.len(), 2);
+        assert_eq!(report.runs[0].pass, "strip_attributes");
+        assert_eq!(report.runs[0].stats.changes, 3);
+        assert_eq!(report.runs[1].pass, "compact_signal_ids");
+        assert_eq!(report.runs[1].stats.changes, 3);
+        let a = netlist.modules.get("a").unwrap();
+        assert_eq!(a.cells.get("c0").unwrap().attributes.len(), 2);
+        assert!(a.cells.get("c1").unwrap().attributes.is_empty());
+        assert!(netlist.modules.get("b").unwrap().attributes.is_empty());
+    }
+
+    #[test]
+    fn port_passes_test() {
+        let mut netlist = Netlist::from_slice(
+            br#"
+            {
+              "modules": {
+                "a": {
+                  "ports": { "d": { "direction": "input", "bits": [ 2, 3, 4 ] } }
+                },
+                "b": {
+                  "ports": { "d": { "direction": "input", "bits": [ 2 ] } }
+                }
+              }
+            }"#,
+        )
+        .unwrap();
+
+        let report = PassManager::new()
+            .add_pass(SplitWidePortsPass {
+                port: "d".to_owned(),
+            })
+            .run(&mut netlist)
+            .unwrap();
+        assert_eq!(report.total_changes(), 3);
+        let a = &netlist.modules["a"];
+        assert!(!a.ports.contains_key("d"));
+        assert_eq!(a.ports["d[1]"].bits, [BitVal::N(3)]);
+        assert!(netlist.modules["b"].ports.contains_key("d"));
+
+        let mut merge = Merge1BitPortsPass {
+            new_name: "d".to_owned(),
+            ports: vec!["d[0]".to_owned(), "d[1]".to_owned(), "d[2]".to_owned()],
+        };
+        let report = PassManager::new()
+            .add_pass(merge.clone())
+            .run(&mut netlist)
+            .unwrap();
+        assert_eq!(report.total_changes(), 3);
+        assert_eq!(
+            netlist.modules["a"].ports["d"].bits,
+            [BitVal::N(2), BitVal::N(3), BitVal::N(4)]
+        );
+
+        // Merging into a port that already exists fails
+        netlist
+            .modules
+            .get_mut("a")
+            .unwrap()
+            .split_wide_ports("d")
+            .unwrap();
+        merge.ports = vec!["d[0]".to_owned(), "d[1]".to_owned()];
+        merge.new_name = "d[2]".to_owned();
+        let err = PassManager::new()
+            .add_pass(merge)
+            .run(&mut netlist)
+            .unwrap_err();
+        assert_eq!(err.pass, "merge_1bit_ports");
+        assert_eq!(err.module, "a");
+    }
+
+    #[test]
+    fn pass_manager_selection_test() {
+        let mut netlist = Netlist::from_slice(PASS_JSON).unwrap();
+        let selection = Query::module().and(Query::name("b")).run(&netlist);
+        let report = PassManager::new()
+            .select(selection)
+            .add_pass(StripAttributesPass)
+            .run(&mut netlist)
+            .unwrap();
+        assert_eq!(report.total_changes(), 1);
+        assert_eq!(netlist.modules.get("a").unwrap().attributes.len(), 1);
+    }
+
+    #[test]
+    fn pass_manager_fixed_point_test() {
+        let mut netlist = Netlist::from_slice(PASS_JSON).unwrap();
+        let report = PassManager::new()
+            .add_fixed_point(vec![Box::new(RemoveOneCell)], 10)
+            .run(&mut netlist)
+            .unwrap();
+        // Two iterations remove the two cells, the third finds nothing to do
+        assert_eq!(report.runs.len(), 3);
+        assert_eq!(report.runs[2].iteration, Some(2));
+        assert_eq!(report.total_changes(), 2);
+        assert!(netlist.modules.get("a").unwrap().cells.is_empty());
+
+        let mut netlist = Netlist::from_slice(PASS_JSON).unwrap();
+        let report = PassManager::new()
+            .add_fixed_point(vec![Box::new(RemoveOneCell)], 1)
+            .run(&mut netlist)
+            .unwrap();
+        assert_eq!(report.runs.len(), 1);
+    }
+
+    #[test]
+    fn pass_manager_error_test() {
+        let mut netlist = Netlist::from_slice(PASS_JSON).unwrap();
+        let err = PassManager::new()
+            .add_pass(Fail)
+            .run(&mut netlist)
+            .unwrap_err();
+        assert_eq!(err.pass, "fail");
+        assert_eq!(err.module, "a");
+    }
+}
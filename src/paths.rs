@@ -0,0 +1,247 @@
+use crate::timing::is_sequential;
+use crate::{BitVal, ConnectivityIndex, Endpoint, Module};
+use std::collections::{HashMap, HashSet};
+
+/// Limits for `Module::paths_between_with`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PathOptions {
+    /// Longest path to report, in cells
+    pub max_hops: usize,
+    /// Stop after this many paths
+    pub max_paths: usize,
+    /// Follow paths through flip-flops and latches instead of stopping at them
+    pub through_registers: bool,
+}
+
+/// One cell on a path found by `Module::paths_between`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct NetPathHop {
+    /// Cell name
+    pub cell: String,
+    /// Cell type
+    pub cell_type: String,
+    /// Input port the path enters through
+    pub from_port: String,
+    /// Output port the path leaves through
+    pub to_port: String,
+    /// Signal driven by the hop
+    pub signal: usize,
+    /// Best name of the signal driven by the hop, if it has one
+    pub net: Option<String>,
+    /// Whether the cell is a flip-flop or latch
+    pub register: bool,
+}
+
+/// A path found by `Module::paths_between`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct NetPath {
+    /// Best name of the signal the path starts at, if it has one
+    pub from_net: Option<String>,
+    /// Cells on the path, in order
+    pub hops: Vec<NetPathHop>,
+}
+
+struct PathSearch<'a> {
+    module: &'a Module,
+    index: ConnectivityIndex<'a>,
+    names: HashMap<usize, String>,
+    to: usize,
+    opts: &'a PathOptions,
+    stack: Vec<NetPathHop>,
+    on_stack: HashSet<usize>,
+    paths: Vec<NetPath>,
+    from_net: Option<String>,
+}
+
+impl PathSearch<'_> {
+    fn search(&mut self, signal: usize) {
+        let mut inputs = self
+            .index
+            .sinks(signal)
+            .iter()
+            .filter_map(|endpoint| match *endpoint {
+                Endpoint::CellPort { cell, port, .. } => Some((cell, port)),
+                Endpoint::ModulePort { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        inputs.dedup();
+
+        for (cell_name, from_port) in inputs {
+            let cell = &self.module.cells[cell_name];
+            let register = is_sequential(&cell.cell_type);
+            if register && !self.opts.through_registers {
+                continue;
+            }
+
+            let mut outputs = cell
+                .connections
+                .iter()
+                .filter(|(name, _)| {
+                    cell.port_directions
+                        .get(*name)
+                        .is_some_and(|dir| dir.drives_net_from_instance())
+                })
+                .collect::<Vec<_>>();
+            outputs.sort_by_key(|(name, _)| name.as_str());
+
+            for (to_port, bits) in outputs {
+                for bit in bits {
+                    if self.paths.len() >= self.opts.max_paths {
+                        return;
+                    }
+                    let BitVal::N(next) = *bit else {
+                        continue;
+                    };
+                    if next != self.to && self.on_stack.contains(&next) {
+                        continue;
+                    }
+
+                    self.stack.push(NetPathHop {
+                        cell: cell_name.to_owned(),
+                        cell_type: cell.cell_type.clone(),
+                        from_port: from_port.to_owned(),
+                        to_port: to_port.clone(),
+                        signal: next,
+                        net: self.names.get(&next).cloned(),
+                        register,
+                    });
+                    if next == self.to {
+                        self.paths.push(NetPath {
+                            from_net: self.from_net.clone(),
+                            hops: self.stack.clone(),
+                        });
+                    } else if self.stack.len() < self.opts.max_hops {
+                        self.on_stack.insert(next);
+                        self.search(next);
+                        self.on_stack.remove(&next);
+                    }
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl Module {
+    /// List paths through cells from signal `from` to signal `to`, stopping at
+    /// flip-flops and latches
+    ///
+    /// See `paths_between_with`.
+    pub fn paths_between(
+        &self,
+        from: usize,
+        to: usize,
+        max_hops: usize,
+        max_paths: usize,
+    ) -> Vec<NetPath> {
+        self.paths_between_with(
+            from,
+            to,
+            &PathOptions {
+                max_hops,
+                max_paths,
+                through_registers: false,
+            },
+        )
+    }
+
+    /// List paths through cells from signal `from` to signal `to`, with at most
+    /// `opts.max_hops` cells each
+    ///
+    /// Only cell ports listed in `port_directions` are followed, and a path never
+    /// visits a signal twice. Paths are found in a fixed order (cells, then ports, by
+    /// name), so the same `opts.max_paths` paths are returned every time.
+    pub fn paths_between_with(&self, from: usize, to: usize, opts: &PathOptions) -> Vec<NetPath> {
+        let names = self.signal_names();
+        let mut search = PathSearch {
+            module: self,
+            index: self.connectivity(),
+            from_net: names.get(&from).cloned(),
+            names,
+            to,
+            opts,
+            stack: Vec::new(),
+            on_stack: HashSet::from([from]),
+            paths: Vec::new(),
+        };
+        if opts.max_hops > 0 {
+            search.search(from);
+        }
+        search.paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netlist;
+
+    fn summary(path: &NetPath) -> Vec<String> {
+        path.hops
+            .iter()
+            .map(|hop| {
+                format!(
+                    "{}.{}>{}{}",
+                    hop.cell,
+                    hop.from_port,
+                    hop.to_port,
+                    if hop.register { " (reg)" } else { "" }
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn paths_between_test() {
+        // a[0] is 2, y is 5
+        let netlist = netlist! {
+            module top {
+                input a[2];
+                input clk;
+                output y;
+                wire t;
+                wire u;
+                wire q;
+                cell "$not" n1 { A: a[0], Y: t }
+                cell "$and" g1 { A: t, B: a[0], Y: u }
+                cell "$or" g2 { A: u, B: t, Y: y }
+                cell "$dff" r { CLK: clk, D: a[0], Q: q }
+                cell "$buf" b { A: q, Y: y }
+            }
+        };
+        let top = &netlist.modules["top"];
+
+        let paths = top.paths_between(2, 5, 10, 10);
+        let summaries = paths.iter().map(summary).collect::<Vec<_>>();
+        assert_eq!(
+            summaries,
+            [
+                vec!["g1.B>Y", "g2.A>Y"],
+                vec!["n1.A>Y", "g1.A>Y", "g2.A>Y"],
+                vec!["n1.A>Y", "g2.B>Y"],
+            ]
+        );
+        assert_eq!(paths[0].from_net.as_deref(), Some("a[0]"));
+        assert_eq!(paths[0].hops[0].net.as_deref(), Some("u"));
+
+        assert_eq!(top.paths_between(2, 5, 2, 10).len(), 2);
+        assert_eq!(top.paths_between(2, 5, 10, 1).len(), 1);
+        assert_eq!(top.paths_between(5, 2, 10, 10), []);
+
+        let opts = PathOptions {
+            max_hops: 2,
+            max_paths: 10,
+            through_registers: true,
+        };
+        let paths = top.paths_between_with(2, 5, &opts);
+        let summaries = paths.iter().map(summary).collect::<Vec<_>>();
+        assert_eq!(
+            summaries,
+            [
+                vec!["g1.B>Y", "g2.A>Y"],
+                vec!["n1.A>Y", "g2.B>Y"],
+                vec!["r.D>Q (reg)", "b.A>Y"],
+            ]
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use crate::{AttributeVal, BitVal, Cell, ConnectivityIndex, Module, NamePattern, Netlist, Netname};
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Not;
+
+/// Numeric comparison used by `Query::attr_cmp`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Ge,
+    Gt,
+}
+
+impl Comparison {
+    fn apply(&self, a: usize, b: usize) -> bool {
+        match self {
+            Comparison::Lt => a < b,
+            Comparison::Le => a <= b,
+            Comparison::Eq => a == b,
+            Comparison::Ne => a != b,
+            Comparison::Ge => a >= b,
+            Comparison::Gt => a > b,
+        }
+    }
+}
+
+/// A composable predicate over the modules, cells, and nets of a netlist
+///
+/// Queries are built from the constructor functions and combined with `and`, `or`,
+/// and `!`, for example
+/// `Query::cell().and(Query::has_attr("keep")).and(Query::cell_type("$dff*"))`.
+#[derive(Clone, Debug)]
+pub enum Query {
+    /// Matches modules
+    IsModule,
+    /// Matches cells
+    IsCell,
+    /// Matches nets (netnames)
+    IsNet,
+    /// Matches objects whose name matches the pattern
+    Name(NamePattern),
+    /// Matches cells whose type matches the pattern
+    CellType(NamePattern),
+    /// Matches objects that have the attribute
+    HasAttr(String),
+    /// Matches objects whose attribute equals this value after `AttributeVal::normalize`
+    AttrEquals(String, AttributeVal),
+    /// Matches objects whose attribute is a string matching the pattern, after
+    /// `AttributeVal::normalize`
+    AttrMatches(String, NamePattern),
+    /// Matches objects whose attribute is a number that compares true against the value
+    AttrCompare(String, Comparison, usize),
+    /// Matches cells with an output bit, or nets with a bit, driving more than this
+    /// many sinks
+    FanoutAbove(usize),
+    /// Matches if all of the queries match
+    And(Vec<Query>),
+    /// Matches if any of the queries match
+    Or(Vec<Query>),
+    /// Matches if the query doesn't match
+    Not(Box<Query>),
+}
+
+/// A set of objects in a netlist, as returned by running a `Query`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Selection {
+    /// Selected module names
+    pub modules: BTreeSet<String>,
+    /// Selected cells as (module name, cell name)
+    pub cells: BTreeSet<(String, String)>,
+    /// Selected nets as (module name, netname)
+    pub nets: BTreeSet<(String, String)>,
+}
+
+impl Selection {
+    /// Whether nothing is selected
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty() && self.cells.is_empty() && self.nets.is_empty()
+    }
+
+    /// Total number of selected objects
+    pub fn len(&self) -> usize {
+        self.modules.len() + self.cells.len() + self.nets.len()
+    }
+
+    /// Whether the module is selected
+    pub fn contains_module(&self, module: &str) -> bool {
+        self.modules.contains(module)
+    }
+
+    /// Whether the cell is selected
+    pub fn contains_cell(&self, module: &str, cell: &str) -> bool {
+        self.cells.contains(&(module.to_owned(), cell.to_owned()))
+    }
+
+    /// Whether the net is selected
+    pub fn contains_net(&self, module: &str, net: &str) -> bool {
+        self.nets.contains(&(module.to_owned(), net.to_owned()))
+    }
+
+    /// Add every object selected in `other` to this selection
+    pub fn union(&mut self, other: Selection) {
+        self.modules.extend(other.modules);
+        self.cells.extend(other.cells);
+        self.nets.extend(other.nets);
+    }
+}
+
+enum Object<'a> {
+    Module(&'a Module),
+    Cell(&'a Cell),
+    Net(&'a Netname),
+}
+
+impl Object<'_> {
+    fn attributes(&self) -> &HashMap<String, AttributeVal> {
+        match self {
+            Object::Module(module) => &module.attributes,
+            Object::Cell(cell) => &cell.attributes,
+            Object::Net(netname) => &netname.attributes,
+        }
+    }
+}
+
+fn max_fanout<'a>(index: &ConnectivityIndex, bits: impl Iterator<Item = &'a BitVal>) -> usize {
+    bits.filter_map(|bit| match bit {
+        BitVal::N(n) => Some(index.fanout(*n)),
+        BitVal::S(_) => None,
+    })
+    .max()
+    .unwrap_or(0)
+}
+
+impl Query {
+    /// Match modules
+    pub fn module() -> Self {
+        Query::IsModule
+    }
+
+    /// Match cells
+    pub fn cell() -> Self {
+        Query::IsCell
+    }
+
+    /// Match nets
+    pub fn net() -> Self {
+        Query::IsNet
+    }
+
+    /// Match objects whose name matches a glob pattern
+    pub fn name(pattern: &str) -> Self {
+        Query::Name(NamePattern::glob(pattern))
+    }
+
+    /// Match cells whose type matches a glob pattern
+    pub fn cell_type(pattern: &str) -> Self {
+        Query::CellType(NamePattern::glob(pattern))
+    }
+
+    /// Match objects with the attribute `key`
+    pub fn has_attr(key: &str) -> Self {
+        Query::HasAttr(key.to_owned())
+    }
+
+    /// Match objects whose attribute `key` is `value`
+    pub fn attr_eq(key: &str, value: AttributeVal) -> Self {
+        Query::AttrEquals(key.to_owned(), value)
+    }
+
+    /// Match objects whose attribute `key` is a string matching a glob pattern
+    pub fn attr_matches(key: &str, pattern: &str) -> Self {
+        Query::AttrMatches(key.to_owned(), NamePattern::glob(pattern))
+    }
+
+    /// Match objects whose attribute `key` is a number for which `attr <op> value` holds
+    pub fn attr_cmp(key: &str, op: Comparison, value: usize) -> Self {
+        Query::AttrCompare(key.to_owned(), op, value)
+    }
+
+    /// Match cells and nets with a bit driving more than `n` sinks
+    pub fn fanout_above(n: usize) -> Self {
+        Query::FanoutAbove(n)
+    }
+
+    /// Match if both this query and `other` match
+    pub fn and(self, other: Query) -> Self {
+        match self {
+            Query::And(mut queries) => {
+                queries.push(other);
+                Query::And(queries)
+            }
+            query => Query::And(vec![query, other]),
+        }
+    }
+
+    /// Match if either this query or `other` matches
+    pub fn or(self, other: Query) -> Self {
+        match self {
+            Query::Or(mut queries) => {
+                queries.push(other);
+                Query::Or(queries)
+            }
+            query => Query::Or(vec![query, other]),
+        }
+    }
+
+    fn uses_connectivity(&self) -> bool {
+        match self {
+            Query::FanoutAbove(_) => true,
+            Query::And(queries) | Query::Or(queries) => {
+                queries.iter().any(|q| q.uses_connectivity())
+            }
+            Query::Not(query) => query.uses_connectivity(),
+            _ => false,
+        }
+    }
+
+    fn matches(&self, name: &str, obj: &Object, index: Option<&ConnectivityIndex>) -> bool {
+        match self {
+            Query::IsModule => matches!(obj, Object::Module(_)),
+            Query::IsCell => matches!(obj, Object::Cell(_)),
+            Query::IsNet => matches!(obj, Object::Net(_)),
+            Query::Name(pattern) => pattern.is_match(name),
+            Query::CellType(pattern) => match obj {
+                Object::Cell(cell) => pattern.is_match(&cell.cell_type),
+                _ => false,
+            },
+            Query::HasAttr(key) => obj.attributes().contains_key(key),
+            Query::AttrEquals(key, value) => obj
+                .attributes()
+                .get(key)
+                .is_some_and(|x| x.normalized_eq(value)),
+            Query::AttrMatches(key, pattern) => match obj.attributes().get(key) {
+                Some(value) => match value.normalize() {
+                    AttributeVal::S(s) => pattern.is_match(&s),
+                    AttributeVal::N(_) => false,
+                },
+                None => false,
+            },
+            Query::AttrCompare(key, op, value) => obj
+                .attributes()
+                .get(key)
+                .and_then(|x| x.to_number())
+                .is_some_and(|x| op.apply(x, *value)),
+            Query::FanoutAbove(n) => {
+                let index = index.unwrap();
+                let fanout = match obj {
+                    Object::Module(_) => 0,
+                    Object::Cell(cell) => max_fanout(
+                        index,
+                        cell.connections
+                            .iter()
+                            .filter(|(port, _)| {
+                                cell.port_directions
+                                    .get(*port)
+                                    .is_some_and(|dir| dir.drives_net_from_instance())
+                            })
+                            .flat_map(|(_, bits)| bits),
+                    ),
+                    Object::Net(netname) => max_fanout(index, netname.bits.iter()),
+                };
+                fanout > *n
+            }
+            Query::And(queries) => queries.iter().all(|q| q.matches(name, obj, index)),
+            Query::Or(queries) => queries.iter().any(|q| q.matches(name, obj, index)),
+            Query::Not(query) => !query.matches(name, obj, index),
+        }
+    }
+
+    /// Select the objects of one module matching this query
+    pub fn run_on_module(&self, module_name: &str, module: &Module) -> Selection {
+        let index = self.uses_connectivity().then(|| module.connectivity());
+        let index = index.as_ref();
+        let mut selection = Selection::default();
+
+        if self.matches(module_name, &Object::Module(module), index) {
+            selection.modules.insert(module_name.to_owned());
+        }
+        for (name, cell) in &module.cells {
+            if self.matches(name, &Object::Cell(cell), index) {
+                selection
+                    .cells
+                    .insert((module_name.to_owned(), name.clone()));
+            }
+        }
+        for (name, netname) in &module.netnames {
+            if self.matches(name, &Object::Net(netname), index) {
+                selection
+                    .nets
+                    .insert((module_name.to_owned(), name.clone()));
+            }
+        }
+        selection
+    }
+
+    /// Select the objects of every module matching this query
+    pub fn run(&self, netlist: &Netlist) -> Selection {
+        let mut selection = Selection::default();
+        for (name, module) in &netlist.modules {
+            selection.union(self.run_on_module(name, module));
+        }
+        selection
+    }
+}
+
+impl Not for Query {
+    type Output = Query;
+
+    fn not(self) -> Query {
+        match self {
+            Query::Not(query) => *query,
+            query => Query::Not(Box::new(query)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUERY_JSON: &[u8] = br#"
+    {
+      "modules": {
+        "top": {
+          "attributes": { "top": "00000000000000000000000000000001" },
+          "ports": {
+            "clk": { "direction": "input", "bits": [ 2 ] },
+            "d": { "direction": "input", "bits": [ 3 ] },
+            "q": { "direction": "output", "bits": [ 4, 5 ] }
+          },
+          "cells": {
+            "ff_keep": {
+              "type": "$dff",
+              "attributes": { "keep": 1, "src": "x.v:3.1-3.10" },
+              "port_directions": { "CLK": "input", "D": "input", "Q": "output" },
+              "connections": { "CLK": [ 2 ], "D": [ 3 ], "Q": [ 6 ] }
+            },
+            "ff": {
+              "type": "$dffe",
+              "attributes": { "src": "y.v:5.1-5.10", "role": "sync " },
+              "port_directions": { "CLK": "input", "D": "input", "Q": "output" },
+              "connections": { "CLK": [ 2 ], "D": [ 6 ], "Q": [ 7 ] }
+            },
+            "buf1": {
+              "type": "$pos",
+              "attributes": { "keep": 1, "src": "x.v:7.1-7.10" },
+              "port_directions": { "A": "input", "Y": "output" },
+              "connections": { "A": [ 6 ], "Y": [ 4 ] }
+            },
+            "buf2": {
+              "type": "$pos",
+              "port_directions": { "A": "input", "Y": "output" },
+              "connections": { "A": [ 7 ], "Y": [ 5 ] }
+            }
+          },
+          "netnames": {
+            "clk": { "bits": [ 2 ], "attributes": { "weight": "00000000000000000000000000000101" } },
+            "mid": { "bits": [ 6 ] }
+          }
+        }
+      }
+    }"#;
+
+    #[test]
+    fn query_test() {
+        let netlist = Netlist::from_slice(QUERY_JSON).unwrap();
+
+        let query = Query::cell()
+            .and(Query::has_attr("keep"))
+            .and(Query::cell_type("$dff*"))
+            .and(Query::attr_matches("src", "x.v:*"));
+        let selection = query.run(&netlist);
+        assert_eq!(selection.len(), 1);
+        assert!(selection.contains_cell("top", "ff_keep"));
+
+        let selection = Query::cell_type("$dff*")
+            .and(!Query::has_attr("keep"))
+            .run(&netlist);
+        assert_eq!(selection.len(), 1);
+        assert!(selection.contains_cell("top", "ff"));
+
+        // Yosys marks string attributes with a trailing space
+        let selection = Query::attr_matches("role", "sync").run(&netlist);
+        assert_eq!(selection.len(), 1);
+        assert!(selection.contains_cell("top", "ff"));
+
+        let selection = Query::name("buf*").or(Query::name("clk")).run(&netlist);
+        assert_eq!(selection.cells.len(), 2);
+        assert!(selection.contains_net("top", "clk"));
+
+        let selection = Query::attr_cmp("weight", Comparison::Ge, 5).run(&netlist);
+        assert_eq!(selection.len(), 1);
+        assert!(selection.contains_net("top", "clk"));
+
+        let selection = Query::module()
+            .and(Query::attr_eq("top", AttributeVal::N(1)))
+            .run(&netlist);
+        assert!(selection.contains_module("top"));
+        assert_eq!(selection.len(), 1);
+
+        // ff_keep drives both ff and buf1, and the clock net drives both flops
+        let selection = Query::fanout_above(1).run(&netlist);
+        assert_eq!(selection.len(), 3);
+        assert!(selection.contains_cell("top", "ff_keep"));
+        assert!(selection.contains_net("top", "clk"));
+        assert!(selection.contains_net("top", "mid"));
+    }
+}
@@ -0,0 +1,352 @@
+use crate::{Error, Netlist};
+use std::collections::HashMap;
+
+/// A pattern for matching object names
+#[derive(Clone, Debug)]
+pub enum NamePattern {
+    /// Glob pattern where `*` matches any sequence of characters and `?` matches
+    /// exactly one character
+    Glob {
+        pattern: String,
+        case_insensitive: bool,
+    },
+    /// Regular expression, which matches if it is found anywhere in the name
+    ///
+    /// Use `(?i)` in the expression for case-insensitive matching.
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+/// Match `name` against a glob pattern supporting `*` and `?`
+///
+/// Runs in `O(pattern.len() * name.len())` time at worst.
+pub(crate) fn glob_match(pattern: &str, name: &str, case_insensitive: bool) -> bool {
+    fn inner(pattern: &[char], name: &[char]) -> bool {
+        let (mut p, mut n) = (0, 0);
+        // Position just after the last `*` seen, and the name position it is
+        // currently assumed to match up to
+        let mut star = None;
+        while n < name.len() {
+            match pattern.get(p) {
+                Some('*') => {
+                    p += 1;
+                    star = Some((p, n));
+                }
+                Some(&c) if c == '?' || c == name[n] => {
+                    p += 1;
+                    n += 1;
+                }
+                _ => {
+                    // Let the last `*` swallow one more character and try again
+                    let Some((star_p, star_n)) = star else {
+                        return false;
+                    };
+                    p = star_p;
+                    n = star_n + 1;
+                    star = Some((star_p, n));
+                }
+            }
+        }
+        pattern[p..].iter().all(|&c| c == '*')
+    }
+
+    let chars = |s: &str| {
+        if case_insensitive {
+            s.to_lowercase().chars().collect::<Vec<_>>()
+        } else {
+            s.chars().collect::<Vec<_>>()
+        }
+    };
+    inner(&chars(pattern), &chars(name))
+}
+
+impl NamePattern {
+    /// Create a case-sensitive glob pattern
+    pub fn glob(pattern: &str) -> Self {
+        NamePattern::Glob {
+            pattern: pattern.to_owned(),
+            case_insensitive: false,
+        }
+    }
+
+    /// Create a case-insensitive glob pattern
+    pub fn glob_case_insensitive(pattern: &str) -> Self {
+        NamePattern::Glob {
+            pattern: pattern.to_owned(),
+            case_insensitive: true,
+        }
+    }
+
+    /// Create a regular expression pattern
+    #[cfg(feature = "regex")]
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(NamePattern::Regex(regex::Regex::new(pattern)?))
+    }
+
+    /// Check whether `name` matches this pattern
+    pub fn is_match(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Glob {
+                pattern,
+                case_insensitive,
+            } => glob_match(pattern, name, *case_insensitive),
+            #[cfg(feature = "regex")]
+            NamePattern::Regex(re) => re.is_match(name),
+        }
+    }
+}
+
+/// Options for `Netlist::find_nets_with`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct NetSearchOptions {
+    /// Stop after this many matches
+    pub limit: Option<usize>,
+    /// Only search modules instantiated below this top module, and report the
+    /// hierarchical path of each match
+    pub top: Option<String>,
+}
+
+/// A netname found by `Netlist::find_nets`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct NetMatch {
+    /// Module containing the net
+    pub module: String,
+    /// Name of the net
+    pub netname: String,
+    /// Number of bits in the net
+    pub width: usize,
+    /// Hierarchical paths of the net, such as `top.u_soc.u_dma/axi_awvalid`, one for
+    /// each instance of the module below the top module
+    ///
+    /// This is empty if no top module was given.
+    pub paths: Vec<String>,
+}
+
+impl Netlist {
+    /// Collect the hierarchical instance paths of every module instantiated below `top`
+    fn instance_paths(&self, top: &str) -> HashMap<&str, Vec<String>> {
+        fn visit<'a>(
+            netlist: &'a Netlist,
+            module: &'a str,
+            path: String,
+            stack: &mut Vec<&'a str>,
+            paths: &mut HashMap<&'a str, Vec<String>>,
+        ) {
+            if stack.contains(&module) {
+                return;
+            }
+            stack.push(module);
+
+            let mut cells = netlist.modules[module].cells.iter().collect::<Vec<_>>();
+            cells.sort_by_key(|(name, _)| name.as_str());
+            for (cell_name, cell) in cells {
+                if let Some((submodule, _)) = netlist.modules.get_key_value(&cell.cell_type) {
+                    let path = format!("{}.{}", path, cell_name);
+                    visit(netlist, submodule, path.clone(), stack, paths);
+                    paths.entry(submodule).or_default().push(path);
+                }
+            }
+
+            stack.pop();
+        }
+
+        let mut paths = HashMap::new();
+        if let Some((top, _)) = self.modules.get_key_value(top) {
+            paths.insert(top.as_str(), vec![top.clone()]);
+            visit(self, top, top.clone(), &mut Vec::new(), &mut paths);
+        }
+        for module_paths in paths.values_mut() {
+            module_paths.sort();
+        }
+        paths
+    }
+
+    /// Search the netnames of every module for names matching `pattern`
+    ///
+    /// Results are sorted by module name and then by net name.
+    pub fn find_nets(&self, pattern: &NamePattern) -> Vec<NetMatch> {
+        self.find_nets_with(pattern, &NetSearchOptions::default())
+            .unwrap()
+    }
+
+    /// Search the netnames of every module for names matching `pattern`
+    ///
+    /// If `options.top` is set, only modules instantiated below it are searched and
+    /// an error is returned if it doesn't exist.
+    pub fn find_nets_with(
+        &self,
+        pattern: &NamePattern,
+        options: &NetSearchOptions,
+    ) -> Result<Vec<NetMatch>, Error> {
+        let instance_paths = match &options.top {
+            Some(top) => {
+                if !self.modules.contains_key(top) {
+                    return Err(Error::ModuleNotFound(top.clone()));
+                }
+                Some(self.instance_paths(top))
+            }
+            None => None,
+        };
+
+        let mut module_names = self.modules.keys().collect::<Vec<_>>();
+        module_names.sort();
+
+        let mut matches = Vec::new();
+        for module_name in module_names {
+            let paths = match &instance_paths {
+                Some(instance_paths) => match instance_paths.get(module_name.as_str()) {
+                    Some(paths) => paths.as_slice(),
+                    None => continue,
+                },
+                None => &[],
+            };
+
+            let mut netnames = self.modules[module_name]
+                .netnames
+                .iter()
+                .filter(|(name, _)| pattern.is_match(name))
+                .collect::<Vec<_>>();
+            netnames.sort_by_key(|(name, _)| name.as_str());
+
+            for (name, netname) in netnames {
+                if options.limit == Some(matches.len()) {
+                    return Ok(matches);
+                }
+                matches.push(NetMatch {
+                    module: module_name.clone(),
+                    netname: name.clone(),
+                    width: netname.bits.len(),
+                    paths: paths
+                        .iter()
+                        .map(|path| format!("{}/{}", path, name))
+                        .collect(),
+                });
+            }
+        }
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOC_JSON: &[u8] = br#"
+    {
+      "modules": {
+        "top": {
+          "cells": { "u_soc": { "type": "soc", "connections": {} } },
+          "netnames": { "clk": { "bits": [ 2 ] } }
+        },
+        "soc": {
+          "cells": {
+            "u_dma": { "type": "dma", "connections": {} },
+            "u_dma2": { "type": "dma", "connections": {} }
+          },
+          "netnames": { "m_axi_awvalid": { "bits": [ 2 ] } }
+        },
+        "dma": {
+          "netnames": {
+            "axi_awvalid": { "bits": [ 2 ] },
+            "AXI_AWADDR": { "bits": [ 3, 4, 5, 6 ] },
+            "irq": { "bits": [ 7 ] }
+          }
+        },
+        "unused": {
+          "netnames": { "axi_awvalid": { "bits": [ 2 ] } }
+        }
+      }
+    }"#;
+
+    #[test]
+    fn glob_match_test() {
+        assert!(glob_match("*", "", false));
+        assert!(glob_match("*axi*", "m_axi_awvalid", false));
+        assert!(glob_match("a?c", "abc", false));
+        assert!(!glob_match("a?c", "ac", false));
+        assert!(!glob_match("*AXI*", "m_axi_awvalid", false));
+        assert!(glob_match("*AXI*", "m_axi_awvalid", true));
+        assert!(glob_match("$dff*", "$dffe", false));
+        assert!(!glob_match("$dff*", "$adff", false));
+        assert!(glob_match("a*b*c", "aXbYbZc", false));
+        assert!(glob_match("*b", "abab", false));
+        assert!(!glob_match("*b*c", "abab", false));
+        assert!(glob_match("**?", "x", false));
+        assert!(!glob_match("", "x", false));
+    }
+
+    #[test]
+    fn glob_match_pathological_test() {
+        // Exponential for a recursive matcher that tries every split at each `*`
+        let name = "a".repeat(10_000);
+        assert!(!glob_match("*a*a*a*a*a*a*a*a*a*a*b", &name, false));
+        assert!(glob_match("*a*a*a*a*a*a*a*a*a*a*", &name, false));
+    }
+
+    #[test]
+    fn find_nets_test() {
+        let netlist = Netlist::from_slice(SOC_JSON).unwrap();
+
+        let matches = netlist.find_nets(&NamePattern::glob("*axi_aw*"));
+        let found = matches
+            .iter()
+            .map(|m| (m.module.as_str(), m.netname.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                ("dma", "axi_awvalid"),
+                ("soc", "m_axi_awvalid"),
+                ("unused", "axi_awvalid")
+            ]
+        );
+        assert!(matches[0].paths.is_empty());
+
+        let matches = netlist.find_nets(&NamePattern::glob_case_insensitive("*axi_aw*"));
+        assert_eq!(matches.len(), 4);
+        assert_eq!(matches[0].netname, "AXI_AWADDR");
+        assert_eq!(matches[0].width, 4);
+
+        let options = NetSearchOptions {
+            limit: None,
+            top: Some("top".to_owned()),
+        };
+        let matches = netlist
+            .find_nets_with(&NamePattern::glob("*axi_awvalid"), &options)
+            .unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(
+            matches[0].paths,
+            [
+                "top.u_soc.u_dma/axi_awvalid",
+                "top.u_soc.u_dma2/axi_awvalid"
+            ]
+        );
+        assert_eq!(matches[1].paths, ["top.u_soc/m_axi_awvalid"]);
+
+        let options = NetSearchOptions {
+            limit: Some(1),
+            top: None,
+        };
+        let matches = netlist
+            .find_nets_with(&NamePattern::glob("*"), &options)
+            .unwrap();
+        assert_eq!(matches.len(), 1);
+
+        let options = NetSearchOptions {
+            limit: None,
+            top: Some("nonexistent".to_owned()),
+        };
+        assert!(netlist
+            .find_nets_with(&NamePattern::glob("*"), &options)
+            .is_err());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn find_nets_regex_test() {
+        let netlist = Netlist::from_slice(SOC_JSON).unwrap();
+        let matches = netlist.find_nets(&NamePattern::regex("(?i)^axi_aw").unwrap());
+        assert_eq!(matches.len(), 3);
+    }
+}
@@ -0,0 +1,625 @@
+use crate::{BitVal, Cell, Module, PortDirection, SimError, SpecialBit};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+
+use SpecialBit as B;
+
+fn not(a: SpecialBit) -> SpecialBit {
+    match a {
+        B::_0 => B::_1,
+        B::_1 => B::_0,
+        _ => B::X,
+    }
+}
+
+fn and(a: SpecialBit, b: SpecialBit) -> SpecialBit {
+    match (a, b) {
+        (B::_0, _) | (_, B::_0) => B::_0,
+        (B::_1, B::_1) => B::_1,
+        _ => B::X,
+    }
+}
+
+fn or(a: SpecialBit, b: SpecialBit) -> SpecialBit {
+    match (a, b) {
+        (B::_1, _) | (_, B::_1) => B::_1,
+        (B::_0, B::_0) => B::_0,
+        _ => B::X,
+    }
+}
+
+fn xor(a: SpecialBit, b: SpecialBit) -> SpecialBit {
+    match (a, b) {
+        (B::_0 | B::_1, B::_0 | B::_1) if a == b => B::_0,
+        (B::_0 | B::_1, B::_0 | B::_1) => B::_1,
+        _ => B::X,
+    }
+}
+
+fn mux(a: SpecialBit, b: SpecialBit, s: SpecialBit) -> SpecialBit {
+    match s {
+        B::_0 => a,
+        B::_1 => b,
+        _ if a == b && matches!(a, B::_0 | B::_1) => a,
+        _ => B::X,
+    }
+}
+
+/// How a supported cell type is evaluated
+#[derive(Copy, Clone, Debug)]
+enum Kind {
+    Unary(fn(SpecialBit) -> SpecialBit),
+    Binary(fn(SpecialBit, SpecialBit) -> SpecialBit),
+    Mux,
+    Lut,
+    /// A flip-flop, clocked every cycle
+    Dff,
+}
+
+fn cell_kind(cell_type: &str) -> Option<Kind> {
+    Some(match cell_type {
+        "$pos" | "$_BUF_" => Kind::Unary(|a| a),
+        "$not" | "$_NOT_" => Kind::Unary(not),
+        "$and" | "$_AND_" => Kind::Binary(and),
+        "$or" | "$_OR_" => Kind::Binary(or),
+        "$xor" | "$_XOR_" => Kind::Binary(xor),
+        "$xnor" | "$_XNOR_" => Kind::Binary(|a, b| not(xor(a, b))),
+        "$_NAND_" => Kind::Binary(|a, b| not(and(a, b))),
+        "$_NOR_" => Kind::Binary(|a, b| not(or(a, b))),
+        "$mux" | "$_MUX_" => Kind::Mux,
+        "$lut" => Kind::Lut,
+        "$dff" | "$_DFF_P_" | "$_DFF_N_" => Kind::Dff,
+        _ => return None,
+    })
+}
+
+/// Format of a vector file for `Simulator::run_vectors`
+///
+/// In the `Csv` format the first line names the columns: `cycle` followed by port
+/// names. Each following line gives a cycle number and, for each port, a binary
+/// value written MSB first (`0`, `1`, `x`, or `z`) or nothing. Input port values are
+/// applied before the cycle's clock edge and output port values are the expected
+/// outputs at that time.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum VectorFormat {
+    /// Comma-separated values
+    Csv,
+}
+
+/// An output that didn't have its expected value
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct VectorMismatch {
+    /// Cycle number from the vector file
+    pub cycle: usize,
+    /// Output port name
+    pub port: String,
+    /// Expected value, MSB first
+    pub expected: String,
+    /// Actual value, MSB first
+    pub actual: String,
+}
+
+/// Result of running a vector file
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct VectorReport {
+    /// Number of cycles run
+    pub cycles: usize,
+    /// Every output that differed from its expected value
+    pub mismatches: Vec<VectorMismatch>,
+}
+
+/// Returned by `Simulator::run_vectors`
+pub type VectorResult = Result<VectorReport, SimError>;
+
+/// A cycle-based, four-state simulator for a single module
+///
+/// Only bitwise logic (`$not`, `$and`, `$or`, `$xor`, `$xnor`, `$pos`), `$mux`,
+/// `$lut`, the equivalent gate-level cells, and simple flip-flops (`$dff`, `$_DFF_P_`,
+/// `$_DFF_N_`) are supported. Every flip-flop is clocked once per cycle, whatever
+/// its clock is connected to, and `z` is treated as `x` by logic.
+#[derive(Clone, Debug)]
+pub struct Simulator<'a> {
+    module: &'a Module,
+    cells: Vec<(&'a Cell, Kind)>,
+    state: HashMap<usize, SpecialBit>,
+    x_tolerant: bool,
+}
+
+impl<'a> Simulator<'a> {
+    /// Prepare to simulate `module`, with every signal initially `x`
+    pub fn new(module: &'a Module) -> Result<Self, SimError> {
+        let mut cells = module.cells.iter().collect::<Vec<_>>();
+        cells.sort_by_key(|(name, _)| name.as_str());
+        let cells = cells
+            .into_iter()
+            .map(|(name, cell)| match cell_kind(&cell.cell_type) {
+                Some(kind) => Ok((cell, kind)),
+                None => Err(SimError::UnsupportedCell {
+                    cell: name.clone(),
+                    cell_type: cell.cell_type.clone(),
+                }),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Simulator {
+            module,
+            cells,
+            state: HashMap::new(),
+            x_tolerant: false,
+        })
+    }
+
+    /// Treat `x` bits in expected values as matching anything
+    pub fn set_x_tolerant(&mut self, x_tolerant: bool) {
+        self.x_tolerant = x_tolerant;
+    }
+
+    /// The current value of a signal
+    pub(crate) fn signal(&self, n: usize) -> SpecialBit {
+        self.state.get(&n).copied().unwrap_or(B::X)
+    }
+
+    fn get(&self, bit: &BitVal) -> SpecialBit {
+        match bit {
+            BitVal::N(n) => self.signal(*n),
+            BitVal::S(s) => *s,
+        }
+    }
+
+    fn port(&self, name: &str, input: bool) -> Result<&'a [BitVal], SimError> {
+        match self.module.ports.get(name) {
+            Some(port) if (port.direction == PortDirection::Input) == input => Ok(&port.bits),
+            _ => Err(SimError::UnknownPort(name.to_owned())),
+        }
+    }
+
+    /// Set an input port to a binary value written MSB first
+    pub fn set_input(&mut self, port: &str, value: &str) -> Result<(), SimError> {
+        let bits = self.port(port, true)?;
+        let bad_value = || SimError::BadValue {
+            port: port.to_owned(),
+            value: value.to_owned(),
+        };
+        if value.len() != bits.len() {
+            return Err(bad_value());
+        }
+        for (bit, c) in bits.iter().zip(value.chars().rev()) {
+            let v = match c {
+                '0' => B::_0,
+                '1' => B::_1,
+                'x' => B::X,
+                'z' => B::Z,
+                _ => return Err(bad_value()),
+            };
+            if let BitVal::N(n) = bit {
+                self.state.insert(*n, v);
+            }
+        }
+        Ok(())
+    }
+
+    /// The value of an output port, MSB first
+    pub fn output(&self, port: &str) -> Result<String, SimError> {
+        let bits = self.port(port, false)?;
+        Ok(bits
+            .iter()
+            .rev()
+            .map(|bit| match self.get(bit) {
+                B::_0 => '0',
+                B::_1 => '1',
+                B::X => 'x',
+                B::Z => 'z',
+            })
+            .collect())
+    }
+
+    /// Evaluate a combinational cell's inputs, extended or truncated to the width of
+    /// its output
+    fn input(&self, cell: &Cell, port: &str, width: usize) -> Vec<SpecialBit> {
+        let bits = cell.connections.get(port).map_or(&[][..], |x| x.as_slice());
+        let signed = cell
+            .parameters
+            .get(&format!("{}_SIGNED", port))
+            .and_then(|x| x.to_number())
+            .is_some_and(|x| x != 0);
+        let fill = match bits.last() {
+            Some(msb) if signed => self.get(msb),
+            _ => B::_0,
+        };
+        (0..width)
+            .map(|i| bits.get(i).map_or(fill, |bit| self.get(bit)))
+            .collect()
+    }
+
+    /// Evaluate combinational logic until nothing changes
+    ///
+    /// Combinational loops that never settle are left in whatever state they reach
+    /// after one pass per cell.
+    pub fn settle(&mut self) {
+        for _ in 0..=self.cells.len() {
+            let mut changed = false;
+            for &(cell, kind) in &self.cells {
+                let Some(out) = cell.connections.get("Y") else {
+                    continue;
+                };
+                let width = out.len();
+                let values = match kind {
+                    Kind::Unary(f) => self.input(cell, "A", width).into_iter().map(f).collect(),
+                    Kind::Binary(f) => self
+                        .input(cell, "A", width)
+                        .into_iter()
+                        .zip(self.input(cell, "B", width))
+                        .map(|(a, b)| f(a, b))
+                        .collect(),
+                    Kind::Mux => {
+                        let s = self.input(cell, "S", 1)[0];
+                        self.input(cell, "A", width)
+                            .into_iter()
+                            .zip(self.input(cell, "B", width))
+                            .map(|(a, b)| mux(a, b, s))
+                            .collect::<Vec<_>>()
+                    }
+                    Kind::Lut => {
+                        let a =
+                            self.input(cell, "A", cell.connections.get("A").map_or(0, |x| x.len()));
+                        let lut = cell.parameters.get("LUT").and_then(|x| x.to_number());
+                        // An index bit past the width of `usize` selects an entry that
+                        // `lut` can't hold, so the output is `x`
+                        let mut idx = Some(0usize);
+                        for (i, bit) in a.iter().enumerate() {
+                            match bit {
+                                B::_0 => {}
+                                B::_1 => {
+                                    let mask =
+                                        u32::try_from(i).ok().and_then(|i| 1usize.checked_shl(i));
+                                    idx = idx.zip(mask).map(|(x, mask)| x | mask);
+                                }
+                                _ => idx = None,
+                            }
+                        }
+                        let v = match (lut, idx) {
+                            (Some(lut), Some(idx)) if idx < usize::BITS as usize => {
+                                if (lut >> idx) & 1 != 0 {
+                                    B::_1
+                                } else {
+                                    B::_0
+                                }
+                            }
+                            _ => B::X,
+                        };
+                        vec![v; width]
+                    }
+                    Kind::Dff => continue,
+                };
+                for (bit, v) in out.iter().zip(values) {
+                    if let BitVal::N(n) = bit {
+                        if self.state.insert(*n, v) != Some(v) {
+                            changed = true;
+                        }
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// Clock every flip-flop once, then settle
+    pub fn step(&mut self) {
+        let mut updates = Vec::new();
+        for &(cell, kind) in &self.cells {
+            if let Kind::Dff = kind {
+                let (Some(d), Some(q)) = (cell.connections.get("D"), cell.connections.get("Q"))
+                else {
+                    continue;
+                };
+                for (d, q) in d.iter().zip(q) {
+                    if let BitVal::N(n) = q {
+                        updates.push((*n, self.get(d)));
+                    }
+                }
+            }
+        }
+        self.state.extend(updates);
+        self.settle();
+    }
+
+    /// Apply the inputs of one row and settle, calling `check` with the expected
+    /// outputs before clocking the flip-flops
+    fn run_rows(
+        &mut self,
+        vectors: &Vectors,
+        mut check: impl FnMut(&mut Self, usize, Vec<(&str, &str)>) -> Result<(), SimError>,
+    ) -> Result<(), SimError> {
+        for (cycle, values) in &vectors.rows {
+            let mut outputs = Vec::new();
+            for (port, value) in vectors.ports.iter().zip(values) {
+                let Some(value) = value else {
+                    continue;
+                };
+                if self.port(port, true).is_ok() {
+                    self.set_input(port, value)?;
+                } else {
+                    outputs.push((port.as_str(), value.as_str()));
+                }
+            }
+            self.settle();
+            check(self, *cycle, outputs)?;
+            self.step();
+        }
+        Ok(())
+    }
+
+    /// Run each cycle of a vector file, checking outputs against their expected values
+    pub fn run_vectors<R: Read>(&mut self, vectors: R, format: VectorFormat) -> VectorResult {
+        let vectors = Vectors::read(vectors, format, self.module)?;
+        let mut report = VectorReport::default();
+        self.run_rows(&vectors, |sim, cycle, outputs| {
+            for (port, expected) in outputs {
+                let actual = sim.output(port)?;
+                let matches = expected.len() == actual.len()
+                    && expected
+                        .chars()
+                        .zip(actual.chars())
+                        .all(|(e, a)| e == a || (sim.x_tolerant && e == 'x'));
+                if !matches {
+                    report.mismatches.push(VectorMismatch {
+                        cycle,
+                        port: port.to_owned(),
+                        expected: expected.to_owned(),
+                        actual,
+                    });
+                }
+            }
+            report.cycles += 1;
+            Ok(())
+        })?;
+        Ok(report)
+    }
+
+    /// Run the inputs of a vector file and write them back out with the actual value of
+    /// every output port, to make a golden file from a known-good netlist
+    pub fn capture_vectors<R: Read, W: Write>(
+        &mut self,
+        stimulus: R,
+        mut writer: W,
+        format: VectorFormat,
+    ) -> Result<(), SimError> {
+        let vectors = Vectors::read(stimulus, format, self.module)?;
+        let inputs = vectors
+            .ports
+            .iter()
+            .enumerate()
+            .filter(|(_, port)| self.port(port, true).is_ok())
+            .collect::<Vec<_>>();
+        let mut outputs = self
+            .module
+            .ports
+            .iter()
+            .filter(|(_, port)| port.direction != PortDirection::Input)
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        outputs.sort();
+
+        let mut header = vec!["cycle"];
+        header.extend(inputs.iter().map(|(_, port)| port.as_str()));
+        header.extend(&outputs);
+        writeln!(writer, "{}", header.join(","))?;
+
+        let mut row = 0;
+        self.run_rows(&vectors, |sim, cycle, _| {
+            let values = &vectors.rows[row].1;
+            row += 1;
+            let mut line = vec![cycle.to_string()];
+            line.extend(
+                inputs
+                    .iter()
+                    .map(|(i, _)| values[*i].clone().unwrap_or_default()),
+            );
+            for port in &outputs {
+                line.push(sim.output(port)?);
+            }
+            writeln!(writer, "{}", line.join(","))?;
+            Ok(())
+        })
+    }
+}
+
+/// A parsed vector file
+struct Vectors {
+    /// Port name of each column after the cycle number
+    ports: Vec<String>,
+    /// Cycle number and the value in each column
+    rows: Vec<(usize, Vec<Option<String>>)>,
+}
+
+impl Vectors {
+    fn read<R: Read>(reader: R, format: VectorFormat, module: &Module) -> Result<Self, SimError> {
+        let VectorFormat::Csv = format;
+        let mut lines = BufReader::new(reader).lines().enumerate();
+        let parse_error = |line: usize, message: String| SimError::Parse {
+            line: line + 1,
+            message,
+        };
+
+        let (_, header) = lines
+            .next()
+            .ok_or_else(|| parse_error(0, "missing header".to_owned()))?;
+        let header = header?;
+        let mut columns = header.split(',').map(|x| x.trim());
+        if columns.next() != Some("cycle") {
+            return Err(parse_error(0, "first column must be `cycle`".to_owned()));
+        }
+        let ports = columns.map(|x| x.to_owned()).collect::<Vec<_>>();
+        if let Some(port) = ports.iter().find(|port| !module.ports.contains_key(*port)) {
+            return Err(SimError::UnknownPort(port.clone()));
+        }
+
+        let mut rows = Vec::new();
+        for (i, line) in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut fields = line.split(',').map(|x| x.trim());
+            let cycle = fields
+                .next()
+                .unwrap()
+                .parse()
+                .map_err(|_| parse_error(i, "bad cycle number".to_owned()))?;
+            let values = fields
+                .map(|x| (!x.is_empty()).then(|| x.to_owned()))
+                .collect::<Vec<_>>();
+            if values.len() > ports.len() {
+                return Err(parse_error(i, "too many columns".to_owned()));
+            }
+            rows.push((
+                cycle,
+                values
+                    .into_iter()
+                    .chain(std::iter::repeat(None))
+                    .take(ports.len())
+                    .collect(),
+            ));
+        }
+        Ok(Vectors { ports, rows })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netlist;
+
+    fn counter_netlist() -> crate::Netlist {
+        // y = registered (a & b), n = !a
+        netlist! {
+            module top {
+                input clk;
+                input a[2];
+                input b[2];
+                output y[2];
+                output n[2];
+                wire t[2];
+                cell "$and" and { A: a, B: b, Y: t }
+                cell "$dff" r { CLK: clk, D: t, Q: y }
+                cell "$not" inv { A: a, Y: n }
+            }
+        }
+    }
+
+    #[test]
+    fn run_vectors_test() {
+        let netlist = counter_netlist();
+        let mut sim = Simulator::new(&netlist.modules["top"]).unwrap();
+        let vectors = "cycle,a,b,y,n\n\
+                       0,11,01,xx,00\n\
+                       1,10,11,01,01\n\
+                       2,,,11,01\n";
+        let report = sim
+            .run_vectors(vectors.as_bytes(), VectorFormat::Csv)
+            .unwrap();
+        assert_eq!(report.cycles, 3);
+        assert_eq!(
+            report.mismatches,
+            [VectorMismatch {
+                cycle: 2,
+                port: "y".to_owned(),
+                expected: "11".to_owned(),
+                actual: "10".to_owned(),
+            }]
+        );
+
+        let mut sim = Simulator::new(&netlist.modules["top"]).unwrap();
+        sim.set_x_tolerant(true);
+        let report = sim
+            .run_vectors("cycle,a,b,y\n0,11,11,x1\n".as_bytes(), VectorFormat::Csv)
+            .unwrap();
+        assert_eq!(report.mismatches.len(), 1);
+        let mut sim = Simulator::new(&netlist.modules["top"]).unwrap();
+        sim.set_x_tolerant(true);
+        let report = sim
+            .run_vectors("cycle,a,b,y\n0,11,11,xx\n".as_bytes(), VectorFormat::Csv)
+            .unwrap();
+        assert_eq!(report.mismatches, []);
+    }
+
+    #[test]
+    fn capture_vectors_test() {
+        let netlist = counter_netlist();
+        let module = &netlist.modules["top"];
+        let stimulus = "cycle,a,b\n0,11,01\n1,10,11\n2,00,\n";
+
+        let mut golden = Vec::new();
+        Simulator::new(module)
+            .unwrap()
+            .capture_vectors(stimulus.as_bytes(), &mut golden, VectorFormat::Csv)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(golden.clone()).unwrap(),
+            "cycle,a,b,n,y\n0,11,01,00,xx\n1,10,11,01,01\n2,00,,11,10\n"
+        );
+
+        let report = Simulator::new(module)
+            .unwrap()
+            .run_vectors(&golden[..], VectorFormat::Csv)
+            .unwrap();
+        assert_eq!(report.mismatches, []);
+    }
+
+    #[test]
+    fn wide_lut_test() {
+        let netlist = netlist! {
+            module top {
+                input a[70];
+                output y;
+                cell "$lut" l { A: a, Y: y } { WIDTH: 70, LUT: 2 }
+            }
+        };
+        let mut sim = Simulator::new(&netlist.modules["top"]).unwrap();
+        let one_hot = |i: usize| {
+            let mut value = "0".repeat(70);
+            value.replace_range(69 - i..70 - i, "1");
+            value
+        };
+        sim.set_input("a", &one_hot(0)).unwrap();
+        sim.settle();
+        assert_eq!(sim.output("y").unwrap(), "1");
+        sim.set_input("a", &one_hot(1)).unwrap();
+        sim.settle();
+        assert_eq!(sim.output("y").unwrap(), "0");
+        sim.set_input("a", &one_hot(68)).unwrap();
+        sim.settle();
+        assert_eq!(sim.output("y").unwrap(), "x");
+    }
+
+    #[test]
+    fn errors_test() {
+        let netlist = netlist! {
+            module top {
+                input a;
+                output y;
+                cell "$add" add { A: a, B: a, Y: y }
+            }
+        };
+        assert!(matches!(
+            Simulator::new(&netlist.modules["top"]),
+            Err(SimError::UnsupportedCell { .. })
+        ));
+
+        let netlist = counter_netlist();
+        let mut sim = Simulator::new(&netlist.modules["top"]).unwrap();
+        assert!(matches!(
+            sim.run_vectors("cycle,q\n".as_bytes(), VectorFormat::Csv),
+            Err(SimError::UnknownPort(_))
+        ));
+        assert!(matches!(
+            sim.run_vectors("cycle,a\n0,1\n".as_bytes(), VectorFormat::Csv),
+            Err(SimError::BadValue { .. })
+        ));
+        assert!(matches!(
+            sim.run_vectors("a\n".as_bytes(), VectorFormat::Csv),
+            Err(SimError::Parse { line: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,382 @@
+//! Helpers for building small netlists in tests
+//!
+//! The `netlist!` macro is the usual way to use this module:
+//!
+//! ```
+//! use yosys_netlist_json::netlist;
+//!
+//! let netlist = netlist! {
+//!     module top {
+//!         input a[8];
+//!         output y[8];
+//!         cell "$not" n1 { A: a, Y: y } { A_WIDTH: 8, Y_WIDTH: 8 }
+//!     }
+//! };
+//! assert_eq!(netlist.modules["top"].cells["n1"].connections["Y"].len(), 8);
+//! ```
+//!
+//! Cell types are string literals because `macro_rules!` can't match a `$` token.
+
+use crate::{
+    AttributeVal, BitVal, Cell, Module, Netlist, Netname, Port, PortDirection, SpecialBit,
+};
+use std::collections::HashMap;
+
+/// The value of a cell connection given to `NetlistBuilder::cell`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Conn<'a> {
+    /// Every bit of a wire
+    Wire(&'a str),
+    /// One bit of a wire, by index from the LSB
+    Bit(&'a str, usize),
+    /// A constant written MSB first like a Verilog literal, e.g. `"01x"`
+    Const(&'a str),
+}
+
+/// Builds a netlist one object at a time, allocating signal numbers as it goes
+///
+/// Every wire gets a netname, and ports also get a module port. Signals are numbered
+/// from 2 in each module in the order wires are declared.
+///
+/// When the netlist is built, instances of modules in the netlist get
+/// `port_directions` from the ports of that module. Internal (`$`) cells get `Y` and
+/// `Q` as outputs and every other port as an input.
+#[derive(Clone, Debug, Default)]
+pub struct NetlistBuilder {
+    netlist: Netlist,
+    next_signal: HashMap<String, usize>,
+}
+
+impl NetlistBuilder {
+    /// Start an empty netlist
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an empty module
+    pub fn module(&mut self, name: &str) -> &mut Self {
+        self.netlist
+            .modules
+            .insert(name.to_owned(), Module::default());
+        self.next_signal.insert(name.to_owned(), 2);
+        self
+    }
+
+    fn module_mut(&mut self, module: &str) -> &mut Module {
+        self.netlist
+            .modules
+            .get_mut(module)
+            .unwrap_or_else(|| panic!("module `{}` not declared", module))
+    }
+
+    /// Add a wire, and a port for it if `direction` is given
+    ///
+    /// Panics if the module hasn't been added.
+    pub fn wire(
+        &mut self,
+        module: &str,
+        name: &str,
+        width: usize,
+        direction: Option<PortDirection>,
+    ) -> &mut Self {
+        let next = self.next_signal.get_mut(module).unwrap();
+        let bits = (*next..*next + width).map(BitVal::N).collect::<Vec<_>>();
+        *next += width;
+
+        let m = self.module_mut(module);
+        if let Some(direction) = direction {
+            m.ports.insert(
+                name.to_owned(),
+                Port {
+                    direction,
+                    bits: bits.clone(),
+                    offset: 0,
+                    upto: 0,
+                    signed: 0,
+                    extra: HashMap::new(),
+                },
+            );
+        }
+        m.netnames.insert(
+            name.to_owned(),
+            Netname {
+                hide_name: name.starts_with('$') as usize,
+                bits,
+                offset: 0,
+                upto: 0,
+                signed: 0,
+                attributes: HashMap::new(),
+                extra: HashMap::new(),
+            },
+        );
+        self
+    }
+
+    /// Add a cell
+    ///
+    /// Panics if the module hasn't been added or a connection names a wire that
+    /// hasn't been added.
+    pub fn cell(
+        &mut self,
+        module: &str,
+        name: &str,
+        cell_type: &str,
+        connections: &[(&str, Conn<'_>)],
+        parameters: Vec<(&str, AttributeVal)>,
+    ) -> &mut Self {
+        let m = self.module_mut(module);
+        let wire = |wire: &str| {
+            &m.netnames
+                .get(wire)
+                .unwrap_or_else(|| panic!("wire `{}` not declared in `{}`", wire, module))
+                .bits
+        };
+        let connections = connections
+            .iter()
+            .map(|&(port, conn)| {
+                let bits = match conn {
+                    Conn::Wire(w) => wire(w).clone(),
+                    Conn::Bit(w, i) => vec![wire(w)[i]],
+                    Conn::Const(c) => c
+                        .chars()
+                        .rev()
+                        .map(|c| match c {
+                            '0' => BitVal::S(SpecialBit::_0),
+                            '1' => BitVal::S(SpecialBit::_1),
+                            'x' => BitVal::S(SpecialBit::X),
+                            'z' => BitVal::S(SpecialBit::Z),
+                            _ => panic!("bad constant bit `{}`", c),
+                        })
+                        .collect(),
+                };
+                (port.to_owned(), bits)
+            })
+            .collect();
+
+        m.cells.insert(
+            name.to_owned(),
+            Cell {
+                hide_name: name.starts_with('$') as usize,
+                cell_type: cell_type.to_owned(),
+                parameters: parameters
+                    .into_iter()
+                    .map(|(k, v)| (k.to_owned(), v))
+                    .collect(),
+                attributes: HashMap::new(),
+                port_directions: HashMap::new(),
+                connections,
+                extra: HashMap::new(),
+            },
+        );
+        self
+    }
+
+    /// Fill in cell port directions and return the netlist
+    pub fn build(&mut self) -> Netlist {
+        let mut netlist = std::mem::take(&mut self.netlist);
+        let directions = netlist
+            .modules
+            .iter()
+            .map(|(name, m)| {
+                let ports = m
+                    .port_directions_map()
+                    .into_iter()
+                    .map(|(port, dir)| (port.to_owned(), dir))
+                    .collect::<HashMap<_, _>>();
+                (name.clone(), ports)
+            })
+            .collect::<HashMap<_, _>>();
+        for module in netlist.modules.values_mut() {
+            for cell in module.cells.values_mut() {
+                let ports = directions.get(&cell.cell_type);
+                for port in cell.connections.keys() {
+                    let dir = match ports {
+                        Some(ports) => ports.get(port).copied(),
+                        None if cell.cell_type.starts_with('$') => {
+                            Some(if port == "Y" || port == "Q" {
+                                PortDirection::Output
+                            } else {
+                                PortDirection::Input
+                            })
+                        }
+                        None => None,
+                    };
+                    if let Some(dir) = dir {
+                        cell.port_directions.insert(port.clone(), dir);
+                    }
+                }
+            }
+        }
+        netlist
+    }
+}
+
+/// Declare a netlist for a test
+///
+/// ```
+/// use yosys_netlist_json::netlist;
+///
+/// let netlist = netlist! {
+///     module top {
+///         input a[2];
+///         output y;
+///         wire t;
+///         cell "$and" u_and { A: a[0], B: a[1], Y: t }
+///         cell "inv" u_inv { i: t, o: y }
+///         cell "$eq" u_eq { A: a, B: "10", Y: t } { A_WIDTH: 2, B_WIDTH: 2 }
+///     }
+///     module inv {
+///         input i;
+///         output o;
+///     }
+/// };
+/// assert_eq!(netlist.modules.len(), 2);
+/// ```
+///
+/// Wires are declared with `input`, `output`, `inout`, or `wire`, with an optional
+/// width in brackets, and must be declared before they are used. A cell is a type
+/// (a string literal), a name, its connections, and optionally its parameters.
+/// Connections are a wire, one bit of a wire, or a constant string written MSB
+/// first. See `testutil::NetlistBuilder` for how the netlist is filled in.
+#[macro_export]
+macro_rules! netlist {
+    ($(module $module:ident { $($body:tt)* })*) => {{
+        let mut builder = $crate::testutil::NetlistBuilder::new();
+        $(
+            builder.module(stringify!($module));
+            $crate::__netlist_items!(builder, stringify!($module), $($body)*);
+        )*
+        builder.build()
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __netlist_items {
+    ($b:ident, $m:expr, ) => {};
+    ($b:ident, $m:expr, input $name:ident $([$w:expr])?; $($rest:tt)*) => {
+        $b.wire($m, stringify!($name), $crate::__netlist_width!($($w)?),
+            Some($crate::PortDirection::Input));
+        $crate::__netlist_items!($b, $m, $($rest)*);
+    };
+    ($b:ident, $m:expr, output $name:ident $([$w:expr])?; $($rest:tt)*) => {
+        $b.wire($m, stringify!($name), $crate::__netlist_width!($($w)?),
+            Some($crate::PortDirection::Output));
+        $crate::__netlist_items!($b, $m, $($rest)*);
+    };
+    ($b:ident, $m:expr, inout $name:ident $([$w:expr])?; $($rest:tt)*) => {
+        $b.wire($m, stringify!($name), $crate::__netlist_width!($($w)?),
+            Some($crate::PortDirection::InOut));
+        $crate::__netlist_items!($b, $m, $($rest)*);
+    };
+    ($b:ident, $m:expr, wire $name:ident $([$w:expr])?; $($rest:tt)*) => {
+        $b.wire($m, stringify!($name), $crate::__netlist_width!($($w)?), None);
+        $crate::__netlist_items!($b, $m, $($rest)*);
+    };
+    ($b:ident, $m:expr,
+        cell $ty:literal $name:ident { $($port:ident : $val:tt $([$bit:expr])?),* $(,)? }
+        { $($param:ident : $pval:expr),* $(,)? }
+        $($rest:tt)*
+    ) => {
+        $b.cell(
+            $m,
+            stringify!($name),
+            $ty,
+            &[$((stringify!($port), $crate::__netlist_conn!($val $([$bit])?))),*],
+            vec![$((stringify!($param), $crate::AttributeVal::from($pval))),*],
+        );
+        $crate::__netlist_items!($b, $m, $($rest)*);
+    };
+    ($b:ident, $m:expr,
+        cell $ty:literal $name:ident { $($port:ident : $val:tt $([$bit:expr])?),* $(,)? }
+        $($rest:tt)*
+    ) => {
+        $crate::__netlist_items!($b, $m, cell $ty $name { $($port: $val $([$bit])?),* } {} $($rest)*);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __netlist_width {
+    () => {
+        1
+    };
+    ($w:expr) => {
+        $w
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __netlist_conn {
+    ($w:ident) => {
+        $crate::testutil::Conn::Wire(stringify!($w))
+    };
+    ($w:ident [$bit:expr]) => {
+        $crate::testutil::Conn::Bit(stringify!($w), $bit)
+    };
+    ($c:literal) => {
+        $crate::testutil::Conn::Const($c)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn netlist_macro_test() {
+        let netlist = netlist! {
+            module top {
+                input a[2];
+                output y;
+                wire t;
+                cell "$and" u_and { A: a[0], B: a[1], Y: t } { A_WIDTH: 1, B_WIDTH: 1 }
+                cell "inv" u_inv { i: t, o: y }
+                cell "$eq" u_eq { A: a, B: "1x" }
+            }
+            module inv {
+                input i;
+                output o;
+            }
+        };
+
+        let top = &netlist.modules["top"];
+        assert_eq!(top.ports["a"].bits, [BitVal::N(2), BitVal::N(3)]);
+        assert_eq!(top.ports["y"].bits, [BitVal::N(4)]);
+        assert_eq!(top.netnames["t"].bits, [BitVal::N(5)]);
+        assert!(!top.ports.contains_key("t"));
+
+        let and = &top.cells["u_and"];
+        assert_eq!(and.connections["B"], [BitVal::N(3)]);
+        assert_eq!(and.parameters["A_WIDTH"], AttributeVal::N(1));
+        assert_eq!(and.port_directions["Y"], PortDirection::Output);
+        assert_eq!(and.port_directions["A"], PortDirection::Input);
+
+        let inv = &top.cells["u_inv"];
+        assert_eq!(inv.cell_type, "inv");
+        assert_eq!(inv.port_directions["o"], PortDirection::Output);
+
+        assert_eq!(
+            top.cells["u_eq"].connections["B"],
+            [BitVal::S(SpecialBit::X), BitVal::S(SpecialBit::_1)]
+        );
+
+        let inv_mod = &netlist.modules["inv"];
+        assert_eq!(inv_mod.ports["i"].bits, [BitVal::N(2)]);
+        assert_eq!(
+            netlist.modules_in_dependency_order().unwrap(),
+            ["inv", "top"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "wire `missing` not declared in `top`")]
+    fn undeclared_wire_test() {
+        netlist! {
+            module top {
+                cell "$not" u { A: missing }
+            }
+        };
+    }
+}
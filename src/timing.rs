@@ -0,0 +1,531 @@
+use crate::{BitVal, ConnectivityIndex, Endpoint, HdlVector, Module, Netname, SequentialKind};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+
+/// Delays of the arcs from input ports to output ports of each cell type
+///
+/// The JSON form maps cell type to input port to output port to delay, e.g.
+/// `{ "$and": { "A": { "Y": 1.0 }, "B": { "Y": 1.0 } } }`. A cell type without an
+/// entry gets `default_delay` on every arc from an input to an output port; a cell
+/// type with an entry only has the arcs listed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DelayTable {
+    /// Arc delays by cell type, input port, and output port
+    pub arcs: HashMap<String, HashMap<String, HashMap<String, f64>>>,
+    /// Delay of every arc of cell types not in `arcs`
+    #[serde(default)]
+    pub default_delay: f64,
+}
+
+impl DelayTable {
+    /// Create an empty table where every arc has `default_delay`
+    pub fn new(default_delay: f64) -> Self {
+        DelayTable {
+            arcs: HashMap::new(),
+            default_delay,
+        }
+    }
+
+    /// Read a table from JSON, giving arcs of unlisted cell types `default_delay`
+    pub fn from_reader<R: Read>(reader: R, default_delay: f64) -> Result<Self, serde_json::Error> {
+        Ok(DelayTable {
+            arcs: serde_json::from_reader(reader)?,
+            default_delay,
+        })
+    }
+
+    /// Read a table from a slice of JSON, giving arcs of unlisted cell types
+    /// `default_delay`
+    pub fn from_slice(input: &[u8], default_delay: f64) -> Result<Self, serde_json::Error> {
+        Ok(DelayTable {
+            arcs: serde_json::from_slice(input)?,
+            default_delay,
+        })
+    }
+
+    /// Set the delay of one arc
+    pub fn set_arc(&mut self, cell_type: &str, from: &str, to: &str, delay: f64) {
+        self.arcs
+            .entry(cell_type.to_owned())
+            .or_default()
+            .entry(from.to_owned())
+            .or_default()
+            .insert(to.to_owned(), delay);
+    }
+}
+
+/// The kind of a path found by `Timing::critical_paths`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PathKind {
+    /// From a register output to a register input
+    RegToReg,
+    /// From a module input to a register input
+    InToReg,
+    /// From a register output to a module output
+    RegToOut,
+    /// From a module input to a module output through combinational logic only
+    InToOut,
+}
+
+/// One cell on a timing path
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimingHop {
+    /// Cell name
+    pub cell: String,
+    /// Cell type
+    pub cell_type: String,
+    /// Input port the path enters through
+    pub from_port: String,
+    /// Output port the path leaves through
+    pub to_port: String,
+    /// Delay of the arc
+    pub delay: f64,
+    /// Whether the cell type wasn't in the delay table, so the default delay was used
+    pub defaulted: bool,
+    /// Best name of the net driven by the hop, if it has one
+    pub net: Option<String>,
+}
+
+/// A path found by `Timing::critical_paths`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimingPath {
+    /// What the path starts and ends at
+    pub kind: PathKind,
+    /// Total delay
+    pub delay: f64,
+    /// Where the path starts, e.g. `port a[0]` or `cell q_reg.Q[0]`
+    pub startpoint: String,
+    /// Where the path ends, in the same form as `startpoint`
+    pub endpoint: String,
+    /// Cells on the path, in order
+    pub hops: Vec<TimingHop>,
+}
+
+/// Whether a cell type is a Yosys flip-flop or latch
+pub(crate) fn is_sequential(cell_type: &str) -> bool {
+    SequentialKind::of_cell_type(cell_type).is_some()
+}
+
+fn is_clock_port(port: &str) -> bool {
+    matches!(port, "CLK" | "C")
+}
+
+/// How the latest arrival at a signal got there
+#[derive(Clone, Debug)]
+enum Via<'a> {
+    /// The signal is a start point
+    Start(String),
+    /// Through an arc of a cell from the signal `prev`
+    Arc {
+        cell: &'a str,
+        from: &'a str,
+        to: &'a str,
+        delay: f64,
+        defaulted: bool,
+        prev: usize,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Arrival<'a> {
+    time: f64,
+    from_reg: bool,
+    via: Via<'a>,
+}
+
+/// A module with delays on the arcs of its cells, as returned by
+/// `Module::annotate_delays`
+#[derive(Clone, Debug)]
+pub struct Timing<'a> {
+    module: &'a Module,
+    table: &'a DelayTable,
+    index: ConnectivityIndex<'a>,
+    net_names: HashMap<usize, String>,
+}
+
+impl Module {
+    /// Attach arc delays from `table` to the cells of this module for path analysis
+    ///
+    /// Only cell ports listed in `port_directions` are followed. Flip-flops and
+    /// latches (Yosys `$dff`-style and `$dlatch`-style cells) start and end paths.
+    pub fn annotate_delays<'a>(&'a self, table: &'a DelayTable) -> Timing<'a> {
+        Timing {
+            module: self,
+            table,
+            index: self.connectivity(),
+            net_names: self.signal_names(),
+        }
+    }
+
+    /// The netname bit that best names each named signal, as the netname's name, the
+    /// netname, and the bit index, preferring public netnames and then the
+    /// alphabetically first
+    pub(crate) fn signal_netname_bits(&self) -> HashMap<usize, (&str, &Netname, usize)> {
+        let mut netnames = self.netnames.iter().collect::<Vec<_>>();
+        netnames.sort_by_key(|(name, netname)| (netname.hide_name, name.as_str()));
+        let mut bits = HashMap::new();
+        for (name, netname) in netnames {
+            for (i, bit) in netname.bits.iter().enumerate() {
+                if let BitVal::N(n) = *bit {
+                    bits.entry(n).or_insert((name.as_str(), netname, i));
+                }
+            }
+        }
+        bits
+    }
+
+    /// The best name of each named signal, such as `count[3]`, as chosen by
+    /// `signal_netname_bits`
+    pub(crate) fn signal_names(&self) -> HashMap<usize, String> {
+        self.signal_netname_bits()
+            .into_iter()
+            .map(|(n, (name, netname, i))| {
+                let name = if netname.bits.len() == 1 {
+                    name.to_owned()
+                } else {
+                    format!("{}[{}]", name, netname.to_hdl_index(i))
+                };
+                (n, name)
+            })
+            .collect()
+    }
+}
+
+impl<'a> Timing<'a> {
+    /// Delay of the arc from `from` to `to` of a cell type, and whether it is the
+    /// default delay
+    fn arc_delay(&self, cell_type: &str, from: &str, to: &str) -> Option<(f64, bool)> {
+        match self.table.arcs.get(cell_type) {
+            Some(arcs) => Some((*arcs.get(from)?.get(to)?, false)),
+            None => Some((self.table.default_delay, true)),
+        }
+    }
+
+    /// Names of the cells whose type isn't in the delay table, sorted
+    pub fn defaulted_cells(&self) -> Vec<&'a str> {
+        let mut cells = self
+            .module
+            .cells
+            .iter()
+            .filter(|(_, cell)| !self.table.arcs.contains_key(&cell.cell_type))
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        cells.sort();
+        cells
+    }
+
+    fn arrival(
+        &self,
+        signal: usize,
+        memo: &mut HashMap<usize, Option<Arrival<'a>>>,
+        visiting: &mut HashSet<usize>,
+    ) -> Option<Arrival<'a>> {
+        if let Some(arrival) = memo.get(&signal) {
+            return arrival.clone();
+        }
+        if !visiting.insert(signal) {
+            return None;
+        }
+
+        let mut best: Option<Arrival<'a>> = None;
+        for driver in self.index.drivers(signal) {
+            let candidate = match *driver {
+                Endpoint::ModulePort { port, bit } => Some(Arrival {
+                    time: 0.0,
+                    from_reg: false,
+                    via: Via::Start(format!("port {}[{}]", port, bit)),
+                }),
+                Endpoint::CellPort { cell, port, bit } => {
+                    let (cell_name, cell) = self.module.cells.get_key_value(cell).unwrap();
+                    if is_sequential(&cell.cell_type) {
+                        Some(Arrival {
+                            time: 0.0,
+                            from_reg: true,
+                            via: Via::Start(format!("cell {}.{}[{}]", cell_name, port, bit)),
+                        })
+                    } else {
+                        self.cell_arrival(cell_name, port, memo, visiting)
+                    }
+                }
+            };
+            if let Some(candidate) = candidate {
+                if !best
+                    .as_ref()
+                    .is_some_and(|best| candidate.time <= best.time)
+                {
+                    best = Some(candidate);
+                }
+            }
+        }
+
+        visiting.remove(&signal);
+        memo.insert(signal, best.clone());
+        best
+    }
+
+    /// Latest arrival at output port `to` of a combinational cell
+    fn cell_arrival(
+        &self,
+        cell_name: &'a str,
+        to: &'a str,
+        memo: &mut HashMap<usize, Option<Arrival<'a>>>,
+        visiting: &mut HashSet<usize>,
+    ) -> Option<Arrival<'a>> {
+        let cell = &self.module.cells[cell_name];
+        let mut inputs = cell
+            .connections
+            .iter()
+            .filter(|(name, _)| {
+                cell.port_directions
+                    .get(*name)
+                    .is_some_and(|dir| dir.flipped().drives_net_from_instance())
+            })
+            .collect::<Vec<_>>();
+        inputs.sort_by_key(|(name, _)| name.as_str());
+
+        let mut best: Option<Arrival<'a>> = None;
+        for (from, bits) in inputs {
+            let Some((delay, defaulted)) = self.arc_delay(&cell.cell_type, from, to) else {
+                continue;
+            };
+            for bit in bits {
+                let BitVal::N(n) = *bit else {
+                    continue;
+                };
+                let Some(prev) = self.arrival(n, memo, visiting) else {
+                    continue;
+                };
+                let time = prev.time + delay;
+                if !best.as_ref().is_some_and(|best| time <= best.time) {
+                    best = Some(Arrival {
+                        time,
+                        from_reg: prev.from_reg,
+                        via: Via::Arc {
+                            cell: cell_name,
+                            from,
+                            to,
+                            delay,
+                            defaulted,
+                            prev: n,
+                        },
+                    });
+                }
+            }
+        }
+        best
+    }
+
+    /// Follow the arcs a signal was reached through back to its start point
+    fn trace(
+        &self,
+        signal: usize,
+        memo: &HashMap<usize, Option<Arrival<'a>>>,
+    ) -> (String, Vec<TimingHop>) {
+        let mut hops = Vec::new();
+        let mut signal = signal;
+        loop {
+            let arrival = memo[&signal].as_ref().unwrap();
+            match &arrival.via {
+                Via::Start(start) => {
+                    hops.reverse();
+                    return (start.clone(), hops);
+                }
+                Via::Arc {
+                    cell,
+                    from,
+                    to,
+                    delay,
+                    defaulted,
+                    prev,
+                } => {
+                    hops.push(TimingHop {
+                        cell: cell.to_string(),
+                        cell_type: self.module.cells[*cell].cell_type.clone(),
+                        from_port: from.to_string(),
+                        to_port: to.to_string(),
+                        delay: *delay,
+                        defaulted: *defaulted,
+                        net: self.net_names.get(&signal).cloned(),
+                    });
+                    signal = *prev;
+                }
+            }
+        }
+    }
+
+    /// The `n` slowest paths, slowest first
+    ///
+    /// Paths end at output ports and at every input of a flip-flop or latch except
+    /// its clock (`CLK` or `C`). Each end point bit gives at most one path, through
+    /// whichever start point reaches it latest.
+    ///
+    /// Loops through combinational logic are cut at the first signal reached a
+    /// second time. End points are visited output ports first, then register inputs,
+    /// each sorted by name, and drivers in sorted order, so where a loop is cut (and
+    /// so the result) doesn't depend on hash map order.
+    pub fn critical_paths(&self, n: usize) -> Vec<TimingPath> {
+        let mut ports = self.module.ports.iter().collect::<Vec<_>>();
+        ports.sort_by_key(|(name, _)| name.as_str());
+        let mut cells = self.module.cells.iter().collect::<Vec<_>>();
+        cells.sort_by_key(|(name, _)| name.as_str());
+
+        let mut endpoints = Vec::new();
+        for (name, port) in ports {
+            if port.direction.flipped().drives_net_inside() {
+                for (i, bit) in port.bits.iter().enumerate() {
+                    endpoints.push((*bit, false, format!("port {}[{}]", name, i)));
+                }
+            }
+        }
+        for (cell_name, cell) in cells {
+            if !is_sequential(&cell.cell_type) {
+                continue;
+            }
+            let mut connections = cell.connections.iter().collect::<Vec<_>>();
+            connections.sort_by_key(|(port, _)| port.as_str());
+            for (port, bits) in connections {
+                if is_clock_port(port) {
+                    continue;
+                }
+                let is_input = cell
+                    .port_directions
+                    .get(port)
+                    .is_some_and(|dir| dir.flipped().drives_net_from_instance());
+                if is_input {
+                    for (i, bit) in bits.iter().enumerate() {
+                        endpoints.push((*bit, true, format!("cell {}.{}[{}]", cell_name, port, i)));
+                    }
+                }
+            }
+        }
+
+        let mut memo = HashMap::new();
+        let mut visiting = HashSet::new();
+        let mut paths = Vec::new();
+        for (bit, to_reg, endpoint) in endpoints {
+            let BitVal::N(signal) = bit else {
+                continue;
+            };
+            let Some(arrival) = self.arrival(signal, &mut memo, &mut visiting) else {
+                continue;
+            };
+            let (startpoint, hops) = self.trace(signal, &memo);
+            paths.push(TimingPath {
+                kind: match (arrival.from_reg, to_reg) {
+                    (true, true) => PathKind::RegToReg,
+                    (false, true) => PathKind::InToReg,
+                    (true, false) => PathKind::RegToOut,
+                    (false, false) => PathKind::InToOut,
+                },
+                delay: arrival.time,
+                startpoint,
+                endpoint,
+                hops,
+            });
+        }
+
+        paths.sort_by(|a, b| {
+            b.delay
+                .total_cmp(&a.delay)
+                .then_with(|| a.endpoint.cmp(&b.endpoint))
+        });
+        paths.truncate(n);
+        paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netlist;
+
+    #[test]
+    fn critical_paths_test() {
+        let netlist = netlist! {
+            module top {
+                input clk;
+                input a;
+                input b;
+                output y;
+                wire q;
+                wire t;
+                wire u;
+                cell "$dff" r1 { CLK: clk, D: a, Q: q }
+                cell "$and" and { A: q, B: b, Y: t }
+                cell "$not" inv { A: t, Y: u }
+                cell "$dff" r2 { CLK: clk, D: u, Q: y }
+                cell "mystery_buf" buf { A: b }
+            }
+        };
+        let top = &netlist.modules["top"];
+        let mut table = DelayTable::from_slice(
+            br#"{ "$and": { "A": { "Y": 2.0 }, "B": { "Y": 1.0 } } }"#,
+            0.5,
+        )
+        .unwrap();
+        let timing = top.annotate_delays(&table);
+        assert_eq!(timing.defaulted_cells(), ["buf", "inv", "r1", "r2"]);
+
+        let paths = timing.critical_paths(10);
+        let summary = paths
+            .iter()
+            .map(|p| (p.kind, p.delay, p.startpoint.as_str(), p.endpoint.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (PathKind::RegToReg, 2.5, "cell r1.Q[0]", "cell r2.D[0]"),
+                (PathKind::InToReg, 0.0, "port a[0]", "cell r1.D[0]"),
+                (PathKind::RegToOut, 0.0, "cell r2.Q[0]", "port y[0]"),
+            ]
+        );
+        let hops = &paths[0].hops;
+        assert_eq!(hops.len(), 2);
+        assert_eq!(hops[0].cell, "and");
+        assert_eq!(hops[0].from_port, "A");
+        assert_eq!(hops[0].net.as_deref(), Some("t"));
+        assert!(!hops[0].defaulted);
+        assert_eq!(hops[1].cell_type, "$not");
+        assert!(hops[1].defaulted);
+
+        table.set_arc("$not", "A", "Y", 3.0);
+        let paths = top.annotate_delays(&table).critical_paths(1);
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].delay, 5.0);
+    }
+
+    #[test]
+    fn critical_paths_loop_test() {
+        // `x` and `z` feed each other, so which arrival gets memoized with the loop
+        // cut depends on which end point is visited first
+        let build = || {
+            netlist! {
+                module top {
+                    input a;
+                    output y;
+                    output w;
+                    wire x;
+                    wire z;
+                    cell "$and" g1 { A: a, B: z, Y: x }
+                    cell "$and" g2 { A: a, B: x, Y: z }
+                    cell "$not" n1 { A: x, Y: y }
+                    cell "$not" n2 { A: z, Y: w }
+                }
+            }
+        };
+        let table = DelayTable::from_slice(br#"{}"#, 1.0).unwrap();
+        let expected = build().modules["top"]
+            .annotate_delays(&table)
+            .critical_paths(10);
+        assert_eq!(expected[0].endpoint, "port w[0]");
+        assert_eq!(expected[0].delay, 3.0);
+        for _ in 0..20 {
+            let netlist = build();
+            let paths = netlist.modules["top"]
+                .annotate_delays(&table)
+                .critical_paths(10);
+            assert_eq!(paths, expected);
+        }
+    }
+}
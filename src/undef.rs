@@ -0,0 +1,336 @@
+use crate::{AttributeVal, BitVal, Module, Netlist, PortDirection, SpecialBit};
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+
+/// Selects which known-benign undefined constants `Netlist::undef_report_filtered` skips
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct UndefFilter {
+    /// Skip `x` on the data inputs (`A`/`B`) of `$mux` and `$pmux` cells, which are
+    /// don't-care values
+    pub ignore_mux_dont_care: bool,
+    /// Skip `z` on tri-state logic: the data inputs of `$mux`/`$pmux` cells, the
+    /// connections of `$tribuf`/`$_TBUF_` cells, and output or inout module ports
+    pub ignore_tristate_z: bool,
+}
+
+/// Where an undefined constant bit was found
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UndefLocation {
+    /// In a connection of a cell
+    Cell { cell: String, port: String },
+    /// In the bits of a module port
+    Port { port: String },
+}
+
+/// One `x` or `z` bit found by `Netlist::undef_report`
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+pub struct UndefBit {
+    /// Module containing the bit
+    pub module: String,
+    /// Cell connection or port containing the bit
+    pub location: UndefLocation,
+    /// Index of the bit within the connection or port
+    pub bit: usize,
+    /// The constant found
+    pub value: SpecialBit,
+    /// Name of a net carrying the same constant at this position, if any
+    pub net_name: Option<String>,
+}
+
+/// One parameter with `x` or `z` bits found by `Netlist::undef_report`
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+pub struct UndefParameter {
+    /// Module containing the parameter
+    pub module: String,
+    /// Cell the parameter is set on, or `None` for a module parameter default value
+    pub cell: Option<String>,
+    /// Parameter name
+    pub parameter: String,
+    /// Binary string value of the parameter
+    pub value: String,
+}
+
+/// Every use of undefined (`x`) and high-impedance (`z`) constants in a netlist
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct UndefReport {
+    /// Constant bits in cell connections and module ports
+    pub bits: Vec<UndefBit>,
+    /// Parameters with `x` or `z` bits
+    pub parameters: Vec<UndefParameter>,
+    /// Number of bits and parameters found in each module that has any
+    pub totals: BTreeMap<String, usize>,
+}
+
+fn is_mux_data(cell_type: &str, port: &str) -> bool {
+    matches!(cell_type, "$mux" | "$pmux") && matches!(port, "A" | "B")
+}
+
+/// The value of a parameter if it is a binary constant containing `x` or `z` bits
+fn undef_parameter_value(val: &AttributeVal) -> Option<&str> {
+    match val {
+        AttributeVal::S(s)
+            if !s.is_empty()
+                && s.chars().all(|c| matches!(c, '0' | '1' | 'x' | 'z'))
+                && s.chars().any(|c| matches!(c, 'x' | 'z')) =>
+        {
+            Some(s)
+        }
+        _ => None,
+    }
+}
+
+/// Find a net whose bits are exactly `bits`, preferring public names
+fn matching_net_name<'a>(module: &'a Module, bits: &[BitVal]) -> Option<&'a str> {
+    module
+        .netnames
+        .iter()
+        .filter(|(_, netname)| netname.bits == bits)
+        .min_by_key(|(name, netname)| (netname.hide_name, name.as_str()))
+        .map(|(name, _)| name.as_str())
+}
+
+fn scan_bits(
+    module_name: &str,
+    module: &Module,
+    location: UndefLocation,
+    bits: &[BitVal],
+    skip: impl Fn(SpecialBit) -> bool,
+    out: &mut Vec<UndefBit>,
+) {
+    let mut net_name = None;
+    for (idx, bit) in bits.iter().enumerate() {
+        let &BitVal::S(value @ (SpecialBit::X | SpecialBit::Z)) = bit else {
+            continue;
+        };
+        if skip(value) {
+            continue;
+        }
+        let net_name = *net_name.get_or_insert_with(|| matching_net_name(module, bits));
+        out.push(UndefBit {
+            module: module_name.to_owned(),
+            location: location.clone(),
+            bit: idx,
+            value,
+            net_name: net_name.map(|name| {
+                if bits.len() == 1 {
+                    name.to_owned()
+                } else {
+                    format!("{}[{}]", name, idx)
+                }
+            }),
+        });
+    }
+}
+
+impl Netlist {
+    /// Find every `x` and `z` constant in the netlist
+    pub fn undef_report(&self) -> UndefReport {
+        self.undef_report_filtered(&UndefFilter::default())
+    }
+
+    /// Find every `x` and `z` constant in the netlist, skipping the benign cases
+    /// selected by `filter`
+    pub fn undef_report_filtered(&self, filter: &UndefFilter) -> UndefReport {
+        let mut report = UndefReport::default();
+
+        let mut module_names = self.modules.keys().collect::<Vec<_>>();
+        module_names.sort();
+        for module_name in module_names {
+            let module = &self.modules[module_name];
+            let bits_start = report.bits.len();
+            let params_start = report.parameters.len();
+
+            let mut port_names = module.ports.keys().collect::<Vec<_>>();
+            port_names.sort();
+            for port_name in port_names {
+                let port = &module.ports[port_name];
+                let tristate = port.direction != PortDirection::Input;
+                scan_bits(
+                    module_name,
+                    module,
+                    UndefLocation::Port {
+                        port: port_name.clone(),
+                    },
+                    &port.bits,
+                    |value| value == SpecialBit::Z && tristate && filter.ignore_tristate_z,
+                    &mut report.bits,
+                );
+            }
+
+            let mut cell_names = module.cells.keys().collect::<Vec<_>>();
+            cell_names.sort();
+            for cell_name in cell_names {
+                let cell = &module.cells[cell_name];
+                let mut conn_names = cell.connections.keys().collect::<Vec<_>>();
+                conn_names.sort();
+                for conn_name in conn_names {
+                    let mux_data = is_mux_data(&cell.cell_type, conn_name);
+                    let tribuf = matches!(cell.cell_type.as_str(), "$tribuf" | "$_TBUF_");
+                    scan_bits(
+                        module_name,
+                        module,
+                        UndefLocation::Cell {
+                            cell: cell_name.clone(),
+                            port: conn_name.clone(),
+                        },
+                        &cell.connections[conn_name],
+                        |value| match value {
+                            SpecialBit::X => mux_data && filter.ignore_mux_dont_care,
+                            SpecialBit::Z => (mux_data || tribuf) && filter.ignore_tristate_z,
+                            _ => false,
+                        },
+                        &mut report.bits,
+                    );
+                }
+
+                let mut params = cell
+                    .parameters
+                    .iter()
+                    .filter_map(|(name, val)| Some((name, undef_parameter_value(val)?)))
+                    .collect::<Vec<_>>();
+                params.sort_by_key(|(name, _)| name.as_str());
+                for (name, val) in params {
+                    report.parameters.push(UndefParameter {
+                        module: module_name.clone(),
+                        cell: Some(cell_name.clone()),
+                        parameter: name.clone(),
+                        value: val.to_owned(),
+                    });
+                }
+            }
+
+            let mut params = module
+                .parameter_default_values
+                .iter()
+                .filter_map(|(name, val)| Some((name, undef_parameter_value(val)?)))
+                .collect::<Vec<_>>();
+            params.sort_by_key(|(name, _)| name.as_str());
+            for (name, val) in params {
+                report.parameters.push(UndefParameter {
+                    module: module_name.clone(),
+                    cell: None,
+                    parameter: name.clone(),
+                    value: val.to_owned(),
+                });
+            }
+
+            let total = report.bits.len() - bits_start + report.parameters.len() - params_start;
+            if total != 0 {
+                report.totals.insert(module_name.clone(), total);
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNDEF_JSON: &[u8] = br#"
+    {
+      "modules": {
+        "top": {
+          "parameter_default_values": { "INIT": "01x0", "NAME": "xyz" },
+          "ports": {
+            "i": { "direction": "input", "bits": [ 2, "x" ] },
+            "o": { "direction": "output", "bits": [ "z", 3 ] }
+          },
+          "cells": {
+            "mux": {
+              "type": "$mux",
+              "parameters": { "WIDTH": 2 },
+              "connections": { "A": [ "x", "x" ], "B": [ "z", 2 ], "S": [ 4 ], "Y": [ 5, 6 ] }
+            },
+            "and": {
+              "type": "$and",
+              "parameters": { "INIT": "1z" },
+              "connections": { "A": [ 2, "x" ], "B": [ "0", "1" ], "Y": [ 7, 8 ] }
+            }
+          },
+          "netnames": {
+            "undef_wire": { "hide_name": 0, "bits": [ "x", "x" ] }
+          }
+        },
+        "clean": {
+          "ports": { "i": { "direction": "input", "bits": [ 2 ] } }
+        }
+      }
+    }"#;
+
+    #[test]
+    fn undef_report_test() {
+        let netlist = Netlist::from_slice(UNDEF_JSON).unwrap();
+        let report = netlist.undef_report();
+
+        let summary = report
+            .bits
+            .iter()
+            .map(|bit| match &bit.location {
+                UndefLocation::Cell { cell, port } => format!("{}.{}[{}]", cell, port, bit.bit),
+                UndefLocation::Port { port } => format!("{}[{}]", port, bit.bit),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            ["i[1]", "o[0]", "and.A[1]", "mux.A[0]", "mux.A[1]", "mux.B[0]"]
+        );
+        assert_eq!(report.bits[1].value, SpecialBit::Z);
+        assert_eq!(report.bits[3].net_name.as_deref(), Some("undef_wire[0]"));
+        assert_eq!(report.bits[2].net_name, None);
+
+        let params = report
+            .parameters
+            .iter()
+            .map(|p| (p.cell.as_deref(), p.parameter.as_str(), p.value.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            params,
+            [(Some("and"), "INIT", "1z"), (None, "INIT", "01x0")]
+        );
+
+        assert_eq!(report.totals.len(), 1);
+        assert_eq!(report.totals.get("top"), Some(&8));
+
+        let json = serde_json::to_string(&report.bits[0]).unwrap();
+        assert_eq!(
+            json,
+            r#"{"module":"top","location":{"port":{"port":"i"}},"bit":1,"value":"x","net_name":null}"#
+        );
+    }
+
+    #[test]
+    fn undef_report_filter_test() {
+        let netlist = Netlist::from_slice(UNDEF_JSON).unwrap();
+
+        let report = netlist.undef_report_filtered(&UndefFilter {
+            ignore_mux_dont_care: true,
+            ignore_tristate_z: false,
+        });
+        assert_eq!(report.bits.len(), 4);
+
+        let report = netlist.undef_report_filtered(&UndefFilter {
+            ignore_mux_dont_care: true,
+            ignore_tristate_z: true,
+        });
+        let locations = report
+            .bits
+            .iter()
+            .map(|bit| bit.location.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            [
+                UndefLocation::Port {
+                    port: "i".to_owned()
+                },
+                UndefLocation::Cell {
+                    cell: "and".to_owned(),
+                    port: "A".to_owned()
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{AttributeVal, BitVal, Cell, Memory, Module, Netlist, Netname, Port};
+use std::collections::HashMap;
+
+/// The object an attribute passed to `visit_attribute` belongs to
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum AttributeOwner<'a> {
+    /// An attribute of the module being visited
+    Module,
+    /// An attribute of the named cell
+    Cell(&'a str),
+    /// An attribute of the named memory
+    Memory(&'a str),
+    /// An attribute of the named netname
+    Netname(&'a str),
+}
+
+/// Callbacks for `Netlist::accept` and `Module::accept`
+///
+/// Every method does nothing by default. Modules are visited in name order. Within a
+/// module the module attributes come first, then ports, cells (each followed by its
+/// attributes and connections), memories, and netnames, each sorted by name.
+#[allow(unused_variables)]
+pub trait NetlistVisitor {
+    fn visit_module(&mut self, name: &str, module: &Module) {}
+    fn visit_port(&mut self, name: &str, port: &Port) {}
+    fn visit_cell(&mut self, name: &str, cell: &Cell) {}
+    fn visit_connection(&mut self, cell: &str, port: &str, bits: &[BitVal]) {}
+    fn visit_memory(&mut self, name: &str, memory: &Memory) {}
+    fn visit_netname(&mut self, name: &str, netname: &Netname) {}
+    fn visit_attribute(&mut self, owner: AttributeOwner<'_>, key: &str, value: &AttributeVal) {}
+}
+
+/// Callbacks for `Netlist::accept_mut` and `Module::accept_mut`, in the same order as
+/// `NetlistVisitor`
+///
+/// Objects can be changed but not added, removed, or renamed.
+#[allow(unused_variables)]
+pub trait NetlistVisitorMut {
+    fn visit_module(&mut self, name: &str, module: &mut Module) {}
+    fn visit_port(&mut self, name: &str, port: &mut Port) {}
+    fn visit_cell(&mut self, name: &str, cell: &mut Cell) {}
+    fn visit_connection(&mut self, cell: &str, port: &str, bits: &mut Vec<BitVal>) {}
+    fn visit_memory(&mut self, name: &str, memory: &mut Memory) {}
+    fn visit_netname(&mut self, name: &str, netname: &mut Netname) {}
+    fn visit_attribute(&mut self, owner: AttributeOwner<'_>, key: &str, value: &mut AttributeVal) {}
+}
+
+fn sorted<V>(map: &HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries = map.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+fn sorted_mut<V>(map: &mut HashMap<String, V>) -> Vec<(&String, &mut V)> {
+    let mut entries = map.iter_mut().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+fn visit_attributes(
+    v: &mut impl NetlistVisitor,
+    owner: AttributeOwner<'_>,
+    attributes: &HashMap<String, AttributeVal>,
+) {
+    for (key, value) in sorted(attributes) {
+        v.visit_attribute(owner, key, value);
+    }
+}
+
+fn visit_attributes_mut(
+    v: &mut impl NetlistVisitorMut,
+    owner: AttributeOwner<'_>,
+    attributes: &mut HashMap<String, AttributeVal>,
+) {
+    for (key, value) in sorted_mut(attributes) {
+        v.visit_attribute(owner, key, value);
+    }
+}
+
+impl Netlist {
+    /// Call `v` on every object of every module
+    pub fn accept(&self, v: &mut impl NetlistVisitor) {
+        for (name, module) in sorted(&self.modules) {
+            v.visit_module(name, module);
+            module.accept(v);
+        }
+    }
+
+    /// Call `v` on every object of every module, allowing it to modify them
+    pub fn accept_mut(&mut self, v: &mut impl NetlistVisitorMut) {
+        for (name, module) in sorted_mut(&mut self.modules) {
+            v.visit_module(name, module);
+            module.accept_mut(v);
+        }
+    }
+}
+
+impl Module {
+    /// Call `v` on every object in this module
+    ///
+    /// `visit_module` is not called since a module doesn't know its own name.
+    pub fn accept(&self, v: &mut impl NetlistVisitor) {
+        visit_attributes(v, AttributeOwner::Module, &self.attributes);
+        for (name, port) in sorted(&self.ports) {
+            v.visit_port(name, port);
+        }
+        for (name, cell) in sorted(&self.cells) {
+            v.visit_cell(name, cell);
+            visit_attributes(v, AttributeOwner::Cell(name), &cell.attributes);
+            for (port, bits) in sorted(&cell.connections) {
+                v.visit_connection(name, port, bits);
+            }
+        }
+        for (name, memory) in sorted(&self.memories) {
+            v.visit_memory(name, memory);
+            visit_attributes(v, AttributeOwner::Memory(name), &memory.attributes);
+        }
+        for (name, netname) in sorted(&self.netnames) {
+            v.visit_netname(name, netname);
+            visit_attributes(v, AttributeOwner::Netname(name), &netname.attributes);
+        }
+    }
+
+    /// Call `v` on every object in this module, allowing it to modify them
+    ///
+    /// `visit_module` is not called since a module doesn't know its own name.
+    pub fn accept_mut(&mut self, v: &mut impl NetlistVisitorMut) {
+        visit_attributes_mut(v, AttributeOwner::Module, &mut self.attributes);
+        for (name, port) in sorted_mut(&mut self.ports) {
+            v.visit_port(name, port);
+        }
+        for (name, cell) in sorted_mut(&mut self.cells) {
+            v.visit_cell(name, cell);
+            visit_attributes_mut(v, AttributeOwner::Cell(name), &mut cell.attributes);
+            for (port, bits) in sorted_mut(&mut cell.connections) {
+                v.visit_connection(name, port, bits);
+            }
+        }
+        for (name, memory) in sorted_mut(&mut self.memories) {
+            v.visit_memory(name, memory);
+            visit_attributes_mut(v, AttributeOwner::Memory(name), &mut memory.attributes);
+        }
+        for (name, netname) in sorted_mut(&mut self.netnames) {
+            v.visit_netname(name, netname);
+            visit_attributes_mut(v, AttributeOwner::Netname(name), &mut netname.attributes);
+        }
+    }
+}
+
+/// Calls a closure on every bit of every port, cell connection, and netname
+pub(crate) struct BitVisitor<F>(pub(crate) F);
+
+impl<F: FnMut(&mut BitVal)> NetlistVisitorMut for BitVisitor<F> {
+    fn visit_port(&mut self, _name: &str, port: &mut Port) {
+        port.bits.iter_mut().for_each(&mut self.0);
+    }
+
+    fn visit_connection(&mut self, _cell: &str, _port: &str, bits: &mut Vec<BitVal>) {
+        bits.iter_mut().for_each(&mut self.0);
+    }
+
+    fn visit_netname(&mut self, _name: &str, netname: &mut Netname) {
+        netname.bits.iter_mut().for_each(&mut self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Trace(Vec<String>);
+
+    impl NetlistVisitor for Trace {
+        fn visit_module(&mut self, name: &str, _module: &Module) {
+            self.0.push(format!("module {}", name));
+        }
+        fn visit_port(&mut self, name: &str, _port: &Port) {
+            self.0.push(format!("port {}", name));
+        }
+        fn visit_cell(&mut self, name: &str, _cell: &Cell) {
+            self.0.push(format!("cell {}", name));
+        }
+        fn visit_connection(&mut self, cell: &str, port: &str, bits: &[BitVal]) {
+            self.0
+                .push(format!("conn {}.{} {}", cell, port, bits.len()));
+        }
+        fn visit_memory(&mut self, name: &str, _memory: &Memory) {
+            self.0.push(format!("memory {}", name));
+        }
+        fn visit_netname(&mut self, name: &str, _netname: &Netname) {
+            self.0.push(format!("net {}", name));
+        }
+        fn visit_attribute(&mut self, owner: AttributeOwner<'_>, key: &str, _: &AttributeVal) {
+            self.0.push(format!("attr {:?} {}", owner, key));
+        }
+    }
+
+    const VISIT_JSON: &[u8] = br#"
+    {
+      "modules": {
+        "top": {
+          "attributes": { "top": 1 },
+          "ports": {
+            "b": { "direction": "input", "bits": [ 3 ] },
+            "a": { "direction": "input", "bits": [ 2 ] }
+          },
+          "cells": {
+            "u": {
+              "type": "$and",
+              "attributes": { "src": "a.v:1" },
+              "connections": { "B": [ 3 ], "A": [ 2 ], "Y": [ 4 ] }
+            }
+          },
+          "memories": {
+            "mem": { "width": 1, "start_offset": 0, "size": 2 }
+          },
+          "netnames": {
+            "y": { "bits": [ 4 ], "attributes": { "keep": 1 } }
+          }
+        },
+        "empty": {}
+      }
+    }"#;
+
+    #[test]
+    fn visit_order_test() {
+        let netlist = Netlist::from_slice(VISIT_JSON).unwrap();
+        let mut v = Trace::default();
+        netlist.accept(&mut v);
+        assert_eq!(
+            v.0,
+            [
+                "module empty",
+                "module top",
+                "attr Module top",
+                "port a",
+                "port b",
+                "cell u",
+                "attr Cell(\"u\") src",
+                "conn u.A 1",
+                "conn u.B 1",
+                "conn u.Y 1",
+                "memory mem",
+                "net y",
+                "attr Netname(\"y\") keep",
+            ]
+        );
+    }
+
+    #[test]
+    fn accept_mut_test() {
+        struct DropSrc;
+        impl NetlistVisitorMut for DropSrc {
+            fn visit_cell(&mut self, _name: &str, cell: &mut Cell) {
+                cell.attributes.remove("src");
+            }
+            fn visit_attribute(
+                &mut self,
+                _: AttributeOwner<'_>,
+                _: &str,
+                value: &mut AttributeVal,
+            ) {
+                *value = AttributeVal::N(0);
+            }
+        }
+
+        let mut netlist = Netlist::from_slice(VISIT_JSON).unwrap();
+        netlist.accept_mut(&mut DropSrc);
+        let top = netlist.modules.get("top").unwrap();
+        assert_eq!(top.attributes.get("top"), Some(&AttributeVal::N(0)));
+        assert!(top.cells.get("u").unwrap().attributes.is_empty());
+        assert_eq!(
+            top.netnames.get("y").unwrap().attributes.get("keep"),
+            Some(&AttributeVal::N(0))
+        );
+    }
+}
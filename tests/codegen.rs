@@ -0,0 +1,123 @@
+//! Compile the output of `codegen::to_rust_const` with rustc, run it, and compare
+//! against `Simulator`
+
+use std::path::PathBuf;
+use std::process::Command;
+use yosys_netlist_json::codegen::{to_rust_const, RustConstOptions};
+use yosys_netlist_json::{Netlist, Simulator};
+
+const ADDER_JSON: &[u8] = br#"
+{
+  "modules": {
+    "top": {
+      "ports": {
+        "a": { "direction": "input", "bits": [ 2, 3 ] },
+        "b": { "direction": "input", "bits": [ 4, 5 ] },
+        "c": { "direction": "input", "bits": [ 6 ] },
+        "sum": { "direction": "output", "bits": [ 7, 8 ] },
+        "sel": { "direction": "output", "bits": [ 9, "1" ] }
+      },
+      "cells": {
+        "x0": {
+          "type": "$xor",
+          "parameters": { "A_WIDTH": 2, "B_WIDTH": 2, "Y_WIDTH": 2 },
+          "connections": { "A": [ 2, 3 ], "B": [ 4, 5 ], "Y": [ 10, 11 ] }
+        },
+        "x1": {
+          "type": "$_XOR_",
+          "connections": { "A": [ 10 ], "B": [ 6 ], "Y": [ 7 ] }
+        },
+        "n1": {
+          "type": "$_NAND_",
+          "connections": { "A": [ 11 ], "B": [ 2 ], "Y": [ 8 ] }
+        },
+        "m": {
+          "type": "$mux",
+          "parameters": { "WIDTH": 1 },
+          "connections": { "A": [ 3 ], "B": [ 12 ], "S": [ 6 ], "Y": [ 9 ] }
+        },
+        "lut": {
+          "type": "$lut",
+          "parameters": { "WIDTH": 3, "LUT": "10010110" },
+          "connections": { "A": [ 2, 4, 6 ], "Y": [ 12 ] }
+        }
+      }
+    }
+  }
+}"#;
+
+const MAIN: &str = r#"
+fn main() {
+    let mut total_bits = 0;
+    for (_, bits) in INPUTS {
+        total_bits += bits.len();
+    }
+    for v in 0u64..(1 << total_bits) {
+        let mut inputs = [0u64; NUM_INPUTS];
+        let mut shift = 0;
+        for (k, (_, bits)) in INPUTS.iter().enumerate() {
+            inputs[k] = (v >> shift) & ((1 << bits.len()) - 1);
+            shift += bits.len();
+        }
+        let outputs = eval(&inputs);
+        let outputs: Vec<String> = outputs.iter().map(|x| x.to_string()).collect();
+        println!("{}", outputs.join(" "));
+    }
+}
+"#;
+
+fn binary(value: u64, width: usize) -> String {
+    (0..width)
+        .rev()
+        .map(|i| if (value >> i) & 1 != 0 { '1' } else { '0' })
+        .collect()
+}
+
+#[test]
+fn generated_eval_matches_simulator() {
+    let netlist = Netlist::from_slice(ADDER_JSON).unwrap();
+    let module = &netlist.modules["top"];
+    let code = to_rust_const(module, &RustConstOptions::default());
+    assert!(code.contains("pub fn eval("));
+
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("codegen");
+    std::fs::create_dir_all(&dir).unwrap();
+    let src = dir.join("main.rs");
+    let bin = dir.join("main");
+    std::fs::write(&src, format!("{}\n{}", code, MAIN)).unwrap();
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let status = Command::new(rustc)
+        .args(["--edition", "2021", "-o"])
+        .arg(&bin)
+        .arg(&src)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = Command::new(&bin).output().unwrap();
+    assert!(output.status.success());
+    let lines = String::from_utf8(output.stdout).unwrap();
+
+    // Inputs and outputs are in name order: a, b, c and sel, sum
+    let mut count = 0;
+    for (v, line) in lines.lines().enumerate() {
+        let v = v as u64;
+        let mut sim = Simulator::new(module).unwrap();
+        sim.set_input("a", &binary(v & 3, 2)).unwrap();
+        sim.set_input("b", &binary((v >> 2) & 3, 2)).unwrap();
+        sim.set_input("c", &binary((v >> 4) & 1, 1)).unwrap();
+        sim.settle();
+
+        let expected = ["sel", "sum"]
+            .iter()
+            .map(|port| {
+                let value = sim.output(port).unwrap();
+                u64::from_str_radix(&value, 2).unwrap().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(line, expected, "inputs {:05b}", v);
+        count += 1;
+    }
+    assert_eq!(count, 32);
+}